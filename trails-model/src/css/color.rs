@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use trails_base::once_cell::sync::Lazy;
+use trails_base::regex::Regex;
+use trails_base::{Result, bail};
+
+/// An sRGB color with alpha. All channels are in the range `0.0..=1.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+    pub alpha: f64,
+}
+
+/// A specified color value, which may refer to the current color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorValue {
+    Color(Color),
+    /// The `currentColor` keyword, i.e. the value of the `color` property.
+    CurrentColor,
+}
+
+static NAMED_COLORS: Lazy<HashMap<&str, u32>> = Lazy::new(|| {
+    let mut map = HashMap::new();
+    map.insert("aliceblue", 0xf0f8ff);
+    map.insert("antiquewhite", 0xfaebd7);
+    map.insert("aqua", 0x00ffff);
+    map.insert("aquamarine", 0x7fffd4);
+    map.insert("azure", 0xf0ffff);
+    map.insert("beige", 0xf5f5dc);
+    map.insert("bisque", 0xffe4c4);
+    map.insert("black", 0x000000);
+    map.insert("blanchedalmond", 0xffebcd);
+    map.insert("blue", 0x0000ff);
+    map.insert("blueviolet", 0x8a2be2);
+    map.insert("brown", 0xa52a2a);
+    map.insert("burlywood", 0xdeb887);
+    map.insert("cadetblue", 0x5f9ea0);
+    map.insert("chartreuse", 0x7fff00);
+    map.insert("chocolate", 0xd2691e);
+    map.insert("coral", 0xff7f50);
+    map.insert("cornflowerblue", 0x6495ed);
+    map.insert("cornsilk", 0xfff8dc);
+    map.insert("crimson", 0xdc143c);
+    map.insert("cyan", 0x00ffff);
+    map.insert("darkblue", 0x00008b);
+    map.insert("darkcyan", 0x008b8b);
+    map.insert("darkgoldenrod", 0xb8860b);
+    map.insert("darkgray", 0xa9a9a9);
+    map.insert("darkgreen", 0x006400);
+    map.insert("darkgrey", 0xa9a9a9);
+    map.insert("darkkhaki", 0xbdb76b);
+    map.insert("darkmagenta", 0x8b008b);
+    map.insert("darkolivegreen", 0x556b2f);
+    map.insert("darkorange", 0xff8c00);
+    map.insert("darkorchid", 0x9932cc);
+    map.insert("darkred", 0x8b0000);
+    map.insert("darksalmon", 0xe9967a);
+    map.insert("darkseagreen", 0x8fbc8f);
+    map.insert("darkslateblue", 0x483d8b);
+    map.insert("darkslategray", 0x2f4f4f);
+    map.insert("darkslategrey", 0x2f4f4f);
+    map.insert("darkturquoise", 0x00ced1);
+    map.insert("darkviolet", 0x9400d3);
+    map.insert("deeppink", 0xff1493);
+    map.insert("deepskyblue", 0x00bfff);
+    map.insert("dimgray", 0x696969);
+    map.insert("dimgrey", 0x696969);
+    map.insert("dodgerblue", 0x1e90ff);
+    map.insert("firebrick", 0xb22222);
+    map.insert("floralwhite", 0xfffaf0);
+    map.insert("forestgreen", 0x228b22);
+    map.insert("fuchsia", 0xff00ff);
+    map.insert("gainsboro", 0xdcdcdc);
+    map.insert("ghostwhite", 0xf8f8ff);
+    map.insert("gold", 0xffd700);
+    map.insert("goldenrod", 0xdaa520);
+    map.insert("gray", 0x808080);
+    map.insert("green", 0x008000);
+    map.insert("greenyellow", 0xadff2f);
+    map.insert("grey", 0x808080);
+    map.insert("honeydew", 0xf0fff0);
+    map.insert("hotpink", 0xff69b4);
+    map.insert("indianred", 0xcd5c5c);
+    map.insert("indigo", 0x4b0082);
+    map.insert("ivory", 0xfffff0);
+    map.insert("khaki", 0xf0e68c);
+    map.insert("lavender", 0xe6e6fa);
+    map.insert("lavenderblush", 0xfff0f5);
+    map.insert("lawngreen", 0x7cfc00);
+    map.insert("lemonchiffon", 0xfffacd);
+    map.insert("lightblue", 0xadd8e6);
+    map.insert("lightcoral", 0xf08080);
+    map.insert("lightcyan", 0xe0ffff);
+    map.insert("lightgoldenrodyellow", 0xfafad2);
+    map.insert("lightgray", 0xd3d3d3);
+    map.insert("lightgreen", 0x90ee90);
+    map.insert("lightgrey", 0xd3d3d3);
+    map.insert("lightpink", 0xffb6c1);
+    map.insert("lightsalmon", 0xffa07a);
+    map.insert("lightseagreen", 0x20b2aa);
+    map.insert("lightskyblue", 0x87cefa);
+    map.insert("lightslategray", 0x778899);
+    map.insert("lightslategrey", 0x778899);
+    map.insert("lightsteelblue", 0xb0c4de);
+    map.insert("lightyellow", 0xffffe0);
+    map.insert("lime", 0x00ff00);
+    map.insert("limegreen", 0x32cd32);
+    map.insert("linen", 0xfaf0e6);
+    map.insert("magenta", 0xff00ff);
+    map.insert("maroon", 0x800000);
+    map.insert("mediumaquamarine", 0x66cdaa);
+    map.insert("mediumblue", 0x0000cd);
+    map.insert("mediumorchid", 0xba55d3);
+    map.insert("mediumpurple", 0x9370db);
+    map.insert("mediumseagreen", 0x3cb371);
+    map.insert("mediumslateblue", 0x7b68ee);
+    map.insert("mediumspringgreen", 0x00fa9a);
+    map.insert("mediumturquoise", 0x48d1cc);
+    map.insert("mediumvioletred", 0xc71585);
+    map.insert("midnightblue", 0x191970);
+    map.insert("mintcream", 0xf5fffa);
+    map.insert("mistyrose", 0xffe4e1);
+    map.insert("moccasin", 0xffe4b5);
+    map.insert("navajowhite", 0xffdead);
+    map.insert("navy", 0x000080);
+    map.insert("oldlace", 0xfdf5e6);
+    map.insert("olive", 0x808000);
+    map.insert("olivedrab", 0x6b8e23);
+    map.insert("orange", 0xffa500);
+    map.insert("orangered", 0xff4500);
+    map.insert("orchid", 0xda70d6);
+    map.insert("palegoldenrod", 0xeee8aa);
+    map.insert("palegreen", 0x98fb98);
+    map.insert("paleturquoise", 0xafeeee);
+    map.insert("palevioletred", 0xdb7093);
+    map.insert("papayawhip", 0xffefd5);
+    map.insert("peachpuff", 0xffdab9);
+    map.insert("peru", 0xcd853f);
+    map.insert("pink", 0xffc0cb);
+    map.insert("plum", 0xdda0dd);
+    map.insert("powderblue", 0xb0e0e6);
+    map.insert("purple", 0x800080);
+    map.insert("rebeccapurple", 0x663399);
+    map.insert("red", 0xff0000);
+    map.insert("rosybrown", 0xbc8f8f);
+    map.insert("royalblue", 0x4169e1);
+    map.insert("saddlebrown", 0x8b4513);
+    map.insert("salmon", 0xfa8072);
+    map.insert("sandybrown", 0xf4a460);
+    map.insert("seagreen", 0x2e8b57);
+    map.insert("seashell", 0xfff5ee);
+    map.insert("sienna", 0xa0522d);
+    map.insert("silver", 0xc0c0c0);
+    map.insert("skyblue", 0x87ceeb);
+    map.insert("slateblue", 0x6a5acd);
+    map.insert("slategray", 0x708090);
+    map.insert("slategrey", 0x708090);
+    map.insert("snow", 0xfffafa);
+    map.insert("springgreen", 0x00ff7f);
+    map.insert("steelblue", 0x4682b4);
+    map.insert("tan", 0xd2b48c);
+    map.insert("teal", 0x008080);
+    map.insert("thistle", 0xd8bfd8);
+    map.insert("tomato", 0xff6347);
+    map.insert("turquoise", 0x40e0d0);
+    map.insert("violet", 0xee82ee);
+    map.insert("wheat", 0xf5deb3);
+    map.insert("white", 0xffffff);
+    map.insert("whitesmoke", 0xf5f5f5);
+    map.insert("yellow", 0xffff00);
+    map.insert("yellowgreen", 0x9acd32);
+    map
+});
+
+static HEX_COLOR: Lazy<Regex> = Lazy::new(|| Regex::new(r"^#([0-9a-fA-F]{3,4}|[0-9a-fA-F]{6}|[0-9a-fA-F]{8})$").unwrap());
+static COLOR_FUNCTION: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?i)(?P<name>rgba?|hsla?)\(\s*(?P<args>[^()]*)\)$").unwrap());
+static FUNCTION_ARG_SEPARATOR: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s*[,/]\s*|\s+").unwrap());
+static NUMERIC_ARG: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?P<number>[+-]?(?:\d+\.?\d*|\.\d+)(?:[eE][+-]?\d+)?)(?P<unit>%|deg|grad|rad|turn)?$").unwrap());
+
+/// A numeric argument to a color function.
+enum ColorArg {
+    Number(f64),
+    Percentage(f64),
+    Angle(f64), // in degrees
+}
+
+impl ColorArg {
+    fn parse(raw: &str) -> Result<Self> {
+        if raw.eq_ignore_ascii_case("none") {
+            return Ok(Self::Number(0.0));
+        }
+        let Some(captures) = NUMERIC_ARG.captures(raw) else {
+            bail!("Invalid color function argument '{}'", raw)
+        };
+        let number: f64 = captures["number"].parse()?;
+        Ok(match captures.name("unit").map(|m| m.as_str()) {
+            Some("%") => Self::Percentage(number),
+            Some("deg") => Self::Angle(number),
+            Some("grad") => Self::Angle(number * 0.9),
+            Some("rad") => Self::Angle(number.to_degrees()),
+            Some("turn") => Self::Angle(number * 360.0),
+            _ => Self::Number(number),
+        })
+    }
+
+    /// Interprets the argument as an RGB channel in `0.0..=1.0`.
+    fn channel(&self) -> f64 {
+        match *self {
+            Self::Number(n) => n / 255.0,
+            Self::Percentage(p) => p / 100.0,
+            Self::Angle(a) => a / 255.0,
+        }.clamp(0.0, 1.0)
+    }
+
+    /// Interprets the argument as an alpha value in `0.0..=1.0`.
+    fn alpha(&self) -> f64 {
+        match *self {
+            Self::Number(n) | Self::Angle(n) => n,
+            Self::Percentage(p) => p / 100.0,
+        }.clamp(0.0, 1.0)
+    }
+
+    /// Interprets the argument as a hue in degrees.
+    fn hue(&self) -> f64 {
+        match *self {
+            Self::Number(n) | Self::Angle(n) | Self::Percentage(n) => n.rem_euclid(360.0),
+        }
+    }
+
+    /// Interprets the argument as a saturation or lightness in `0.0..=1.0`.
+    fn fraction(&self) -> f64 {
+        match *self {
+            Self::Number(n) | Self::Angle(n) | Self::Percentage(n) => n / 100.0,
+        }.clamp(0.0, 1.0)
+    }
+}
+
+impl Color {
+    pub const BLACK: Self = Self::rgb8(0x00, 0x00, 0x00);
+    pub const WHITE: Self = Self::rgb8(0xff, 0xff, 0xff);
+    pub const BLUE: Self = Self::rgb8(0x00, 0x00, 0xff);
+    pub const TRANSPARENT: Self = Self::rgba(0.0, 0.0, 0.0, 0.0);
+
+    /// Creates a color from floating-point channels in `0.0..=1.0`.
+    pub const fn rgba(red: f64, green: f64, blue: f64, alpha: f64) -> Self {
+        Self { red, green, blue, alpha }
+    }
+
+    /// Creates an opaque color from 8-bit channels.
+    pub const fn rgb8(red: u8, green: u8, blue: u8) -> Self {
+        Self::rgba(red as f64 / 255.0, green as f64 / 255.0, blue as f64 / 255.0, 1.0)
+    }
+
+    /// Creates an opaque color from a `0xRRGGBB` value.
+    pub const fn from_rgb_u32(rgb: u32) -> Self {
+        Self::rgb8((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    }
+
+    /// Creates an opaque color from hue (in degrees), saturation and lightness (in `0.0..=1.0`).
+    pub fn hsl(hue: f64, saturation: f64, lightness: f64) -> Self {
+        // See https://www.w3.org/TR/css-color-4/#hsl-to-rgb
+        let f = |n: f64| {
+            let k = (n + hue / 30.0) % 12.0;
+            let a = saturation * lightness.min(1.0 - lightness);
+            lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+        };
+        Self::rgba(f(0.0), f(8.0), f(4.0), 1.0)
+    }
+
+    /// Returns the same color with the given alpha.
+    pub fn with_alpha(self, alpha: f64) -> Self {
+        Self { alpha, ..self }
+    }
+
+    /// Parses a color in any of the supported notations (named, hex, `rgb()`, `rgba()`, `hsl()`, `hsla()`).
+    pub fn parse(raw: &str) -> Result<Self> {
+        let raw = raw.trim();
+        let lower = raw.to_ascii_lowercase();
+
+        if lower == "transparent" {
+            Ok(Self::TRANSPARENT)
+        } else if let Some(&rgb) = NAMED_COLORS.get(lower.as_str()) {
+            Ok(Self::from_rgb_u32(rgb))
+        } else if let Some(captures) = HEX_COLOR.captures(raw) {
+            Self::parse_hex(&captures[1])
+        } else if let Some(captures) = COLOR_FUNCTION.captures(raw) {
+            let args = FUNCTION_ARG_SEPARATOR.split(captures["args"].trim())
+                .map(ColorArg::parse)
+                .collect::<Result<Vec<_>>>()?;
+            let alpha = match args.len() {
+                3 => 1.0,
+                4 => args[3].alpha(),
+                n => bail!("Color function '{}' expects 3 or 4 arguments, but got {}", raw, n),
+            };
+            if captures["name"].to_ascii_lowercase().starts_with("rgb") {
+                Ok(Self::rgba(args[0].channel(), args[1].channel(), args[2].channel(), alpha))
+            } else {
+                Ok(Self::hsl(args[0].hue(), args[1].fraction(), args[2].fraction()).with_alpha(alpha))
+            }
+        } else {
+            bail!("Invalid color '{}'", raw)
+        }
+    }
+
+    /// Parses the digits of a hex color (without the leading `#`).
+    fn parse_hex(digits: &str) -> Result<Self> {
+        // Expand the short forms #rgb and #rgba
+        let digits = if digits.len() <= 4 {
+            digits.chars().flat_map(|c| [c, c]).collect()
+        } else {
+            digits.to_owned()
+        };
+        let value = u32::from_str_radix(&digits, 16)?;
+        Ok(if digits.len() == 8 {
+            Self::from_rgb_u32(value >> 8).with_alpha((value & 0xff) as f64 / 255.0)
+        } else {
+            Self::from_rgb_u32(value)
+        })
+    }
+}
+
+impl ColorValue {
+    /// Parses a color value, which may also be `currentColor`.
+    pub fn parse(raw: &str) -> Result<Self> {
+        if raw.trim().eq_ignore_ascii_case("currentcolor") {
+            Ok(Self::CurrentColor)
+        } else {
+            Ok(Self::Color(Color::parse(raw)?))
+        }
+    }
+
+    /// Resolves the value given the current color (i.e. the element's `color`).
+    pub fn resolve(&self, current_color: Color) -> Color {
+        match *self {
+            Self::Color(color) => color,
+            Self::CurrentColor => current_color,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use crate::parse::css::{parse_url, split_commas, split_components};
+
+/// A single CSS declaration, e.g. `color: red !important`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declaration {
+    /// The (lowercased) property name.
+    pub name: String,
+    /// The raw value. We keep this unparsed since its interpretation
+    /// depends on the property.
+    pub value: String,
+    /// Whether the declaration is marked `!important`.
+    pub important: bool,
+}
+
+impl Declaration {
+    /// The URLs of the images referenced by this declaration if it is a
+    /// `background` or `background-image` declaration.
+    pub fn image_urls(&self) -> Vec<String> {
+        if !matches!(self.name.as_str(), "background" | "background-image") {
+            return Vec::new();
+        }
+        split_commas(&self.value).into_iter()
+            .flat_map(split_components)
+            .filter_map(parse_url)
+            .collect()
+    }
+}
@@ -0,0 +1,412 @@
+use std::ops::{Add, Mul, Neg};
+
+use trails_base::once_cell::sync::Lazy;
+use trails_base::regex::Regex;
+use trails_base::{Result, bail};
+
+use crate::parse::tokens::Tokens;
+
+/// A unit for a CSS length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// Reference pixels.
+    Px,
+    /// Relative to the element's font size.
+    Em,
+    /// Relative to the root element's font size.
+    Rem,
+    /// Relative to some reference length that depends on the property.
+    Percent,
+    /// 1% of the viewport's width.
+    Vw,
+    /// 1% of the viewport's height.
+    Vh,
+    /// Points, i.e. 1/72 of an inch.
+    Pt,
+    /// The advance of the `0` glyph (approximated).
+    Ch,
+}
+
+/// A number with a unit, e.g. `12px`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dimension {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+/// A specified length, e.g. `2em` or `calc(100% - 20px)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Length {
+    Dimension(Dimension),
+    Calc(Box<Calc>),
+}
+
+/// A `calc()` expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Calc {
+    Dimension(Dimension),
+    Number(f64),
+    Sum(Box<Calc>, Box<Calc>),
+    Difference(Box<Calc>, Box<Calc>),
+    Product(Box<Calc>, Box<Calc>),
+    Quotient(Box<Calc>, Box<Calc>),
+}
+
+/// The size of the viewport.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub width: f64,
+    pub height: f64,
+}
+
+/// The context against which relative lengths are resolved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResolveContext {
+    /// The font size that `em` and `ch` refer to. For `font-size` itself
+    /// this is the parent's font size.
+    pub font_size: f64,
+    /// The root element's font size, which `rem` refers to.
+    pub root_font_size: f64,
+    /// The viewport size, which `vw` and `vh` refer to.
+    pub viewport: Viewport,
+}
+
+/// A length with all units except percentages resolved, i.e. `px + percent%`.
+/// Since `calc()` only allows multiplication and division by numbers, every
+/// length can be represented in this form.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ComputedLength {
+    pub px: f64,
+    pub percent: f64,
+}
+
+static DIMENSION: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?P<number>[+-]?(?:\d+\.?\d*|\.\d+)(?:[eE][+-]?\d+)?)(?P<unit>[a-zA-Z]+|%)?$").unwrap());
+static CALC_LEXER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&[
+        // Numbers with an optional unit, e.g. 12px or 3
+        r#"(?P<number>(?:\d+\.?\d*|\.\d+)(?:[eE][+-]?\d+)?(?:[a-zA-Z]+|%)?)"#,
+        // Opening parentheses, including nested calc(
+        r#"(?P<open>(?i:calc)?\()"#,
+        // Closing parentheses
+        r#"(?P<close>\))"#,
+        // Operators
+        r#"(?P<operator>[+\-*/])"#,
+        // Whitespace
+        r#"(?P<white>\s+)"#,
+    ].join("|")).unwrap()
+});
+
+#[derive(Debug, Clone, PartialEq)]
+enum CalcToken {
+    Number(f64),
+    Dimension(Dimension),
+    Open,
+    Close,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+}
+
+impl Unit {
+    /// Parses a unit (case-insensitively).
+    pub fn parse(raw: &str) -> Result<Self> {
+        Ok(match raw.to_ascii_lowercase().as_str() {
+            "px" => Self::Px,
+            "em" => Self::Em,
+            "rem" => Self::Rem,
+            "%" => Self::Percent,
+            "vw" => Self::Vw,
+            "vh" => Self::Vh,
+            "pt" => Self::Pt,
+            "ch" => Self::Ch,
+            _ => bail!("Unsupported unit '{}'", raw),
+        })
+    }
+}
+
+impl Dimension {
+    /// Parses a dimension, e.g. `12px`. A unitless zero is treated as `0px`.
+    pub fn parse(raw: &str) -> Result<Self> {
+        match Self::parse_number(raw)? {
+            (value, Some(unit)) => Ok(Self { value, unit }),
+            (value, None) if value == 0.0 => Ok(Self { value, unit: Unit::Px }),
+            _ => bail!("Length '{}' requires a unit", raw),
+        }
+    }
+
+    /// Parses a number with an optional unit.
+    fn parse_number(raw: &str) -> Result<(f64, Option<Unit>)> {
+        let Some(captures) = DIMENSION.captures(raw.trim()) else {
+            bail!("Invalid dimension '{}'", raw)
+        };
+        let value = captures["number"].parse()?;
+        let unit = captures.name("unit").map(|m| Unit::parse(m.as_str())).transpose()?;
+        Ok((value, unit))
+    }
+
+    /// Resolves the dimension, leaving percentages unresolved.
+    pub fn compute(&self, ctx: &ResolveContext) -> ComputedLength {
+        let value = self.value;
+        let px = match self.unit {
+            Unit::Px => value,
+            Unit::Em => value * ctx.font_size,
+            Unit::Rem => value * ctx.root_font_size,
+            Unit::Percent => return ComputedLength { px: 0.0, percent: value },
+            Unit::Vw => value * ctx.viewport.width / 100.0,
+            Unit::Vh => value * ctx.viewport.height / 100.0,
+            Unit::Pt => value * 96.0 / 72.0,
+            // TODO: Measure the actual advance of '0' in the current font
+            Unit::Ch => value * ctx.font_size * 0.5,
+        };
+        ComputedLength::px(px)
+    }
+}
+
+impl Length {
+    /// Creates a length in pixels.
+    pub fn px(value: f64) -> Self {
+        Self::Dimension(Dimension { value, unit: Unit::Px })
+    }
+
+    /// Creates a length relative to the font size.
+    pub fn em(value: f64) -> Self {
+        Self::Dimension(Dimension { value, unit: Unit::Em })
+    }
+
+    /// Creates a percentage.
+    pub fn percent(value: f64) -> Self {
+        Self::Dimension(Dimension { value, unit: Unit::Percent })
+    }
+
+    /// Parses a length, e.g. `12px`, `50%` or `calc(100% - 2em)`.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let raw = raw.trim();
+        if raw.get(..5).is_some_and(|prefix| prefix.eq_ignore_ascii_case("calc(")) {
+            Ok(Self::Calc(Box::new(Calc::parse(raw)?)))
+        } else {
+            Ok(Self::Dimension(Dimension::parse(raw)?))
+        }
+    }
+
+    /// Resolves everything except percentages.
+    pub fn compute(&self, ctx: &ResolveContext) -> ComputedLength {
+        match self {
+            Self::Dimension(dimension) => dimension.compute(ctx),
+            Self::Calc(calc) => calc.compute(ctx),
+        }
+    }
+
+    /// Resolves the length to pixels, using the given base for percentages.
+    pub fn resolve(&self, ctx: &ResolveContext, percentage_base: f64) -> f64 {
+        self.compute(ctx).resolve(percentage_base)
+    }
+}
+
+impl Calc {
+    /// Parses a `calc(...)` expression.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let mut tokens = Tokens::new(lex_calc(raw)?);
+        let calc = Self::parse_parenthesized(&mut tokens)?;
+        if tokens.peek().is_ok() {
+            bail!("Unexpected trailing tokens in '{}'", raw);
+        }
+        if calc.is_number() {
+            bail!("calc() expression '{}' must be a length", raw);
+        }
+        Ok(calc)
+    }
+
+    /// Parses `( sum )`.
+    fn parse_parenthesized(tokens: &mut Tokens<CalcToken>) -> Result<Self> {
+        tokens.expect(&CalcToken::Open)?;
+        let calc = Self::parse_sum(tokens)?;
+        tokens.expect(&CalcToken::Close)?;
+        Ok(calc)
+    }
+
+    /// Parses `product ((+|-) product)*`.
+    fn parse_sum(tokens: &mut Tokens<CalcToken>) -> Result<Self> {
+        let mut lhs = Self::parse_product(tokens)?;
+        loop {
+            let is_sum = match tokens.peek()? {
+                CalcToken::Plus => true,
+                CalcToken::Minus => false,
+                _ => break,
+            };
+            tokens.next()?;
+            let rhs = Self::parse_product(tokens)?;
+            if lhs.is_number() != rhs.is_number() {
+                bail!("Cannot add or subtract numbers and lengths in calc()");
+            }
+            lhs = if is_sum {
+                Self::Sum(Box::new(lhs), Box::new(rhs))
+            } else {
+                Self::Difference(Box::new(lhs), Box::new(rhs))
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// Parses `value ((*|/) value)*`.
+    fn parse_product(tokens: &mut Tokens<CalcToken>) -> Result<Self> {
+        let mut lhs = Self::parse_value(tokens)?;
+        loop {
+            let is_product = match tokens.peek()? {
+                CalcToken::Star => true,
+                CalcToken::Slash => false,
+                _ => break,
+            };
+            tokens.next()?;
+            let rhs = Self::parse_value(tokens)?;
+            lhs = if is_product {
+                if !lhs.is_number() && !rhs.is_number() {
+                    bail!("Cannot multiply two lengths in calc()");
+                }
+                Self::Product(Box::new(lhs), Box::new(rhs))
+            } else {
+                if !rhs.is_number() {
+                    bail!("Cannot divide by a length in calc()");
+                }
+                Self::Quotient(Box::new(lhs), Box::new(rhs))
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// Parses a number, a dimension, a negated value or a parenthesized sum.
+    fn parse_value(tokens: &mut Tokens<CalcToken>) -> Result<Self> {
+        match tokens.peek()?.clone() {
+            CalcToken::Open => Self::parse_parenthesized(tokens),
+            CalcToken::Minus => {
+                tokens.next()?;
+                let value = Self::parse_value(tokens)?;
+                Ok(Self::Product(Box::new(Self::Number(-1.0)), Box::new(value)))
+            },
+            CalcToken::Number(n) => {
+                tokens.next()?;
+                Ok(Self::Number(n))
+            },
+            CalcToken::Dimension(d) => {
+                tokens.next()?;
+                Ok(Self::Dimension(d))
+            },
+            token => bail!("Unexpected {:?} in calc()", token),
+        }
+    }
+
+    /// Whether this expression evaluates to a plain number.
+    fn is_number(&self) -> bool {
+        match self {
+            Self::Number(_) => true,
+            Self::Dimension(_) => false,
+            Self::Sum(lhs, _) | Self::Difference(lhs, _) | Self::Quotient(lhs, _) => lhs.is_number(),
+            Self::Product(lhs, rhs) => lhs.is_number() && rhs.is_number(),
+        }
+    }
+
+    /// Evaluates the expression to a number, treating dimensions as zero.
+    fn number(&self) -> f64 {
+        match self {
+            Self::Number(n) => *n,
+            Self::Dimension(_) => 0.0,
+            Self::Sum(lhs, rhs) => lhs.number() + rhs.number(),
+            Self::Difference(lhs, rhs) => lhs.number() - rhs.number(),
+            Self::Product(lhs, rhs) => lhs.number() * rhs.number(),
+            Self::Quotient(lhs, rhs) => lhs.number() / rhs.number(),
+        }
+    }
+
+    /// Resolves everything except percentages.
+    pub fn compute(&self, ctx: &ResolveContext) -> ComputedLength {
+        match self {
+            Self::Number(_) => ComputedLength::default(),
+            Self::Dimension(dimension) => dimension.compute(ctx),
+            Self::Sum(lhs, rhs) => lhs.compute(ctx) + rhs.compute(ctx),
+            Self::Difference(lhs, rhs) => lhs.compute(ctx) + -rhs.compute(ctx),
+            Self::Product(lhs, rhs) if lhs.is_number() => rhs.compute(ctx) * lhs.number(),
+            Self::Product(lhs, rhs) => lhs.compute(ctx) * rhs.number(),
+            Self::Quotient(lhs, rhs) => {
+                let divisor = rhs.number();
+                if divisor == 0.0 {
+                    ComputedLength::default()
+                } else {
+                    lhs.compute(ctx) * divisor.recip()
+                }
+            },
+        }
+    }
+}
+
+/// Tokenizes a `calc()` expression.
+fn lex_calc(raw: &str) -> Result<Vec<CalcToken>> {
+    let mut tokens = Vec::new();
+    let mut last_end: usize = 0;
+
+    for raw_token in CALC_LEXER.captures_iter(raw) {
+        let range = raw_token.get(0).unwrap().range();
+        if last_end != range.start {
+            bail!("Unexpected '{}' in calc()", &raw[last_end..range.start]);
+        }
+        last_end = range.end;
+
+        if let Some(number) = raw_token.name("number") {
+            tokens.push(match Dimension::parse_number(number.as_str())? {
+                (value, Some(unit)) => CalcToken::Dimension(Dimension { value, unit }),
+                (value, None) => CalcToken::Number(value),
+            });
+        } else if raw_token.name("open").is_some() {
+            tokens.push(CalcToken::Open);
+        } else if raw_token.name("close").is_some() {
+            tokens.push(CalcToken::Close);
+        } else if let Some(operator) = raw_token.name("operator") {
+            tokens.push(match operator.as_str() {
+                "+" => CalcToken::Plus,
+                "-" => CalcToken::Minus,
+                "*" => CalcToken::Star,
+                _ => CalcToken::Slash,
+            });
+        }
+    }
+
+    if last_end != raw.len() {
+        bail!("Unexpected '{}' in calc()", &raw[last_end..]);
+    }
+    Ok(tokens)
+}
+
+impl ComputedLength {
+    /// Creates an absolute length.
+    pub fn px(px: f64) -> Self {
+        Self { px, percent: 0.0 }
+    }
+
+    /// Resolves the length to pixels using the given base for percentages.
+    pub fn resolve(&self, percentage_base: f64) -> f64 {
+        self.px + self.percent * percentage_base / 100.0
+    }
+}
+
+impl Add for ComputedLength {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self { px: self.px + rhs.px, percent: self.percent + rhs.percent }
+    }
+}
+
+impl Neg for ComputedLength {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self { px: -self.px, percent: -self.percent }
+    }
+}
+
+impl Mul<f64> for ComputedLength {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self {
+        Self { px: self.px * rhs, percent: self.percent * rhs }
+    }
+}
@@ -0,0 +1,379 @@
+use trails_base::once_cell::sync::Lazy;
+use trails_base::regex::Regex;
+use trails_base::{Result, bail};
+
+use super::{Length, ResolveContext, Viewport};
+
+/// The type of media a document is presented on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaType {
+    Screen,
+    Print,
+}
+
+/// The user's preferred color scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+/// The environment that media queries are evaluated against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MediaEnvironment {
+    /// The size of the viewport.
+    pub viewport: Viewport,
+    /// The type of media.
+    pub media_type: MediaType,
+    /// The preferred color scheme.
+    pub color_scheme: ColorScheme,
+    /// The initial font size, which relative lengths in media queries refer to.
+    pub font_size: f64,
+}
+
+/// A comma-separated list of media queries, e.g. `screen and (min-width: 600px), print`.
+/// The list matches if any of its queries match (or if it is empty).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList {
+    pub queries: Vec<MediaQuery>,
+}
+
+/// A single media query, e.g. `not screen and (orientation: portrait)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    /// Whether the query is negated via `not`.
+    pub negated: bool,
+    /// The media type to match, `None` meaning `all`.
+    pub media_type: Option<MediaType>,
+    /// The feature conditions, which all have to match.
+    pub features: Vec<MediaFeature>,
+    /// Whether the query can never match, e.g. due to an unknown media type.
+    pub never: bool,
+}
+
+/// A comparison in a media feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+/// A value in a media feature.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaValue {
+    Length(Length),
+    Number(f64),
+    Keyword(String),
+}
+
+/// A single media feature condition, e.g. `(min-width: 600px)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+    /// A boolean test like `(color)`.
+    Boolean(String),
+    /// A comparison like `(width >= 600px)` or `(min-width: 600px)`.
+    Range { name: String, comparison: Comparison, value: MediaValue },
+    /// A plain (equality) test like `(orientation: landscape)`.
+    Plain { name: String, value: MediaValue },
+    /// A feature that we don't understand and which thus never matches.
+    Unknown,
+}
+
+static MEDIA_FEATURE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\(\s*(?P<name>[\w-]+)\s*(?::\s*(?P<value>[^()]+?))?\s*\)$").unwrap()
+});
+static RANGE_FEATURE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\(\s*(?:(?P<lhs>[^<>=()]+?)\s*(?P<lhsop><=|>=|<|>|=)\s*)?(?P<name>[a-z-]+)\s*(?:(?P<rhsop><=|>=|<|>|=)\s*(?P<rhs>[^<>=()]+?))?\s*\)$").unwrap()
+});
+static RATIO: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?P<num>[\d.]+)\s*/\s*(?P<den>[\d.]+)$").unwrap());
+
+impl Comparison {
+    fn parse(raw: &str) -> Result<Self> {
+        Ok(match raw {
+            "<" => Self::Less,
+            "<=" => Self::LessOrEqual,
+            "=" => Self::Equal,
+            ">=" => Self::GreaterOrEqual,
+            ">" => Self::Greater,
+            _ => bail!("Invalid comparison '{}'", raw),
+        })
+    }
+
+    /// Flips the comparison, e.g. for turning `600px < width` into `width > 600px`.
+    fn flipped(self) -> Self {
+        match self {
+            Self::Less => Self::Greater,
+            Self::LessOrEqual => Self::GreaterOrEqual,
+            Self::Equal => Self::Equal,
+            Self::GreaterOrEqual => Self::LessOrEqual,
+            Self::Greater => Self::Less,
+        }
+    }
+
+    fn holds(self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            Self::Less => lhs < rhs,
+            Self::LessOrEqual => lhs <= rhs,
+            Self::Equal => lhs == rhs,
+            Self::GreaterOrEqual => lhs >= rhs,
+            Self::Greater => lhs > rhs,
+        }
+    }
+}
+
+impl MediaValue {
+    fn parse(raw: &str) -> Self {
+        let raw = raw.trim();
+        if let Some(captures) = RATIO.captures(raw) {
+            let numerator: f64 = captures["num"].parse().unwrap_or(0.0);
+            let denominator: f64 = captures["den"].parse().unwrap_or(1.0);
+            Self::Number(numerator / denominator)
+        } else if let Ok(number) = raw.parse::<f64>() {
+            Self::Number(number)
+        } else if let Ok(length) = Length::parse(raw) {
+            Self::Length(length)
+        } else {
+            Self::Keyword(raw.to_ascii_lowercase())
+        }
+    }
+
+    /// Resolves the value to a number (lengths are resolved to pixels).
+    fn number(&self, env: &MediaEnvironment) -> Option<f64> {
+        match self {
+            Self::Number(number) => Some(*number),
+            Self::Length(length) => Some(length.resolve(&env.resolve_context(), 0.0)),
+            Self::Keyword(_) => None,
+        }
+    }
+}
+
+impl MediaFeature {
+    /// Parses a parenthesized feature, e.g. `(max-width: 600px)`. Returns
+    /// multiple features for double ranges like `(400px <= width <= 700px)`.
+    fn parse(raw: &str) -> Vec<Self> {
+        let raw = raw.trim().to_ascii_lowercase();
+        if let Some(captures) = MEDIA_FEATURE.captures(&raw) {
+            let name = captures["name"].to_owned();
+            let feature = match captures.name("value") {
+                None => Self::Boolean(name),
+                Some(value) => {
+                    let value = MediaValue::parse(value.as_str());
+                    if let Some(name) = name.strip_prefix("min-") {
+                        Self::Range { name: name.to_owned(), comparison: Comparison::GreaterOrEqual, value }
+                    } else if let Some(name) = name.strip_prefix("max-") {
+                        Self::Range { name: name.to_owned(), comparison: Comparison::LessOrEqual, value }
+                    } else {
+                        Self::Plain { name, value }
+                    }
+                },
+            };
+            return vec![feature];
+        }
+        if let Some(captures) = RANGE_FEATURE.captures(&raw) {
+            let name = &captures["name"];
+            let mut features = Vec::new();
+            if let (Some(lhs), Some(op)) = (captures.name("lhs"), captures.name("lhsop")) {
+                match Comparison::parse(op.as_str()) {
+                    Ok(comparison) => features.push(Self::Range {
+                        name: name.to_owned(),
+                        comparison: comparison.flipped(),
+                        value: MediaValue::parse(lhs.as_str()),
+                    }),
+                    Err(_) => features.push(Self::Unknown),
+                }
+            }
+            if let (Some(rhs), Some(op)) = (captures.name("rhs"), captures.name("rhsop")) {
+                match Comparison::parse(op.as_str()) {
+                    Ok(comparison) => features.push(Self::Range {
+                        name: name.to_owned(),
+                        comparison,
+                        value: MediaValue::parse(rhs.as_str()),
+                    }),
+                    Err(_) => features.push(Self::Unknown),
+                }
+            }
+            if !features.is_empty() {
+                return features;
+            }
+        }
+        vec![Self::Unknown]
+    }
+
+    /// Evaluates the feature against the given environment.
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        match self {
+            Self::Boolean(name) => matches!(name.as_str(), "width" | "height" | "color" | "hover" | "pointer" | "any-hover" | "any-pointer"),
+            Self::Range { name, comparison, value } => match (env.numeric_feature(name), value.number(env)) {
+                (Some(actual), Some(expected)) => comparison.holds(actual, expected),
+                _ => false,
+            },
+            Self::Plain { name, value } => match value {
+                MediaValue::Keyword(keyword) => env.keyword_feature(name) == Some(keyword.as_str()),
+                _ => match (env.numeric_feature(name), value.number(env)) {
+                    (Some(actual), Some(expected)) => actual == expected,
+                    _ => false,
+                },
+            },
+            Self::Unknown => false,
+        }
+    }
+}
+
+impl MediaQuery {
+    /// Parses a single media query.
+    pub fn parse(raw: &str) -> Self {
+        let mut query = Self { negated: false, media_type: None, features: Vec::new(), never: false };
+        let mut rest = raw.trim();
+
+        // Parse the optional modifier and media type
+        if !rest.starts_with('(') {
+            let (head, tail) = rest.split_once(|c: char| c.is_whitespace()).unwrap_or((rest, ""));
+            let mut media_type = head.to_ascii_lowercase();
+            rest = tail.trim_start();
+            if media_type == "not" || media_type == "only" {
+                query.negated = media_type == "not";
+                let (head, tail) = rest.split_once(|c: char| c.is_whitespace()).unwrap_or((rest, ""));
+                media_type = head.to_ascii_lowercase();
+                rest = tail.trim_start();
+            }
+            match media_type.as_str() {
+                "all" => {},
+                "screen" => query.media_type = Some(MediaType::Screen),
+                "print" => query.media_type = Some(MediaType::Print),
+                _ => query.never = true,
+            }
+            if !rest.is_empty() {
+                match strip_keyword(rest, "and") {
+                    Some(tail) => rest = tail,
+                    None => query.never = true,
+                }
+            }
+        }
+
+        // Parse the feature conditions, which are joined by `and`
+        while !rest.is_empty() {
+            let Some(end) = matching_paren(rest) else {
+                query.never = true;
+                break
+            };
+            query.features.extend(MediaFeature::parse(&rest[..=end]));
+            rest = rest[end + 1..].trim_start();
+            if !rest.is_empty() {
+                match strip_keyword(rest, "and") {
+                    Some(tail) => rest = tail,
+                    None => {
+                        // TODO: Support `or` and nested conditions
+                        query.never = true;
+                        break
+                    },
+                }
+            }
+        }
+
+        query
+    }
+
+    /// Evaluates the query against the given environment.
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        if self.never {
+            return false;
+        }
+        let matches = self.media_type.is_none_or(|t| t == env.media_type)
+            && self.features.iter().all(|f| f.matches(env));
+        matches != self.negated
+    }
+}
+
+impl MediaQueryList {
+    /// Parses a comma-separated list of media queries.
+    pub fn parse(raw: &str) -> Self {
+        let queries = raw.split(',')
+            .map(|q| q.trim())
+            .filter(|q| !q.is_empty())
+            .map(MediaQuery::parse)
+            .collect();
+        Self { queries }
+    }
+
+    /// Evaluates the list against the given environment.
+    pub fn matches(&self, env: &MediaEnvironment) -> bool {
+        self.queries.is_empty() || self.queries.iter().any(|q| q.matches(env))
+    }
+}
+
+impl MediaEnvironment {
+    /// The context against which lengths in media queries are resolved.
+    pub fn resolve_context(&self) -> ResolveContext {
+        ResolveContext {
+            font_size: self.font_size,
+            root_font_size: self.font_size,
+            viewport: self.viewport,
+        }
+    }
+
+    /// Looks up a numeric media feature.
+    fn numeric_feature(&self, name: &str) -> Option<f64> {
+        let Viewport { width, height } = self.viewport;
+        match name {
+            "width" => Some(width),
+            "height" => Some(height),
+            "aspect-ratio" if height > 0.0 => Some(width / height),
+            "color" => Some(8.0),
+            "monochrome" => Some(0.0),
+            "resolution" => Some(1.0),
+            _ => None,
+        }
+    }
+
+    /// Looks up a keyword media feature.
+    fn keyword_feature(&self, name: &str) -> Option<&str> {
+        let Viewport { width, height } = self.viewport;
+        match name {
+            "orientation" => Some(if height >= width { "portrait" } else { "landscape" }),
+            "prefers-color-scheme" => Some(match self.color_scheme {
+                ColorScheme::Light => "light",
+                ColorScheme::Dark => "dark",
+            }),
+            "prefers-reduced-motion" | "prefers-reduced-transparency" | "prefers-contrast" => Some("no-preference"),
+            "hover" | "any-hover" => Some("hover"),
+            "pointer" | "any-pointer" => Some("fine"),
+            "scripting" => Some("none"),
+            _ => None,
+        }
+    }
+}
+
+/// Strips a leading keyword followed by whitespace (case-insensitively).
+fn strip_keyword<'a>(raw: &'a str, keyword: &str) -> Option<&'a str> {
+    let head = raw.get(..keyword.len())?;
+    let tail = &raw[keyword.len()..];
+    if head.eq_ignore_ascii_case(keyword) && tail.starts_with(char::is_whitespace) {
+        Some(tail.trim_start())
+    } else {
+        None
+    }
+}
+
+/// Finds the index of the parenthesis closing the one at the start of the string.
+fn matching_paren(raw: &str) -> Option<usize> {
+    if !raw.starts_with('(') {
+        return None;
+    }
+    let mut depth = 0;
+    for (i, c) in raw.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
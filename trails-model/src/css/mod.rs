@@ -0,0 +1,13 @@
+mod color;
+mod declaration;
+mod length;
+mod media;
+mod selector;
+mod stylesheet;
+
+pub use color::*;
+pub use declaration::*;
+pub use length::*;
+pub use media::*;
+pub use selector::*;
+pub use stylesheet::*;
@@ -0,0 +1,552 @@
+use trails_base::log::debug;
+use trails_base::once_cell::sync::Lazy;
+use trails_base::regex::Regex;
+use trails_base::{Result, bail};
+
+use crate::dom::{Element, Node};
+use crate::parse::tokens::Tokens;
+
+/// A complex selector, e.g. `nav > ul li.active::before`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    /// The compound selectors, from left to right.
+    pub compounds: Vec<CompoundSelector>,
+    /// The combinators between the compound selectors, i.e. `combinators[i]`
+    /// is between `compounds[i]` and `compounds[i + 1]`.
+    pub combinators: Vec<Combinator>,
+    /// The pseudo-element targeted by this selector, if any.
+    pub pseudo_element: Option<PseudoElement>,
+}
+
+/// A combinator between two compound selectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
+/// A sequence of simple selectors without combinators, e.g. `a.external[href]`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CompoundSelector {
+    /// The tag name, `None` for the universal selector (or if omitted).
+    pub tag_name: Option<String>,
+    pub ids: Vec<String>,
+    pub classes: Vec<String>,
+    pub attributes: Vec<AttributeSelector>,
+    pub pseudo_classes: Vec<PseudoClass>,
+}
+
+/// An operator in an attribute selector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOperator {
+    /// `[attr=value]`
+    Equals,
+    /// `[attr~=value]`
+    Includes,
+    /// `[attr|=value]`
+    DashMatch,
+    /// `[attr^=value]`
+    Prefix,
+    /// `[attr$=value]`
+    Suffix,
+    /// `[attr*=value]`
+    Substring,
+}
+
+/// An attribute selector, e.g. `[lang|=en]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeSelector {
+    pub name: String,
+    /// The operator and value, `None` if only testing for presence.
+    pub matcher: Option<(AttributeOperator, String)>,
+    /// Whether the value is compared case-insensitively.
+    pub case_insensitive: bool,
+}
+
+/// An `an+b` pattern as used by `:nth-child()` and friends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nth {
+    pub a: i32,
+    pub b: i32,
+}
+
+/// A pseudo-class, e.g. `:first-child`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PseudoClass {
+    Root,
+    Empty,
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    FirstOfType,
+    LastOfType,
+    NthChild(Nth),
+    NthLastChild(Nth),
+    NthOfType(Nth),
+    Link,
+    Not(Vec<Selector>),
+    Is(Vec<Selector>),
+    Where(Vec<Selector>),
+    /// A pseudo-class that depends on state we don't track (e.g. `:hover`)
+    /// or one we don't know about. Never matches.
+    Never(String),
+}
+
+/// A pseudo-element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PseudoElement {
+    Before,
+    After,
+    Marker,
+}
+
+/// The specificity of a selector as an `(id, class, type)` triple.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Specificity(pub u32, pub u32, pub u32);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SelectorToken {
+    Tag(String),
+    Universal,
+    Id(String),
+    Class(String),
+    Attribute(AttributeSelector),
+    PseudoClass(String),
+    PseudoFunction(String),
+    Nth(String, Nth),
+    PseudoElement(String),
+    Combinator(Combinator),
+    Whitespace,
+    Comma,
+    Close,
+}
+
+static SELECTOR_LEXER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&[
+        // Pseudo-elements, e.g. ::before
+        r#"(?:::(?P<pseudoelement>[\w-]+))"#,
+        // Nth-pseudo-classes, e.g. :nth-child(2n + 1)
+        r#"(?::(?P<nthname>nth-child|nth-last-child|nth-of-type)\(\s*(?P<nth>[^)]*?)\s*\))"#,
+        // Functional pseudo-classes, e.g. :not(
+        r#"(?::(?P<pseudofunction>[\w-]+)\(\s*)"#,
+        // Pseudo-classes, e.g. :first-child
+        r#"(?::(?P<pseudoclass>[\w-]+))"#,
+        // Attribute selectors, e.g. [href^="https"]
+        r#"(?:\[\s*(?P<attrname>[\w-]+)\s*(?:(?P<attrop>[~|^$*]?=)\s*(?:"(?P<doublequoted>[^"]*)"|'(?P<singlequoted>[^']*)'|(?P<unquoted>[\w-]+))\s*(?P<attrflag>[iIsS])?\s*)?\])"#,
+        // IDs, e.g. #main
+        r#"(?:\#(?P<id>[\w-]+))"#,
+        // Classes, e.g. .active
+        r#"(?:\.(?P<class>[\w-]+))"#,
+        // Tag names, e.g. div
+        r#"(?P<tag>[\w-]+)"#,
+        // The universal selector
+        r#"(?P<universal>\*)"#,
+        // Combinators (with surrounding whitespace)
+        r#"(?:\s*(?P<combinator>[>+~])\s*)"#,
+        // Commas (with surrounding whitespace)
+        r#"(?:\s*(?P<comma>,)\s*)"#,
+        // Closing parentheses of functional pseudo-classes
+        r#"(?:\s*(?P<close>\)))"#,
+        // Whitespace, i.e. the descendant combinator
+        r#"(?P<white>\s+)"#,
+    ].join("|")).unwrap()
+});
+
+static NTH: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:(?P<odd>odd)|(?P<even>even)|(?P<a>[+-]?\d*)n\s*(?:(?P<sign>[+-])\s*(?P<b1>\d+))?|(?P<b2>[+-]?\d+))$").unwrap()
+});
+
+/// Tokenizes a selector.
+fn lex_selector(raw: &str) -> Result<Vec<SelectorToken>> {
+    let mut tokens = Vec::new();
+    let mut last_end: usize = 0;
+
+    for raw_token in SELECTOR_LEXER.captures_iter(raw) {
+        let range = raw_token.get(0).unwrap().range();
+        if last_end != range.start {
+            bail!("Unexpected '{}' in selector '{}'", &raw[last_end..range.start], raw);
+        }
+        last_end = range.end;
+
+        if let Some(name) = raw_token.name("pseudoelement") {
+            tokens.push(SelectorToken::PseudoElement(name.as_str().to_ascii_lowercase()));
+        } else if let Some(name) = raw_token.name("nthname") {
+            let nth = Nth::parse(&raw_token["nth"])?;
+            tokens.push(SelectorToken::Nth(name.as_str().to_owned(), nth));
+        } else if let Some(name) = raw_token.name("pseudofunction") {
+            tokens.push(SelectorToken::PseudoFunction(name.as_str().to_ascii_lowercase()));
+        } else if let Some(name) = raw_token.name("pseudoclass") {
+            tokens.push(SelectorToken::PseudoClass(name.as_str().to_ascii_lowercase()));
+        } else if let Some(name) = raw_token.name("attrname") {
+            let value = raw_token.name("doublequoted")
+                .or_else(|| raw_token.name("singlequoted"))
+                .or_else(|| raw_token.name("unquoted"))
+                .map(|m| m.as_str().to_owned())
+                .unwrap_or_default();
+            let matcher = raw_token.name("attrop").map(|op| (match op.as_str() {
+                "~=" => AttributeOperator::Includes,
+                "|=" => AttributeOperator::DashMatch,
+                "^=" => AttributeOperator::Prefix,
+                "$=" => AttributeOperator::Suffix,
+                "*=" => AttributeOperator::Substring,
+                _ => AttributeOperator::Equals,
+            }, value));
+            let case_insensitive = raw_token.name("attrflag").is_some_and(|f| f.as_str().eq_ignore_ascii_case("i"));
+            tokens.push(SelectorToken::Attribute(AttributeSelector {
+                name: name.as_str().to_ascii_lowercase(),
+                matcher,
+                case_insensitive,
+            }));
+        } else if let Some(id) = raw_token.name("id") {
+            tokens.push(SelectorToken::Id(id.as_str().to_owned()));
+        } else if let Some(class) = raw_token.name("class") {
+            tokens.push(SelectorToken::Class(class.as_str().to_owned()));
+        } else if let Some(tag) = raw_token.name("tag") {
+            tokens.push(SelectorToken::Tag(tag.as_str().to_ascii_lowercase()));
+        } else if raw_token.name("universal").is_some() {
+            tokens.push(SelectorToken::Universal);
+        } else if let Some(combinator) = raw_token.name("combinator") {
+            tokens.push(SelectorToken::Combinator(match combinator.as_str() {
+                ">" => Combinator::Child,
+                "+" => Combinator::NextSibling,
+                _ => Combinator::SubsequentSibling,
+            }));
+        } else if raw_token.name("comma").is_some() {
+            tokens.push(SelectorToken::Comma);
+        } else if raw_token.name("close").is_some() {
+            tokens.push(SelectorToken::Close);
+        } else if raw_token.name("white").is_some() {
+            tokens.push(SelectorToken::Whitespace);
+        }
+    }
+
+    if last_end != raw.len() {
+        bail!("Unexpected '{}' in selector '{}'", &raw[last_end..], raw);
+    }
+    Ok(tokens)
+}
+
+impl Nth {
+    /// Parses an `an+b` pattern, e.g. `2n+1`, `odd` or `-n + 3`.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let raw = raw.trim().to_ascii_lowercase();
+        let Some(captures) = NTH.captures(&raw) else {
+            bail!("Invalid an+b pattern '{}'", raw)
+        };
+        if captures.name("odd").is_some() {
+            Ok(Self { a: 2, b: 1 })
+        } else if captures.name("even").is_some() {
+            Ok(Self { a: 2, b: 0 })
+        } else if let Some(b) = captures.name("b2") {
+            Ok(Self { a: 0, b: b.as_str().parse()? })
+        } else {
+            let a = match &captures["a"] {
+                "" | "+" => 1,
+                "-" => -1,
+                a => a.parse()?,
+            };
+            let b = match captures.name("b1") {
+                Some(b) => {
+                    let b: i32 = b.as_str().parse()?;
+                    if &captures["sign"] == "-" { -b } else { b }
+                },
+                None => 0,
+            };
+            Ok(Self { a, b })
+        }
+    }
+
+    /// Whether the given (1-based) index matches the pattern.
+    pub fn matches(&self, index: i32) -> bool {
+        if self.a == 0 {
+            index == self.b
+        } else {
+            let n = (index - self.b) / self.a;
+            n >= 0 && self.a * n + self.b == index
+        }
+    }
+}
+
+impl Selector {
+    /// Parses a comma-separated list of selectors.
+    pub fn parse_list(raw: &str) -> Result<Vec<Self>> {
+        let mut tokens = Tokens::new(lex_selector(raw.trim())?);
+        let selectors = Self::parse_list_tokens(&mut tokens)?;
+        if tokens.peek().is_ok() {
+            bail!("Unexpected trailing tokens in selector '{}'", raw);
+        }
+        Ok(selectors)
+    }
+
+    /// Parses a comma-separated list of selectors, stopping at `)` or the end.
+    fn parse_list_tokens(tokens: &mut Tokens<SelectorToken>) -> Result<Vec<Self>> {
+        let mut selectors = vec![Self::parse_tokens(tokens)?];
+        while tokens.expect_optionally(&SelectorToken::Comma).unwrap_or(false) {
+            selectors.push(Self::parse_tokens(tokens)?);
+        }
+        Ok(selectors)
+    }
+
+    /// Parses a single complex selector.
+    fn parse_tokens(tokens: &mut Tokens<SelectorToken>) -> Result<Self> {
+        let mut selector = Self {
+            compounds: vec![CompoundSelector::parse_tokens(tokens)?],
+            combinators: Vec::new(),
+            pseudo_element: None,
+        };
+        loop {
+            let combinator = match tokens.peek() {
+                Ok(SelectorToken::Combinator(combinator)) => *combinator,
+                Ok(SelectorToken::Whitespace) => Combinator::Descendant,
+                Ok(SelectorToken::PseudoElement(name)) => {
+                    let Some(pseudo_element) = PseudoElement::parse(name) else {
+                        bail!("Unsupported pseudo-element ::{}", name)
+                    };
+                    selector.pseudo_element = Some(pseudo_element);
+                    tokens.next()?;
+                    break
+                },
+                // Legacy single-colon syntax, e.g. :before
+                Ok(SelectorToken::PseudoClass(name)) if PseudoElement::parse(name).is_some() => {
+                    selector.pseudo_element = PseudoElement::parse(name);
+                    tokens.next()?;
+                    break
+                },
+                _ => break,
+            };
+            tokens.next()?;
+            // Whitespace around other combinators is already consumed by the lexer,
+            // but trailing whitespace (e.g. before a comma) may remain.
+            if combinator == Combinator::Descendant {
+                if let Ok(SelectorToken::Combinator(_) | SelectorToken::Comma | SelectorToken::Close) = tokens.peek() {
+                    continue;
+                }
+                if tokens.peek().is_err() {
+                    break;
+                }
+            }
+            selector.combinators.push(combinator);
+            selector.compounds.push(CompoundSelector::parse_tokens(tokens)?);
+        }
+        Ok(selector)
+    }
+
+    /// Computes the specificity of the selector.
+    pub fn specificity(&self) -> Specificity {
+        let mut specificity = self.compounds.iter()
+            .map(|c| c.specificity())
+            .fold(Specificity::default(), |acc, s| acc + s);
+        if self.pseudo_element.is_some() {
+            specificity.2 += 1;
+        }
+        specificity
+    }
+
+    /// Whether the selector matches the given element, which has the given
+    /// ancestors (from the root to the parent). The pseudo-element is ignored.
+    pub fn matches(&self, element: &Element, ancestors: &[&Element]) -> bool {
+        self.matches_from(self.compounds.len() - 1, element, ancestors)
+    }
+
+    /// Matches the compound selectors up to (and including) the given index
+    /// from right to left.
+    fn matches_from(&self, index: usize, element: &Element, ancestors: &[&Element]) -> bool {
+        if !self.compounds[index].matches(element, ancestors) {
+            return false;
+        }
+        if index == 0 {
+            return true;
+        }
+        match self.combinators[index - 1] {
+            Combinator::Child => match ancestors.split_last() {
+                Some((parent, rest)) => self.matches_from(index - 1, parent, rest),
+                None => false,
+            },
+            Combinator::Descendant => (0..ancestors.len()).rev()
+                .any(|i| self.matches_from(index - 1, ancestors[i], &ancestors[..i])),
+            Combinator::NextSibling => previous_siblings(element, ancestors).next()
+                .is_some_and(|sibling| self.matches_from(index - 1, sibling, ancestors)),
+            Combinator::SubsequentSibling => previous_siblings(element, ancestors)
+                .any(|sibling| self.matches_from(index - 1, sibling, ancestors)),
+        }
+    }
+}
+
+impl CompoundSelector {
+    /// Parses a compound selector.
+    fn parse_tokens(tokens: &mut Tokens<SelectorToken>) -> Result<Self> {
+        let mut compound = Self::default();
+        let mut empty = true;
+        loop {
+            match tokens.peek() {
+                Ok(SelectorToken::Tag(tag)) if empty => compound.tag_name = Some(tag.clone()),
+                Ok(SelectorToken::Universal) if empty => {},
+                Ok(SelectorToken::Id(id)) => compound.ids.push(id.clone()),
+                Ok(SelectorToken::Class(class)) => compound.classes.push(class.clone()),
+                Ok(SelectorToken::Attribute(attribute)) => compound.attributes.push(attribute.clone()),
+                Ok(SelectorToken::Nth(name, nth)) => compound.pseudo_classes.push(match name.as_str() {
+                    "nth-child" => PseudoClass::NthChild(*nth),
+                    "nth-last-child" => PseudoClass::NthLastChild(*nth),
+                    _ => PseudoClass::NthOfType(*nth),
+                }),
+                Ok(SelectorToken::PseudoClass(name)) => compound.pseudo_classes.push(match name.as_str() {
+                    "root" => PseudoClass::Root,
+                    "empty" => PseudoClass::Empty,
+                    "first-child" => PseudoClass::FirstChild,
+                    "last-child" => PseudoClass::LastChild,
+                    "only-child" => PseudoClass::OnlyChild,
+                    "first-of-type" => PseudoClass::FirstOfType,
+                    "last-of-type" => PseudoClass::LastOfType,
+                    "link" | "any-link" => PseudoClass::Link,
+                    // Legacy single-colon syntax for pseudo-elements is handled
+                    // by the caller, so we stop here.
+                    name if PseudoElement::parse(name).is_some() => break,
+                    _ => PseudoClass::Never(name.clone()),
+                }),
+                Ok(SelectorToken::PseudoFunction(name)) => {
+                    let name = name.clone();
+                    tokens.next()?;
+                    let selectors = Selector::parse_list_tokens(tokens)?;
+                    tokens.expect(&SelectorToken::Close)?;
+                    compound.pseudo_classes.push(match name.as_str() {
+                        "not" => PseudoClass::Not(selectors),
+                        "is" | "matches" | "any" => PseudoClass::Is(selectors),
+                        "where" => PseudoClass::Where(selectors),
+                        _ => PseudoClass::Never(name),
+                    });
+                    empty = false;
+                    continue;
+                },
+                _ => break,
+            }
+            tokens.next()?;
+            empty = false;
+        }
+        if empty {
+            bail!("Expected a compound selector, but got {:?}", tokens.peek().ok());
+        }
+        Ok(compound)
+    }
+
+    /// Computes the specificity of the compound selector.
+    pub fn specificity(&self) -> Specificity {
+        let mut specificity = Specificity(
+            self.ids.len() as u32,
+            (self.classes.len() + self.attributes.len()) as u32,
+            self.tag_name.is_some() as u32,
+        );
+        for pseudo_class in &self.pseudo_classes {
+            specificity = specificity + match pseudo_class {
+                PseudoClass::Not(selectors) | PseudoClass::Is(selectors) => {
+                    selectors.iter().map(|s| s.specificity()).max().unwrap_or_default()
+                },
+                PseudoClass::Where(_) => Specificity::default(),
+                _ => Specificity(0, 1, 0),
+            };
+        }
+        specificity
+    }
+
+    /// Whether the compound selector matches the given element.
+    pub fn matches(&self, element: &Element, ancestors: &[&Element]) -> bool {
+        self.tag_name.as_ref().is_none_or(|t| t.eq_ignore_ascii_case(element.tag_name()))
+            && self.ids.iter().all(|id| element.attribute("id") == Some(id.as_str()))
+            && self.classes.iter().all(|class| element.has_class(class))
+            && self.attributes.iter().all(|a| a.matches(element))
+            && self.pseudo_classes.iter().all(|p| p.matches(element, ancestors))
+    }
+}
+
+impl AttributeSelector {
+    /// Whether the attribute selector matches the given element.
+    pub fn matches(&self, element: &Element) -> bool {
+        let Some(actual) = element.attribute(&self.name) else {
+            return false;
+        };
+        let Some((operator, expected)) = &self.matcher else {
+            return true;
+        };
+        let (actual, expected) = if self.case_insensitive {
+            (actual.to_lowercase(), expected.to_lowercase())
+        } else {
+            (actual.to_owned(), expected.clone())
+        };
+        match operator {
+            AttributeOperator::Equals => actual == expected,
+            AttributeOperator::Includes => actual.split_whitespace().any(|w| w == expected),
+            AttributeOperator::DashMatch => actual == expected || actual.starts_with(&format!("{}-", expected)),
+            AttributeOperator::Prefix => !expected.is_empty() && actual.starts_with(&expected),
+            AttributeOperator::Suffix => !expected.is_empty() && actual.ends_with(&expected),
+            AttributeOperator::Substring => !expected.is_empty() && actual.contains(&expected),
+        }
+    }
+}
+
+impl PseudoClass {
+    /// Whether the pseudo-class matches the given element.
+    pub fn matches(&self, element: &Element, ancestors: &[&Element]) -> bool {
+        let siblings = || ancestors.last().into_iter().flat_map(|p| p.child_elements());
+        let same_type = |e: &&Element| e.tag_name().eq_ignore_ascii_case(element.tag_name());
+        match self {
+            Self::Root => ancestors.last().is_some_and(|p| p.tag_name() == "$root"),
+            Self::Empty => element.children().all(|c| matches!(c, Node::Text(t) if t.is_empty())),
+            Self::FirstChild => Self::NthChild(Nth { a: 0, b: 1 }).matches(element, ancestors),
+            Self::LastChild => Self::NthLastChild(Nth { a: 0, b: 1 }).matches(element, ancestors),
+            Self::OnlyChild => siblings().count() == 1,
+            Self::FirstOfType => siblings().find(same_type).is_some_and(|e| std::ptr::eq(e, element)),
+            Self::LastOfType => siblings().filter(same_type).last().is_some_and(|e| std::ptr::eq(e, element)),
+            Self::NthChild(nth) => siblings().position(|e| std::ptr::eq(e, element))
+                .is_some_and(|i| nth.matches(i as i32 + 1)),
+            Self::NthLastChild(nth) => {
+                let count = siblings().count();
+                siblings().position(|e| std::ptr::eq(e, element))
+                    .is_some_and(|i| nth.matches((count - i) as i32))
+            },
+            Self::NthOfType(nth) => siblings().filter(same_type).position(|e| std::ptr::eq(e, element))
+                .is_some_and(|i| nth.matches(i as i32 + 1)),
+            Self::Link => matches!(element.tag_name(), "a" | "area") && element.attribute("href").is_some(),
+            Self::Not(selectors) => !selectors.iter().any(|s| s.matches(element, ancestors)),
+            Self::Is(selectors) | Self::Where(selectors) => selectors.iter().any(|s| s.matches(element, ancestors)),
+            Self::Never(name) => {
+                debug!("Not matching unsupported pseudo-class :{}", name);
+                false
+            },
+        }
+    }
+}
+
+/// Iterates the element siblings preceding the given element (closest first).
+fn previous_siblings<'a>(element: &'a Element, ancestors: &[&'a Element]) -> impl Iterator<Item=&'a Element> {
+    let siblings: Vec<&Element> = ancestors.last()
+        .into_iter()
+        .flat_map(|p| p.child_elements())
+        .take_while(|e| !std::ptr::eq(*e, element))
+        .collect();
+    siblings.into_iter().rev()
+}
+
+impl PseudoElement {
+    /// Parses the name of a pseudo-element (without colons).
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "before" => Some(Self::Before),
+            "after" => Some(Self::After),
+            "marker" => Some(Self::Marker),
+            _ => None,
+        }
+    }
+}
+
+impl std::ops::Add for Specificity {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0 + rhs.0, self.1 + rhs.1, self.2 + rhs.2)
+    }
+}
@@ -0,0 +1,159 @@
+use crate::parse::css::{parse_string, parse_url, split_commas, split_components};
+
+use super::{Declaration, MediaEnvironment, MediaQueryList, Selector};
+
+/// A parsed CSS stylesheet.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stylesheet {
+    pub rules: Vec<Rule>,
+}
+
+/// A top-level or nested rule in a stylesheet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    Style(StyleRule),
+    Media(MediaRule),
+    FontFace(FontFaceRule),
+}
+
+/// A style rule, e.g. `h1, h2 { color: red }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleRule {
+    pub selectors: Vec<Selector>,
+    pub declarations: Vec<Declaration>,
+}
+
+/// A conditional group rule, e.g. `@media print { ... }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRule {
+    pub queries: MediaQueryList,
+    pub rules: Vec<Rule>,
+}
+
+/// A `@font-face` rule declaring a web font, e.g.
+/// `@font-face { font-family: Foo; src: url(foo.ttf) }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFaceRule {
+    pub declarations: Vec<Declaration>,
+}
+
+/// A font file a `@font-face` rule may be loaded from, in its `src`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontFaceSource {
+    /// The URL as referenced in the stylesheet.
+    pub url: String,
+    /// The format hint, e.g. `truetype` or `woff2`, if any.
+    pub format: Option<String>,
+}
+
+impl FontFaceRule {
+    /// The value of the given descriptor, e.g. `font-weight`. The last one wins.
+    pub fn descriptor(&self, name: &str) -> Option<&str> {
+        self.declarations.iter().rev()
+            .find(|d| d.name == name)
+            .map(|d| d.value.as_str())
+    }
+
+    /// The family name the font is declared for.
+    pub fn family(&self) -> Option<String> {
+        self.descriptor("font-family").map(parse_family_name)
+    }
+
+    /// The font files the font may be loaded from, in order of preference.
+    /// Locally installed fonts (`local(...)`) are skipped.
+    pub fn sources(&self) -> Vec<FontFaceSource> {
+        let Some(src) = self.descriptor("src") else {
+            return Vec::new();
+        };
+        split_commas(src).into_iter()
+            .filter_map(|source| {
+                let mut url = None;
+                let mut format = None;
+                for component in split_components(source) {
+                    if let Some(parsed) = parse_url(component) {
+                        url = Some(parsed);
+                    } else if let Some(raw) = function_argument(component, "format") {
+                        format = Some(parse_string(raw).unwrap_or_else(|| raw.trim().to_owned()).to_ascii_lowercase());
+                    }
+                }
+                Some(FontFaceSource { url: url?, format })
+            })
+            .collect()
+    }
+}
+
+/// Parses a font family name, which is either a string or a sequence of
+/// identifiers, e.g. `"Open Sans"` or `Open Sans`.
+pub fn parse_family_name(raw: &str) -> String {
+    parse_string(raw).unwrap_or_else(|| split_components(raw).join(" "))
+}
+
+/// The argument of a functional notation with the given name, e.g. `a.ttf` in `url(a.ttf)`.
+fn function_argument<'a>(raw: &'a str, name: &str) -> Option<&'a str> {
+    let (function, rest) = raw.split_once('(')?;
+    if function.eq_ignore_ascii_case(name) {
+        rest.strip_suffix(')')
+    } else {
+        None
+    }
+}
+
+impl Stylesheet {
+    /// Collects the style rules that apply in the given environment,
+    /// flattening `@media` rules.
+    pub fn style_rules<'a>(&'a self, env: &MediaEnvironment) -> Vec<&'a StyleRule> {
+        let mut style_rules = Vec::new();
+        collect_style_rules(&self.rules, env, &mut style_rules);
+        style_rules
+    }
+
+    /// Collects the `@font-face` rules, including those nested in `@media`
+    /// rules regardless of their conditions, since fonts are loaded before
+    /// the environment is known.
+    pub fn font_face_rules(&self) -> Vec<&FontFaceRule> {
+        let mut font_face_rules = Vec::new();
+        collect_font_face_rules(&self.rules, &mut font_face_rules);
+        font_face_rules
+    }
+
+    /// Collects the URLs of the (background) images referenced by the style
+    /// rules, including those nested in `@media` rules regardless of their
+    /// conditions, so they can be loaded before the environment is known.
+    pub fn image_urls(&self) -> Vec<String> {
+        let mut urls = Vec::new();
+        collect_image_urls(&self.rules, &mut urls);
+        urls
+    }
+}
+
+fn collect_style_rules<'a>(rules: &'a [Rule], env: &MediaEnvironment, style_rules: &mut Vec<&'a StyleRule>) {
+    for rule in rules {
+        match rule {
+            Rule::Style(style_rule) => style_rules.push(style_rule),
+            Rule::Media(media_rule) => if media_rule.queries.matches(env) {
+                collect_style_rules(&media_rule.rules, env, style_rules);
+            },
+            Rule::FontFace(_) => {},
+        }
+    }
+}
+
+fn collect_font_face_rules<'a>(rules: &'a [Rule], font_face_rules: &mut Vec<&'a FontFaceRule>) {
+    for rule in rules {
+        match rule {
+            Rule::FontFace(font_face_rule) => font_face_rules.push(font_face_rule),
+            Rule::Media(media_rule) => collect_font_face_rules(&media_rule.rules, font_face_rules),
+            Rule::Style(_) => {},
+        }
+    }
+}
+
+fn collect_image_urls(rules: &[Rule], urls: &mut Vec<String>) {
+    for rule in rules {
+        match rule {
+            Rule::Style(style_rule) => urls.extend(style_rule.declarations.iter().flat_map(|d| d.image_urls())),
+            Rule::Media(media_rule) => collect_image_urls(&media_rule.rules, urls),
+            Rule::FontFace(_) => {},
+        }
+    }
+}
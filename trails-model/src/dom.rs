@@ -7,6 +7,11 @@ use trails_base::regex::Regex;
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Document {
     root: Element,
+    /// The URL the document was loaded from, if any.
+    url: Option<String>,
+    /// Subresources (e.g. linked stylesheets), keyed by the URL as referenced
+    /// in the document (or, for web fonts, by their absolute URL).
+    resources: HashMap<String, Vec<u8>>,
 }
 
 impl Document {
@@ -14,6 +19,8 @@ impl Document {
     pub fn new() -> Self {
         Self {
             root: Element::root(),
+            url: None,
+            resources: HashMap::new(),
         }
     }
 
@@ -21,11 +28,33 @@ impl Document {
     pub fn from_root(root: Element) -> Self {
         Self {
             root,
+            url: None,
+            resources: HashMap::new(),
         }
     }
 
     /// The root node.
     pub fn root(&self) -> &Element { &self.root }
+
+    /// The URL the document was loaded from, which relative references are resolved against.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Sets the URL the document was loaded from.
+    pub fn set_url(&mut self, url: &str) {
+        self.url = Some(url.to_owned());
+    }
+
+    /// Fetches a loaded subresource by the URL it is referenced by.
+    pub fn resource(&self, url: &str) -> Option<&[u8]> {
+        self.resources.get(url).map(|r| r.as_slice())
+    }
+
+    /// Adds a loaded subresource.
+    pub fn add_resource(&mut self, url: &str, contents: Vec<u8>) {
+        self.resources.insert(url.to_owned(), contents);
+    }
 }
 
 /// A node in the DOM tree.
@@ -107,11 +136,40 @@ impl Element {
         self.children.iter()
     }
 
+    /// Iterates the children that are elements.
+    pub fn child_elements(&self) -> impl Iterator<Item=&Element> {
+        self.children.iter().filter_map(|c| match c {
+            Node::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    /// Iterates the descendant elements in document order (excluding this element).
+    pub fn descendants(&self) -> impl Iterator<Item=&Element> {
+        let mut stack: Vec<&Element> = self.child_elements().collect();
+        stack.reverse();
+        std::iter::from_fn(move || {
+            let element = stack.pop()?;
+            stack.extend(element.child_elements().collect::<Vec<_>>().into_iter().rev());
+            Some(element)
+        })
+    }
+
     /// Fetches an attribute.
     pub fn attribute(&self, key: &str) -> Option<&str> {
         self.attributes.get(key).map(|s| s.as_str())
     }
 
+    /// Iterates the attributes as key-value pairs.
+    pub fn attributes(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.attributes.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Whether the element's `class` attribute contains the given class.
+    pub fn has_class(&self, class: &str) -> bool {
+        self.attribute("class").is_some_and(|c| c.split_whitespace().any(|c| c == class))
+    }
+
     /// Adds a new child to the element.
     pub fn add_child(&mut self, child: Node) {
         self.children.push(child);
@@ -1,2 +1,3 @@
+pub mod css;
 pub mod dom;
 pub mod parse;
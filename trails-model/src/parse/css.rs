@@ -0,0 +1,307 @@
+use trails_base::log::debug;
+use trails_base::once_cell::sync::Lazy;
+use trails_base::regex::Regex;
+use trails_base::Result;
+
+use crate::css::{Declaration, FontFaceRule, MediaQueryList, MediaRule, Rule, Selector, StyleRule, Stylesheet};
+
+use super::tokens::Tokens;
+
+/// A parser for CSS.
+#[derive(Default)]
+pub struct Parser {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CssToken {
+    AtKeyword(String), // e.g. @media
+    Open, // {
+    Close, // }
+    Semicolon, // ;
+    Text(String), // e.g. selectors, property names, values, strings, ...
+}
+
+static CSS_LEXER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&[
+        // Comments, i.e. /* ... */
+        r#"(?P<comment>/\*[\s\S]*?(?:\*/|$))"#,
+        // Strings, e.g. "..." or '...'
+        r#"(?P<string>"(?:[^"\\]|\\[\s\S])*"|'(?:[^'\\]|\\[\s\S])*')"#,
+        // At-keywords, e.g. @media
+        r#"(?:@(?P<atkeyword>[\w-]+))"#,
+        // Braces and semicolons
+        r#"(?P<open>\{)"#,
+        r#"(?P<close>\})"#,
+        r#"(?P<semicolon>;)"#,
+        // Any other text
+        r#"(?P<text>[^{};"'/@]+|[/@"'])"#,
+    ].join("|")).unwrap()
+});
+
+/// Tokenizes a raw stylesheet.
+fn lex_stylesheet(raw: &str) -> Vec<CssToken> {
+    let mut tokens = Vec::new();
+
+    for raw_token in CSS_LEXER.captures_iter(raw) {
+        if let Some(name) = raw_token.name("atkeyword") {
+            tokens.push(CssToken::AtKeyword(name.as_str().to_ascii_lowercase()));
+        } else if raw_token.name("open").is_some() {
+            tokens.push(CssToken::Open);
+        } else if raw_token.name("close").is_some() {
+            tokens.push(CssToken::Close);
+        } else if raw_token.name("semicolon").is_some() {
+            tokens.push(CssToken::Semicolon);
+        } else if let Some(text) = raw_token.name("string").or_else(|| raw_token.name("text")) {
+            tokens.push(CssToken::Text(text.as_str().to_owned()));
+        }
+    }
+
+    tokens
+}
+
+// A recursive descent parser for CSS.
+
+impl Parser {
+    /// Parses a stylesheet. Invalid rules are skipped.
+    pub fn parse_stylesheet(&self, raw: &str) -> Stylesheet {
+        // Legacy stylesheets are sometimes wrapped in HTML comments to hide them from old browsers
+        let raw = raw.replace("<!--", "").replace("-->", "");
+        let mut tokens = Tokens::new(lex_stylesheet(&raw));
+        let mut rules = Vec::new();
+        loop {
+            rules.extend(self.parse_rules(&mut tokens));
+            // Skip stray closing braces at the top level
+            if tokens.next().is_err() {
+                break;
+            }
+        }
+        Stylesheet { rules }
+    }
+
+    /// Parses rules until a closing brace (which is not consumed) or the end.
+    fn parse_rules(&self, tokens: &mut Tokens<CssToken>) -> Vec<Rule> {
+        let mut rules = Vec::new();
+        loop {
+            let result = match tokens.peek() {
+                Err(_) | Ok(CssToken::Close) => break,
+                Ok(CssToken::Semicolon) => tokens.next().map(|_| None),
+                Ok(CssToken::AtKeyword(_)) => self.parse_at_rule(tokens),
+                Ok(_) => self.parse_style_rule(tokens),
+            };
+            match result {
+                Ok(Some(rule)) => rules.push(rule),
+                Ok(None) => {},
+                Err(e) => debug!("Skipping invalid rule: {}", e),
+            }
+        }
+        rules
+    }
+
+    /// Parses `selectors { declarations }`.
+    fn parse_style_rule(&self, tokens: &mut Tokens<CssToken>) -> Result<Option<Rule>> {
+        let prelude = self.parse_prelude(tokens)?;
+        if !tokens.expect_optionally(&CssToken::Open)? {
+            // We hit a semicolon or a closing brace, drop the tokens seen so far
+            tokens.expect_optionally(&CssToken::Semicolon)?;
+            debug!("Skipping '{}' without a block", prelude.trim());
+            return Ok(None);
+        }
+        let block = self.parse_block(tokens)?;
+        let selectors = Selector::parse_list(&prelude)?;
+        Ok(Some(Rule::Style(StyleRule {
+            selectors,
+            declarations: self.parse_declarations(&block),
+        })))
+    }
+
+    /// Parses `@name prelude;` or `@name prelude { ... }`.
+    fn parse_at_rule(&self, tokens: &mut Tokens<CssToken>) -> Result<Option<Rule>> {
+        let CssToken::AtKeyword(name) = tokens.next()? else {
+            unreachable!("parse_at_rule is only called on at-keywords")
+        };
+        let prelude = self.parse_prelude(tokens)?;
+        if !tokens.expect_optionally(&CssToken::Open)? {
+            // A statement at-rule, e.g. @import or @charset
+            // TODO: Support @import
+            tokens.expect_optionally(&CssToken::Semicolon)?;
+            debug!("Skipping @{} {}", name, prelude.trim());
+            return Ok(None);
+        }
+        match name.as_str() {
+            "media" => {
+                let rules = self.parse_rules(tokens);
+                tokens.expect(&CssToken::Close)?;
+                Ok(Some(Rule::Media(MediaRule {
+                    queries: MediaQueryList::parse(&prelude),
+                    rules,
+                })))
+            },
+            "font-face" => {
+                let block = self.parse_block(tokens)?;
+                Ok(Some(Rule::FontFace(FontFaceRule {
+                    declarations: self.parse_declarations(&block),
+                })))
+            },
+            _ => {
+                debug!("Skipping @{} block", name);
+                self.parse_block(tokens)?;
+                Ok(None)
+            },
+        }
+    }
+
+    /// Collects the raw text up to (but excluding) the next `{`, `;` or `}`.
+    fn parse_prelude(&self, tokens: &mut Tokens<CssToken>) -> Result<String> {
+        let mut prelude = String::new();
+        while let Ok(CssToken::Text(text)) = tokens.peek() {
+            prelude.push_str(text);
+            tokens.next()?;
+        }
+        Ok(prelude)
+    }
+
+    /// Collects the raw text of a block up to the matching `}`, which is consumed.
+    fn parse_block(&self, tokens: &mut Tokens<CssToken>) -> Result<String> {
+        let mut block = String::new();
+        let mut depth = 0;
+        loop {
+            match tokens.next() {
+                Ok(CssToken::Close) if depth == 0 => break,
+                Ok(CssToken::Close) => {
+                    depth -= 1;
+                    block.push('}');
+                },
+                Ok(CssToken::Open) => {
+                    depth += 1;
+                    block.push('{');
+                },
+                Ok(CssToken::Semicolon) => block.push(';'),
+                Ok(CssToken::AtKeyword(name)) => {
+                    block.push('@');
+                    block.push_str(&name);
+                },
+                Ok(CssToken::Text(text)) => block.push_str(&text),
+                // Unterminated blocks are closed at the end of the stylesheet
+                Err(_) => break,
+            }
+        }
+        Ok(block)
+    }
+
+    /// Parses a list of declarations, e.g. the contents of a `style` attribute.
+    /// Invalid declarations are skipped.
+    pub fn parse_declarations(&self, raw: &str) -> Vec<Declaration> {
+        split_top_level(raw, |c| c == ';')
+            .into_iter()
+            .filter_map(|raw_declaration| self.parse_declaration(raw_declaration))
+            .collect()
+    }
+
+    /// Parses `name: value [!important]`.
+    fn parse_declaration(&self, raw: &str) -> Option<Declaration> {
+        let (name, value) = raw.split_once(':')?;
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            return None;
+        }
+        // Custom properties are case-sensitive, all others are not
+        let name = if name.starts_with("--") { name.to_owned() } else { name.to_ascii_lowercase() };
+        let mut value = value.trim();
+        let mut important = false;
+        if let Some((rest, flag)) = value.rsplit_once('!') {
+            if flag.trim().eq_ignore_ascii_case("important") {
+                value = rest.trim_end();
+                important = true;
+            }
+        }
+        Some(Declaration { name, value: value.to_owned(), important })
+    }
+}
+
+/// Splits a property value into its whitespace-separated components, e.g.
+/// `1px solid rgb(0, 0, 0)` into `1px`, `solid` and `rgb(0, 0, 0)`.
+pub fn split_components(raw: &str) -> Vec<&str> {
+    split_top_level(raw, char::is_whitespace)
+}
+
+/// Splits a comma-separated property value or function arguments, e.g.
+/// `counters(item, ".")` arguments into `item` and `"."`.
+pub fn split_commas(raw: &str) -> Vec<&str> {
+    split_top_level(raw, |c| c == ',').into_iter().map(|p| p.trim()).collect()
+}
+
+/// Parses a quoted CSS string, resolving escapes, e.g. `"\2022  "` into `•  `.
+pub fn parse_string(raw: &str) -> Option<String> {
+    let raw = raw.trim();
+    let quote = raw.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let inner = raw.strip_prefix(quote)?.strip_suffix(quote)?;
+    let mut string = String::with_capacity(inner.len());
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+        // See https://www.w3.org/TR/css-syntax-3/#consume-escaped-code-point
+        let mut hex = String::new();
+        while let Some(&d) = chars.peek().filter(|d| d.is_ascii_hexdigit() && hex.len() < 6) {
+            hex.push(d);
+            chars.next();
+        }
+        if hex.is_empty() {
+            // Escaped newlines are removed, other characters are taken literally
+            match chars.next() {
+                Some('\n') | None => {},
+                Some(c) => string.push(c),
+            }
+        } else {
+            chars.next_if(|c| c.is_whitespace());
+            let code = u32::from_str_radix(&hex, 16).ok()?;
+            string.push(char::from_u32(code).filter(|&c| c != '\0').unwrap_or('\u{FFFD}'));
+        }
+    }
+    Some(string)
+}
+
+/// Parses a `url()` reference, whose argument may be quoted, e.g. `a.png`
+/// in `url("a.png")`.
+pub fn parse_url(raw: &str) -> Option<String> {
+    let (function, rest) = raw.trim().split_once('(')?;
+    if !function.eq_ignore_ascii_case("url") {
+        return None;
+    }
+    let arg = rest.strip_suffix(')')?;
+    Some(parse_string(arg).unwrap_or_else(|| arg.trim().to_owned()))
+}
+
+/// Splits the given string at separators, ignoring separators in strings,
+/// parentheses and brackets.
+fn split_top_level(raw: &str, is_separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth: usize = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in raw.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (_, '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {},
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth = depth.saturating_sub(1),
+            (None, c) if is_separator(c) && depth == 0 => {
+                parts.push(&raw[start..i]);
+                start = i + c.len_utf8();
+            },
+            _ => {},
+        }
+    }
+    parts.push(&raw[start..]);
+
+    parts.into_iter().filter(|p| !p.trim().is_empty()).collect()
+}
@@ -22,7 +22,7 @@ impl Default for Parser {
 enum HtmlToken {
     Doctype(String), // <!DOCTYPE ...>
     Script(String), // <script> ... </script>
-    Style(String), // <style> ... </style>
+    Style { attributes: HashMap<String, String>, content: String }, // <style> ... </style>
     Opening { tag_name: String, attributes: HashMap<String, String>, self_closing: bool }, // <tag attr="value">
     Closing { tag_name: String }, // </tag>
     Text(String), // e.g. tag names, attribute names, values, ...
@@ -37,7 +37,7 @@ static HTML_LEXER: Lazy<Regex> = Lazy::new(|| {
         // Script tags, i.e. <script> ... </script>
         r#"(?:<\s*[sS][cC][rR][iI][pP][tT][^>]*>(?P<script>[\s\S]*?)</\s*[sS][cC][rR][iI][pP][tT]\s*>)"#,
         // Style tags, i.e. <style> ... </style>
-        r#"(?:<\s*[sS][tT][yY][lL][eE][^>]*>(?P<style>[\s\S]*?)</\s*[sS][tT][yY][lL][eE]\s*>)"#,
+        r#"(?:<\s*[sS][tT][yY][lL][eE](?P<styleattributes>[^>]*)>(?P<style>[\s\S]*?)</\s*[sS][tT][yY][lL][eE]\s*>)"#,
         // Opening/self-closing tags, e.g. <meta charset="utf-8" />
         r#"(?:<\s*(?P<openingtag>[\w\-]+)(?P<attributes>(?:\s+[\w\-:]+\s*(?:=\s*(?:"[^"]*"|'[^']*'|[\w:%\-]+))?)*)\s*(?P<selfclosing>/)?\s*>)"#,
         // Closing tags, e.g. </html>
@@ -78,32 +78,43 @@ fn lex_document(raw: &str) -> Vec<HtmlToken> {
             debug!("Skipping comment '{}'", comment.as_str());
         } else if let Some(script) = raw_token.name("script").map(|m| m.as_str().to_owned()) {
             tokens.push(HtmlToken::Script(script));
-        } else if let Some(style) = raw_token.name("style").map(|m| m.as_str().to_owned()) {
-            tokens.push(HtmlToken::Style(style));
+        } else if let Some(content) = raw_token.name("style").map(|m| m.as_str().to_owned()) {
+            let attributes = lex_attributes(&raw_token["styleattributes"]);
+            tokens.push(HtmlToken::Style { attributes, content });
         } else if let Some(tag_name) = raw_token.name("openingtag").map(|m| m.as_str().to_owned()) {
-            let raw_attributes = &raw_token["attributes"];
+            let attributes = lex_attributes(&raw_token["attributes"]);
             let self_closing = raw_token.name("selfclosing").is_some();
-            let mut attributes = HashMap::new();
-            for raw_attribute in ATTRIBUTE_LEXER.captures_iter(raw_attributes) {
-                let key = raw_attribute["key"].to_owned();
-                let value = raw_attribute.name("doublequoted").map(|m| m.as_str())
-                    .or_else(|| raw_attribute.name("singlequoted").map(|m| m.as_str()))
-                    .or_else(|| raw_attribute.name("unquoted").map(|m| m.as_str()))
-                    .unwrap_or_else(|| "")
-                    .to_owned();
-                attributes.insert(key, value);
-            }
             tokens.push(HtmlToken::Opening { tag_name, attributes, self_closing });
         } else if let Some(tag_name) = raw_token.name("closingtag").map(|m| m.as_str().to_owned()) {
             tokens.push(HtmlToken::Closing { tag_name });
-        } else if let Some(text) = raw_token.name("text").map(|m| m.as_str().to_owned()) {
-            tokens.push(HtmlToken::Text(text));
+        } else if let Some(text) = raw_token.name("white").or_else(|| raw_token.name("text")).map(|m| m.as_str()) {
+            // Whitespace is significant in text, so we merge it with adjacent text
+            if let Some(HtmlToken::Text(previous)) = tokens.last_mut() {
+                previous.push_str(text);
+            } else {
+                tokens.push(HtmlToken::Text(text.to_owned()));
+            }
         }
     }
 
     tokens
 }
 
+/// Tokenizes the attributes of an opening tag.
+fn lex_attributes(raw: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    for raw_attribute in ATTRIBUTE_LEXER.captures_iter(raw) {
+        let key = raw_attribute["key"].to_owned();
+        let value = raw_attribute.name("doublequoted").map(|m| m.as_str())
+            .or_else(|| raw_attribute.name("singlequoted").map(|m| m.as_str()))
+            .or_else(|| raw_attribute.name("unquoted").map(|m| m.as_str()))
+            .unwrap_or("")
+            .to_owned();
+        attributes.insert(key, value);
+    }
+    attributes
+}
+
 struct Opening {
     tag_name: String,
     attributes: HashMap<String, String>,
@@ -131,6 +142,17 @@ static SINGLETON_TAGS: Lazy<HashSet<&str>> = Lazy::new(|| {
     set
 });
 
+/// Tags in which a leading newline is ignored.
+const PREFORMATTED_TAGS: [&str; 3] = ["pre", "listing", "textarea"];
+
+/// Skips whitespace-only text, e.g. between the doctype and the `<html>` tag.
+fn skip_whitespace(tokens: &mut Tokens<HtmlToken>) -> Result<()> {
+    while matches!(tokens.peek()?, HtmlToken::Text(text) if text.trim().is_empty()) {
+        tokens.next()?;
+    }
+    Ok(())
+}
+
 // A recursive descent parser for HTML.
 
 impl Parser {
@@ -143,9 +165,11 @@ impl Parser {
     fn parse_document(&self, tokens: &mut Tokens<HtmlToken>) -> Result<Document> {
         let mut root = Element::root();
 
+        skip_whitespace(tokens)?;
         if let HtmlToken::Doctype(_) = tokens.peek()? {
             // Ignore doctype for now
             tokens.next()?;
+            skip_whitespace(tokens)?;
         }
 
         // Parse <html> ... </html>
@@ -174,10 +198,10 @@ impl Parser {
                     Node::Text(script)
                 ]));
             },
-            HtmlToken::Style(style) => {
+            HtmlToken::Style { attributes, content } => {
                 tokens.next()?;
-                return Ok(Element::new("style", HashMap::new(), vec![
-                    Node::Text(style)
+                return Ok(Element::new("style", attributes, vec![
+                    Node::Text(content)
                 ]));
             },
             _ => {},
@@ -205,6 +229,16 @@ impl Parser {
             }
         }
 
+        // A newline directly after the opening tag is ignored in preformatted elements
+        // See https://html.spec.whatwg.org/multipage/syntax.html#element-restrictions
+        if PREFORMATTED_TAGS.contains(&opening.tag_name.as_str()) {
+            if let Some(Node::Text(text)) = children.first_mut() {
+                if let Some(stripped) = text.strip_prefix("\r\n").or_else(|| text.strip_prefix('\n')) {
+                    *text = stripped.to_owned();
+                }
+            }
+        }
+
         Ok(Element::new(&opening.tag_name, opening.attributes, children))
     }
 
@@ -1,2 +1,3 @@
+pub mod css;
 pub mod html;
 pub mod tokens;
@@ -38,7 +38,7 @@ impl<T> Tokens<T> where T: Debug {
     }
 }
 
-impl<T> Tokens<T> where T: Eq + Debug {
+impl<T> Tokens<T> where T: PartialEq + Debug {
     /// Consumes a token by expecting one.
     pub fn expect(&mut self, expected: &T) -> Result<()> {
         let token = self.next()?;
@@ -24,7 +24,7 @@ impl Default for Session {
 
 impl Session {
     /// Performs a GET request to the given URL.
-    fn get(&mut self, url: Url) -> Result<Vec<u8>> {
+    pub fn get(&mut self, url: Url) -> Result<Vec<u8>> {
         info!("Getting {}", url);
         // TODO: Async
         match url.scheme() {
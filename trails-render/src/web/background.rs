@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use piet::{kurbo::{Affine, Point, Rect, Size, Vec2}, FixedLinearGradient, FixedRadialGradient, GradientStop, ImageFormat, InterpolationMode, RenderContext};
+use trails_base::log::{debug, warn};
+use trails_model::css::Color;
+
+use super::{paint_svg, to_piet_color, BackgroundImage, BackgroundLayer, BackgroundSize, ColorStop, ComputedStyle, GradientDirection, ImageData, LinearGradient, RadialGradient, RadialShape, RadialSize, RepeatStyle};
+
+/// The maximum number of tiles painted for a repeated background image,
+/// layers with more (i.e. tiny) tiles are skipped.
+// TODO: Paint tiny tiles as a pattern instead
+const MAX_TILES: usize = 10_000;
+
+/// Paints a background, i.e. its color and image layers, across the given
+/// painting area (e.g. the border box). Images are positioned and sized
+/// relative to the given positioning area (e.g. the padding box), tiles
+/// outside the given region are skipped.
+pub(crate) fn paint_background_layers<P>(paint: &mut P, style: &ComputedStyle, painting_area: Rect, positioning_area: Rect, images: &HashMap<String, Arc<ImageData>>, region: Rect) where P: RenderContext {
+    let clip = painting_area.intersect(region);
+    if clip.area() <= 0.0 {
+        return;
+    }
+    let background_color = style.used_background_color();
+    if background_color.alpha > 0.0 {
+        paint.fill(clip, &to_piet_color(background_color));
+    }
+    // The first layer is the topmost one
+    for layer in style.background_layers().iter().rev() {
+        let result = paint.with_save(|paint| {
+            paint.clip(clip);
+            paint_layer(paint, layer, style.color, clip, positioning_area, images);
+            Ok(())
+        });
+        if let Err(e) = result {
+            warn!("Could not paint background layer: {}", e);
+        }
+    }
+}
+
+/// Paints the image of a background layer, tiled as needed to cover the given
+/// clip rectangle. `currentColor` in gradients is resolved to the given color.
+fn paint_layer<P>(paint: &mut P, layer: &BackgroundLayer, color: Color, clip: Rect, area: Rect, images: &HashMap<String, Arc<ImageData>>) where P: RenderContext {
+    let Some(image) = &layer.image else {
+        return;
+    };
+    let loaded = match image {
+        BackgroundImage::Url(url) => match images.get(url) {
+            Some(loaded) => Some(loaded),
+            // The image hasn't been loaded (yet) or couldn't be decoded
+            None => return,
+        },
+        BackgroundImage::LinearGradient(_) | BackgroundImage::RadialGradient(_) => None,
+    };
+    let mut size = tile_size(layer.size, loaded.map(|i| i.size()), area.size());
+    if layer.repeat.x == RepeatStyle::Round && size.width > 0.0 {
+        size.width = area.width() / (area.width() / size.width).round().max(1.0);
+    }
+    if layer.repeat.y == RepeatStyle::Round && size.height > 0.0 {
+        size.height = area.height() / (area.height() / size.height).round().max(1.0);
+    }
+    if size.width <= 0.0 || size.height <= 0.0 {
+        return;
+    }
+
+    let xs = tile_offsets(layer.repeat.x, area.x0 + layer.position.x.resolve(area.width() - size.width), size.width, area.x0, area.width(), clip.x0, clip.x1);
+    let ys = tile_offsets(layer.repeat.y, area.y0 + layer.position.y.resolve(area.height() - size.height), size.height, area.y0, area.height(), clip.y0, clip.y1);
+    let (Some(xs), Some(ys)) = (xs, ys) else {
+        debug!("Skipping background layer with too many tiles");
+        return;
+    };
+    if xs.len() * ys.len() > MAX_TILES {
+        debug!("Skipping background layer with {} tiles", xs.len() * ys.len());
+        return;
+    }
+    let tiles = ys.iter().flat_map(|&y| xs.iter().map(move |&x| Rect::from_origin_size(Point::new(x, y), size)));
+
+    match (image, loaded.map(|i| &**i)) {
+        (_, Some(ImageData::Raster(raster))) => {
+            match paint.make_image(raster.width, raster.height, &raster.pixels, ImageFormat::RgbaSeparate) {
+                Ok(backend_image) => for tile in tiles {
+                    paint.draw_image(&backend_image, tile, InterpolationMode::Bilinear);
+                },
+                Err(e) => warn!("Could not create background image: {}", e),
+            }
+        },
+        (_, Some(ImageData::Vector(tree))) => for tile in tiles {
+            paint_svg(paint, tile, tree);
+        },
+        (BackgroundImage::LinearGradient(gradient), None) => for tile in tiles {
+            paint_linear_gradient(paint, gradient, color, tile);
+        },
+        (BackgroundImage::RadialGradient(gradient), None) => for tile in tiles {
+            paint_radial_gradient(paint, gradient, color, tile);
+        },
+        (BackgroundImage::Url(_), None) => {},
+    }
+}
+
+/// The size of a background image with the given intrinsic size (if any)
+/// in a positioning area of the given size. Images without an intrinsic
+/// size (i.e. gradients) fill the area by default.
+/// See https://www.w3.org/TR/css-backgrounds-3/#background-size
+fn tile_size(size: BackgroundSize, intrinsic: Option<Size>, area: Size) -> Size {
+    let ratio = intrinsic.filter(|i| i.width > 0.0 && i.height > 0.0).map(|i| i.width / i.height);
+    match (size, ratio) {
+        (BackgroundSize::Cover, Some(ratio)) => if area.width / area.height > ratio {
+            Size::new(area.width, area.width / ratio)
+        } else {
+            Size::new(area.height * ratio, area.height)
+        },
+        (BackgroundSize::Contain, Some(ratio)) => if area.width / area.height > ratio {
+            Size::new(area.height * ratio, area.height)
+        } else {
+            Size::new(area.width, area.width / ratio)
+        },
+        (BackgroundSize::Cover | BackgroundSize::Contain, None) => area,
+        (BackgroundSize::Explicit(width, height), _) => match (width.resolve(area.width), height.resolve(area.height)) {
+            (Some(width), Some(height)) => Size::new(width, height),
+            (Some(width), None) => Size::new(width, ratio.map_or(area.height, |r| width / r)),
+            (None, Some(height)) => Size::new(ratio.map_or(area.width, |r| height * r), height),
+            (None, None) => intrinsic.unwrap_or(area),
+        },
+    }
+}
+
+/// The offsets of the tiles of a background image with the given (tile)
+/// size and offset along an axis of the positioning area (given by its
+/// start and length), covering the clip range from `min` to `max`. Returns
+/// `None` if there are more than `MAX_TILES`.
+fn tile_offsets(repeat: RepeatStyle, offset: f64, size: f64, start: f64, length: f64, min: f64, max: f64) -> Option<Vec<f64>> {
+    let (offset, step) = match repeat {
+        RepeatStyle::NoRepeat => return Some(vec![offset]),
+        RepeatStyle::Repeat | RepeatStyle::Round => (offset, size),
+        RepeatStyle::Space => {
+            // Fit as many tiles as possible, with the first and last ones touching the edges
+            let count = (length / size).floor();
+            if count < 2.0 {
+                return Some(vec![offset]);
+            }
+            (start, size + (length - count * size) / (count - 1.0))
+        },
+    };
+    let first = offset - ((offset - min) / step).ceil() * step;
+    let count = ((max - first) / step).ceil().max(0.0) as usize;
+    (count <= MAX_TILES).then(|| (0..count).map(|i| first + i as f64 * step).collect())
+}
+
+/// Paints a linear gradient into the given rectangle, resolving `currentColor`
+/// to the given color.
+fn paint_linear_gradient<P>(paint: &mut P, gradient: &LinearGradient, color: Color, rect: Rect) where P: RenderContext {
+    let (width, height) = (rect.width(), rect.height());
+    let angle = match gradient.direction {
+        GradientDirection::Angle(degrees) => degrees.to_radians(),
+        GradientDirection::Corner { right, bottom } => {
+            // Perpendicular to the diagonal between the two neighboring corners
+            let dx = if right { height } else { -height };
+            let dy = if bottom { width } else { -width };
+            dx.atan2(-dy)
+        },
+    };
+    // See https://www.w3.org/TR/css-images-3/#linear-gradient-syntax
+    let direction = Vec2::new(angle.sin(), -angle.cos());
+    let length = (width * angle.sin()).abs() + (height * angle.cos()).abs();
+    if length <= 0.0 {
+        return;
+    }
+    let center = rect.center();
+    let brush = paint.gradient(FixedLinearGradient {
+        start: center - direction * length / 2.0,
+        end: center + direction * length / 2.0,
+        stops: gradient_stops(&gradient.stops, color, length, length, gradient.repeating),
+    });
+    match brush {
+        Ok(brush) => paint.fill(rect, &brush),
+        Err(e) => warn!("Could not create linear gradient: {}", e),
+    }
+}
+
+/// Paints a radial gradient into the given rectangle, resolving `currentColor`
+/// to the given color.
+fn paint_radial_gradient<P>(paint: &mut P, gradient: &RadialGradient, color: Color, rect: Rect) where P: RenderContext {
+    let (width, height) = (rect.width(), rect.height());
+    let (cx, cy) = (gradient.position.x.resolve(width), gradient.position.y.resolve(height));
+    let (dx_min, dx_max) = (cx.abs().min((width - cx).abs()), cx.abs().max((width - cx).abs()));
+    let (dy_min, dy_max) = (cy.abs().min((height - cy).abs()), cy.abs().max((height - cy).abs()));
+    let circle = gradient.shape == RadialShape::Circle;
+    // See https://www.w3.org/TR/css-images-3/#radial-gradient-syntax
+    let (rx, ry) = match gradient.size {
+        RadialSize::ClosestSide if circle => (dx_min.min(dy_min), dx_min.min(dy_min)),
+        RadialSize::FarthestSide if circle => (dx_max.max(dy_max), dx_max.max(dy_max)),
+        RadialSize::ClosestCorner if circle => (dx_min.hypot(dy_min), dx_min.hypot(dy_min)),
+        RadialSize::FarthestCorner if circle => (dx_max.hypot(dy_max), dx_max.hypot(dy_max)),
+        RadialSize::ClosestSide => (dx_min, dy_min),
+        RadialSize::FarthestSide => (dx_max, dy_max),
+        // Ellipses through a corner keep the aspect ratio of the corresponding side-sized ellipse
+        RadialSize::ClosestCorner => corner_ellipse(dx_min, dy_min),
+        RadialSize::FarthestCorner => corner_ellipse(dx_max, dy_max),
+        RadialSize::Explicit(x, y) => (x.resolve(width), y.resolve(height)),
+    };
+    if rx <= 0.0 || ry <= 0.0 {
+        return;
+    }
+
+    // Draw ellipses as circles scaled vertically
+    let center = Point::new(rect.x0 + cx, rect.y0 + cy);
+    let transform = Affine::translate(center.to_vec2()) * Affine::scale_non_uniform(1.0, ry / rx) * Affine::translate(-center.to_vec2());
+    let scaled_rect = transform.inverse().transform_rect_bbox(rect);
+    // Repeating gradients are extended to the farthest corner, others are padded with their last color
+    let last = gradient.stops.iter().filter_map(|s| s.position).map(|p| p.resolve(rx)).fold(rx, f64::max);
+    let range = if gradient.repeating {
+        [scaled_rect.origin(), Point::new(scaled_rect.x1, scaled_rect.y0), Point::new(scaled_rect.x0, scaled_rect.y1), Point::new(scaled_rect.x1, scaled_rect.y1)]
+            .into_iter()
+            .map(|corner| corner.distance(center))
+            .fold(rx, f64::max)
+    } else {
+        last
+    };
+    let result = paint.with_save(|paint| {
+        paint.transform(transform);
+        let brush = paint.gradient(FixedRadialGradient {
+            center,
+            origin_offset: Vec2::ZERO,
+            radius: range,
+            stops: gradient_stops(&gradient.stops, color, rx, range, gradient.repeating),
+        })?;
+        paint.fill(scaled_rect, &brush);
+        Ok(())
+    });
+    if let Err(e) = result {
+        warn!("Could not paint radial gradient: {}", e);
+    }
+}
+
+/// The radii of an ellipse through the corner at the given distances from
+/// the center that has the aspect ratio of those distances.
+fn corner_ellipse(dx: f64, dy: f64) -> (f64, f64) {
+    if dx <= 0.0 || dy <= 0.0 {
+        return (dx, dy);
+    }
+    (dx * std::f64::consts::SQRT_2, dy * std::f64::consts::SQRT_2)
+}
+
+/// Resolves the color stops of a gradient along a gradient line (or ray)
+/// of the given length, which percentages refer to, and `currentColor` to
+/// the given color. Missing positions are
+/// distributed evenly and repeating gradients are repeated. The returned
+/// stops cover the range from 0 to `range` (at least the length).
+/// See https://www.w3.org/TR/css-images-3/#color-stop-fixup
+fn gradient_stops(stops: &[ColorStop], color: Color, length: f64, range: f64, repeating: bool) -> Vec<GradientStop> {
+    let mut positions: Vec<Option<f64>> = stops.iter().map(|s| s.position.map(|p| p.resolve(length))).collect();
+    if let Some(first) = positions.first_mut() {
+        first.get_or_insert(0.0);
+    }
+    if let Some(last) = positions.last_mut() {
+        last.get_or_insert(length);
+    }
+    // Positions may not decrease
+    let mut max = f64::NEG_INFINITY;
+    for position in positions.iter_mut().flatten() {
+        max = max.max(*position);
+        *position = max;
+    }
+    // Distribute stops without a position evenly between their neighbors
+    let mut resolved: Vec<(f64, Color)> = Vec::with_capacity(stops.len());
+    let mut i = 0;
+    while i < stops.len() {
+        match positions[i] {
+            Some(position) => {
+                resolved.push((position, stops[i].color.resolve(color)));
+                i += 1;
+            },
+            None => {
+                let start = resolved.last().map_or(0.0, |(p, _)| *p);
+                let end_index = (i..stops.len()).find(|&j| positions[j].is_some()).unwrap_or(stops.len() - 1);
+                let end = positions[end_index].unwrap_or(length);
+                let count = (end_index - i + 1) as f64;
+                for (k, stop) in stops[i..end_index].iter().enumerate() {
+                    resolved.push((start + (end - start) * (k + 1) as f64 / count, stop.color.resolve(color)));
+                }
+                i = end_index;
+            },
+        }
+    }
+
+    if repeating {
+        let (first, last) = (resolved[0].0, resolved[resolved.len() - 1].0);
+        let period = last - first;
+        // TODO: Paint the average color if the period is too small to be visible
+        if period > 0.0 && range / period < MAX_TILES as f64 {
+            let start = ((0.0 - first) / period).floor() as i64;
+            let end = ((range - first) / period).ceil() as i64;
+            resolved = (start..end)
+                .flat_map(|k| resolved.iter().map(move |&(p, c)| (p + k as f64 * period, c)))
+                .collect();
+        }
+    }
+
+    // Clip the stops to the range, interpolating the colors at its ends
+    let mut clipped = vec![(0.0, color_at(&resolved, 0.0))];
+    clipped.extend(resolved.iter().copied().filter(|&(p, _)| p > 0.0 && p < range));
+    clipped.push((range, color_at(&resolved, range)));
+    clipped.into_iter()
+        .map(|(position, color)| GradientStop {
+            pos: (position / range) as f32,
+            color: to_piet_color(color),
+        })
+        .collect()
+}
+
+/// The color of a gradient with the given (sorted) stops at the given
+/// position, i.e. the color after any hard stop there.
+fn color_at(stops: &[(f64, Color)], position: f64) -> Color {
+    let i = stops.partition_point(|(p, _)| *p <= position);
+    match (i.checked_sub(1).map(|j| stops[j]), stops.get(i)) {
+        (Some((p0, c0)), Some(&(p1, c1))) => {
+            let t = (position - p0) / (p1 - p0);
+            let mix = |a: f64, b: f64| a + (b - a) * t;
+            Color { red: mix(c0.red, c1.red), green: mix(c0.green, c1.green), blue: mix(c0.blue, c1.blue), alpha: mix(c0.alpha, c1.alpha) }
+        },
+        (Some((_, color)), None) | (None, Some(&(_, color))) => color,
+        (None, None) => Color::TRANSPARENT,
+    }
+}
@@ -0,0 +1,219 @@
+use std::f64::consts::FRAC_PI_4;
+
+use piet::{kurbo::{BezPath, Point, Rect, Size, Vec2}, RenderContext};
+use trails_base::log::warn;
+use trails_model::css::Color;
+
+use super::{to_piet_color, BorderRadius, BoxShadow, ComputedStyle, CornerRadius, Sides};
+
+/// The factor for the distance of the control points of a cubic Bézier
+/// curve approximating a quarter of an ellipse from its ends.
+const ARC_CONTROL: f64 = 0.552_284_749_8;
+
+/// The used radii of the corners of a box, i.e. with percentages resolved
+/// and scaled down so that adjacent corners don't overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct CornerRadii {
+    pub top_left: Size,
+    pub top_right: Size,
+    pub bottom_right: Size,
+    pub bottom_left: Size,
+}
+
+impl CornerRadii {
+    /// Resolves the radii of the corners of a border box.
+    /// See https://www.w3.org/TR/css-backgrounds-3/#corner-overlap
+    pub fn of(radius: &BorderRadius, rect: Rect) -> Self {
+        let (width, height) = (rect.width(), rect.height());
+        let resolve = |r: CornerRadius| {
+            let size = Size::new(r.horizontal.resolve(width), r.vertical.resolve(height));
+            if size.width > 0.0 && size.height > 0.0 { size } else { Size::ZERO }
+        };
+        let radii = Self {
+            top_left: resolve(radius.top_left),
+            top_right: resolve(radius.top_right),
+            bottom_right: resolve(radius.bottom_right),
+            bottom_left: resolve(radius.bottom_left),
+        };
+        let factor = [
+            (width, radii.top_left.width + radii.top_right.width),
+            (width, radii.bottom_left.width + radii.bottom_right.width),
+            (height, radii.top_left.height + radii.bottom_left.height),
+            (height, radii.top_right.height + radii.bottom_right.height),
+        ].into_iter()
+            .filter(|&(_, sum)| sum > 0.0)
+            .map(|(length, sum)| length / sum)
+            .fold(1.0, f64::min);
+        radii.map(|r| r * factor.max(0.0))
+    }
+
+    /// Whether all corners are square.
+    pub fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The radii of the inner edge of a border with the given widths.
+    pub fn inner(&self, border: Sides<f64>) -> Self {
+        let shrink = |r: Size, dx: f64, dy: f64| Size::new((r.width - dx).max(0.0), (r.height - dy).max(0.0));
+        Self {
+            top_left: shrink(self.top_left, border.left, border.top),
+            top_right: shrink(self.top_right, border.right, border.top),
+            bottom_right: shrink(self.bottom_right, border.right, border.bottom),
+            bottom_left: shrink(self.bottom_left, border.left, border.bottom),
+        }
+    }
+
+    /// The radii of a shape expanded by the given distance (or shrunk, if
+    /// negative), e.g. by the spread of a shadow. Square corners stay square.
+    pub fn expanded(&self, distance: f64) -> Self {
+        self.map(|r| if r == Size::ZERO { r } else { Size::new((r.width + distance).max(0.0), (r.height + distance).max(0.0)) })
+    }
+
+    fn map(&self, f: impl Fn(Size) -> Size) -> Self {
+        Self {
+            top_left: f(self.top_left),
+            top_right: f(self.top_right),
+            bottom_right: f(self.bottom_right),
+            bottom_left: f(self.bottom_left),
+        }
+    }
+}
+
+/// The outline of a rectangle with the given rounded corners.
+pub(crate) fn rounded_rect(rect: Rect, radii: &CornerRadii) -> BezPath {
+    let mut path = BezPath::new();
+    append_rounded_rect(&mut path, rect, radii, false);
+    path
+}
+
+/// Appends the outline of a rectangle with the given rounded corners to a
+/// path, clockwise or, if reversed, counter-clockwise (e.g. to cut a hole
+/// into another outline).
+pub(crate) fn append_rounded_rect(path: &mut BezPath, rect: Rect, radii: &CornerRadii, reversed: bool) {
+    let Rect { x0, y0, x1, y1 } = rect;
+    let k = 1.0 - ARC_CONTROL;
+    let (tl, tr, br, bl) = (radii.top_left, radii.top_right, radii.bottom_right, radii.bottom_left);
+    // The arcs around the corners clockwise from the top right, each as the
+    // start, control and end points of a curve
+    let arcs = [
+        [Point::new(x1 - tr.width, y0), Point::new(x1 - tr.width * k, y0), Point::new(x1, y0 + tr.height * k), Point::new(x1, y0 + tr.height)],
+        [Point::new(x1, y1 - br.height), Point::new(x1, y1 - br.height * k), Point::new(x1 - br.width * k, y1), Point::new(x1 - br.width, y1)],
+        [Point::new(x0 + bl.width, y1), Point::new(x0 + bl.width * k, y1), Point::new(x0, y1 - bl.height * k), Point::new(x0, y1 - bl.height)],
+        [Point::new(x0, y0 + tl.height), Point::new(x0, y0 + tl.height * k), Point::new(x0 + tl.width * k, y0), Point::new(x0 + tl.width, y0)],
+    ];
+    path.move_to(arcs[3][3]);
+    if reversed {
+        for [end, c2, c1, start] in arcs.iter().rev() {
+            path.line_to(*start);
+            if start != end {
+                path.curve_to(*c1, *c2, *end);
+            }
+        }
+    } else {
+        for [start, c1, c2, end] in &arcs {
+            path.line_to(*start);
+            if start != end {
+                path.curve_to(*c1, *c2, *end);
+            }
+        }
+    }
+    path.close_path();
+}
+
+/// The area covered by a border box with the given style and its outer shadows.
+pub(crate) fn shadow_bounds(style: &ComputedStyle, rect: Rect) -> Rect {
+    style.box_shadow.iter()
+        .filter(|s| !s.inset)
+        .map(|s| {
+            let extent = s.spread + 2.0 * s.blur;
+            (rect + Vec2::new(s.offset_x, s.offset_y)).inflate(extent, extent)
+        })
+        .fold(rect, |bounds, r| bounds.union(r))
+}
+
+/// Paints the outer or inset shadows of a box with the given border box
+/// and border widths. `currentColor` is resolved to the box's color.
+/// See https://www.w3.org/TR/css-backgrounds-3/#shadow-shape
+pub(crate) fn paint_box_shadows<P>(paint: &mut P, style: &ComputedStyle, rect: Rect, border: Sides<f64>, inset: bool) where P: RenderContext {
+    let radii = CornerRadii::of(&style.border_radius, rect);
+    // The first shadow is the topmost one
+    for shadow in style.box_shadow.iter().rev().filter(|s| s.inset == inset) {
+        let result = paint.with_save(|paint| {
+            if inset {
+                let padding_box = Rect::new(rect.x0 + border.left, rect.y0 + border.top, rect.x1 - border.right, rect.y1 - border.bottom);
+                paint_inset_shadow(paint, shadow, style.color, padding_box, &radii.inner(border));
+            } else {
+                paint_outer_shadow(paint, shadow, style.color, rect, &radii);
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            warn!("Could not paint box shadow: {}", e);
+        }
+    }
+}
+
+/// Paints a shadow cast outside of the given border box, which it doesn't cover.
+fn paint_outer_shadow<P>(paint: &mut P, shadow: &BoxShadow, current_color: Color, rect: Rect, radii: &CornerRadii) where P: RenderContext {
+    let shape = (rect + Vec2::new(shadow.offset_x, shadow.offset_y)).inflate(shadow.spread, shadow.spread);
+    if shape.width() <= 0.0 || shape.height() <= 0.0 {
+        return;
+    }
+    // The blur fades out within about three standard deviations
+    let bounds = shape.inflate(2.0 * shadow.blur, 2.0 * shadow.blur).union(rect);
+    let mut clip = rounded_rect(bounds, &CornerRadii::default());
+    append_rounded_rect(&mut clip, rect, radii, true);
+    paint.clip(clip);
+    let color = to_piet_color(shadow.color.resolve(current_color));
+    let radii = radii.expanded(shadow.spread);
+    if shadow.blur > 0.0 {
+        // Round the corners by cutting the blurred shadow off where it has
+        // faded out, along the rounded shape expanded accordingly
+        let extent = 2.0 * shadow.blur;
+        paint.clip(rounded_rect(shape.inflate(extent, extent), &radii.expanded(extent)));
+        paint.blurred_rect(shape, shadow.blur / 2.0, &color);
+    } else {
+        paint.fill(rounded_rect(shape, &radii), &color);
+    }
+}
+
+/// Paints a shadow cast inside of the given padding box, i.e. around a
+/// hole in the shape of the padding box, moved by the offset and shrunk
+/// by the spread.
+fn paint_inset_shadow<P>(paint: &mut P, shadow: &BoxShadow, current_color: Color, padding_box: Rect, radii: &CornerRadii) where P: RenderContext {
+    paint.clip(rounded_rect(padding_box, radii));
+    let color = to_piet_color(shadow.color.resolve(current_color));
+    let hole = (padding_box + Vec2::new(shadow.offset_x, shadow.offset_y)).inflate(-shadow.spread, -shadow.spread);
+    if hole.width() <= 0.0 || hole.height() <= 0.0 {
+        paint.fill(padding_box, &color);
+        return;
+    }
+    let radii = radii.expanded(-shadow.spread);
+    let extent = 2.0 * shadow.blur + 1.0;
+    let outer = padding_box.union(hole).inflate(extent, extent);
+    if shadow.blur > 0.0 {
+        // Blur the area around the hole in four parts, whose blurred edges
+        // add up where they overlap at the corners of the hole, and the parts
+        // of the hole outside of its rounded corners, each approximated by a
+        // rectangle with the same area in the corner
+        let k = (1.0 - FRAC_PI_4).sqrt();
+        let (tl, tr, br, bl) = (radii.top_left * k, radii.top_right * k, radii.bottom_right * k, radii.bottom_left * k);
+        let parts = [
+            Rect::new(outer.x0, outer.y0, outer.x1, hole.y0),
+            Rect::new(outer.x0, hole.y1, outer.x1, outer.y1),
+            Rect::new(outer.x0, hole.y0, hole.x0, hole.y1),
+            Rect::new(hole.x1, hole.y0, outer.x1, hole.y1),
+            Rect::new(hole.x0, hole.y0, hole.x0 + tl.width, hole.y0 + tl.height),
+            Rect::new(hole.x1 - tr.width, hole.y0, hole.x1, hole.y0 + tr.height),
+            Rect::new(hole.x1 - br.width, hole.y1 - br.height, hole.x1, hole.y1),
+            Rect::new(hole.x0, hole.y1 - bl.height, hole.x0 + bl.width, hole.y1),
+        ];
+        for part in parts.into_iter().filter(|p| p.area() > 0.0) {
+            paint.blurred_rect(part, shadow.blur / 2.0, &color);
+        }
+    } else {
+        let mut area = rounded_rect(outer, &CornerRadii::default());
+        append_rounded_rect(&mut area, hole, &radii, true);
+        paint.fill(area, &color);
+    }
+}
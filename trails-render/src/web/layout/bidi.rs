@@ -0,0 +1,132 @@
+use std::ops::Range;
+
+use unic_bidi::{BidiInfo, Level};
+
+use crate::web::{ComputedStyle, Direction, UnicodeBidi};
+
+/// The explicit formatting characters that open and close embeddings,
+/// overrides and isolates.
+const LRE: &str = "\u{202A}";
+const RLE: &str = "\u{202B}";
+const PDF: &str = "\u{202C}";
+const LRO: &str = "\u{202D}";
+const RLO: &str = "\u{202E}";
+const LRI: &str = "\u{2066}";
+const RLI: &str = "\u{2067}";
+const FSI: &str = "\u{2068}";
+const PDI: &str = "\u{2069}";
+
+/// The character standing in for an atomic inline in the text.
+const OBJECT_REPLACEMENT: char = '\u{FFFC}';
+
+/// The inline content of a block container as text for the Unicode
+/// Bidirectional Algorithm, where inline boxes open and close embeddings
+/// (depending on their `unicode-bidi`) using explicit formatting characters.
+/// See https://www.w3.org/TR/css-writing-modes-3/#bidi-algo
+pub(super) struct BidiText {
+    text: String,
+    /// The characters closing the embeddings of the open inline boxes.
+    closing: Vec<&'static [&'static str]>,
+    /// The paragraph level, `None` if determined from the text.
+    base: Option<Level>,
+}
+
+impl BidiText {
+    /// Creates the (empty) text of a block container with the given style.
+    pub fn new(style: &ComputedStyle) -> Self {
+        let base = match (style.unicode_bidi, style.direction) {
+            (UnicodeBidi::Plaintext, _) => None,
+            (_, Direction::Ltr) => Some(Level::ltr()),
+            (_, Direction::Rtl) => Some(Level::rtl()),
+        };
+        let mut text = Self { text: String::new(), closing: Vec::new(), base };
+        // Overriding the direction of a block container applies to all of its inline content
+        if matches!(style.unicode_bidi, UnicodeBidi::BidiOverride | UnicodeBidi::IsolateOverride) {
+            text.text.push_str(if style.direction == Direction::Rtl { RLO } else { LRO });
+        }
+        text
+    }
+
+    /// Appends a run of text, returning its range in the text.
+    pub fn push_text(&mut self, text: &str) -> Range<usize> {
+        let start = self.text.len();
+        self.text.push_str(text);
+        start..self.text.len()
+    }
+
+    /// Appends an atomic inline, which is treated as a neutral character,
+    /// returning its range in the text.
+    pub fn push_object(&mut self) -> Range<usize> {
+        let start = self.text.len();
+        self.text.push(OBJECT_REPLACEMENT);
+        start..self.text.len()
+    }
+
+    /// Starts an inline box with the given style.
+    pub fn open(&mut self, style: &ComputedStyle) {
+        let rtl = style.direction == Direction::Rtl;
+        let (opening, closing): (&[&str], &'static [&'static str]) = match style.unicode_bidi {
+            UnicodeBidi::Normal => (&[], &[]),
+            UnicodeBidi::Embed => (&[if rtl { RLE } else { LRE }], &[PDF]),
+            UnicodeBidi::Isolate => (&[if rtl { RLI } else { LRI }], &[PDI]),
+            UnicodeBidi::BidiOverride => (&[if rtl { RLO } else { LRO }], &[PDF]),
+            UnicodeBidi::IsolateOverride => (&[if rtl { RLI } else { LRI }, if rtl { RLO } else { LRO }], &[PDF, PDI]),
+            UnicodeBidi::Plaintext => (&[FSI], &[PDI]),
+        };
+        self.text.extend(opening.iter().copied());
+        self.closing.push(closing);
+    }
+
+    /// Ends the most recently started inline box.
+    pub fn close(&mut self) {
+        if let Some(closing) = self.closing.pop() {
+            self.text.extend(closing.iter().copied());
+        }
+    }
+
+    /// Resolves the embedding levels of the text, returning `None` if all of
+    /// it is left-to-right (i.e. at level 0).
+    pub fn resolve(self) -> Option<BidiLevels> {
+        let info = BidiInfo::new(&self.text, self.base);
+        if info.levels.iter().all(|l| l.number() == 0) {
+            return None;
+        }
+        Some(BidiLevels {
+            // TODO: Use the levels of the paragraphs after the first one for their lines
+            base: info.paragraphs.first().map_or(0, |p| p.level.number()),
+            levels: info.levels.iter().map(Level::number).collect(),
+        })
+    }
+}
+
+/// The resolved embedding levels of the inline content of a block container.
+pub(super) struct BidiLevels {
+    /// The level of the (first) paragraph, which is odd if it is right-to-left.
+    pub base: u8,
+    /// The levels of each byte of the text.
+    levels: Vec<u8>,
+}
+
+impl BidiLevels {
+    /// The levels of the bytes of the given range of the text.
+    pub fn get(&self, range: Range<usize>) -> &[u8] {
+        &self.levels[range]
+    }
+}
+
+/// Splits the given range of a text with the given levels (per byte, or
+/// none if all of it is at level 0) into runs at the same level.
+pub(super) fn level_runs(range: Range<usize>, levels: &[u8]) -> Vec<(Range<usize>, u8)> {
+    if levels.is_empty() || range.is_empty() {
+        let level = levels.get(range.start).copied().unwrap_or(0);
+        return vec![(range, level)];
+    }
+    let mut runs: Vec<(Range<usize>, u8)> = Vec::new();
+    for i in range {
+        match runs.last_mut() {
+            Some((run, level)) if *level == levels[i] => run.end = i + 1,
+            _ => runs.push((i..i + 1, levels[i])),
+        }
+    }
+    runs
+}
@@ -0,0 +1,456 @@
+use std::sync::Arc;
+
+use trails_model::css::{Color, ColorValue, ComputedLength};
+use trails_model::dom::{Element, Node};
+
+use crate::web::{ComputedStyle, Content, Display, Float, LengthOrAuto, ListStylePosition, StyleTree, WritingMode};
+
+use super::Counters;
+
+/// The kind of a box in the box tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoxKind {
+    /// A block container, which stacks its children vertically or contains
+    /// only inline-level boxes (which are then laid out in lines).
+    Block,
+    /// An inline box, whose contents are laid out in the lines of the
+    /// surrounding block container.
+    Inline,
+    /// A floated box, which is taken out of the flow and shifted to the left
+    /// or right of the lines around it. Its content is laid out like that
+    /// of a block container.
+    Float,
+    /// An absolutely positioned (or fixed) box, which is taken out of the
+    /// flow and placed relative to its containing block. Its content is laid
+    /// out like that of a block container.
+    Absolute,
+    /// An atomic inline-level box, i.e. an inline replaced element (such as
+    /// an image) or an inline-level table, flex or grid container, which is
+    /// placed on a line as a whole.
+    Atomic,
+    /// A run of text.
+    Text(String),
+    /// A forced line break, i.e. a `<br>`.
+    LineBreak,
+}
+
+/// A box generated by an element (or anonymously, e.g. for text or to
+/// wrap inline boxes between blocks).
+#[derive(Debug, Clone)]
+pub struct LayoutBox<'d> {
+    pub kind: BoxKind,
+    /// The computed style, which is the parent's for text and anonymous boxes.
+    pub style: Arc<ComputedStyle>,
+    /// The element that generated this box, `None` if anonymous.
+    pub element: Option<&'d Element>,
+    /// The text of the marker placed outside of a list item.
+    pub marker: Option<String>,
+    /// The target of the link a block-level box was split out of, if any.
+    pub link: Option<&'d str>,
+    pub children: Vec<LayoutBox<'d>>,
+}
+
+impl<'d> LayoutBox<'d> {
+    /// Builds the box tree for an element with the given styles, tracking
+    /// counters in document order. Returns `None` if the element doesn't
+    /// generate a box (e.g. `display: none`).
+    pub fn build(element: &'d Element, styles: &StyleTree, counters: &mut Counters) -> Option<Self> {
+        let style = &logical_style(styles.style());
+        if style.display == Display::None {
+            return None;
+        }
+        counters.apply(Some(element), style);
+
+        // Generate the list marker
+        let mut marker = None;
+        let mut children = Vec::new();
+        if style.display == Display::ListItem {
+            if let Some(text) = style.list_style_type.marker(counters.list_item()) {
+                match style.list_style_position {
+                    ListStylePosition::Outside => marker = Some(text),
+                    ListStylePosition::Inside => children.push(Self::text(&text, style)),
+                }
+            }
+        }
+
+        let scope = counters.enter();
+        children.extend(styles.before().and_then(|s| Self::pseudo_element(element, &logical_style(s), counters)));
+        let mut child_styles = styles.children().iter();
+        for child in element.children() {
+            match child {
+                Node::Element(child_element) => {
+                    let child_styles = child_styles.next().expect("Style tree does not match the DOM");
+                    children.extend(Self::build(child_element, child_styles, counters));
+                },
+                Node::Text(text) => children.push(Self::text(text, style)),
+            }
+        }
+        children.extend(styles.after().and_then(|s| Self::pseudo_element(element, &logical_style(s), counters)));
+        counters.leave(scope);
+        // Inline boxes are laid out in our lines, so they can't change the writing mode
+        for child in &mut children {
+            child.take_writing_mode(style.writing_mode);
+        }
+
+        // Replaced elements don't render their children, images show their
+        // alternative text instead until (or unless) they are loaded
+        if is_replaced_element(element) {
+            children.clear();
+            if let Some(alt) = element.attribute("alt").filter(|alt| !alt.is_empty()) {
+                children.push(Self::text(alt, style));
+            }
+        }
+
+        let kind = match style.display {
+            _ if style.position.is_absolute() => BoxKind::Absolute,
+            _ if style.float != Float::None => BoxKind::Float,
+            Display::Inline if is_replaced_element(element) => BoxKind::Atomic,
+            Display::Inline if element.tag_name() == "br" => BoxKind::LineBreak,
+            Display::Inline => BoxKind::Inline,
+            Display::InlineTable | Display::InlineFlex | Display::InlineGrid => BoxKind::Atomic,
+            _ => BoxKind::Block,
+        };
+        let style = &inner_style(style);
+        // Inline boxes containing blocks are split around them by their container
+        if matches!(style.display, Display::Flex | Display::Grid) {
+            children = wrap_items(children, style);
+        } else if kind != BoxKind::Inline && children.iter().any(LayoutBox::has_block_level_content) {
+            children = wrap_inline_runs(children, style);
+        }
+        children = wrap_table_parts(children, style);
+
+        Some(Self {
+            kind,
+            style: style.clone(),
+            element: Some(element),
+            marker,
+            link: None,
+            children,
+        })
+    }
+
+    /// Builds the box for a `::before` or `::after` pseudo-element of an
+    /// element from its generated content.
+    fn pseudo_element(element: &'d Element, style: &Arc<ComputedStyle>, counters: &mut Counters) -> Option<Self> {
+        let Content::Items(items) = &style.content else {
+            return None;
+        };
+        if style.display == Display::None {
+            return None;
+        }
+        counters.apply(None, style);
+        let text = counters.resolve_content(items, element);
+        let kind = match style.display {
+            _ if style.position.is_absolute() => BoxKind::Absolute,
+            _ if style.float != Float::None => BoxKind::Float,
+            Display::Inline => BoxKind::Inline,
+            Display::InlineTable | Display::InlineFlex | Display::InlineGrid => BoxKind::Atomic,
+            _ => BoxKind::Block,
+        };
+        let style = &inner_style(style);
+        Some(Self {
+            kind,
+            style: style.clone(),
+            element: None,
+            marker: None,
+            link: None,
+            children: vec![Self::text(&text, style)],
+        })
+    }
+
+    /// Creates an (anonymous) text box.
+    fn text(text: &str, parent_style: &Arc<ComputedStyle>) -> Self {
+        Self {
+            kind: BoxKind::Text(text.to_owned()),
+            style: parent_style.clone(),
+            element: None,
+            marker: None,
+            link: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Creates an anonymous block box wrapping the given (inline-level) boxes.
+    fn anonymous_block(children: Vec<Self>, parent_style: &ComputedStyle) -> Self {
+        Self {
+            kind: BoxKind::Block,
+            style: Arc::new(ComputedStyle::inherit(parent_style)),
+            element: None,
+            marker: None,
+            link: None,
+            children,
+        }
+    }
+
+    /// Creates an anonymous table part (i.e. a row or cell) wrapping the given boxes.
+    fn anonymous_table_part(display: Display, children: Vec<Self>, parent_style: &ComputedStyle) -> Self {
+        let style = ComputedStyle { display, ..ComputedStyle::inherit(parent_style) };
+        Self {
+            kind: BoxKind::Block,
+            children: wrap_table_parts(children, &style),
+            style: Arc::new(style),
+            element: None,
+            marker: None,
+            link: None,
+        }
+    }
+
+    /// Makes an inline box (and the inline boxes inside of it) take the
+    /// given writing mode of its container, in whose lines it is laid out.
+    /// Other boxes keep their own, establishing flows in it.
+    fn take_writing_mode(&mut self, mode: WritingMode) {
+        if !matches!(self.kind, BoxKind::Inline | BoxKind::Text(_) | BoxKind::LineBreak) || self.style.writing_mode == mode {
+            return;
+        }
+        let style = ComputedStyle { writing_mode: mode, ..self.style.to_physical() };
+        self.style = logical_style(&Arc::new(style));
+        for child in &mut self.children {
+            child.take_writing_mode(mode);
+        }
+    }
+
+    /// Whether this box only contains whitespace, e.g. the text between table rows.
+    fn is_whitespace_only(&self) -> bool {
+        match &self.kind {
+            BoxKind::Text(text) => text.chars().all(char::is_whitespace),
+            _ => self.element.is_none() && self.children.iter().all(Self::is_whitespace_only),
+        }
+    }
+
+    /// Whether this box participates in a block formatting context.
+    pub fn is_block_level(&self) -> bool {
+        self.kind == BoxKind::Block
+    }
+
+    /// Whether this box is block-level or an inline box containing
+    /// block-level boxes (possibly nested in other inline boxes).
+    fn has_block_level_content(&self) -> bool {
+        self.is_block_level() || (self.kind == BoxKind::Inline && self.children.iter().any(Self::has_block_level_content))
+    }
+
+    /// Whether this box is absolutely positioned, i.e. doesn't take part in
+    /// the layout of its siblings.
+    pub fn is_absolute(&self) -> bool {
+        self.kind == BoxKind::Absolute
+    }
+
+    /// Whether this box is generated by a replaced element, i.e. an image,
+    /// whose content is outside the scope of CSS.
+    pub fn is_replaced(&self) -> bool {
+        self.element.is_some_and(is_replaced_element)
+    }
+
+    /// Takes the background that is painted across the canvas from the box
+    /// of the root element (i.e. usually `<html>`) in this document box, or
+    /// else from its `<body>` child if the root element has none. Returns
+    /// the style the background is taken from, `None` if neither has a
+    /// background.
+    /// See https://www.w3.org/TR/css-backgrounds-3/#special-backgrounds
+    pub fn take_canvas_background(&mut self) -> Option<Arc<ComputedStyle>> {
+        let root = self.children.iter_mut().find(|c| c.element.is_some())?;
+        let source = if root.style.has_background() {
+            root
+        } else {
+            root.children.iter_mut()
+                .find(|c| c.element.is_some_and(|e| e.tag_name() == "body") && c.style.has_background())?
+        };
+        let style = source.style.clone();
+        source.style = Arc::new(ComputedStyle {
+            background_color: ColorValue::Color(Color::TRANSPARENT),
+            background_image: vec![None],
+            ..(*style).clone()
+        });
+        Some(style)
+    }
+
+    /// Makes this document box, i.e. the initial containing block, take the
+    /// writing mode of the box of the root element (i.e. usually `<html>`).
+    /// See https://www.w3.org/TR/css-writing-modes-3/#principal-flow
+    pub fn take_principal_writing_mode(&mut self) {
+        let Some(root) = self.children.iter().find(|c| c.element.is_some()) else {
+            return;
+        };
+        let style = ComputedStyle { writing_mode: root.style.writing_mode, ..self.style.to_physical() };
+        self.style = logical_style(&Arc::new(style));
+    }
+
+    /// Whether this box establishes an independent formatting context, i.e.
+    /// contains the floats inside of it and doesn't overlap those outside.
+    pub fn establishes_formatting_context(&self) -> bool {
+        matches!(self.kind, BoxKind::Float | BoxKind::Absolute)
+            || self.is_replaced()
+            || matches!(self.style.display, Display::Table | Display::Flex | Display::Grid)
+            || self.style.is_scroll_container()
+    }
+
+    /// The link target if this box is generated by a link (or was split out of one).
+    pub fn href(&self) -> Option<&'d str> {
+        self.element
+            .filter(|e| e.tag_name() == "a")
+            .and_then(|e| e.attribute("href"))
+            .or(self.link)
+    }
+}
+
+/// Whether the given element is a replaced element, i.e. an `<img>` or an inline `<svg>`.
+fn is_replaced_element(element: &Element) -> bool {
+    matches!(element.tag_name(), "img" | "svg")
+}
+
+/// The style of a box as seen by the layout, which lays out boxes in a
+/// vertical writing mode as horizontal ones (rotated back afterwards).
+fn logical_style(style: &Arc<ComputedStyle>) -> Arc<ComputedStyle> {
+    if style.writing_mode.is_vertical() {
+        Arc::new(style.to_logical())
+    } else {
+        style.clone()
+    }
+}
+
+/// The style of a box as seen by the layout of its contents, i.e. with the
+/// display of inline-level tables and flex and grid containers (which are
+/// placed on lines as atomic boxes) replaced by their block-level one.
+fn inner_style(style: &Arc<ComputedStyle>) -> Arc<ComputedStyle> {
+    if matches!(style.display, Display::InlineTable | Display::InlineFlex | Display::InlineGrid) {
+        Arc::new(ComputedStyle { display: style.display.blockified(), ..(**style).clone() })
+    } else {
+        style.clone()
+    }
+}
+
+/// Wraps consecutive inline-level boxes in anonymous block boxes, so that a
+/// block container only contains block-level boxes. Inline boxes containing
+/// block-level boxes are split around them first.
+fn wrap_inline_runs<'d>(children: Vec<LayoutBox<'d>>, parent_style: &ComputedStyle) -> Vec<LayoutBox<'d>> {
+    let mut wrapped = Vec::new();
+    let mut run = Vec::new();
+    for child in children.into_iter().flat_map(split_around_blocks) {
+        if child.is_block_level() {
+            if !run.is_empty() {
+                wrapped.push(LayoutBox::anonymous_block(std::mem::take(&mut run), parent_style));
+            }
+            wrapped.push(child);
+        } else {
+            run.push(child);
+        }
+    }
+    if !run.is_empty() {
+        wrapped.push(LayoutBox::anonymous_block(run, parent_style));
+    }
+    wrapped
+}
+
+/// Splits an inline box containing block-level boxes (possibly nested in
+/// other inline boxes) into the pieces of the inline box before, between
+/// and after them and the block-level boxes themselves, which become their
+/// siblings. Only the first and last piece have the start and end edges
+/// (i.e. margin, border and padding) of the inline box respectively. Other
+/// boxes are returned as-is.
+/// See https://www.w3.org/TR/CSS2/visuren.html#anonymous-block-level
+fn split_around_blocks(layout_box: LayoutBox<'_>) -> Vec<LayoutBox<'_>> {
+    if layout_box.kind != BoxKind::Inline || !layout_box.has_block_level_content() {
+        return vec![layout_box];
+    }
+    let href = layout_box.href();
+    let mut pieces = Vec::new();
+    let mut inline_children = Vec::new();
+    let mut blocks = Vec::new();
+    for mut child in layout_box.children.iter().cloned().flat_map(split_around_blocks) {
+        if child.is_block_level() {
+            if child.href().is_none() {
+                child.link = href;
+            }
+            blocks.push(child);
+        } else {
+            if !blocks.is_empty() {
+                pieces.push((std::mem::take(&mut inline_children), std::mem::take(&mut blocks)));
+            }
+            inline_children.push(child);
+        }
+    }
+    pieces.push((inline_children, blocks));
+
+    let count = pieces.len();
+    let mut split = Vec::new();
+    for (i, (children, blocks)) in pieces.into_iter().enumerate() {
+        let mut style = (*layout_box.style).clone();
+        if i > 0 {
+            style.margin.left = LengthOrAuto::default();
+            style.padding.left = ComputedLength::default();
+            style.border_width.left = 0.0;
+        }
+        if i + 1 < count {
+            style.margin.right = LengthOrAuto::default();
+            style.padding.right = ComputedLength::default();
+            style.border_width.right = 0.0;
+        }
+        split.push(LayoutBox { style: Arc::new(style), children, ..layout_box.clone() });
+        split.extend(blocks);
+    }
+    split
+}
+
+/// Turns the children of a flex or grid container into items, i.e. makes
+/// inline-level boxes block-level and wraps runs of text in anonymous
+/// blocks, dropping those that only contain whitespace. Absolutely
+/// positioned children don't become items.
+/// See https://www.w3.org/TR/css-flexbox-1/#flex-items
+/// and https://www.w3.org/TR/css-grid-1/#grid-items
+fn wrap_items<'d>(children: Vec<LayoutBox<'d>>, parent_style: &ComputedStyle) -> Vec<LayoutBox<'d>> {
+    let mut items = Vec::new();
+    let mut run = Vec::new();
+    let flush = |run: &mut Vec<LayoutBox<'d>>, items: &mut Vec<LayoutBox<'d>>| {
+        if !run.iter().all(LayoutBox::is_whitespace_only) {
+            items.push(LayoutBox::anonymous_block(std::mem::take(run), parent_style));
+        }
+        run.clear();
+    };
+    for mut child in children {
+        if let BoxKind::Text(_) = child.kind {
+            run.push(child);
+        } else {
+            flush(&mut run, &mut items);
+            if !child.is_absolute() {
+                child.kind = BoxKind::Block;
+            }
+            items.push(child);
+        }
+    }
+    flush(&mut run, &mut items);
+    items
+}
+
+/// Generates the anonymous boxes required by the table model, i.e. wraps
+/// children of tables and row groups that aren't rows in anonymous rows
+/// and children of rows that aren't cells in anonymous cells. Whitespace
+/// between the parts of a table is dropped.
+/// See https://www.w3.org/TR/CSS2/tables.html#anonymous-boxes
+fn wrap_table_parts<'d>(children: Vec<LayoutBox<'d>>, parent_style: &ComputedStyle) -> Vec<LayoutBox<'d>> {
+    let (is_part, wrapper): (fn(Display) -> bool, _) = match parent_style.display {
+        Display::Table => (
+            |d| matches!(d, Display::TableCaption | Display::TableRowGroup | Display::TableHeaderGroup | Display::TableFooterGroup | Display::TableRow),
+            Display::TableRow,
+        ),
+        Display::TableRowGroup | Display::TableHeaderGroup | Display::TableFooterGroup => (|d| d == Display::TableRow, Display::TableRow),
+        Display::TableRow => (|d| d == Display::TableCell, Display::TableCell),
+        _ => return children,
+    };
+    let mut wrapped = Vec::new();
+    let mut run = Vec::new();
+    let flush = |run: &mut Vec<LayoutBox<'d>>, wrapped: &mut Vec<LayoutBox<'d>>| {
+        if !run.iter().all(LayoutBox::is_whitespace_only) {
+            wrapped.push(LayoutBox::anonymous_table_part(wrapper, std::mem::take(run), parent_style));
+        }
+        run.clear();
+    };
+    for child in children {
+        if child.element.is_some() && is_part(child.style.display) {
+            flush(&mut run, &mut wrapped);
+            wrapped.push(child);
+        } else {
+            run.push(child);
+        }
+    }
+    flush(&mut run, &mut wrapped);
+    wrapped
+}
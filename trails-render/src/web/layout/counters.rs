@@ -0,0 +1,155 @@
+use trails_model::dom::Element;
+
+use crate::web::{ComputedStyle, ContentItem, Display};
+
+/// The name of the counter used for list item markers.
+const LIST_ITEM: &str = "list-item";
+
+/// An instance of a counter.
+#[derive(Debug, Clone)]
+struct Counter {
+    name: String,
+    value: i32,
+    /// The depth of the element that created the counter.
+    depth: usize,
+    /// Whether list items decrement (rather than increment) the counter.
+    reversed: bool,
+}
+
+/// The counters in scope while building the box tree in document order.
+/// A counter created on an element is in scope for the element, its
+/// following siblings and all their descendants.
+/// See https://www.w3.org/TR/css-lists-3/#creating-a-counter
+#[derive(Debug, Clone, Default)]
+pub struct Counters {
+    /// The counter instances, innermost last.
+    counters: Vec<Counter>,
+    /// The depth of the current element.
+    depth: usize,
+}
+
+/// The position of the counters before entering an element's children, to
+/// restore after leaving them.
+pub struct CounterScope {
+    len: usize,
+}
+
+impl Counters {
+    /// Enters the children of the current element.
+    pub fn enter(&mut self) -> CounterScope {
+        self.depth += 1;
+        CounterScope { len: self.counters.len() }
+    }
+
+    /// Leaves the children of an element, ending the scope of the counters
+    /// created by them.
+    pub fn leave(&mut self, scope: CounterScope) {
+        self.depth -= 1;
+        self.counters.truncate(scope.len);
+    }
+
+    /// Applies the counter properties (and presentational attributes such as
+    /// `start` on `<ol>`) of an element (or pseudo-element) with the given style.
+    pub fn apply(&mut self, element: Option<&Element>, style: &ComputedStyle) {
+        let tag_name = element.map(|e| e.tag_name());
+        let attribute = |name| element.and_then(|e| e.attribute(name));
+
+        for (name, value) in &style.counter_reset {
+            self.reset(name, *value, false);
+        }
+        if tag_name == Some("ol") {
+            // See https://html.spec.whatwg.org/multipage/grouping-content.html#the-ol-element
+            let reversed = attribute("reversed").is_some();
+            let start = attribute("start").and_then(|s| s.trim().parse::<i32>().ok());
+            let item_count = element.map_or(0, |e| e.child_elements().filter(|c| c.tag_name() == "li").count()) as i32;
+            let value = match (start, reversed) {
+                (Some(start), false) => start - 1,
+                (Some(start), true) => start + 1,
+                (None, false) => 0,
+                (None, true) => item_count + 1,
+            };
+            self.reset(LIST_ITEM, value, reversed);
+        }
+
+        let mut increments_list_item = false;
+        for (name, value) in &style.counter_increment {
+            increments_list_item |= name == LIST_ITEM;
+            self.increment(name, *value);
+        }
+        if style.display == Display::ListItem && !increments_list_item {
+            let step = if self.find(LIST_ITEM).is_some_and(|c| c.reversed) { -1 } else { 1 };
+            self.increment(LIST_ITEM, step);
+        }
+
+        for (name, value) in &style.counter_set {
+            self.set(name, *value);
+        }
+        if let (Some("li"), Some(value)) = (tag_name, attribute("value").and_then(|v| v.trim().parse::<i32>().ok())) {
+            self.set(LIST_ITEM, value);
+        }
+    }
+
+    /// The value of the innermost counter with the given name (0 if there is none).
+    pub fn value(&self, name: &str) -> i32 {
+        self.find(name).map_or(0, |c| c.value)
+    }
+
+    /// The value of the `list-item` counter.
+    pub fn list_item(&self) -> i32 {
+        self.value(LIST_ITEM)
+    }
+
+    /// Resolves generated content to a string, using the given element for `attr()`.
+    pub fn resolve_content(&self, items: &[ContentItem], element: &Element) -> String {
+        items.iter()
+            .map(|item| match item {
+                ContentItem::String(string) => string.clone(),
+                ContentItem::Counter { name, style } => style.format(self.value(name)),
+                ContentItem::Counters { name, separator, style } => {
+                    let values: Vec<_> = self.counters.iter()
+                        .filter(|c| &c.name == name)
+                        .map(|c| style.format(c.value))
+                        .collect();
+                    if values.is_empty() { style.format(0) } else { values.join(separator) }
+                },
+                ContentItem::Attr(name) => element.attribute(name).unwrap_or_default().to_owned(),
+            })
+            .collect()
+    }
+
+    /// Creates a counter on the current element, replacing one created by a
+    /// preceding sibling.
+    fn reset(&mut self, name: &str, value: i32, reversed: bool) {
+        let counter = Counter { name: name.to_owned(), value, depth: self.depth, reversed };
+        match self.counters.iter_mut().rev().find(|c| c.name == name) {
+            Some(existing) if existing.depth == self.depth => *existing = counter,
+            _ => self.counters.push(counter),
+        }
+    }
+
+    /// Adds to the innermost counter with the given name, creating it if needed.
+    fn increment(&mut self, name: &str, value: i32) {
+        if self.find(name).is_none() {
+            self.reset(name, 0, false);
+        }
+        if let Some(counter) = self.find_mut(name) {
+            counter.value = counter.value.saturating_add(value);
+        }
+    }
+
+    /// Sets the innermost counter with the given name, creating it if needed.
+    fn set(&mut self, name: &str, value: i32) {
+        match self.find_mut(name) {
+            Some(counter) => counter.value = value,
+            None => self.reset(name, value, false),
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&Counter> {
+        self.counters.iter().rev().find(|c| c.name == name)
+    }
+
+    fn find_mut(&mut self, name: &str) -> Option<&mut Counter> {
+        self.counters.iter_mut().rev().find(|c| c.name == name)
+    }
+}
@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use piet::{kurbo::{Point, Rect, Size, Vec2}, Text, TextLayout};
+use trails_model::css::{ColorScheme, MediaEnvironment, MediaType, Viewport};
+use trails_model::dom::Document;
+
+use crate::web::{BoxSizing, ComputedStyle, Direction, Display, LinkAreas, ScrollAreas, Sides, StyleCache, WritingMode, DEFAULT_FONT_SIZE};
+
+use super::{apply_insets, rotate_flow, text_baseline, Counters, FloatContext, FontCache, Fonts, Fragment, FragmentContent, ImageCache, ImageData, Images, LayoutBox};
+
+/// Parameters to pass to the (top-level) layouter.
+pub struct LayoutParams {
+    /// The width of the area to lay out the document in.
+    pub width: f64,
+    /// The size of the visible viewport, which media queries and viewport units refer to.
+    pub viewport_size: Size,
+    /// The type of media we render to.
+    pub media_type: MediaType,
+    /// The user's preferred color scheme.
+    pub color_scheme: ColorScheme,
+}
+
+/// Results from the layout pass, which can be painted (and hit-tested)
+/// without laying out the document again.
+pub struct LayoutResult<L> {
+    /// The size of the laid out document, including overflowing content.
+    pub size: Size,
+    /// The root of the fragment tree, `None` if the document has no boxes.
+    pub root: Option<Fragment<L>>,
+    /// The clickable link areas.
+    pub link_areas: LinkAreas,
+    /// The areas clipping their content, which may be scrolled.
+    pub scroll_areas: ScrollAreas,
+    /// The title of the document.
+    pub title: Option<String>,
+    /// The style whose background is painted across the whole canvas, i.e.
+    /// that of the root element or else the `<body>`.
+    pub canvas_background: Option<Arc<ComputedStyle>>,
+    /// The decoded background images by their URL (as referenced).
+    pub background_images: HashMap<String, Arc<ImageData>>,
+}
+
+/// A vertical margin collapsed from (possibly) several adjoining margins,
+/// whose used value is the largest positive minus the most negative margin.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct CollapsedMargin {
+    positive: f64,
+    negative: f64,
+}
+
+impl CollapsedMargin {
+    /// Creates a collapsed margin from a single margin.
+    pub(super) fn new(margin: f64) -> Self {
+        Self { positive: margin.max(0.0), negative: (-margin).max(0.0) }
+    }
+
+    /// Collapses this margin with another.
+    pub(super) fn with(self, other: Self) -> Self {
+        Self {
+            positive: self.positive.max(other.positive),
+            negative: self.negative.max(other.negative),
+        }
+    }
+
+    /// The used value of the margin.
+    pub(super) fn value(self) -> f64 {
+        self.positive - self.negative
+    }
+}
+
+/// A laid out block-level box.
+pub(super) struct BlockLayout<L> {
+    /// The fragment, with its border box positioned at the origin.
+    pub(super) fragment: Fragment<L>,
+    /// The used left margin.
+    pub(super) margin_left: f64,
+    /// The top margin, collapsed with those of descendants where they adjoin.
+    pub(super) margin_top: CollapsedMargin,
+    /// The bottom margin, collapsed with those of descendants where they adjoin.
+    pub(super) margin_bottom: CollapsedMargin,
+    /// Whether the top and bottom margins adjoin, e.g. for an empty block,
+    /// in which case they collapse through it.
+    pub(super) collapses_through: bool,
+}
+
+/// The used margins, borders and padding of a box.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct BoxEdges {
+    /// The margins, `None` if `auto`.
+    pub(super) margin: Sides<Option<f64>>,
+    pub(super) border: Sides<f64>,
+    pub(super) padding: Sides<f64>,
+}
+
+impl BoxEdges {
+    /// Resolves the edges of a box with the given style in a containing
+    /// block of the given width (which percentages refer to).
+    pub(super) fn of(style: &ComputedStyle, containing_width: f64) -> Self {
+        // Percentages are treated as zero while computing max-content widths
+        let containing_width = if containing_width.is_finite() { containing_width } else { 0.0 };
+        Self {
+            margin: style.margin.map(|m| m.resolve(containing_width)),
+            border: style.border_widths(),
+            padding: style.padding.map(|p| p.resolve(containing_width)),
+        }
+    }
+
+    /// The distances from the border edge to the content edge, i.e. the
+    /// sums of the border widths and the padding.
+    pub(super) fn inner(&self) -> Sides<f64> {
+        Sides {
+            top: self.border.top + self.padding.top,
+            right: self.border.right + self.padding.right,
+            bottom: self.border.bottom + self.padding.bottom,
+            left: self.border.left + self.padding.left,
+        }
+    }
+}
+
+/// Converts a specified `width` or `height` to the size of the content box,
+/// given the sum of the border widths and padding along that axis.
+pub(super) fn content_size(style: &ComputedStyle, size: f64, edges: f64) -> f64 {
+    match style.box_sizing {
+        BoxSizing::ContentBox => size,
+        BoxSizing::BorderBox => (size - edges).max(0.0),
+    }
+}
+
+/// The layout engine, which turns a styled document into a tree of
+/// positioned fragments. Text is measured (and laid out) using the given
+/// piet text factory, the resulting text layouts are then used for painting.
+pub struct Layouter<'a, T> {
+    /// The text factory used to lay out text.
+    pub(super) text: &'a mut T,
+    /// The width of the area to lay out the document in.
+    width: f64,
+    /// The environment to evaluate media queries against.
+    pub(super) media: MediaEnvironment,
+    /// The width of the containing block of absolutely positioned boxes, i.e.
+    /// the padding box of the nearest positioned ancestor.
+    pub(super) containing_width: f64,
+    /// The floats in the current block formatting context.
+    pub(super) floats: FloatContext,
+    /// The fonts available to the document.
+    pub(super) fonts: Fonts,
+    /// The loaded images of the document.
+    pub(super) images: Images,
+    /// The writing mode of the flow boxes are laid out in. Boxes in vertical
+    /// flows are laid out as horizontal ones, which are rotated afterwards.
+    pub(super) writing_mode: WritingMode,
+}
+
+impl<'a, T> Layouter<'a, T> where T: Text {
+    /// Creates a new layouter with the given parameters.
+    pub fn new(params: LayoutParams, text: &'a mut T) -> Self {
+        Self {
+            text,
+            width: params.width,
+            media: MediaEnvironment {
+                viewport: Viewport {
+                    width: params.viewport_size.width,
+                    height: params.viewport_size.height,
+                },
+                media_type: params.media_type,
+                color_scheme: params.color_scheme,
+                font_size: DEFAULT_FONT_SIZE,
+            },
+            containing_width: params.width,
+            floats: FloatContext::default(),
+            fonts: Fonts::default(),
+            images: Images::default(),
+            writing_mode: WritingMode::HorizontalTb,
+        }
+    }
+
+    /// Lays out a DOM document, using (and updating) the given cached styles,
+    /// web fonts and images.
+    pub fn layout_document(mut self, document: &Arc<Document>, styles: &mut StyleCache, fonts: &mut FontCache, images: &mut ImageCache) -> LayoutResult<T::TextLayout> {
+        // Compute the styles of the document (if they changed) and build the box tree
+        let mut root_box = LayoutBox::build(document.root(), styles.styles(document, self.media), &mut Counters::default());
+        self.fonts.load_web_fonts(document, styles.font_faces(), fonts, self.text);
+        self.images.load(document, root_box.as_ref(), images);
+        let canvas_background = root_box.as_mut().and_then(|root_box| root_box.take_canvas_background());
+        if let Some(root_box) = &mut root_box {
+            root_box.take_principal_writing_mode();
+        }
+
+        // Lay out the boxes
+        let width = self.width;
+        let root = root_box.map(|root_box| {
+            let viewport = self.media.viewport;
+            let mode = root_box.style.writing_mode;
+            self.writing_mode = mode;
+            let mut root = self.layout_block(&root_box, if mode.is_vertical() { viewport.height } else { width }, None);
+            let margin_top = root.margin_top.value().max(0.0);
+            let margin_bottom = root.margin_bottom.value().max(0.0);
+            root.fragment.translate(Vec2::new(root.margin_left, margin_top));
+            // The margin after the bounds of the root, which extends the document
+            let end_margin = match mode {
+                WritingMode::HorizontalTb => Vec2::new(0.0, margin_bottom),
+                WritingMode::VerticalLr => {
+                    let height = root.fragment.rect.y1 + margin_bottom;
+                    rotate_flow(&mut root.fragment, mode, height);
+                    Vec2::new(margin_bottom, 0.0)
+                },
+                WritingMode::VerticalRl => {
+                    // Lines stacked from the right start at the right edge of the viewport
+                    // TODO: Scroll documents wider than the viewport to their right edge initially
+                    let height = (root.fragment.rect.y1 + margin_bottom).max(width);
+                    rotate_flow(&mut root.fragment, mode, height);
+                    Vec2::new(margin_top, 0.0)
+                },
+            };
+            // Positioned boxes are placed relative to the initial containing block (or the viewport) by default
+            let initial = Rect::from_origin_size(Point::ZERO, Size::new(width, viewport.height));
+            apply_insets(&mut root.fragment, initial, Rect::from_origin_size(Point::ZERO, Size::new(viewport.width, viewport.height)));
+            (root.fragment, end_margin)
+        });
+
+        // Aggregate results from the layout pass
+        let size = root.as_ref()
+            .map(|(root, end_margin)| {
+                let bounds = root.bounds();
+                Size::new((bounds.x1 + end_margin.x).max(0.0), (bounds.y1 + end_margin.y).max(0.0))
+            })
+            .unwrap_or(Size::ZERO);
+        let root = root.map(|(root, _)| root);
+        let scroll_areas = root.as_ref()
+            .map(ScrollAreas::from_fragments)
+            .unwrap_or(ScrollAreas { areas: Vec::new() });
+        let link_areas = root.as_ref()
+            .map(|root| LinkAreas::from_fragments(root, &scroll_areas))
+            .unwrap_or(LinkAreas { areas: Vec::new() });
+        let title = document.root().descendants()
+            .find(|e| e.tag_name() == "title")
+            .map(|e| e.text().split_whitespace().collect::<Vec<_>>().join(" "));
+
+        let background_images = std::mem::take(&mut self.images.backgrounds);
+
+        LayoutResult { size, root, link_areas, scroll_areas, title, canvas_background, background_images }
+    }
+
+    /// Lays out a block-level box in a containing block of the given
+    /// width and (if definite) height.
+    pub(super) fn layout_block(&mut self, block: &LayoutBox, containing_width: f64, containing_height: Option<f64>) -> BlockLayout<T::TextLayout> {
+        if block.style.writing_mode != self.writing_mode {
+            self.layout_flow_block(block, containing_width, containing_height)
+        } else {
+            self.layout_horizontal_block(block, containing_width, containing_height, false)
+        }
+    }
+
+    /// Lays out a block-level box as a horizontal one in a containing block
+    /// of the given width and (if definite) height. If the box is
+    /// `independent`, it establishes an independent formatting context.
+    pub(super) fn layout_horizontal_block(&mut self, block: &LayoutBox, containing_width: f64, containing_height: Option<f64>, independent: bool) -> BlockLayout<T::TextLayout> {
+        if block.style.display == Display::Table {
+            return self.layout_table(block, containing_width, containing_height);
+        }
+
+        // See https://www.w3.org/TR/CSS2/visudet.html#blockwidth
+        let style = &block.style;
+        let mut edges = BoxEdges::of(style, containing_width);
+        let inner = edges.inner();
+        let margin = edges.margin;
+        let replaced = self.replaced_size(block, containing_width, containing_height);
+        let specified_width = style.width.resolve_definite(containing_width.is_finite().then_some(containing_width)).map(|width| content_size(style, width, inner.horizontal()));
+        let width = match replaced.map(|size| size.width).or(specified_width) {
+            Some(width) => {
+                let remaining = containing_width - width - inner.horizontal();
+                edges.margin.left = Some(match (margin.left, margin.right) {
+                    (None, None) => (remaining / 2.0).max(0.0),
+                    (None, Some(right)) => remaining - right,
+                    // If over-constrained, the right margin is ignored
+                    (Some(left), _) => left,
+                });
+                width
+            },
+            None => {
+                let margin_left = margin.left.unwrap_or(0.0);
+                let margin_right = margin.right.unwrap_or(0.0);
+                (containing_width - margin_left - margin_right - inner.horizontal()).max(0.0)
+            },
+        };
+        let height = replaced.map(|size| size.height).or_else(|| {
+            style.height.resolve_definite(containing_height).map(|height| content_size(style, height, inner.vertical()))
+        });
+
+        // Scroll containers establish an independent formatting context
+        self.layout_sized_block(block, &edges, width, height, independent || style.is_scroll_container())
+    }
+
+    /// Lays out a block-level box whose edges and content width (and height,
+    /// if specified) have already been determined, e.g. by its container.
+    /// Boxes in another writing mode than the current flow (e.g. floats or
+    /// flex items) are laid out with these sizes in a flow of their own.
+    pub(super) fn layout_sized_block(&mut self, block: &LayoutBox, edges: &BoxEdges, width: f64, height: Option<f64>, independent: bool) -> BlockLayout<T::TextLayout> {
+        let mode = block.style.writing_mode;
+        if mode != self.writing_mode {
+            let margin_right = edges.margin.right.unwrap_or(0.0);
+            return self.layout_flow(mode, margin_right, |this| this.layout_sized_block(block, edges, width, height, true));
+        }
+
+        // Positioned boxes are the containing blocks of their absolutely positioned descendants
+        let outer_containing_width = self.containing_width;
+        if block.style.position.is_positioned() {
+            self.containing_width = width + edges.padding.horizontal();
+        }
+        let layout = if let Some(image) = self.images.get(block).cloned() {
+            self.layout_image(block, image, edges, width, height)
+        } else {
+            match block.style.display {
+                Display::Flex => self.layout_flex(block, edges, width, height),
+                Display::Grid => self.layout_grid(block, edges, width, height),
+                Display::Table => {
+                    let margin = edges.margin.map(|m| m.unwrap_or(0.0)).horizontal();
+                    self.layout_table(block, width + edges.inner().horizontal() + margin, height)
+                },
+                _ => self.layout_block_contents(block, edges, width, height, independent),
+            }
+        };
+        self.containing_width = outer_containing_width;
+        layout
+    }
+
+    /// Lays out the children of a block-level box with the given edges and
+    /// content width (and height, if specified). If the box is `independent`,
+    /// i.e. establishes an independent formatting context (e.g. a table cell),
+    /// the margins of its children never collapse with its own and the floats
+    /// inside of it are contained in it.
+    pub(super) fn layout_block_contents(&mut self, block: &LayoutBox, edges: &BoxEdges, width: f64, height: Option<f64>, independent: bool) -> BlockLayout<T::TextLayout> {
+        let border = edges.border;
+        let margin = edges.margin;
+        let edges = edges.inner();
+        let content_origin = Point::new(edges.left, edges.top);
+        // Margins of children adjoin ours if there is no border or padding in between
+        let collapses_top = edges.top == 0.0 && !independent;
+        let collapses_bottom = edges.bottom == 0.0 && height.is_none() && !independent;
+        let mut margin_top = CollapsedMargin::new(margin.top.unwrap_or(0.0));
+        let mut margin_bottom = CollapsedMargin::new(margin.bottom.unwrap_or(0.0));
+
+        // Track our position in the formatting context to place floats relative to us
+        let outer_origin = self.floats.origin;
+        let outer_floats = independent.then(|| std::mem::take(&mut self.floats));
+        self.floats.origin.x += margin.left.unwrap_or(0.0);
+        let origin = self.floats.origin;
+
+        let (mut children, mut content_height, collapses_through) = if block.children.iter().any(|c| c.is_block_level()) {
+            // Stack the block-level children vertically, collapsing adjoining margins
+            let mut children = Vec::new();
+            let mut y = 0.0;
+            let mut pending = CollapsedMargin::default();
+            let mut placed_any = false;
+            for child in &block.children {
+                // Estimate the position of the child before laying it out, assuming
+                // that only its own top margin collapses with the preceding ones
+                let escapes = !placed_any && collapses_top;
+                let own_margin = CollapsedMargin::new(BoxEdges::of(&child.style, width).margin.top.unwrap_or(0.0));
+                let mut estimate = if escapes { y } else { y + pending.with(own_margin).value() };
+                // Clearance moves the child below the floats it clears
+                let mut clearance = self.floats.clearance(child.style.clear)
+                    .map(|bottom| bottom - content_origin.y)
+                    .filter(|&bottom| bottom > estimate);
+                if let Some(bottom) = clearance {
+                    estimate = bottom;
+                }
+                let (x, mut layout) = loop {
+                    // Boxes establishing an independent formatting context are placed next to floats
+                    let top = content_origin.y + estimate;
+                    let beside_floats = child.establishes_formatting_context() && !self.floats.is_empty();
+                    let (x, containing_width) = if beside_floats {
+                        let (left, right) = self.floats.available(top, top, content_origin.x, content_origin.x + width);
+                        (left - content_origin.x, (right - left).max(0.0))
+                    } else {
+                        (0.0, width)
+                    };
+                    self.floats.origin = origin + Vec2::new(content_origin.x + x, top);
+                    let layout = self.layout_block(child, containing_width, height);
+                    self.floats.origin = origin;
+                    // If they don't fit, they are moved below the floats
+                    match self.floats.next_bottom(top, top + layout.fragment.rect.height()) {
+                        Some(bottom) if beside_floats && layout.fragment.rect.width() > containing_width => {
+                            estimate = bottom - content_origin.y;
+                            clearance = Some(estimate);
+                        },
+                        _ => break (x, layout),
+                    }
+                };
+
+                let offset = if let Some(bottom) = clearance {
+                    // Margins don't collapse across clearance
+                    if escapes {
+                        margin_top = margin_top.with(pending);
+                    }
+                    pending = layout.margin_bottom;
+                    placed_any = true;
+                    y = bottom + layout.fragment.rect.height();
+                    bottom
+                } else {
+                    pending = pending.with(layout.margin_top);
+                    if layout.collapses_through {
+                        pending = pending.with(layout.margin_bottom);
+                        y
+                    } else {
+                        if escapes {
+                            // The margin escapes through our top edge
+                            margin_top = margin_top.with(pending);
+                        } else {
+                            y += pending.value();
+                        }
+                        pending = layout.margin_bottom;
+                        placed_any = true;
+                        let offset = y;
+                        y += layout.fragment.rect.height();
+                        offset
+                    }
+                };
+                layout.fragment.translate(Vec2::new(content_origin.x + x + layout.margin_left, content_origin.y + offset));
+                children.push(layout.fragment);
+            }
+            let collapses_through = !placed_any && collapses_top && collapses_bottom;
+            if collapses_through {
+                margin_top = margin_top.with(pending);
+            } else if collapses_bottom {
+                margin_bottom = margin_bottom.with(pending);
+            } else {
+                y += pending.value();
+            }
+            (children, y, collapses_through)
+        } else {
+            // Lay out the inline-level children in lines
+            let (children, content_height) = self.layout_inline_content(block, content_origin, width);
+            let collapses_through = content_height == 0.0 && collapses_top && collapses_bottom;
+            (children, content_height, collapses_through)
+        };
+
+        if let Some(marker) = &block.marker {
+            children.extend(self.marker_fragment(marker, &block.style, &children, content_origin, width));
+        }
+
+        if let Some(outer_floats) = outer_floats {
+            // The floats inside of us extend our height
+            if let Some(bottom) = self.floats.bottom() {
+                content_height = content_height.max(bottom - content_origin.y);
+            }
+            self.floats = outer_floats;
+        }
+        self.floats.origin = outer_origin;
+
+        let content_height = height.unwrap_or(content_height);
+        if collapses_through && content_height == 0.0 {
+            margin_top = margin_top.with(margin_bottom);
+            margin_bottom = CollapsedMargin::default();
+        }
+
+        BlockLayout {
+            fragment: Fragment {
+                rect: Rect::from_origin_size(Point::ZERO, Size::new(width + edges.horizontal(), content_height + edges.vertical())),
+                style: block.style.clone(),
+                content: FragmentContent::Box,
+                border,
+                href: block.href().map(|href| href.to_owned()),
+                children,
+            },
+            margin_left: margin.left.unwrap_or(0.0),
+            margin_top,
+            margin_bottom,
+            collapses_through: collapses_through && content_height == 0.0,
+        }
+    }
+
+    /// Lays out the marker of a list item, placing it to the left of the
+    /// content box with the given width (or to the right for right-to-left
+    /// text), aligned with the baseline of the first line.
+    fn marker_fragment(&mut self, marker: &str, style: &Arc<ComputedStyle>, children: &[Fragment<T::TextLayout>], content_origin: Point, width: f64) -> Option<Fragment<T::TextLayout>> {
+        let layout = self.text_layout(marker, style)?;
+        let first_baseline = children.iter()
+            .find_map(|c| c.first_baseline())
+            .unwrap_or(content_origin.y + text_baseline(&layout));
+        let x = match style.direction {
+            Direction::Ltr => content_origin.x - layout.trailing_whitespace_width(),
+            Direction::Rtl => content_origin.x + width,
+        };
+        let origin = Point::new(x, first_baseline - text_baseline(&layout));
+        Some(Fragment {
+            rect: Rect::from_origin_size(origin, layout.size()),
+            style: style.clone(),
+            content: FragmentContent::Text(layout),
+            border: Sides::default(),
+            href: None,
+            children: Vec::new(),
+        })
+    }
+}
@@ -0,0 +1,368 @@
+use std::ops::Range;
+
+use piet::{kurbo::{Point, Rect, Size, Vec2}, Text};
+
+use crate::web::{AlignItems, FlexWrap, JustifyContent, LengthOrAuto, Sides};
+
+use super::{content_size, BlockLayout, BoxEdges, CollapsedMargin, Fragment, FragmentContent, IntrinsicWidths, LayoutBox, Layouter};
+
+/// A flex item whose size and position are being resolved.
+struct FlexItem<'b, 'd, L> {
+    item: &'b LayoutBox<'d>,
+    /// The used edges, with `auto` margins treated as zero.
+    edges: BoxEdges,
+    /// The margins along the main axis (from its start to its end), `None` if `auto`.
+    main_margins: (Option<f64>, Option<f64>),
+    /// The margins along the cross axis (from its start to its end), `None` if `auto`.
+    cross_margins: (Option<f64>, Option<f64>),
+    /// The flex base size of the border box.
+    base: f64,
+    /// The automatic minimum size of the border box, which it never shrinks below.
+    min: f64,
+    /// The (target) main size of the border box.
+    main: f64,
+    /// Whether the main size has been resolved.
+    frozen: bool,
+    /// The laid out item, once its main size is known (or measured).
+    fragment: Option<Fragment<L>>,
+}
+
+impl<L> FlexItem<'_, '_, L> {
+    /// The main size clamped by the minimum size.
+    fn hypothetical(&self) -> f64 {
+        self.base.max(self.min)
+    }
+
+    /// The sum of the margins along the main axis, with `auto` as zero.
+    fn main_margin(&self) -> f64 {
+        self.main_margins.0.unwrap_or(0.0) + self.main_margins.1.unwrap_or(0.0)
+    }
+
+    /// The sum of the margins along the cross axis, with `auto` as zero.
+    fn cross_margin(&self) -> f64 {
+        self.cross_margins.0.unwrap_or(0.0) + self.cross_margins.1.unwrap_or(0.0)
+    }
+}
+
+/// The margins of a box along an axis, from the start to the end of it.
+fn axis_margins(margin: &Sides<Option<f64>>, horizontal: bool, reverse: bool) -> (Option<f64>, Option<f64>) {
+    let (start, end) = if horizontal { (margin.left, margin.right) } else { (margin.top, margin.bottom) };
+    if reverse { (end, start) } else { (start, end) }
+}
+
+impl<'a, T> Layouter<'a, T> where T: Text {
+    /// Lays out a flex container with the given edges and content width (and
+    /// height, if specified), placing its children as flex items in lines.
+    /// See https://www.w3.org/TR/css-flexbox-1/#layout-algorithm
+    pub(super) fn layout_flex(&mut self, container: &LayoutBox, edges: &BoxEdges, width: f64, height: Option<f64>) -> BlockLayout<T::TextLayout> {
+        let style = &container.style;
+        let row = style.flex_direction.is_row();
+        let reverse = style.flex_direction.is_reverse();
+        let wrap_reverse = style.flex_wrap == FlexWrap::WrapReverse;
+        let main_size = if row { Some(width) } else { height };
+        let cross_size = if row { height } else { Some(width) };
+        let column_gap = style.column_gap.resolve(width);
+        let row_gap = style.row_gap.resolve(height.unwrap_or(0.0));
+        let (main_gap, cross_gap) = if row { (column_gap, row_gap) } else { (row_gap, column_gap) };
+
+        // Determine the flex base sizes of the items in order
+        let mut children: Vec<_> = container.children.iter().filter(|c| !c.is_absolute()).collect();
+        children.sort_by_key(|c| c.style.order);
+        let mut items: Vec<_> = children.into_iter()
+            .map(|item| self.flex_item(item, container, width, height, reverse, wrap_reverse))
+            .collect();
+
+        // Collect the items into lines
+        let mut lines: Vec<Range<usize>> = Vec::new();
+        let mut start = 0;
+        let mut used = 0.0;
+        for (i, item) in items.iter().enumerate() {
+            let outer = item.hypothetical() + item.main_margin();
+            if style.flex_wrap != FlexWrap::NoWrap && i > start && used + main_gap + outer > main_size.unwrap_or(f64::INFINITY) {
+                lines.push(start..i);
+                start = i;
+                used = outer;
+            } else {
+                used += if i > start { main_gap } else { 0.0 } + outer;
+            }
+        }
+        if start < items.len() {
+            lines.push(start..items.len());
+        }
+
+        // Resolve the main sizes of the items and lay them out
+        for line in &lines {
+            let line_items = &mut items[line.clone()];
+            let gaps = main_gap * (line_items.len() - 1) as f64;
+            let margins: f64 = line_items.iter().map(|i| i.main_margin()).sum();
+            let available = main_size.map(|size| size - gaps - margins);
+            resolve_flexible_lengths(line_items, available);
+        }
+        for item in &mut items {
+            let fragment = match item.fragment.take() {
+                Some(fragment) => fragment,
+                None => {
+                    let inner = item.edges.inner();
+                    let item_style = &item.item.style;
+                    let item_height = item_style.height.resolve_definite(height)
+                        .map(|h| content_size(item_style, h, inner.vertical()));
+                    let content_width = (item.main - inner.horizontal()).max(0.0);
+                    self.layout_sized_block(item.item, &item.edges, content_width, item_height, true).fragment
+                },
+            };
+            item.fragment = Some(fragment);
+        }
+        let cross = |item: &FlexItem<_>| {
+            let rect = item.fragment.as_ref().map_or(Rect::ZERO, |f| f.rect);
+            if row { rect.height() } else { rect.width() }
+        };
+
+        // Determine the cross sizes of the lines
+        let align = |item: &FlexItem<_>| item.item.style.align_self.unwrap_or(style.align_items);
+        let baselines: Vec<Option<f64>> = items.iter()
+            .map(|item| match align(item) {
+                AlignItems::Baseline if row => {
+                    let fragment = item.fragment.as_ref()?;
+                    let baseline = fragment.first_baseline().map_or(fragment.rect.height(), |b| b - fragment.rect.y0);
+                    Some(item.cross_margins.0.unwrap_or(0.0) + baseline)
+                },
+                _ => None,
+            })
+            .collect();
+        let mut line_sizes: Vec<f64> = lines.iter()
+            .map(|line| {
+                let largest = items[line.clone()].iter().map(|i| cross(i) + i.cross_margin()).fold(0.0, f64::max);
+                let ascent = baselines[line.clone()].iter().flatten().copied().fold(0.0, f64::max);
+                let descent = items[line.clone()].iter().zip(&baselines[line.clone()])
+                    .filter_map(|(i, b)| b.map(|b| cross(i) + i.cross_margin() - b))
+                    .fold(0.0, f64::max);
+                largest.max(ascent + descent)
+            })
+            .collect();
+        let cross_gaps = cross_gap * lines.len().saturating_sub(1) as f64;
+        match (cross_size, line_sizes.as_mut_slice()) {
+            (Some(size), [line]) if style.flex_wrap == FlexWrap::NoWrap => *line = size,
+            (Some(size), _) => {
+                // Stretch the lines to fill the container
+                let extra = size - line_sizes.iter().sum::<f64>() - cross_gaps;
+                if extra > 0.0 {
+                    let count = line_sizes.len() as f64;
+                    for line in &mut line_sizes {
+                        *line += extra / count;
+                    }
+                }
+            },
+            _ => {},
+        }
+        let content_cross = cross_size.unwrap_or(line_sizes.iter().sum::<f64>() + cross_gaps);
+        let content_main = main_size.unwrap_or_else(|| lines.iter()
+            .map(|line| items[line.clone()].iter().map(|i| i.main + i.main_margin()).sum::<f64>() + main_gap * (line.len() - 1) as f64)
+            .fold(0.0, f64::max));
+
+        // Position the items along both axes
+        let inner = edges.inner();
+        let mut children = Vec::new();
+        let mut line_start = 0.0;
+        for (line, line_size) in lines.iter().zip(&line_sizes) {
+            let line_items = &items[line.clone()];
+            let count = line_items.len() as f64;
+            let used = line_items.iter().map(|i| i.main + i.main_margin()).sum::<f64>() + main_gap * (count - 1.0);
+            let free = content_main - used;
+            let auto_margins = line_items.iter()
+                .map(|i| i.main_margins.0.is_none() as usize + i.main_margins.1.is_none() as usize)
+                .sum::<usize>();
+            let auto_margin = if free > 0.0 && auto_margins > 0 { free / auto_margins as f64 } else { 0.0 };
+            let (mut main_pos, between) = match style.justify_content {
+                _ if auto_margin > 0.0 => (0.0, 0.0),
+                JustifyContent::FlexStart => (0.0, 0.0),
+                JustifyContent::FlexEnd => (free, 0.0),
+                JustifyContent::Center => (free / 2.0, 0.0),
+                JustifyContent::SpaceBetween if free > 0.0 && count > 1.0 => (0.0, free / (count - 1.0)),
+                JustifyContent::SpaceAround if free > 0.0 => (free / count / 2.0, free / count),
+                JustifyContent::SpaceEvenly if free > 0.0 => (free / (count + 1.0), free / (count + 1.0)),
+                JustifyContent::SpaceBetween => (0.0, 0.0),
+                JustifyContent::SpaceAround | JustifyContent::SpaceEvenly => (free / 2.0, 0.0),
+            };
+            let ascent = baselines[line.clone()].iter().flatten().copied().fold(0.0, f64::max);
+
+            for (item, baseline) in items[line.clone()].iter_mut().zip(&baselines[line.clone()]) {
+                main_pos += item.main_margins.0.unwrap_or(auto_margin);
+                let item_main_pos = main_pos;
+                main_pos += item.main + item.main_margins.1.unwrap_or(auto_margin) + main_gap + between;
+
+                // Align the item within the line
+                let mut item_cross = cross(item);
+                let free = line_size - item_cross - item.cross_margin();
+                let offset = match item.cross_margins {
+                    (None, None) => free.max(0.0) / 2.0,
+                    (None, Some(_)) => free.max(0.0),
+                    (Some(_), None) => 0.0,
+                    (Some(_), Some(_)) => match align(item) {
+                        AlignItems::Stretch => {
+                            let size_property = if row { &item.item.style.height } else { &item.item.style.width };
+                            if *size_property == LengthOrAuto::Auto {
+                                item_cross = (line_size - item.cross_margin()).max(item_cross);
+                            }
+                            0.0
+                        },
+                        AlignItems::FlexStart => 0.0,
+                        AlignItems::FlexEnd => free,
+                        AlignItems::Center => free / 2.0,
+                        AlignItems::Baseline => baseline.map_or(0.0, |b| ascent - b),
+                    },
+                };
+                let item_cross_pos = line_start + item.cross_margins.0.unwrap_or(0.0) + offset;
+
+                // Reversed axes are laid out like the regular ones and then mirrored
+                let item_main_pos = if reverse { content_main - item_main_pos - item.main } else { item_main_pos };
+                let item_cross_pos = if wrap_reverse { content_cross - item_cross_pos - item_cross } else { item_cross_pos };
+                let (x, y, w, h) = if row {
+                    (item_main_pos, item_cross_pos, item.main, item_cross)
+                } else {
+                    (item_cross_pos, item_main_pos, item_cross, item.main)
+                };
+                let mut fragment = item.fragment.take().expect("Flex item has not been laid out");
+                fragment.rect = Rect::from_origin_size(fragment.rect.origin(), Size::new(w, h));
+                fragment.translate(Vec2::new(inner.left + x, inner.top + y));
+                children.push(fragment);
+            }
+            line_start += line_size + cross_gap;
+        }
+        children.extend(self.layout_absolute_children(container, Point::new(inner.left, inner.top)));
+
+        let content_height = if row { content_cross } else { content_main };
+        let margin = edges.margin;
+        BlockLayout {
+            fragment: Fragment {
+                rect: Rect::from_origin_size(Point::ZERO, Size::new(width + inner.horizontal(), content_height + inner.vertical())),
+                style: style.clone(),
+                content: FragmentContent::Box,
+                border: edges.border,
+                href: container.href().map(|href| href.to_owned()),
+                children,
+            },
+            margin_left: margin.left.unwrap_or(0.0),
+            margin_top: CollapsedMargin::new(margin.top.unwrap_or(0.0)),
+            margin_bottom: CollapsedMargin::new(margin.bottom.unwrap_or(0.0)),
+            collapses_through: false,
+        }
+    }
+
+    /// Determines the flex base size and minimum size of an item in a flex
+    /// container with the given content width (and height, if definite).
+    /// Items in column containers are laid out here to measure their height.
+    fn flex_item<'b, 'd>(&mut self, item: &'b LayoutBox<'d>, container: &LayoutBox, width: f64, height: Option<f64>, reverse: bool, wrap_reverse: bool) -> FlexItem<'b, 'd, T::TextLayout> {
+        let container_style = &container.style;
+        let row = container_style.flex_direction.is_row();
+        let style = &item.style;
+        let mut edges = BoxEdges::of(style, width);
+        let margin = edges.margin;
+        edges.margin = margin.map(|m| Some(m.unwrap_or(0.0)));
+        let inner = edges.inner();
+        let main_size = if row { Some(width) } else { height };
+
+        let (basis, min, fragment) = if row {
+            let to_border_box = |size| content_size(style, size, inner.horizontal()) + inner.horizontal();
+            let specified = style.width.resolve_definite(Some(width)).map(to_border_box);
+            let content = self.content_intrinsic_widths(item) + inner.horizontal();
+            let basis = style.flex_basis.resolve_definite(main_size).map(to_border_box);
+            let min = specified.map_or(content.min, |s| s.min(content.min));
+            (basis.or(specified).unwrap_or(content.max), min, None)
+        } else {
+            // Lay out the item at its cross size to measure its height
+            let available = width - edges.margin.map(|m| m.unwrap_or(0.0)).horizontal();
+            let stretch = container_style.align_items == AlignItems::Stretch && style.align_self.is_none()
+                || style.align_self == Some(AlignItems::Stretch);
+            let item_width = match style.width.resolve_definite(Some(width)) {
+                Some(w) => content_size(style, w, inner.horizontal()),
+                None if stretch && margin.left.is_some() && margin.right.is_some() => available - inner.horizontal(),
+                None => {
+                    let content = self.content_intrinsic_widths(item);
+                    (available - inner.horizontal()).max(content.min).min(content.max)
+                },
+            }.max(0.0);
+            let to_border_box = |size| content_size(style, size, inner.vertical()) + inner.vertical();
+            let item_height = style.height.resolve_definite(height).map(|h| content_size(style, h, inner.vertical()));
+            let fragment = self.layout_sized_block(item, &edges, item_width, item_height, true).fragment;
+            let measured = fragment.rect.height();
+            let basis = style.flex_basis.resolve_definite(main_size).map(to_border_box);
+            (basis.unwrap_or(measured), measured, Some(fragment))
+        };
+
+        FlexItem {
+            item,
+            edges,
+            main_margins: axis_margins(&margin, row, reverse),
+            cross_margins: axis_margins(&margin, !row, wrap_reverse),
+            base: basis,
+            min,
+            main: basis.max(min),
+            frozen: false,
+            fragment,
+        }
+    }
+
+    /// Computes the intrinsic widths of the content box of a flex container.
+    pub(super) fn flex_intrinsic_widths(&mut self, container: &LayoutBox) -> IntrinsicWidths {
+        let style = &container.style;
+        let items: Vec<_> = container.children.iter()
+            .filter(|c| !c.is_absolute())
+            .map(|item| self.intrinsic_widths(item)).collect();
+        if !style.flex_direction.is_row() {
+            return items.into_iter().fold(IntrinsicWidths::default(), IntrinsicWidths::max);
+        }
+        let gaps = style.column_gap.resolve(0.0) * items.len().saturating_sub(1) as f64;
+        let max = items.iter().map(|i| i.max).sum::<f64>() + gaps;
+        let min = if style.flex_wrap == FlexWrap::NoWrap {
+            items.iter().map(|i| i.min).sum::<f64>() + gaps
+        } else {
+            items.iter().map(|i| i.min).fold(0.0, f64::max)
+        };
+        IntrinsicWidths { min, max }
+    }
+}
+
+/// Resolves the main sizes of the items on a line by growing or shrinking
+/// them to fill the available space (if definite), in proportion to their
+/// flex factors.
+/// See https://www.w3.org/TR/css-flexbox-1/#resolve-flexible-lengths
+fn resolve_flexible_lengths<L>(items: &mut [FlexItem<L>], available: Option<f64>) {
+    let Some(available) = available else {
+        for item in items {
+            item.main = item.hypothetical();
+        }
+        return;
+    };
+    let growing = items.iter().map(|i| i.hypothetical()).sum::<f64>() < available;
+    let factor = |item: &FlexItem<L>| if growing { item.item.style.flex_grow } else { item.item.style.flex_shrink };
+    for item in items.iter_mut() {
+        item.main = item.hypothetical();
+        item.frozen = factor(item) == 0.0 || (!growing && item.base < item.min);
+    }
+    let free_space = |items: &[FlexItem<L>]| available - items.iter().map(|i| if i.frozen { i.main } else { i.base }).sum::<f64>();
+    let initial_free = free_space(items);
+
+    while items.iter().any(|i| !i.frozen) {
+        // A sum of flex factors below 1 only distributes part of the free space
+        let factors: f64 = items.iter().filter(|i| !i.frozen).map(factor).sum();
+        let mut free = free_space(items);
+        if factors < 1.0 && (initial_free * factors).abs() < free.abs() {
+            free = initial_free * factors;
+        }
+
+        // Distribute the free space and fix minimum size violations
+        let scaled: f64 = items.iter().filter(|i| !i.frozen).map(|i| factor(i) * if growing { 1.0 } else { i.base }).sum();
+        let mut violation = 0.0;
+        for item in items.iter_mut().filter(|i| !i.frozen) {
+            let share = if scaled > 0.0 { factor(item) * if growing { 1.0 } else { item.base } / scaled } else { 0.0 };
+            let target = item.base + free * share;
+            item.main = target.max(item.min);
+            violation += item.main - target;
+        }
+        for item in items.iter_mut().filter(|i| !i.frozen) {
+            if violation <= 0.0 || item.main == item.min {
+                item.frozen = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use piet::{kurbo::{Point, Rect, Size, Vec2}, Text};
+
+use crate::web::{Clear, Display, Float, Sides};
+
+use super::{content_size, BoxEdges, Fragment, LayoutBox, Layouter};
+
+/// A float placed in a block formatting context.
+#[derive(Debug, Clone, Copy)]
+struct PlacedFloat {
+    side: Float,
+    /// The margin box, relative to the formatting context.
+    rect: Rect,
+}
+
+/// The floats placed in a block formatting context, which shorten the
+/// line boxes next to them.
+/// See https://www.w3.org/TR/CSS2/visuren.html#floats
+#[derive(Debug, Clone, Default)]
+pub(super) struct FloatContext {
+    floats: Vec<PlacedFloat>,
+    /// The position of the block currently being laid out relative to the
+    /// formatting context. All other positions are relative to this block.
+    pub(super) origin: Vec2,
+}
+
+impl FloatContext {
+    /// Whether no floats have been placed.
+    pub(super) fn is_empty(&self) -> bool {
+        self.floats.is_empty()
+    }
+
+    /// The margin boxes of the floats relative to the current block.
+    fn rects(&self) -> impl Iterator<Item=(Float, Rect)> + '_ {
+        self.floats.iter().map(|f| (f.side, f.rect - self.origin))
+    }
+
+    /// The floats intersecting the band between the given vertical
+    /// positions. An empty band intersects the floats next to its position.
+    fn intersecting(&self, y0: f64, y1: f64) -> impl Iterator<Item=(Float, Rect)> + '_ {
+        self.rects().filter(move |(_, r)| r.y1 > y0 && (r.y0 < y1 || r.y0 <= y0))
+    }
+
+    /// The horizontal range left between the floats in the band between
+    /// the given vertical positions, within the given range.
+    pub(super) fn available(&self, y0: f64, y1: f64, x0: f64, x1: f64) -> (f64, f64) {
+        self.intersecting(y0, y1).fold((x0, x1), |(left, right), (side, rect)| match side {
+            Float::Left => (left.max(rect.x1), right),
+            _ => (left, right.min(rect.x0)),
+        })
+    }
+
+    /// The position below the given one where the first of the floats
+    /// intersecting the given band ends, `None` if there are none.
+    pub(super) fn next_bottom(&self, y0: f64, y1: f64) -> Option<f64> {
+        self.intersecting(y0, y1)
+            .map(|(_, r)| r.y1)
+            .reduce(f64::min)
+    }
+
+    /// The position below the floats cleared by the given value, `None` if
+    /// there are none.
+    pub(super) fn clearance(&self, clear: Clear) -> Option<f64> {
+        self.rects()
+            .filter(|(side, _)| clear.clears(*side))
+            .map(|(_, r)| r.y1)
+            .reduce(f64::max)
+    }
+
+    /// The position below all floats, `None` if there are none.
+    pub(super) fn bottom(&self) -> Option<f64> {
+        self.clearance(Clear::Both)
+    }
+
+    /// Places a float with the given margin box size as high as possible,
+    /// but not above the given position or earlier floats, and as far to
+    /// its side within the given horizontal range as possible. Returns the
+    /// position of its margin box.
+    fn place(&mut self, side: Float, size: Size, y: f64, x0: f64, x1: f64) -> Point {
+        let mut y = self.rects().map(|(_, r)| r.y0).fold(y, f64::max);
+        loop {
+            let (left, right) = self.available(y, y + size.height, x0, x1);
+            match self.next_bottom(y, y + size.height) {
+                Some(bottom) if right - left < size.width => y = bottom,
+                _ => {
+                    let x = match side {
+                        Float::Left => left,
+                        _ => right - size.width,
+                    };
+                    let position = Point::new(x, y);
+                    self.floats.push(PlacedFloat { side, rect: Rect::from_origin_size(position + self.origin, size) });
+                    return position;
+                },
+            }
+        }
+    }
+}
+
+/// A laid out float that hasn't been placed yet.
+pub(super) struct FloatLayout<L> {
+    /// The fragment, with its border box positioned at the origin.
+    fragment: Fragment<L>,
+    margin: Sides<f64>,
+    side: Float,
+    clear: Clear,
+}
+
+impl<L> FloatLayout<L> {
+    /// The size of the margin box.
+    pub(super) fn size(&self) -> Size {
+        let size = self.fragment.rect.size();
+        Size::new(size.width + self.margin.horizontal(), size.height + self.margin.vertical())
+    }
+}
+
+impl<'a, T> Layouter<'a, T> where T: Text {
+    /// Lays out a float in a containing block of the given width.
+    /// See https://www.w3.org/TR/CSS2/visudet.html#float-width
+    pub(super) fn layout_float(&mut self, float: &LayoutBox, containing_width: f64) -> FloatLayout<T::TextLayout> {
+        let (fragment, margin) = self.layout_shrink_to_fit(float, containing_width);
+        FloatLayout { fragment, margin, side: float.style.float, clear: float.style.clear }
+    }
+
+    /// Lays out a box that is sized independently of the boxes around it,
+    /// i.e. a float or an atomic inline-level box, in a containing block of
+    /// the given width. Auto margins are zero and boxes without a specified
+    /// (or intrinsic) width shrink to fit their content. Returns the fragment
+    /// with its border box positioned at the origin and the used margins.
+    pub(super) fn layout_shrink_to_fit(&mut self, layout_box: &LayoutBox, containing_width: f64) -> (Fragment<T::TextLayout>, Sides<f64>) {
+        let style = &layout_box.style;
+        let mut edges = BoxEdges::of(style, containing_width);
+        edges.margin = edges.margin.map(|m| Some(m.unwrap_or(0.0)));
+        let margin = edges.margin.map(|m| m.unwrap_or(0.0));
+        let inner = edges.inner();
+        let available = (containing_width - margin.horizontal() - inner.horizontal()).max(0.0);
+        let replaced = self.replaced_size(layout_box, containing_width, None);
+        let specified_width = style.width.resolve_definite(containing_width.is_finite().then_some(containing_width)).map(|width| content_size(style, width, inner.horizontal()));
+        let width = match replaced.map(|size| size.width).or(specified_width) {
+            Some(width) => width,
+            // Tables shrink to fit by themselves
+            None if style.display == Display::Table => available,
+            None => self.shrink_to_fit_width(layout_box, available),
+        };
+        let height = replaced.map(|size| size.height).or_else(|| {
+            style.height.resolve_definite(None).map(|h| content_size(style, h, inner.vertical()))
+        });
+        let fragment = self.layout_sized_block(layout_box, &edges, width, height, true).fragment;
+        (fragment, margin)
+    }
+
+    /// Places a laid out float in the current formatting context at or
+    /// below the given position, within the horizontal range of the
+    /// containing block. Returns the positioned fragment.
+    pub(super) fn place_float(&mut self, float: FloatLayout<T::TextLayout>, y: f64, x0: f64, x1: f64) -> Fragment<T::TextLayout> {
+        let y = self.floats.clearance(float.clear).map_or(y, |c| c.max(y));
+        let position = self.floats.place(float.side, float.size(), y, x0, x1);
+        let mut fragment = float.fragment;
+        fragment.translate(position.to_vec2() + Vec2::new(float.margin.left, float.margin.top));
+        fragment
+    }
+
+    /// Runs the given function in a new block formatting context, which
+    /// floats outside of it don't affect.
+    pub(super) fn in_new_context<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let outer = std::mem::take(&mut self.floats);
+        let result = f(self);
+        self.floats = outer;
+        result
+    }
+}
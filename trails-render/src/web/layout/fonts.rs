@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use piet::{FontFamily, Text};
+use trails_base::log::{debug, warn};
+use trails_model::dom::Document;
+
+use crate::web::{FamilyName, FontFace, GenericFamily};
+
+use super::decode_font;
+
+/// The web fonts registered with the text backend, which are kept across
+/// layouts since the backend can't unregister them.
+#[derive(Default)]
+pub struct FontCache {
+    /// The families of the loaded fonts by a hash of their data, `None` if
+    /// the data couldn't be loaded.
+    loaded: HashMap<u64, Option<FontFamily>>,
+}
+
+impl FontCache {
+    /// Creates a new (empty) font cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the given font data (unless already registered), returns
+    /// the family of the font if it could be loaded. WOFF and WOFF2 fonts
+    /// are decoded first, since the text backends only load TrueType and
+    /// OpenType fonts.
+    fn load<T>(&mut self, data: &[u8], text: &mut T) -> Option<FontFamily> where T: Text {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        self.loaded.entry(hasher.finish())
+            .or_insert_with(|| {
+                let sfnt = match decode_font(data) {
+                    Ok(sfnt) => sfnt,
+                    Err(e) => {
+                        warn!("Could not decode font: {}", e);
+                        return None;
+                    },
+                };
+                match text.load_font(&sfnt) {
+                    Ok(family) => Some(family),
+                    Err(e) => {
+                        warn!("Could not load font: {}", e);
+                        None
+                    },
+                }
+            })
+            .clone()
+    }
+}
+
+/// Resolves the font families of styles to those known to the text backend.
+#[derive(Default)]
+pub(super) struct Fonts {
+    /// The families of the web fonts of the document by their lowercase declared names.
+    web_fonts: HashMap<String, FontFamily>,
+    /// The installed families looked up so far by their lowercase names, `None` if not installed.
+    installed: HashMap<String, Option<FontFamily>>,
+}
+
+impl Fonts {
+    /// Loads the web fonts declared by the given `@font-face` rules from the
+    /// subresources of the document (by their absolute URLs), using the
+    /// first source that has been loaded. Declaring the same family several times (e.g. for different
+    /// weights) loads all of the faces, the text backend chooses between them.
+    pub(super) fn load_web_fonts<T>(&mut self, document: &Document, font_faces: &[FontFace], cache: &mut FontCache, text: &mut T) where T: Text {
+        for font_face in font_faces {
+            let Some(name) = font_face.rule.family() else {
+                continue;
+            };
+            let Some(data) = font_face.source_urls().into_iter().find_map(|url| document.resource(&url)) else {
+                debug!("No source of font family '{}' is loaded", name);
+                continue;
+            };
+            if let Some(family) = cache.load(data, text) {
+                self.web_fonts.entry(name.to_lowercase()).or_insert(family);
+            }
+        }
+    }
+
+    /// The first family of the given list that is available, falling back
+    /// to the default (serif) font if there is none.
+    pub(super) fn resolve<T>(&mut self, families: &[FamilyName], text: &mut T) -> FontFamily where T: Text {
+        families.iter()
+            .find_map(|family| match family {
+                FamilyName::Named(name) => {
+                    let key = name.to_lowercase();
+                    self.web_fonts.get(&key).cloned().or_else(|| {
+                        self.installed.entry(key)
+                            .or_insert_with(|| text.font_family(name))
+                            .clone()
+                    })
+                },
+                FamilyName::Generic(generic) => Some(generic_family(*generic)),
+            })
+            .unwrap_or(FontFamily::SERIF)
+    }
+}
+
+/// The font family a generic family is mapped to.
+fn generic_family(generic: GenericFamily) -> FontFamily {
+    match generic {
+        GenericFamily::Serif => FontFamily::SERIF,
+        GenericFamily::SansSerif => FontFamily::SANS_SERIF,
+        GenericFamily::Monospace => FontFamily::MONOSPACE,
+        GenericFamily::SystemUi => FontFamily::SYSTEM_UI,
+        // TODO: Map cursive and fantasy fonts to installed ones
+        GenericFamily::Cursive | GenericFamily::Fantasy => FontFamily::SERIF,
+    }
+}
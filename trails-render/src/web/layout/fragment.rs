@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use piet::{kurbo::{Rect, Vec2}, TextLayout};
+
+use crate::web::{ComputedStyle, Sides};
+
+use super::ImageData;
+
+/// The content of a fragment.
+#[derive(Debug, Clone)]
+pub enum FragmentContent<L> {
+    /// A (block or inline) box, whose content is in the child fragments.
+    Box,
+    /// A laid out piece of text, positioned at the fragment's origin.
+    Text(L),
+    /// A laid out piece of text in a vertical line, which is rotated by 90°
+    /// clockwise unless it is upright (e.g. Chinese or Japanese characters).
+    VerticalText { layout: L, upright: bool },
+    /// A decoded image, scaled to fill the fragment's rectangle.
+    Image(Arc<ImageData>),
+}
+
+/// A positioned piece of a box, as produced by layout. A box may generate
+/// several fragments, e.g. an inline box spanning multiple lines.
+#[derive(Debug, Clone)]
+pub struct Fragment<L> {
+    /// The rectangle occupied by the fragment, in document coordinates.
+    pub rect: Rect,
+    /// The computed style of the box that generated the fragment.
+    pub style: Arc<ComputedStyle>,
+    pub content: FragmentContent<L>,
+    /// The used border widths. Inline boxes split across lines only have
+    /// borders at their start and end edges.
+    pub border: Sides<f64>,
+    /// The link target if the fragment was generated by a link.
+    pub href: Option<String>,
+    pub children: Vec<Fragment<L>>,
+}
+
+impl<L> Fragment<L> {
+    /// Iterates this fragment and its descendants in painting order.
+    pub fn descendants_and_self(&self) -> impl Iterator<Item=&Fragment<L>> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let fragment = stack.pop()?;
+            stack.extend(fragment.children.iter().rev());
+            Some(fragment)
+        })
+    }
+
+    /// Moves this fragment and its descendants by the given offset.
+    pub fn translate(&mut self, offset: Vec2) {
+        self.rect = self.rect + offset;
+        for child in &mut self.children {
+            child.translate(offset);
+        }
+    }
+
+    /// Whether this fragment is generated by a positioned box, which is
+    /// painted in its own layer.
+    pub fn is_positioned(&self) -> bool {
+        matches!(self.content, FragmentContent::Box) && self.style.position.is_positioned()
+    }
+
+    /// Whether this fragment is generated by a box that forms a stacking
+    /// context of its own, i.e. a positioned or translucent one, which is
+    /// painted atomically.
+    pub fn is_stacking_context(&self) -> bool {
+        self.is_positioned() || (matches!(self.content, FragmentContent::Box) && self.style.opacity < 1.0)
+    }
+
+    /// The padding box, i.e. the rectangle inside of the borders.
+    pub fn padding_box(&self) -> Rect {
+        let border = self.border;
+        let rect = self.rect;
+        Rect::new(rect.x0 + border.left, rect.y0 + border.top, rect.x1 - border.right, rect.y1 - border.bottom)
+    }
+
+    /// Whether this fragment is generated by a box that clips its overflowing content.
+    pub fn clips_overflow(&self) -> bool {
+        matches!(self.content, FragmentContent::Box) && self.style.clips_overflow()
+    }
+
+    /// The bounding rectangle of this fragment and its descendants, except
+    /// for those clipped by it.
+    pub fn bounds(&self) -> Rect {
+        if self.clips_overflow() {
+            self.rect
+        } else {
+            self.children.iter().fold(self.rect, |rect, child| rect.union(child.bounds()))
+        }
+    }
+
+}
+
+impl<L> Fragment<L> where L: TextLayout {
+    /// The position of the baseline of the first line of text in this
+    /// fragment or its descendants, if any.
+    pub fn first_baseline(&self) -> Option<f64> {
+        self.descendants_and_self().find_map(|f| match &f.content {
+            FragmentContent::Text(layout) => Some(f.rect.y0 + text_baseline(layout)),
+            // Upright text is only found while laying out vertical lines horizontally
+            FragmentContent::VerticalText { upright: true, .. } => Some(f.rect.y0 + upright_baseline(f.rect.height(), f.style.font_size)),
+            FragmentContent::VerticalText { upright: false, .. } | FragmentContent::Box | FragmentContent::Image(_) => None,
+        })
+    }
+}
+
+/// The distance from the top of a text layout to the baseline of its first line.
+pub fn text_baseline(layout: &impl TextLayout) -> f64 {
+    layout.line_metric(0).map_or(layout.size().height, |m| m.baseline)
+}
+
+/// The distance from the top of a piece of upright text in a vertical line,
+/// as laid out horizontally (i.e. with its width as its height), to the
+/// baseline of the (sideways) text around it. Upright text is centered on
+/// the central baseline, which is approximately this fraction of the font
+/// size above the alphabetic one.
+pub fn upright_baseline(height: f64, font_size: f64) -> f64 {
+    const CENTRAL_BASELINE: f64 = 0.3;
+    height / 2.0 + CENTRAL_BASELINE * font_size
+}
@@ -0,0 +1,679 @@
+use std::ops::Range;
+
+use piet::{kurbo::{Point, Rect, Size, Vec2}, Text};
+
+use crate::web::{AlignItems, ComputedStyle, GridLine, LengthOrAuto, TrackBreadth, TrackList, TrackSize};
+
+use super::{content_size, BlockLayout, BoxEdges, CollapsedMargin, Fragment, FragmentContent, IntrinsicWidths, LayoutBox, Layouter};
+
+/// The maximum number of tracks generated by `repeat(auto-fill, ...)`.
+const MAX_AUTO_REPETITIONS: usize = 1000;
+/// The maximum distance of an item's lines from the explicit grid and the
+/// maximum number of tracks it spans, beyond which they are clamped.
+const MAX_LINE_DISTANCE: i32 = 1000;
+
+/// An item placed in the grid, with the ranges of tracks it spans.
+struct GridItem<'b, 'd> {
+    item: &'b LayoutBox<'d>,
+    rows: Range<usize>,
+    columns: Range<usize>,
+}
+
+/// The tracks of a grid in one axis, including implicit ones.
+struct GridAxis {
+    sizes: Vec<TrackSize>,
+    /// Whether each track is an empty `auto-fit` track, which collapses
+    /// (along with the gaps next to it).
+    collapsed: Vec<bool>,
+}
+
+/// The explicit tracks of a grid in one axis along with the names of their
+/// lines, including those implicitly named by areas.
+struct ExplicitAxis {
+    sizes: Vec<TrackSize>,
+    line_names: Vec<Vec<String>>,
+    /// The tracks repeated by `auto-fit`.
+    fit: Range<usize>,
+}
+
+impl ExplicitAxis {
+    /// Resolves the explicit tracks from a track list, repeating tracks as
+    /// often as they fit into the available size (if definite). The number
+    /// of tracks is extended to the given minimum (from the named areas).
+    fn new(list: &TrackList, min_tracks: usize, available: Option<f64>, gap: f64) -> Self {
+        let repetitions = match (&list.auto_repeat, available) {
+            (Some(repeat), Some(available)) => {
+                // See https://www.w3.org/TR/css-grid-1/#auto-repeat
+                let fixed_size = |t: &TrackSize| match (t.max, t.min) {
+                    (TrackBreadth::Length(l), _) | (_, TrackBreadth::Length(l)) => l.resolve(available),
+                    _ => 0.0,
+                };
+                let others = list.tracks.iter().map(fixed_size).sum::<f64>() + gap * list.tracks.len() as f64;
+                let repeated = repeat.tracks.iter().map(fixed_size).sum::<f64>() + gap * repeat.tracks.len() as f64;
+                if repeated > 0.0 {
+                    (((available - others + gap) / repeated).floor().max(1.0) as usize).min(MAX_AUTO_REPETITIONS)
+                } else {
+                    1
+                }
+            },
+            _ => 1,
+        };
+        let (mut sizes, mut line_names, fit) = list.expand(repetitions);
+        line_names.resize(sizes.len().max(min_tracks) + 1, Vec::new());
+        sizes.truncate(line_names.len() - 1);
+        Self { sizes, line_names, fit }
+    }
+
+    /// The number of explicit tracks.
+    fn len(&self) -> usize {
+        self.line_names.len() - 1
+    }
+
+    /// The indices of the lines with the given name.
+    fn lines_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item=i32> + 'a {
+        self.line_names.iter().enumerate()
+            .filter(move |(_, names)| names.iter().any(|n| n == name))
+            .map(|(i, _)| i as i32)
+    }
+
+    /// Resolves a line to its index, relative to the start of the explicit
+    /// grid, `None` if it is `auto` or a span.
+    /// See https://www.w3.org/TR/css-grid-1/#line-placement
+    fn resolve(&self, line: &GridLine, start: bool) -> Option<i32> {
+        let explicit = self.len() as i32;
+        match line {
+            GridLine::Auto | GridLine::Span(_) => None,
+            GridLine::Line(n, None) if *n > 0 => Some(n - 1),
+            GridLine::Line(n, None) => Some((explicit + 1).saturating_add(*n)),
+            GridLine::Line(n, Some(name)) => {
+                let lines: Vec<_> = self.lines_named(name).collect();
+                let count = lines.len() as i32;
+                // Missing lines are assumed to be implicit lines beyond the grid
+                Some(if *n > 0 {
+                    lines.get(*n as usize - 1).copied().unwrap_or((explicit - count).saturating_add(*n))
+                } else {
+                    lines.get((count + n) as usize).copied().unwrap_or(n + count)
+                })
+            },
+            GridLine::Name(name) => {
+                let suffixed = format!("{}-{}", name, if start { "start" } else { "end" });
+                let line = self.lines_named(&suffixed).next()
+                    .or_else(|| self.lines_named(name).next());
+                line.or(Some(explicit + 1))
+            },
+        }
+    }
+
+    /// Resolves the start line (if definite) and span of an item. Lines
+    /// far outside of the explicit grid and huge spans are clamped.
+    fn placement(&self, start: &GridLine, end: &GridLine) -> (Option<i32>, usize) {
+        let span = |line: &GridLine| match line {
+            GridLine::Span(n) => (*n as usize).min(MAX_LINE_DISTANCE as usize),
+            _ => 1,
+        };
+        let explicit = self.len() as i32;
+        let resolve = |line, start| self.resolve(line, start).map(|l| l.clamp(-MAX_LINE_DISTANCE, explicit + MAX_LINE_DISTANCE));
+        match (resolve(start, true), resolve(end, false)) {
+            (Some(s), Some(e)) if e > s => (Some(s), (e - s) as usize),
+            (Some(s), Some(e)) if e < s => (Some(e), (s - e) as usize),
+            (Some(s), Some(_)) => (Some(s), 1),
+            (Some(s), None) => (Some(s), span(end)),
+            (None, Some(e)) => (Some(e - span(start) as i32), span(start)),
+            (None, None) => (None, span(start).max(span(end))),
+        }
+    }
+}
+
+/// The placement of an item in one axis before auto-placement.
+#[derive(Clone, Copy)]
+struct AxisPlacement {
+    start: Option<usize>,
+    span: usize,
+}
+
+/// The cells of the grid occupied by items, indexed by the major axis (the
+/// one that auto-placement advances along) and then the minor one.
+struct Occupancy {
+    cells: Vec<Vec<bool>>,
+    minor: usize,
+}
+
+impl Occupancy {
+    fn is_free(&self, major: &Range<usize>, minor: &Range<usize>) -> bool {
+        major.clone().all(|i| self.cells.get(i).is_none_or(|row| minor.clone().all(|j| !row[j])))
+    }
+
+    fn occupy(&mut self, major: &Range<usize>, minor: &Range<usize>) {
+        if self.cells.len() < major.end {
+            self.cells.resize(major.end, vec![false; self.minor]);
+        }
+        for row in &mut self.cells[major.clone()] {
+            row[minor.clone()].fill(true);
+        }
+    }
+}
+
+/// The grid of a container with its items placed.
+struct Grid<'b, 'd> {
+    items: Vec<GridItem<'b, 'd>>,
+    rows: GridAxis,
+    columns: GridAxis,
+}
+
+impl<'b, 'd> Grid<'b, 'd> {
+    /// Places the items of a grid container with the given content size
+    /// (if definite), first those with definite positions, then the others
+    /// in order using auto-placement.
+    /// See https://www.w3.org/TR/css-grid-1/#auto-placement-algo
+    fn place(container: &'b LayoutBox<'d>, width: Option<f64>, height: Option<f64>) -> Self {
+        let style = &container.style;
+        let areas = &style.grid_template_areas;
+        let mut explicit_rows = ExplicitAxis::new(&style.grid_template_rows, areas.rows.len(), height, style.row_gap.resolve(height.unwrap_or(0.0)));
+        let mut explicit_columns = ExplicitAxis::new(&style.grid_template_columns, areas.columns(), width, style.column_gap.resolve(width.unwrap_or(0.0)));
+        for name in areas.rows.iter().flatten().flatten() {
+            if let Some((row_start, row_end, column_start, column_end)) = areas.area(name) {
+                explicit_rows.line_names[row_start].push(format!("{}-start", name));
+                explicit_rows.line_names[row_end].push(format!("{}-end", name));
+                explicit_columns.line_names[column_start].push(format!("{}-start", name));
+                explicit_columns.line_names[column_end].push(format!("{}-end", name));
+            }
+        }
+
+        // Resolve the definite lines, shifting them to make room for implicit tracks before the explicit ones
+        let mut children: Vec<_> = container.children.iter().filter(|c| !c.is_absolute()).collect();
+        children.sort_by_key(|c| c.style.order);
+        let placements: Vec<_> = children.iter()
+            .map(|c| (
+                explicit_rows.placement(&c.style.grid_row_start, &c.style.grid_row_end),
+                explicit_columns.placement(&c.style.grid_column_start, &c.style.grid_column_end),
+            ))
+            .collect();
+        let row_offset = placements.iter().filter_map(|(r, _)| r.0).min().unwrap_or(0).min(0).unsigned_abs() as usize;
+        let column_offset = placements.iter().filter_map(|(_, c)| c.0).min().unwrap_or(0).min(0).unsigned_abs() as usize;
+        let shift = |(start, span): (Option<i32>, usize), offset: usize| AxisPlacement {
+            start: start.map(|s| (s + offset as i32) as usize),
+            span,
+        };
+        let placements: Vec<_> = placements.into_iter()
+            .map(|(rows, columns)| (shift(rows, row_offset), shift(columns, column_offset)))
+            .collect();
+
+        // Auto-placement advances along the major axis, while the minor axis has a fixed number of tracks
+        let column_flow = style.grid_auto_flow.column;
+        let dense = style.grid_auto_flow.dense;
+        let (major_offset, minor_offset) = if column_flow { (column_offset, row_offset) } else { (row_offset, column_offset) };
+        let (major_explicit, minor_explicit) = if column_flow { (&explicit_columns, &explicit_rows) } else { (&explicit_rows, &explicit_columns) };
+        let axes = |(rows, columns): (AxisPlacement, AxisPlacement)| if column_flow { (columns, rows) } else { (rows, columns) };
+        let minor_count = placements.iter()
+            .map(|&p| {
+                let (_, minor) = axes(p);
+                minor.start.unwrap_or(0) + minor.span
+            })
+            .fold(minor_explicit.len() + minor_offset, usize::max);
+        let mut occupancy = Occupancy { cells: Vec::new(), minor: minor_count };
+        let mut areas: Vec<Option<(Range<usize>, Range<usize>)>> = vec![None; placements.len()];
+
+        // Place the items with definite positions in both axes and those locked to a major track
+        for (i, &p) in placements.iter().enumerate() {
+            let (major, minor) = axes(p);
+            if let (Some(major_start), Some(minor_start)) = (major.start, minor.start) {
+                areas[i] = Some((major_start..major_start + major.span, minor_start..minor_start + minor.span));
+            }
+        }
+        for (major, minor) in areas.iter().flatten() {
+            occupancy.occupy(major, minor);
+        }
+        for (i, &p) in placements.iter().enumerate() {
+            let (major, minor) = axes(p);
+            if let (Some(major_start), None) = (major.start, minor.start) {
+                let major = major_start..major_start + major.span;
+                let minor_start = (0..=minor_count - minor.span.min(minor_count))
+                    .find(|&m| occupancy.is_free(&major, &(m..m + minor.span)))
+                    .unwrap_or(0);
+                let minor = minor_start..minor_start + minor.span;
+                occupancy.occupy(&major, &minor);
+                areas[i] = Some((major, minor));
+            }
+        }
+
+        // Place the remaining items using a cursor
+        let mut cursor = (0, 0);
+        for (i, &p) in placements.iter().enumerate() {
+            if areas[i].is_some() {
+                continue;
+            }
+            let (major, minor) = axes(p);
+            if dense {
+                cursor = (0, 0);
+            }
+            let span = minor.span.min(minor_count);
+            let (major_start, minor_start) = match minor.start {
+                Some(minor_start) => {
+                    if minor_start < cursor.1 && !dense {
+                        cursor.0 += 1;
+                    }
+                    let minor = minor_start..minor_start + minor.span;
+                    let major_start = (cursor.0..).find(|&m| occupancy.is_free(&(m..m + major.span), &minor)).unwrap();
+                    cursor = (major_start, minor_start);
+                    (major_start, minor_start)
+                },
+                None => {
+                    let position = (cursor.0..)
+                        .find_map(|m| {
+                            let first = if m == cursor.0 { cursor.1 } else { 0 };
+                            (first..=minor_count - span)
+                                .find(|&n| occupancy.is_free(&(m..m + major.span), &(n..n + span)))
+                                .map(|n| (m, n))
+                        })
+                        .unwrap();
+                    cursor = (position.0, position.1 + span);
+                    position
+                },
+            };
+            let area = (major_start..major_start + major.span, minor_start..minor_start + minor.span);
+            occupancy.occupy(&area.0, &area.1);
+            areas[i] = Some(area);
+        }
+
+        // Determine the tracks of both axes, including the implicit ones
+        let items: Vec<_> = children.into_iter().zip(areas)
+            .map(|(item, area)| {
+                let (major, minor) = area.expect("Grid item has not been placed");
+                let (rows, columns) = if column_flow { (minor, major) } else { (major, minor) };
+                GridItem { item, rows, columns }
+            })
+            .collect();
+        let major_count = items.iter()
+            .map(|i| if column_flow { i.columns.end } else { i.rows.end })
+            .fold(major_explicit.len() + major_offset, usize::max);
+        let (row_count, column_count) = if column_flow { (minor_count, major_count) } else { (major_count, minor_count) };
+        let rows = GridAxis::new(&explicit_rows, row_offset, row_count, &style.grid_auto_rows, items.iter().map(|i| &i.rows));
+        let columns = GridAxis::new(&explicit_columns, column_offset, column_count, &style.grid_auto_columns, items.iter().map(|i| &i.columns));
+        Self { items, rows, columns }
+    }
+}
+
+impl GridAxis {
+    /// Creates the tracks of an axis from the explicit ones (starting at the
+    /// given offset) and the implicit track sizes. Empty `auto-fit` tracks
+    /// collapse.
+    fn new<'a>(explicit: &ExplicitAxis, offset: usize, count: usize, auto: &[TrackSize], spans: impl Iterator<Item=&'a Range<usize>>) -> Self {
+        let explicit_range = offset..offset + explicit.len();
+        let implicit = |i: usize| if auto.is_empty() {
+            TrackSize::default()
+        } else if i < offset {
+            // Implicit tracks before the explicit grid repeat the sizes backwards
+            auto[(auto.len() - (offset - i) % auto.len()) % auto.len()]
+        } else {
+            auto[(i - explicit_range.end) % auto.len()]
+        };
+        let mut sizes: Vec<_> = (0..count)
+            .map(|i| match explicit.sizes.get(i.wrapping_sub(offset)) {
+                Some(size) if explicit_range.contains(&i) => *size,
+                _ => implicit(i),
+            })
+            .collect();
+        let mut collapsed = vec![!explicit.fit.is_empty(); count];
+        for (i, collapsed) in collapsed.iter_mut().enumerate() {
+            *collapsed &= explicit.fit.contains(&i.wrapping_sub(offset));
+        }
+        for span in spans {
+            collapsed[span.clone()].fill(false);
+        }
+        for (size, _) in sizes.iter_mut().zip(&collapsed).filter(|(_, c)| **c) {
+            *size = TrackSize { min: TrackBreadth::Length(Default::default()), max: TrackBreadth::Length(Default::default()) };
+        }
+        Self { sizes, collapsed }
+    }
+
+    /// The total size of the gaps between the (non-collapsed) tracks.
+    fn gaps(&self, gap: f64) -> f64 {
+        gap * self.collapsed.iter().filter(|c| !**c).count().saturating_sub(1) as f64
+    }
+
+    /// The offsets of the tracks with the given sizes.
+    fn offsets(&self, sizes: &[f64], gap: f64) -> Vec<f64> {
+        let mut offsets = Vec::with_capacity(sizes.len());
+        let mut offset = 0.0;
+        let mut first = true;
+        for (size, collapsed) in sizes.iter().zip(&self.collapsed) {
+            if !collapsed && !first {
+                offset += gap;
+            }
+            first &= *collapsed;
+            offsets.push(offset);
+            offset += size;
+        }
+        offsets
+    }
+}
+
+/// The contribution of an item to the sizes of the tracks it spans.
+struct Contribution {
+    tracks: Range<usize>,
+    min: f64,
+    max: f64,
+}
+
+/// A track whose size is being resolved.
+struct Track {
+    min: TrackBreadth,
+    max: TrackBreadth,
+    base: f64,
+    /// The growth limit, `None` if infinite.
+    limit: Option<f64>,
+}
+
+impl Track {
+    fn flex(&self) -> Option<f64> {
+        match self.max {
+            TrackBreadth::Flex(flex) => Some(flex),
+            _ => None,
+        }
+    }
+}
+
+/// Resolves the sizes of the tracks in one axis from the contributions of
+/// the items in them, given the available size (if definite).
+/// See https://www.w3.org/TR/css-grid-1/#algo-track-sizing
+fn size_tracks(sizes: &[TrackSize], contributions: &[Contribution], available: Option<f64>, gap: f64, gaps: f64) -> Vec<f64> {
+    // Percentages of an indefinite size behave like auto
+    let normalize = |breadth: TrackBreadth| match (breadth, available) {
+        (TrackBreadth::Length(l), None) if l.percent != 0.0 => TrackBreadth::Auto,
+        _ => breadth,
+    };
+    let resolve = |breadth: TrackBreadth| match breadth {
+        TrackBreadth::Length(l) => Some(l.resolve(available.unwrap_or(0.0))),
+        _ => None,
+    };
+    let mut tracks: Vec<_> = sizes.iter()
+        .map(|size| {
+            let (min, max) = (normalize(size.min), normalize(size.max));
+            let base = resolve(min).unwrap_or(0.0);
+            let limit = match max {
+                TrackBreadth::Flex(_) => Some(base),
+                _ => resolve(max).map(|l| l.max(base)),
+            };
+            Track { min, max, base, limit }
+        })
+        .collect();
+    let crosses_flex: Vec<_> = contributions.iter()
+        .map(|c| tracks[c.tracks.clone()].iter().any(|t| t.flex().is_some()))
+        .collect();
+    let inflexible = || contributions.iter().zip(&crosses_flex).filter(|(_, f)| !**f).map(|(c, _)| c);
+    let flexible = || contributions.iter().zip(&crosses_flex).filter(|(_, f)| **f).map(|(c, _)| c);
+    let span_gaps = |c: &Contribution| gap * (c.tracks.len() - 1) as f64;
+
+    // Size the tracks to fit the items spanning a single one
+    for c in inflexible().filter(|c| c.tracks.len() == 1) {
+        let track = &mut tracks[c.tracks.start];
+        match track.min {
+            TrackBreadth::MinContent | TrackBreadth::Auto => track.base = track.base.max(c.min),
+            TrackBreadth::MaxContent => track.base = track.base.max(c.max),
+            _ => {},
+        }
+        match track.max {
+            TrackBreadth::MinContent => track.limit = Some(track.limit.unwrap_or(0.0).max(c.min)),
+            TrackBreadth::MaxContent | TrackBreadth::Auto => track.limit = Some(track.limit.unwrap_or(0.0).max(c.max)),
+            _ => {},
+        }
+    }
+
+    // Distribute the sizes of items spanning several tracks among the intrinsic ones
+    let mut spanning: Vec<_> = inflexible().filter(|c| c.tracks.len() > 1).collect();
+    spanning.sort_by_key(|c| c.tracks.len());
+    for c in spanning {
+        let spanned = &mut tracks[c.tracks.clone()];
+        let extra = c.min - spanned.iter().map(|t| t.base).sum::<f64>() - span_gaps(c);
+        let count = spanned.iter().filter(|t| t.min.is_intrinsic()).count();
+        if extra > 0.0 && count > 0 {
+            for track in spanned.iter_mut().filter(|t| t.min.is_intrinsic()) {
+                track.base += extra / count as f64;
+            }
+        }
+        let extra = c.max - spanned.iter().map(|t| t.limit.unwrap_or(t.base)).sum::<f64>() - span_gaps(c);
+        let count = spanned.iter().filter(|t| t.max.is_intrinsic()).count();
+        if extra > 0.0 && count > 0 {
+            for track in spanned.iter_mut().filter(|t| t.max.is_intrinsic()) {
+                track.limit = Some(track.limit.unwrap_or(track.base) + extra / count as f64);
+            }
+        }
+    }
+
+    // Items crossing flexible tracks only contribute their minimum size to them
+    for c in flexible() {
+        let spanned = &mut tracks[c.tracks.clone()];
+        let extra = c.min - spanned.iter().map(|t| t.base).sum::<f64>() - span_gaps(c);
+        let factors: f64 = spanned.iter().filter(|t| t.min.is_intrinsic()).filter_map(|t| t.flex()).sum();
+        let count = spanned.iter().filter(|t| t.min.is_intrinsic() && t.flex().is_some()).count();
+        if extra > 0.0 && count > 0 {
+            for track in spanned.iter_mut().filter(|t| t.min.is_intrinsic()) {
+                if let Some(flex) = track.flex() {
+                    track.base += extra * if factors > 0.0 { flex / factors } else { 1.0 / count as f64 };
+                }
+            }
+        }
+    }
+    for track in &mut tracks {
+        track.limit = Some(track.limit.unwrap_or(track.base).max(track.base));
+    }
+
+    // Grow the tracks up to their limits, which is all of them if the size is indefinite
+    let free = available.map_or(f64::INFINITY, |a| a - tracks.iter().map(|t| t.base).sum::<f64>() - gaps);
+    distribute_to_limits(&mut tracks, free);
+
+    // Expand the flexible tracks
+    if tracks.iter().any(|t| t.flex().is_some()) {
+        let fraction = match available {
+            Some(available) => {
+                let leftover = available - gaps - tracks.iter().filter(|t| t.flex().is_none()).map(|t| t.base).sum::<f64>();
+                find_fraction(&tracks, leftover)
+            },
+            None => {
+                let from_tracks = tracks.iter()
+                    .filter_map(|t| t.flex().map(|flex| if flex > 1.0 { t.base / flex } else { t.base }))
+                    .fold(0.0, f64::max);
+                flexible()
+                    .map(|c| {
+                        let spanned = &tracks[c.tracks.clone()];
+                        let fixed: f64 = spanned.iter().filter(|t| t.flex().is_none()).map(|t| t.base).sum();
+                        let factors: f64 = spanned.iter().filter_map(|t| t.flex()).sum();
+                        (c.max - fixed - span_gaps(c)) / factors.max(1.0)
+                    })
+                    .fold(from_tracks, f64::max)
+            },
+        };
+        for track in &mut tracks {
+            if let Some(flex) = track.flex() {
+                track.base = track.base.max(fraction * flex);
+            }
+        }
+    } else if let Some(available) = available {
+        // Stretch the tracks with an automatic maximum into the remaining space
+        let free = available - tracks.iter().map(|t| t.base).sum::<f64>() - gaps;
+        let count = tracks.iter().filter(|t| t.max == TrackBreadth::Auto).count();
+        if free > 0.0 && count > 0 {
+            for track in tracks.iter_mut().filter(|t| t.max == TrackBreadth::Auto) {
+                track.base += free / count as f64;
+            }
+        }
+    }
+
+    tracks.into_iter().map(|t| t.base).collect()
+}
+
+/// Distributes free space equally among the inflexible tracks, without
+/// growing them beyond their limits.
+fn distribute_to_limits(tracks: &mut [Track], mut free: f64) {
+    loop {
+        let growable = tracks.iter().filter(|t| t.flex().is_none() && t.limit.is_some_and(|l| l > t.base)).count();
+        if growable == 0 || free <= 0.0 {
+            break;
+        }
+        let share = free / growable as f64;
+        for track in tracks.iter_mut().filter(|t| t.flex().is_none()) {
+            let limit = track.limit.unwrap_or(track.base);
+            if limit > track.base {
+                let growth = share.min(limit - track.base);
+                track.base += growth;
+                free -= growth;
+            }
+        }
+    }
+}
+
+/// Finds the size of `1fr` given the space left for the flexible tracks,
+/// treating tracks whose base size exceeds their share as inflexible.
+/// See https://www.w3.org/TR/css-grid-1/#algo-find-fr-size
+fn find_fraction(tracks: &[Track], leftover: f64) -> f64 {
+    let mut inflexible = vec![false; tracks.len()];
+    loop {
+        let space = leftover - tracks.iter().zip(&inflexible).filter(|(_, i)| **i).map(|(t, _)| t.base).sum::<f64>();
+        let factors: f64 = tracks.iter().zip(&inflexible).filter(|(_, i)| !**i).filter_map(|(t, _)| t.flex()).sum();
+        let fraction = space.max(0.0) / factors.max(1.0);
+        let mut changed = false;
+        for (track, inflexible) in tracks.iter().zip(&mut inflexible) {
+            if let Some(flex) = track.flex().filter(|_| !*inflexible) {
+                if fraction * flex < track.base {
+                    *inflexible = true;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return fraction;
+        }
+    }
+}
+
+impl<'a, T> Layouter<'a, T> where T: Text {
+    /// Lays out a grid container with the given edges and content width (and
+    /// height, if specified), placing its children in the cells of the grid.
+    /// See https://www.w3.org/TR/css-grid-1/#layout-algorithm
+    pub(super) fn layout_grid(&mut self, container: &LayoutBox, edges: &BoxEdges, width: f64, height: Option<f64>) -> BlockLayout<T::TextLayout> {
+        let style = &container.style;
+        let grid = Grid::place(container, Some(width), height);
+        let column_gap = style.column_gap.resolve(width);
+        let row_gap = style.row_gap.resolve(height.unwrap_or(0.0));
+
+        // Size the columns, then lay out the items in them to size the rows
+        let contributions: Vec<_> = grid.items.iter()
+            .map(|item| {
+                let widths = self.intrinsic_widths(item.item);
+                Contribution { tracks: item.columns.clone(), min: widths.min, max: widths.max }
+            })
+            .collect();
+        let columns = size_tracks(&grid.columns.sizes, &contributions, Some(width), column_gap, grid.columns.gaps(column_gap));
+        let column_offsets = grid.columns.offsets(&columns, column_gap);
+        let span = |offsets: &[f64], sizes: &[f64], range: &Range<usize>| match range.len() {
+            0 => 0.0,
+            _ => offsets[range.end - 1] + sizes[range.end - 1] - offsets[range.start],
+        };
+        let mut laid_out = Vec::new();
+        for item in &grid.items {
+            let area_width = span(&column_offsets, &columns, &item.columns);
+            laid_out.push(self.layout_grid_item(item.item, area_width, width));
+        }
+        let contributions: Vec<_> = grid.items.iter().zip(&laid_out)
+            .map(|(item, (fragment, margin))| {
+                let height = fragment.rect.height() + margin.top.unwrap_or(0.0) + margin.bottom.unwrap_or(0.0);
+                Contribution { tracks: item.rows.clone(), min: height, max: height }
+            })
+            .collect();
+        let rows = size_tracks(&grid.rows.sizes, &contributions, height, row_gap, grid.rows.gaps(row_gap));
+        let row_offsets = grid.rows.offsets(&rows, row_gap);
+
+        // Align the items in their areas
+        let inner = edges.inner();
+        let mut children = Vec::new();
+        for (item, (mut fragment, margin)) in grid.items.iter().zip(laid_out) {
+            let area = Rect::from_origin_size(
+                Point::new(column_offsets.get(item.columns.start).copied().unwrap_or(0.0), row_offsets.get(item.rows.start).copied().unwrap_or(0.0)),
+                Size::new(span(&column_offsets, &columns, &item.columns), span(&row_offsets, &rows, &item.rows)),
+            );
+            let item_style = &item.item.style;
+            let x = area.x0 + match (margin.left, margin.right) {
+                (None, None) => (area.width() - fragment.rect.width()).max(0.0) / 2.0,
+                (None, Some(right)) => (area.width() - fragment.rect.width() - right).max(0.0),
+                (Some(left), _) => left,
+            };
+            let free = area.height() - fragment.rect.height() - margin.top.unwrap_or(0.0) - margin.bottom.unwrap_or(0.0);
+            let offset = match (margin.top, margin.bottom) {
+                (None, None) => free.max(0.0) / 2.0,
+                (None, Some(_)) => free.max(0.0),
+                (Some(_), None) => 0.0,
+                (Some(_), Some(_)) => match item_style.align_self.unwrap_or(style.align_items) {
+                    AlignItems::Stretch => {
+                        if item_style.height == LengthOrAuto::Auto && free > 0.0 {
+                            fragment.rect.y1 += free;
+                        }
+                        0.0
+                    },
+                    AlignItems::FlexStart | AlignItems::Baseline => 0.0,
+                    AlignItems::FlexEnd => free,
+                    AlignItems::Center => free / 2.0,
+                },
+            };
+            let y = area.y0 + margin.top.unwrap_or(0.0) + offset;
+            fragment.translate(Vec2::new(inner.left + x, inner.top + y));
+            children.push(fragment);
+        }
+        children.extend(self.layout_absolute_children(container, Point::new(inner.left, inner.top)));
+
+        let content_height = height.unwrap_or_else(|| rows.iter().sum::<f64>() + grid.rows.gaps(row_gap));
+        let margin = edges.margin;
+        BlockLayout {
+            fragment: Fragment {
+                rect: Rect::from_origin_size(Point::ZERO, Size::new(width + inner.horizontal(), content_height + inner.vertical())),
+                style: style.clone(),
+                content: FragmentContent::Box,
+                border: edges.border,
+                href: container.href().map(|href| href.to_owned()),
+                children,
+            },
+            margin_left: margin.left.unwrap_or(0.0),
+            margin_top: CollapsedMargin::new(margin.top.unwrap_or(0.0)),
+            margin_bottom: CollapsedMargin::new(margin.bottom.unwrap_or(0.0)),
+            collapses_through: false,
+        }
+    }
+
+    /// Lays out a grid item in an area of the given width, stretching it to
+    /// fill the area unless it has a specified width or `auto` margins.
+    /// Returns the fragment and the margins (`None` if `auto`).
+    fn layout_grid_item(&mut self, item: &LayoutBox, area_width: f64, container_width: f64) -> (Fragment<T::TextLayout>, crate::web::Sides<Option<f64>>) {
+        let style: &ComputedStyle = &item.style;
+        let mut edges = BoxEdges::of(style, container_width);
+        let margin = edges.margin;
+        edges.margin = margin.map(|m| Some(m.unwrap_or(0.0)));
+        let inner = edges.inner();
+        let available = area_width - margin.left.unwrap_or(0.0) - margin.right.unwrap_or(0.0) - inner.horizontal();
+        let width = match style.width.resolve(area_width) {
+            Some(width) => content_size(style, width, inner.horizontal()),
+            None if margin.left.is_some() && margin.right.is_some() => available,
+            None => self.shrink_to_fit_width(item, available),
+        }.max(0.0);
+        let height = style.height.resolve_definite(None).map(|h| content_size(style, h, inner.vertical()));
+        let fragment = self.layout_sized_block(item, &edges, width, height, true).fragment;
+        (fragment, margin)
+    }
+
+    /// Computes the intrinsic widths of the content box of a grid container.
+    pub(super) fn grid_intrinsic_widths(&mut self, container: &LayoutBox) -> IntrinsicWidths {
+        let grid = Grid::place(container, None, None);
+        let gap = container.style.column_gap.resolve(0.0);
+        let gaps = grid.columns.gaps(gap);
+        let contributions: Vec<_> = grid.items.iter()
+            .map(|item| {
+                let widths = self.intrinsic_widths(item.item);
+                Contribution { tracks: item.columns.clone(), min: widths.min, max: widths.max }
+            })
+            .collect();
+        let max = size_tracks(&grid.columns.sizes, &contributions, None, gap, gaps).iter().sum::<f64>() + gaps;
+        let min_contributions: Vec<_> = contributions.into_iter()
+            .map(|c| Contribution { max: c.min, ..c })
+            .collect();
+        let min = size_tracks(&grid.columns.sizes, &min_contributions, None, gap, gaps).iter().sum::<f64>() + gaps;
+        IntrinsicWidths { min, max }
+    }
+}
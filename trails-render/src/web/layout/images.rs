@@ -0,0 +1,246 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use piet::{kurbo::{Point, Rect, Size}, Text};
+use trails_base::log::{debug, warn};
+use trails_model::dom::{Document, Element};
+use url::Url;
+
+use crate::web::{resolve_url, svg_markup, BackgroundImage, Sides};
+
+use super::{content_size, BlockLayout, BoxEdges, CollapsedMargin, Fragment, FragmentContent, LayoutBox, Layouter};
+
+/// A decoded image.
+pub enum ImageData {
+    /// A bitmap image.
+    Raster(RasterImage),
+    /// A vector (i.e. SVG) image, which is drawn at the size it is displayed at.
+    Vector(Box<usvg::Tree>),
+}
+
+impl ImageData {
+    /// Decodes an image from the given data, guessing its format (PNG, JPEG,
+    /// GIF, WebP, ICO or SVG). Animated images are decoded to their first frame.
+    fn decode(data: &[u8]) -> Option<Self> {
+        if image::guess_format(data).is_err() {
+            return match usvg::Tree::from_data(data, &usvg::Options::default()) {
+                Ok(tree) => Some(Self::Vector(Box::new(tree))),
+                Err(e) => {
+                    warn!("Could not decode image: {}", e);
+                    None
+                },
+            };
+        }
+        match image::load_from_memory(data) {
+            Ok(image) => {
+                let image = image.into_rgba8();
+                Some(Self::Raster(RasterImage {
+                    width: image.width() as usize,
+                    height: image.height() as usize,
+                    pixels: image.into_raw(),
+                }))
+            },
+            Err(e) => {
+                warn!("Could not decode image: {}", e);
+                None
+            },
+        }
+    }
+
+    /// The intrinsic size in pixels.
+    pub fn size(&self) -> Size {
+        match self {
+            Self::Raster(image) => Size::new(image.width as f64, image.height as f64),
+            Self::Vector(tree) => Size::new(tree.size().width() as f64, tree.size().height() as f64),
+        }
+    }
+}
+
+impl fmt::Debug for ImageData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Raster(image) => f.debug_tuple("Raster").field(image).finish(),
+            Self::Vector(_) => f.debug_tuple("Vector").field(&self.size()).finish(),
+        }
+    }
+}
+
+/// A decoded bitmap image.
+#[derive(Debug)]
+pub struct RasterImage {
+    pub width: usize,
+    pub height: usize,
+    /// The pixels row by row, as RGBA with separate (i.e. not premultiplied) alpha.
+    pub pixels: Vec<u8>,
+}
+
+/// The decoded images of the most recently laid out document, which are
+/// kept across layouts since decoding is expensive.
+#[derive(Default)]
+pub struct ImageCache {
+    /// The decoded images by a hash of their data, `None` if the data couldn't be decoded.
+    decoded: HashMap<u64, Option<Arc<ImageData>>>,
+}
+
+impl ImageCache {
+    /// Creates a new (empty) image cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the given image data (unless already cached), marking it as
+    /// used by adding its key to the given set.
+    fn decode(&mut self, data: &[u8], used: &mut HashSet<u64>) -> Option<Arc<ImageData>> {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let key = hasher.finish();
+        used.insert(key);
+        self.decoded.entry(key)
+            .or_insert_with(|| ImageData::decode(data).map(Arc::new))
+            .clone()
+    }
+}
+
+/// The loaded images of a document.
+#[derive(Default)]
+pub(super) struct Images {
+    /// The decoded images by the element (`<img>` or `<svg>`) displaying them.
+    by_element: HashMap<*const Element, Arc<ImageData>>,
+    /// The decoded background images by their (absolute) URL.
+    pub(super) backgrounds: HashMap<String, Arc<ImageData>>,
+}
+
+impl Images {
+    /// Decodes the images of a document (unless already cached), i.e. those
+    /// referenced by its `<img>` elements and the backgrounds of its boxes
+    /// (from its subresources) and its inline `<svg>` elements. Images that
+    /// haven't been loaded (yet) or can't be decoded are left out. The cache
+    /// only keeps the images of this document afterwards.
+    pub(super) fn load(&mut self, document: &Document, root_box: Option<&LayoutBox>, cache: &mut ImageCache) {
+        let mut used = HashSet::new();
+        let document_url = document.url().and_then(|url| Url::parse(url).ok());
+        for element in document.root().descendants() {
+            let data = match element.tag_name() {
+                "img" => {
+                    let Some(src) = element.attribute("src") else {
+                        continue;
+                    };
+                    let Some(data) = resolve_url(document_url.as_ref(), src).and_then(|url| document.resource(url.as_str())) else {
+                        debug!("Image {} is not loaded", src);
+                        continue;
+                    };
+                    Cow::Borrowed(data)
+                },
+                "svg" => Cow::Owned(svg_markup(element, document).into_bytes()),
+                _ => continue,
+            };
+            if let Some(image) = cache.decode(&data, &mut used) {
+                self.by_element.insert(element, image);
+            }
+        }
+        let mut urls = HashSet::new();
+        if let Some(root_box) = root_box {
+            collect_background_urls(root_box, &mut urls);
+        }
+        for url in urls {
+            let Some(data) = document.resource(url) else {
+                debug!("Background image {} is not loaded", url);
+                continue;
+            };
+            if let Some(image) = cache.decode(data, &mut used) {
+                self.backgrounds.insert(url.to_owned(), image);
+            }
+        }
+        cache.decoded.retain(|key, _| used.contains(key));
+    }
+
+    /// The image of a replaced box, if it has been loaded.
+    pub(super) fn get(&self, layout_box: &LayoutBox) -> Option<&Arc<ImageData>> {
+        let element = layout_box.element.filter(|_| layout_box.is_replaced())?;
+        self.by_element.get(&(element as *const Element))
+    }
+}
+
+impl<'a, T> Layouter<'a, T> where T: Text {
+    /// The used size of the content box of a replaced box (i.e. a loaded
+    /// image) in a containing block of the given width and (if definite)
+    /// height. A missing `width` or `height` is derived from the other one
+    /// using the aspect ratio of the image, or else taken from its intrinsic
+    /// size. Returns `None` if the box isn't a loaded image.
+    /// See https://www.w3.org/TR/CSS2/visudet.html#inline-replaced-width
+    pub(super) fn replaced_size(&self, layout_box: &LayoutBox, containing_width: f64, containing_height: Option<f64>) -> Option<Size> {
+        let intrinsic = self.images.get(layout_box)?.size();
+        let style = &layout_box.style;
+        let inner = BoxEdges::of(style, containing_width).inner();
+        // Percentages are treated as auto while computing max-content widths
+        let width = style.width.resolve_definite(containing_width.is_finite().then_some(containing_width))
+            .map(|w| content_size(style, w, inner.horizontal()));
+        let height = style.height.resolve_definite(containing_height)
+            .map(|h| content_size(style, h, inner.vertical()));
+        Some(match (width, height) {
+            (Some(width), Some(height)) => Size::new(width, height),
+            (Some(width), None) => Size::new(width, height_for_width(intrinsic, width)),
+            (None, Some(height)) if intrinsic.height > 0.0 => Size::new(height * intrinsic.width / intrinsic.height, height),
+            (None, Some(height)) => Size::new(intrinsic.width, height),
+            (None, None) => intrinsic,
+        })
+    }
+
+    /// Lays out a replaced box (i.e. a loaded image) with the given edges
+    /// and content width (and height, if specified), deriving the height
+    /// from the aspect ratio of the image otherwise. The image fills the
+    /// content box.
+    pub(super) fn layout_image(&mut self, layout_box: &LayoutBox, image: Arc<ImageData>, edges: &BoxEdges, width: f64, height: Option<f64>) -> BlockLayout<T::TextLayout> {
+        let height = height.unwrap_or_else(|| height_for_width(image.size(), width));
+        let inner = edges.inner();
+        let margin = edges.margin;
+        let content = Fragment {
+            rect: Rect::from_origin_size(Point::new(inner.left, inner.top), Size::new(width, height)),
+            style: layout_box.style.clone(),
+            content: FragmentContent::Image(image),
+            border: Sides::default(),
+            href: None,
+            children: Vec::new(),
+        };
+        BlockLayout {
+            fragment: Fragment {
+                rect: Rect::from_origin_size(Point::ZERO, Size::new(width + inner.horizontal(), height + inner.vertical())),
+                style: layout_box.style.clone(),
+                content: FragmentContent::Box,
+                border: edges.border,
+                href: None,
+                children: vec![content],
+            },
+            margin_left: margin.left.unwrap_or(0.0),
+            margin_top: CollapsedMargin::new(margin.top.unwrap_or(0.0)),
+            margin_bottom: CollapsedMargin::new(margin.bottom.unwrap_or(0.0)),
+            collapses_through: false,
+        }
+    }
+}
+
+/// Collects the URLs of the background images of a box and its descendants.
+fn collect_background_urls<'b>(layout_box: &'b LayoutBox, urls: &mut HashSet<&'b str>) {
+    for image in layout_box.style.background_image.iter().flatten() {
+        if let BackgroundImage::Url(url) = image {
+            urls.insert(url);
+        }
+    }
+    for child in &layout_box.children {
+        collect_background_urls(child, urls);
+    }
+}
+
+/// The height of an image with the given intrinsic size when scaled to
+/// the given width, preserving its aspect ratio.
+fn height_for_width(intrinsic: Size, width: f64) -> f64 {
+    if intrinsic.width > 0.0 {
+        width * intrinsic.height / intrinsic.width
+    } else {
+        intrinsic.height
+    }
+}
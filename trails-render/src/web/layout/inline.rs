@@ -0,0 +1,815 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use piet::{kurbo::{Point, Rect, Size, Vec2}, Text, TextLayout, TextLayoutBuilder};
+use trails_base::log::warn;
+use xi_unicode::LineBreakIterator;
+
+use crate::web::{to_piet_color, ComputedStyle, Sides, TextAlign};
+
+use super::{level_runs, upright_baseline, BidiText, BoxEdges, BoxKind, FloatLayout, Fragment, FragmentContent, LayoutBox, Layouter};
+
+/// An item in the flattened inline content of a block container.
+enum InlineItem<'b, 'd> {
+    /// The start of an inline box.
+    Start(&'b LayoutBox<'d>),
+    /// The end of the most recently started inline box.
+    End,
+    /// A run of text with the given style, with whitespace already processed.
+    Text(String, &'b Arc<ComputedStyle>),
+    /// A floated box.
+    Float(&'b LayoutBox<'d>),
+    /// An absolutely positioned box.
+    Absolute(&'b LayoutBox<'d>),
+    /// An atomic inline-level box, e.g. an image.
+    Atomic(&'b LayoutBox<'d>),
+    /// A forced line break with the given style.
+    LineBreak(&'b Arc<ComputedStyle>),
+}
+
+/// A piece of text placed on a line.
+struct PlacedText<'b, L> {
+    /// The horizontal offset from the start of the line.
+    x: f64,
+    /// The text (node) this piece is part of.
+    source: &'b str,
+    /// The range of the piece in the source text.
+    range: Range<usize>,
+    layout: L,
+    /// The size, excluding trailing whitespace.
+    size: Size,
+    /// The distance from the top of the text to its baseline.
+    baseline: f64,
+    /// The bidi embedding level, which is odd for right-to-left text.
+    level: u8,
+    /// Whether the text is upright in a vertical line, in which case its
+    /// size is rotated.
+    upright: bool,
+    style: Arc<ComputedStyle>,
+}
+
+/// A laid out atomic inline-level box placed on a line.
+struct PlacedAtomic<L> {
+    /// The horizontal offset (of its margin edge) from the start of the line.
+    x: f64,
+    /// The fragment, with its border box positioned at the origin.
+    fragment: Fragment<L>,
+    margin: Sides<f64>,
+    /// The bidi embedding level.
+    level: u8,
+}
+
+impl<L> PlacedAtomic<L> {
+    /// The size of the margin box.
+    fn size(&self) -> Size {
+        let size = self.fragment.rect.size();
+        Size::new(size.width + self.margin.horizontal(), size.height + self.margin.vertical())
+    }
+}
+
+impl<L> PlacedAtomic<L> where L: TextLayout {
+    /// The distance from the top of the margin box to the baseline, i.e.
+    /// that of its first line of text or else its bottom margin edge.
+    fn baseline(&self) -> f64 {
+        self.fragment.first_baseline()
+            .map(|baseline| baseline - self.fragment.rect.y0 + self.margin.top)
+            .unwrap_or(self.size().height)
+    }
+}
+
+/// A piece of a text segment, i.e. the whole segment or a single character
+/// if the characters are spaced apart or upright in a vertical line.
+struct SegmentPiece<L> {
+    /// The horizontal offset from the start of the segment.
+    x: f64,
+    /// The range of the piece in the text item.
+    range: Range<usize>,
+    /// The bidi embedding level.
+    level: u8,
+    /// Whether the piece is upright in a vertical line.
+    upright: bool,
+    layout: L,
+}
+
+impl<L> SegmentPiece<L> where L: TextLayout {
+    /// The size of the piece along the line, which is rotated if it is upright.
+    fn size(&self) -> Size {
+        let size = self.layout.size();
+        if self.upright { Size::new(size.height, size.width) } else { size }
+    }
+}
+
+/// An item placed on a line.
+enum LineItem<'b, 'd, L> {
+    /// The start of an inline box at the given offset (of its border edge),
+    /// which is not the first edge of the box if it continues from a previous line.
+    Start { layout_box: &'b LayoutBox<'d>, x: f64, first: bool },
+    /// The end of an inline box at the given offset (of its border edge).
+    End { x: f64 },
+    Text(PlacedText<'b, L>),
+    Atomic(PlacedAtomic<L>),
+}
+
+/// A line box that is being filled.
+struct Line<'b, 'd, L> {
+    items: Vec<LineItem<'b, 'd, L>>,
+    /// The offset of the line from the start of the containing block, e.g. to make room for floats.
+    x: f64,
+    /// The width available for content on the line.
+    available: f64,
+    /// The width of the content placed on the line so far.
+    width: f64,
+    /// Whether any text other than collapsible whitespace has been placed on the line.
+    has_content: bool,
+    /// Whether the line may be broken after the content placed so far.
+    can_break: bool,
+    /// Whether the line ends at a forced break or the end of the content,
+    /// i.e. isn't stretched when justifying.
+    last: bool,
+    /// The alignment of the content on the line.
+    align: TextAlign,
+    /// The bidi level of the paragraph, which is odd if it is right-to-left.
+    base_level: u8,
+    /// The floats that didn't fit on the line, which are placed below it.
+    floats_below: Vec<FloatLayout<L>>,
+}
+
+impl<'b, 'd, L> Line<'b, 'd, L> {
+    /// Creates a line with the given width, alignment and paragraph level
+    /// that continues the given (still open) inline boxes.
+    fn continuing(open: &[&'b LayoutBox<'d>], available: f64, align: TextAlign, base_level: u8) -> Self {
+        Self {
+            items: open.iter().map(|&layout_box| LineItem::Start { layout_box, x: 0.0, first: false }).collect(),
+            x: 0.0,
+            available,
+            width: 0.0,
+            has_content: false,
+            can_break: false,
+            last: false,
+            align,
+            base_level,
+            floats_below: Vec::new(),
+        }
+    }
+}
+
+/// An inline box whose fragment on the current line is being built.
+struct InlineFrame<'b, 'd, L> {
+    layout_box: &'b LayoutBox<'d>,
+    /// The horizontal offset at which the box starts on the line.
+    x: f64,
+    /// Whether the box starts on this line.
+    first: bool,
+    children: Vec<Fragment<L>>,
+}
+
+impl<'a, T> Layouter<'a, T> where T: Text {
+    /// Lays out the inline-level children of a block container in lines
+    /// starting at the given position with the given width, shortening the
+    /// lines next to floats. Returns the fragments and the total height
+    /// (excluding floats).
+    pub(super) fn layout_inline_content(&mut self, block: &LayoutBox, origin: Point, width: f64) -> (Vec<Fragment<T::TextLayout>>, f64) {
+        let mut items = Vec::new();
+        flatten_inline_items(&block.children, &mut items);
+        process_whitespace(&mut items);
+        let (base_level, levels) = resolve_bidi_levels(&items, &block.style);
+
+        let mut fragments = Vec::new();
+        let mut y = origin.y;
+        let mut open: Vec<&LayoutBox> = Vec::new();
+        let mut line = Line::continuing(&open, width, block.style.text_align, base_level);
+        // Percentages are treated as zero while computing max-content widths
+        line.width = block.style.text_indent.resolve(if width.is_finite() { width } else { 0.0 });
+        y = self.fit_line(&mut line, y, Size::ZERO, origin.x, width);
+
+        for (item, levels) in items.iter().zip(&levels) {
+            match *item {
+                InlineItem::Start(layout_box) => {
+                    let edges = BoxEdges::of(&layout_box.style, width);
+                    line.width += edges.margin.left.unwrap_or(0.0);
+                    line.items.push(LineItem::Start { layout_box, x: line.width, first: true });
+                    line.width += edges.border.left + edges.padding.left;
+                    open.push(layout_box);
+                },
+                InlineItem::End => {
+                    let layout_box = open.pop().expect("Unbalanced inline boxes");
+                    let edges = BoxEdges::of(&layout_box.style, width);
+                    line.width += edges.padding.right + edges.border.right;
+                    line.items.push(LineItem::End { x: line.width });
+                    line.width += edges.margin.right.unwrap_or(0.0);
+                },
+                InlineItem::Text(ref text, style) => {
+                    let mut start = 0;
+                    for (end, _) in LineBreakIterator::new(text) {
+                        // Preserved newlines force a line break
+                        let forced = text[start..end].ends_with('\n');
+                        let content_end = if forced { end - 1 } else { end };
+                        let segment = &text[start..content_end];
+                        let (pieces, advance) = self.layout_segment(text, start..content_end, style, levels);
+                        let Some(last) = pieces.last() else {
+                            start = end;
+                            continue;
+                        };
+                        let size = Size::new(
+                            last.x + last.size().width,
+                            pieces.iter().map(|p| p.size().height).fold(0.0, f64::max),
+                        );
+                        // Break the line before this segment if it would overflow the container,
+                        // trailing whitespace may hang over the edge
+                        if line.has_content && line.can_break && line.width + size.width > line.available {
+                            y = self.next_line(&mut line, &open, y, origin, width, &mut fragments);
+                        }
+                        if !line.has_content {
+                            y = self.fit_line(&mut line, y, size, origin.x, width);
+                        }
+                        for piece in pieces {
+                            let size = piece.size();
+                            let SegmentPiece { x, range, level, upright, layout } = piece;
+                            let baseline = if upright {
+                                upright_baseline(size.height, style.font_size)
+                            } else {
+                                layout.line_metric(0).map(|m| m.baseline).unwrap_or(size.height)
+                            };
+                            line.items.push(LineItem::Text(PlacedText {
+                                x: line.width + x,
+                                source: text,
+                                range,
+                                layout,
+                                size,
+                                baseline,
+                                level,
+                                upright,
+                                style: style.clone(),
+                            }));
+                        }
+                        line.width += advance;
+                        line.has_content |= forced || !(style.white_space.collapses_spaces() && segment.trim().is_empty());
+                        // The end of the text is only a break opportunity if followed by whitespace
+                        line.can_break = style.white_space.wraps() && (end < text.len() || text.ends_with(char::is_whitespace));
+                        if forced {
+                            line.last = true;
+                            y = self.next_line(&mut line, &open, y, origin, width, &mut fragments);
+                        }
+                        start = end;
+                    }
+                },
+                InlineItem::Atomic(layout_box) => {
+                    let (fragment, margin) = self.layout_shrink_to_fit(layout_box, width);
+                    let level = levels.first().copied().unwrap_or(0);
+                    let atomic = PlacedAtomic { x: 0.0, fragment, margin, level };
+                    let size = atomic.size();
+                    if line.has_content && line.can_break && line.width + size.width > line.available {
+                        y = self.next_line(&mut line, &open, y, origin, width, &mut fragments);
+                    }
+                    if !line.has_content {
+                        y = self.fit_line(&mut line, y, size, origin.x, width);
+                    }
+                    line.items.push(LineItem::Atomic(PlacedAtomic { x: line.width, ..atomic }));
+                    line.width += size.width;
+                    line.has_content = true;
+                    // Lines may be broken before and after atomic inlines
+                    line.can_break = layout_box.style.white_space.wraps();
+                },
+                InlineItem::LineBreak(style) => {
+                    // The break is placed as empty text, so that the line is
+                    // at least as tall as an empty line of text in its style
+                    let Some(layout) = self.text_layout("", style) else {
+                        continue;
+                    };
+                    let size = Size::new(0.0, layout.size().height);
+                    if !line.has_content {
+                        y = self.fit_line(&mut line, y, size, origin.x, width);
+                    }
+                    let baseline = layout.line_metric(0).map(|m| m.baseline).unwrap_or(size.height);
+                    line.items.push(LineItem::Text(PlacedText {
+                        x: line.width,
+                        source: "",
+                        range: 0..0,
+                        layout,
+                        size,
+                        baseline,
+                        level: line.base_level,
+                        upright: false,
+                        style: style.clone(),
+                    }));
+                    line.has_content = true;
+                    line.last = true;
+                    y = self.next_line(&mut line, &open, y, origin, width, &mut fragments);
+                },
+                InlineItem::Float(float) => {
+                    // Floats are placed next to the current line if they fit, otherwise below it
+                    let float = self.layout_float(float, width);
+                    if !line.has_content || line.width + float.size().width <= line.available {
+                        fragments.push(self.place_float(float, y, origin.x, origin.x + width));
+                        y = self.fit_line(&mut line, y, Size::ZERO, origin.x, width);
+                    } else {
+                        line.floats_below.push(float);
+                    }
+                },
+                InlineItem::Absolute(layout_box) => {
+                    // Absolutely positioned boxes start at their static position, i.e. where they would be in the flow
+                    let mut fragment = self.layout_absolute(layout_box);
+                    let x = if line.has_content { line.x + line.width } else { line.x };
+                    fragment.translate(Vec2::new(origin.x + x, y));
+                    fragments.push(fragment);
+                },
+            }
+        }
+        line.last = true;
+        y = self.next_line(&mut line, &open, y, origin, width, &mut fragments);
+
+        (fragments, y - origin.y)
+    }
+
+    /// Finishes the current line at the given position and starts the next
+    /// one below it, placing the floats that didn't fit on the finished line
+    /// first. Returns the position of the next line.
+    fn next_line<'b, 'd>(&mut self, line: &mut Line<'b, 'd, T::TextLayout>, open: &[&'b LayoutBox<'d>], y: f64, origin: Point, width: f64, fragments: &mut Vec<Fragment<T::TextLayout>>) -> f64 {
+        let mut full_line = std::mem::replace(line, Line::continuing(open, width, line.align, line.base_level));
+        let floats = std::mem::take(&mut full_line.floats_below);
+        let x = origin.x + full_line.x;
+        let y = y + self.finish_line(full_line, Point::new(x, y), width, fragments);
+        for float in floats {
+            fragments.push(self.place_float(float, y, origin.x, origin.x + width));
+        }
+        self.fit_line(line, y, Size::ZERO, origin.x, width)
+    }
+
+    /// Fits a line between the floats at the given position in a containing
+    /// block starting at the given offset with the given width, moving it
+    /// down below floats if its content and the given additional content
+    /// don't fit. Returns the position of the line.
+    fn fit_line<L>(&self, line: &mut Line<L>, mut y: f64, size: Size, x: f64, width: f64) -> f64 {
+        if self.floats.is_empty() {
+            return y;
+        }
+        loop {
+            let (left, right) = self.floats.available(y, y + size.height, x, x + width);
+            match self.floats.next_bottom(y, y + size.height) {
+                Some(bottom) if right - left < line.width + size.width => y = bottom,
+                _ => {
+                    line.x = left - x;
+                    line.available = right - left;
+                    return y;
+                },
+            }
+        }
+    }
+
+    /// Merges adjacent pieces of the same text on a line into single runs
+    /// (unless they are spaced apart), then creates the fragments for the
+    /// line. Returns the height of the line.
+    fn finish_line<'b, 'd>(&mut self, line: Line<'b, 'd, T::TextLayout>, origin: Point, containing_width: f64, fragments: &mut Vec<Fragment<T::TextLayout>>) -> f64 {
+        let mut items: Vec<LineItem<T::TextLayout>> = Vec::with_capacity(line.items.len());
+        let mut merged = Vec::new();
+        // Lines containing only collapsible whitespace are not rendered
+        let has_content = line.has_content;
+        let justified = line.align == TextAlign::Justify && !line.last;
+        for item in line.items.into_iter().filter(|item| has_content || !matches!(item, LineItem::Text(_))) {
+            match (items.last_mut(), item) {
+                (Some(LineItem::Text(run)), LineItem::Text(text)) if std::ptr::eq(run.source, text.source) && run.range.end == text.range.start
+                    && run.level == text.level && !run.upright && !text.upright && !justified && run.style.letter_spacing == 0.0 => {
+                    run.range.end = text.range.end;
+                    merged.push(items.len() - 1);
+                },
+                (_, item) => items.push(item),
+            }
+        }
+        merged.dedup();
+        for i in merged {
+            if let LineItem::Text(run) = &mut items[i] {
+                if let Some(layout) = self.text_layout(&run.source[run.range.clone()], &run.style) {
+                    run.size = layout.size();
+                    run.layout = layout;
+                }
+            }
+        }
+        finish_line(Line { items, ..line }, origin, containing_width, fragments)
+    }
+
+    /// Lays out a segment of text, i.e. the given range of a text item
+    /// with the given bidi levels (per byte), splitting it into runs at the
+    /// same level and into its characters if they are spaced apart or
+    /// upright in a vertical line. Returns the pieces (in logical order)
+    /// along with the advance of the whole segment.
+    fn layout_segment(&mut self, text: &str, range: Range<usize>, style: &ComputedStyle, levels: &[u8]) -> (Vec<SegmentPiece<T::TextLayout>>, f64) {
+        let vertical = style.writing_mode.is_vertical();
+        let spaced = style.letter_spacing != 0.0;
+        let ranges: Vec<_> = level_runs(range, levels).into_iter()
+            .flat_map(|(range, level)| orientation_runs(text, range, vertical, spaced).into_iter()
+                .map(move |(range, upright)| (range, level, upright)))
+            .collect();
+        let mut pieces = Vec::with_capacity(ranges.len());
+        let mut advance = 0.0;
+        for (range, level, upright) in ranges {
+            if let Some(layout) = self.text_layout(&text[range.clone()], style) {
+                let x = advance;
+                // Upright characters advance by their height
+                advance += if upright { layout.size().height } else { layout.trailing_whitespace_width() } + style.letter_spacing;
+                pieces.push(SegmentPiece { x, range, level, upright, layout });
+            }
+        }
+        (pieces, advance)
+    }
+
+    /// Lays out a run of text with the given style.
+    pub(super) fn text_layout(&mut self, text: &str, style: &ComputedStyle) -> Option<T::TextLayout> {
+        let result = self.text.new_text_layout(text.to_owned())
+            .font(self.fonts.resolve(&style.font_family, self.text), style.font_size)
+            .default_attribute(style.font_weight)
+            .default_attribute(style.font_style)
+            .text_color(to_piet_color(style.color))
+            .build();
+        match result {
+            Ok(layout) => Some(layout),
+            Err(e) => {
+                warn!("Could not lay out text '{}': {}", text, e);
+                None
+            },
+        }
+    }
+}
+
+/// Flattens the given inline-level boxes into a sequence of items.
+fn flatten_inline_items<'b, 'd>(boxes: &'b [LayoutBox<'d>], items: &mut Vec<InlineItem<'b, 'd>>) {
+    for layout_box in boxes {
+        match &layout_box.kind {
+            BoxKind::Text(text) => {
+                let text = layout_box.style.text_transform.apply(text).into_owned();
+                items.push(InlineItem::Text(text, &layout_box.style));
+            },
+            BoxKind::Inline => {
+                items.push(InlineItem::Start(layout_box));
+                flatten_inline_items(&layout_box.children, items);
+                items.push(InlineItem::End);
+            },
+            BoxKind::Float => items.push(InlineItem::Float(layout_box)),
+            BoxKind::Absolute => items.push(InlineItem::Absolute(layout_box)),
+            BoxKind::Atomic => items.push(InlineItem::Atomic(layout_box)),
+            BoxKind::LineBreak => items.push(InlineItem::LineBreak(&layout_box.style)),
+            BoxKind::Block => unreachable!("Block-level boxes are never laid out inline"),
+        }
+    }
+}
+
+/// Splits the given range of a text into runs of sideways and upright text
+/// in a vertical line (or horizontal text otherwise), with each upright
+/// character (and each character if they are spaced apart) in its own run.
+fn orientation_runs(text: &str, range: Range<usize>, vertical: bool, spaced: bool) -> Vec<(Range<usize>, bool)> {
+    if range.is_empty() || !(vertical || spaced) {
+        return vec![(range, false)];
+    }
+    let mut runs: Vec<(Range<usize>, bool)> = Vec::new();
+    for (i, c) in text[range.clone()].char_indices() {
+        let char_range = range.start + i..range.start + i + c.len_utf8();
+        let upright = vertical && is_upright(c);
+        match runs.last_mut() {
+            Some((run, false)) if !upright && !spaced => run.end = char_range.end,
+            _ => runs.push((char_range, upright)),
+        }
+    }
+    runs
+}
+
+/// Whether a character is set upright in vertical lines, which approximates
+/// the Unicode property `Vertical_Orientation` with the blocks of the East
+/// Asian scripts, symbols and fullwidth forms.
+/// See https://www.unicode.org/reports/tr50/
+fn is_upright(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11FF}' // Hangul Jamo
+        | '\u{2E80}'..='\u{A4CF}' // CJK radicals and symbols, kana, Bopomofo, CJK ideographs, Yi
+        | '\u{AC00}'..='\u{D7FF}' // Hangul syllables
+        | '\u{F900}'..='\u{FAFF}' // CJK compatibility ideographs
+        | '\u{FE30}'..='\u{FE4F}' // CJK compatibility forms
+        | '\u{FF00}'..='\u{FF60}' | '\u{FFE0}'..='\u{FFE6}' // Fullwidth forms
+        | '\u{1F000}'..='\u{1FAFF}' // Emoji and other pictographs
+        | '\u{20000}'..='\u{3FFFF}' // Supplementary ideographs
+    )
+}
+
+/// Resolves the bidi embedding levels of the (whitespace-processed) inline
+/// items of a block container with the given style, returning the level of
+/// the paragraph and the levels of each item (per byte of text), which are
+/// empty if all of the content is left-to-right.
+fn resolve_bidi_levels(items: &[InlineItem], style: &ComputedStyle) -> (u8, Vec<Vec<u8>>) {
+    let mut text = BidiText::new(style);
+    let ranges: Vec<_> = items.iter()
+        .map(|item| match item {
+            InlineItem::Start(layout_box) => {
+                text.open(&layout_box.style);
+                0..0
+            },
+            InlineItem::End => {
+                text.close();
+                0..0
+            },
+            InlineItem::Text(t, _) => text.push_text(t),
+            InlineItem::Atomic(_) => text.push_object(),
+            InlineItem::LineBreak(_) => {
+                text.push_text("\n");
+                0..0
+            },
+            InlineItem::Float(_) | InlineItem::Absolute(_) => 0..0,
+        })
+        .collect();
+    match text.resolve() {
+        Some(levels) => (levels.base, ranges.into_iter().map(|r| levels.get(r).to_vec()).collect()),
+        None => (0, vec![Vec::new(); items.len()]),
+    }
+}
+
+/// The number of spaces between tab stops in preserved whitespace.
+const TAB_SIZE: usize = 8;
+
+/// Processes the whitespace in the text items according to their `white-space`
+/// style, collapsing spaces across item boundaries.
+/// See https://www.w3.org/TR/css-text-3/#white-space-phase-1
+fn process_whitespace(items: &mut [InlineItem]) {
+    // Whether the preceding text ends with a collapsible space (or there is none)
+    let mut after_space = true;
+    for item in items {
+        let (text, style) = match item {
+            InlineItem::Text(text, style) => (text, style),
+            // Spaces at the start of the line after a break are removed
+            InlineItem::LineBreak(_) => {
+                after_space = true;
+                continue;
+            },
+            _ => continue,
+        };
+        let white_space = style.white_space;
+        let mut processed = String::with_capacity(text.len());
+        if white_space.collapses_spaces() {
+            for c in text.chars() {
+                if c == '\n' && white_space.preserves_newlines() {
+                    // Spaces around preserved newlines are removed
+                    while processed.ends_with(' ') {
+                        processed.pop();
+                    }
+                    processed.push('\n');
+                    after_space = true;
+                } else if c.is_ascii_whitespace() {
+                    if !after_space {
+                        processed.push(' ');
+                        after_space = true;
+                    }
+                } else {
+                    processed.push(c);
+                    after_space = false;
+                }
+            }
+        } else {
+            // Preserve spaces, expanding tabs to the next tab stop
+            let mut column = 0;
+            for c in text.chars() {
+                match c {
+                    '\t' => {
+                        let spaces = TAB_SIZE - column % TAB_SIZE;
+                        processed.push_str(&" ".repeat(spaces));
+                        column += spaces;
+                    },
+                    '\r' => {},
+                    '\n' => {
+                        processed.push('\n');
+                        column = 0;
+                    },
+                    _ => {
+                        processed.push(c);
+                        column += 1;
+                    },
+                }
+            }
+            after_space = processed.ends_with('\n');
+        }
+        *text = processed;
+    }
+}
+
+/// Aligns the items of a line horizontally and at their baselines and
+/// creates the fragments for it, with inline boxes wrapping their content.
+/// Returns the height of the line.
+fn finish_line<L>(mut line: Line<L>, origin: Point, containing_width: f64, fragments: &mut Vec<Fragment<L>>) -> f64 where L: TextLayout {
+    align_line(&mut line);
+    // The space between the line height and the height of the text is
+    // split evenly above and below it, atomic inlines are aligned by their
+    // margin boxes
+    // See https://www.w3.org/TR/CSS2/visudet.html#leading
+    let half_leading = |text: &PlacedText<L>| (text.style.line_height.resolve(text.style.font_size, text.size.height) - text.size.height) / 2.0;
+    let (ascents, descents): (Vec<_>, Vec<_>) = line.items.iter()
+        .filter_map(|item| match item {
+            LineItem::Text(text) => Some((text.baseline + half_leading(text), text.size.height - text.baseline + half_leading(text))),
+            LineItem::Atomic(atomic) => Some((atomic.baseline(), atomic.size().height - atomic.baseline())),
+            _ => None,
+        })
+        .unzip();
+    let baseline = ascents.into_iter().fold(0.0, f64::max);
+    let descent = descents.into_iter().fold(0.0, f64::max);
+    let height = baseline + descent;
+
+    let mut stack: Vec<InlineFrame<L>> = Vec::new();
+    let mut x = 0.0;
+    for item in line.items {
+        match item {
+            LineItem::Start { layout_box, x: start, first } => {
+                x = start;
+                stack.push(InlineFrame { layout_box, x, first, children: Vec::new() });
+            },
+            LineItem::End { x: end } => {
+                x = end;
+                let frame = stack.pop().expect("Unbalanced inline boxes");
+                push_fragment(frame.into_fragment(origin, end, true, height, containing_width), &mut stack, fragments);
+            },
+            LineItem::Text(text) => {
+                let text_origin = Point::new(origin.x + text.x, origin.y + baseline - text.baseline);
+                x = text.x + text.size.width;
+                let content = if text.upright {
+                    FragmentContent::VerticalText { layout: text.layout, upright: true }
+                } else {
+                    FragmentContent::Text(text.layout)
+                };
+                push_fragment(Fragment {
+                    rect: Rect::from_origin_size(text_origin, text.size),
+                    style: text.style,
+                    content,
+                    border: Sides::default(),
+                    href: None,
+                    children: Vec::new(),
+                }, &mut stack, fragments);
+            },
+            LineItem::Atomic(atomic) => {
+                x = atomic.x + atomic.size().width;
+                let offset = Vec2::new(origin.x + atomic.x + atomic.margin.left, origin.y + baseline - atomic.baseline() + atomic.margin.top);
+                let mut fragment = atomic.fragment;
+                fragment.translate(offset);
+                push_fragment(fragment, &mut stack, fragments);
+            },
+        }
+    }
+    // Close the inline boxes continuing on the next line
+    while let Some(frame) = stack.pop() {
+        push_fragment(frame.into_fragment(origin, x, false, height, containing_width), &mut stack, fragments);
+    }
+
+    height
+}
+
+/// Distributes the free space on a line according to its alignment, i.e.
+/// shifts its items or (if justified) widens the spaces between them.
+/// See https://www.w3.org/TR/css-text-3/#text-align-property
+fn align_line<L>(line: &mut Line<L>) {
+    let content_width = line.items.iter()
+        .map(|item| match item {
+            LineItem::Start { x, .. } | LineItem::End { x } => *x,
+            LineItem::Text(text) => text.x + text.size.width,
+            LineItem::Atomic(atomic) => atomic.x + atomic.size().width,
+        })
+        .fold(0.0, f64::max);
+    // Lines are as wide as their content when computing the max-content width
+    let free = line.available - content_width;
+    let free = if free.is_finite() { free.max(0.0) } else { 0.0 };
+    let justified = line.align == TextAlign::Justify && !line.last && free > 0.0;
+    if justified {
+        justify_line(line, free);
+    }
+    let (width, free) = if justified { (line.available, 0.0) } else { (content_width, free) };
+    reorder_line(line, width);
+    let rtl = line.base_level % 2 == 1;
+    let shift = match line.align {
+        TextAlign::Left => 0.0,
+        TextAlign::Right => free,
+        TextAlign::Center => free / 2.0,
+        TextAlign::Start | TextAlign::Justify => if rtl { free } else { 0.0 },
+        TextAlign::End => if rtl { 0.0 } else { free },
+    };
+    if shift == 0.0 {
+        return;
+    }
+    for item in &mut line.items {
+        match item {
+            LineItem::Start { x, .. } | LineItem::End { x } => *x += shift,
+            LineItem::Text(text) => text.x += shift,
+            LineItem::Atomic(atomic) => atomic.x += shift,
+        }
+    }
+}
+
+/// Reorders the items on a line of the given width visually by their bidi
+/// levels, i.e. reverses the runs of items at or above each odd level,
+/// starting with the highest level.
+/// See https://unicode.org/reports/tr9/#L2
+fn reorder_line<L>(line: &mut Line<L>, width: f64) {
+    let leaves: Vec<(usize, u8)> = line.items.iter().enumerate()
+        .filter_map(|(i, item)| match item {
+            LineItem::Text(text) => Some((i, text.level)),
+            LineItem::Atomic(atomic) => Some((i, atomic.level)),
+            LineItem::Start { .. } | LineItem::End { .. } => None,
+        })
+        .collect();
+    let (Some(min), Some(max)) = (leaves.iter().map(|l| l.1).min(), leaves.iter().map(|l| l.1).max()) else {
+        return;
+    };
+    for level in ((min | 1)..=max).rev() {
+        // A right-to-left paragraph is reversed as a whole, including the
+        // whitespace and empty boxes around its content
+        if level == line.base_level {
+            mirror_items(&mut line.items, width);
+            continue;
+        }
+        for run in leaves.split(|l| l.1 < level).filter(|run| !run.is_empty()) {
+            let (first, last) = (run[0].0, run[run.len() - 1].0);
+            let (x0, x1) = line.items[first..=last].iter()
+                .filter_map(|item| match item {
+                    LineItem::Text(text) => Some((text.x, text.x + text.size.width)),
+                    LineItem::Atomic(atomic) => Some((atomic.x, atomic.x + atomic.size().width)),
+                    LineItem::Start { .. } | LineItem::End { .. } => None,
+                })
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(x0, x1), (a, b)| (x0.min(a), x1.max(b)));
+            mirror_items(&mut line.items[first..=last], x0 + x1);
+        }
+    }
+}
+
+/// Mirrors the given items on a line around the middle of the range from
+/// zero to the given offset.
+fn mirror_items<L>(items: &mut [LineItem<L>], axis: f64) {
+    for item in items {
+        match item {
+            LineItem::Start { x, .. } | LineItem::End { x } => *x = axis - *x,
+            LineItem::Text(text) => text.x = axis - text.x - text.size.width,
+            LineItem::Atomic(atomic) => atomic.x = axis - atomic.x - atomic.size().width,
+        }
+    }
+}
+
+/// Stretches a line by distributing the given free space evenly across the
+/// spaces between its text pieces (excluding the hanging ones at its end).
+fn justify_line<L>(line: &mut Line<L>, free: f64) {
+    let Some(last) = line.items.iter().rposition(|item| matches!(item, LineItem::Text(_))) else {
+        return;
+    };
+    let is_opportunity = |item: &LineItem<L>| matches!(item, LineItem::Text(text) if text.source[text.range.clone()].ends_with(' '));
+    let opportunities = line.items[..last].iter().filter(|item| is_opportunity(item)).count();
+    if opportunities == 0 {
+        return;
+    }
+    let extra = free / opportunities as f64;
+    let mut shift = 0.0;
+    for (i, item) in line.items.iter_mut().enumerate() {
+        let opportunity = i < last && is_opportunity(item);
+        match item {
+            LineItem::Start { x, .. } | LineItem::End { x } => *x += shift,
+            LineItem::Text(text) => text.x += shift,
+            LineItem::Atomic(atomic) => atomic.x += shift,
+        }
+        if opportunity {
+            shift += extra;
+        }
+    }
+}
+
+/// Adds a fragment to the innermost open inline box (or the line if there is none).
+fn push_fragment<L>(fragment: Fragment<L>, stack: &mut [InlineFrame<L>], fragments: &mut Vec<Fragment<L>>) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(fragment),
+        None => fragments.push(fragment),
+    }
+}
+
+impl<'b, 'd, L> InlineFrame<'b, 'd, L> {
+    /// Creates the fragment for this inline box on a line at the given
+    /// position with the given height, ending at the given offset (and at
+    /// the last edge of the box if `last` is set).
+    fn into_fragment(self, line_origin: Point, end: f64, last: bool, line_height: f64, containing_width: f64) -> Fragment<L> {
+        let edges = BoxEdges::of(&self.layout_box.style, containing_width);
+        let (y0, y1) = self.children.iter()
+            .map(|c| (c.rect.y0, c.rect.y1))
+            .reduce(|(y0, y1), (c0, c1)| (y0.min(c0), y1.max(c1)))
+            .unwrap_or((line_origin.y, line_origin.y + line_height));
+        // The vertical edges extend outside of the line without affecting its height
+        let rect = Rect::new(
+            line_origin.x + self.x,
+            y0 - edges.padding.top - edges.border.top,
+            line_origin.x + end,
+            y1 + edges.padding.bottom + edges.border.bottom,
+        );
+        // Boxes in right-to-left text start on the right
+        let reversed = end < self.x;
+        let (start_border, end_border) = (
+            if self.first { edges.border.left } else { 0.0 },
+            if last { edges.border.right } else { 0.0 },
+        );
+        Fragment {
+            rect: rect.abs(),
+            style: self.layout_box.style.clone(),
+            content: FragmentContent::Box,
+            border: Sides {
+                left: if reversed { end_border } else { start_border },
+                right: if reversed { start_border } else { end_border },
+                ..edges.border
+            },
+            href: self.layout_box.href().map(|href| href.to_owned()),
+            children: self.children,
+        }
+    }
+}
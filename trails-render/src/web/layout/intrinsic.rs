@@ -0,0 +1,120 @@
+use std::ops::Add;
+use std::sync::Arc;
+
+use piet::{kurbo::Point, Text};
+
+use crate::web::{Display, Float};
+
+use super::{content_size, BoxEdges, BoxKind, LayoutBox, Layouter};
+
+/// The intrinsic widths of a box, i.e. its width when taking every line
+/// break opportunity (the min-content width) and when taking none (the
+/// max-content width).
+/// See https://www.w3.org/TR/css-sizing-3/#intrinsic-sizes
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct IntrinsicWidths {
+    pub(super) min: f64,
+    pub(super) max: f64,
+}
+
+impl IntrinsicWidths {
+    /// The widths of a box that has the same width regardless of its content.
+    pub(super) fn fixed(width: f64) -> Self {
+        Self { min: width, max: width }
+    }
+
+    /// The widths of a box containing boxes with either of the given widths.
+    pub(super) fn max(self, other: Self) -> Self {
+        Self { min: self.min.max(other.min), max: self.max.max(other.max) }
+    }
+}
+
+impl Add<f64> for IntrinsicWidths {
+    type Output = Self;
+
+    fn add(self, rhs: f64) -> Self {
+        Self { min: self.min + rhs, max: self.max + rhs }
+    }
+}
+
+impl<'a, T> Layouter<'a, T> where T: Text {
+    /// Computes the intrinsic widths of a block-level box, including its
+    /// margins, borders and padding. Percentages are treated as zero (or
+    /// `auto`), since the width of the containing block isn't known yet.
+    pub(super) fn intrinsic_widths(&mut self, block: &LayoutBox) -> IntrinsicWidths {
+        let style = &block.style;
+        let edges = BoxEdges::of(style, 0.0);
+        let inner = edges.inner().horizontal();
+        let outer = edges.margin.left.unwrap_or(0.0) + edges.margin.right.unwrap_or(0.0) + inner;
+        let specified = self.replaced_size(block, f64::INFINITY, None).map(|size| size.width)
+            .or_else(|| style.width.resolve_definite(None).map(|width| content_size(style, width, inner)));
+        let content = match (style.display, specified) {
+            // Tables and cells grow to fit their content, even if a width is specified
+            (Display::Table | Display::TableCell, Some(width)) => IntrinsicWidths::fixed(width.max(self.content_intrinsic_widths(block).min)),
+            (_, Some(width)) => IntrinsicWidths::fixed(width),
+            (_, None) => self.content_intrinsic_widths(block),
+        };
+        content + outer
+    }
+
+    /// The width of the content box of a box that shrinks to fit its content
+    /// within the given available width.
+    /// See https://www.w3.org/TR/CSS2/visudet.html#shrink-to-fit-float
+    pub(super) fn shrink_to_fit_width(&mut self, block: &LayoutBox, available: f64) -> f64 {
+        let content = self.content_intrinsic_widths(block);
+        available.max(content.min).min(content.max)
+    }
+
+    /// Computes the intrinsic widths of the content box of a block-level box.
+    pub(super) fn content_intrinsic_widths(&mut self, block: &LayoutBox) -> IntrinsicWidths {
+        if block.style.display == Display::Table {
+            self.table_intrinsic_widths(block)
+        } else if block.style.display == Display::Flex {
+            self.flex_intrinsic_widths(block)
+        } else if block.style.display == Display::Grid {
+            self.grid_intrinsic_widths(block)
+        } else if block.children.iter().any(|c| c.is_block_level()) {
+            block.children.iter()
+                .map(|child| self.intrinsic_widths(child))
+                .fold(IntrinsicWidths::default(), IntrinsicWidths::max)
+        } else {
+            self.inline_intrinsic_widths(block)
+        }
+    }
+
+    /// Computes the intrinsic widths of the inline-level content of a block
+    /// container by laying it out in lines as narrow and as wide as possible.
+    /// Floats are assumed to be placed next to each other and the lines (in
+    /// the widest lines, left floats are placed before them already, while
+    /// right ones are at their infinite end), absolutely positioned boxes
+    /// don't contribute.
+    fn inline_intrinsic_widths(&mut self, block: &LayoutBox) -> IntrinsicWidths {
+        let mut out_of_flow = Vec::new();
+        collect_out_of_flow(&block.children, &mut out_of_flow);
+        let placed = |b: &LayoutBox, width: f64| width.is_infinite() && b.kind == BoxKind::Float && b.style.float == Float::Left;
+        let mut widest_line = |width: f64| self.in_new_context(|this| {
+            let (fragments, _) = this.layout_inline_content(block, Point::ZERO, width);
+            fragments.iter()
+                .filter(|f| !out_of_flow.iter().any(|b| Arc::ptr_eq(&b.style, &f.style) && !placed(b, width)))
+                .map(|f| f.bounds().x1)
+                .fold(0.0, f64::max)
+        });
+        let lines = IntrinsicWidths { min: widest_line(0.0), max: widest_line(f64::INFINITY) };
+        out_of_flow.iter().filter(|b| b.kind == BoxKind::Float).fold(lines, |widths, float| {
+            let widths_of_float = self.intrinsic_widths(float);
+            let max = if placed(float, f64::INFINITY) { widths.max } else { widths.max + widths_of_float.max };
+            IntrinsicWidths { min: widths.min.max(widths_of_float.min), max }
+        })
+    }
+}
+
+/// Collects the floats and absolutely positioned boxes in the given inline-level content.
+fn collect_out_of_flow<'b, 'd>(boxes: &'b [LayoutBox<'d>], out_of_flow: &mut Vec<&'b LayoutBox<'d>>) {
+    for layout_box in boxes {
+        match layout_box.kind {
+            BoxKind::Float | BoxKind::Absolute => out_of_flow.push(layout_box),
+            BoxKind::Inline => collect_out_of_flow(&layout_box.children, out_of_flow),
+            _ => {},
+        }
+    }
+}
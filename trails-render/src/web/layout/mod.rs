@@ -0,0 +1,29 @@
+mod bidi;
+mod boxes;
+mod counters;
+mod engine;
+mod flex;
+mod float;
+mod fonts;
+mod fragment;
+mod grid;
+mod images;
+mod inline;
+mod intrinsic;
+mod positioned;
+mod table;
+mod vertical;
+mod woff;
+
+pub(crate) use boxes::*;
+pub(crate) use counters::*;
+pub use engine::*;
+pub use fonts::*;
+pub use fragment::*;
+pub use images::*;
+use bidi::*;
+use float::*;
+use intrinsic::*;
+use positioned::*;
+use vertical::*;
+use woff::*;
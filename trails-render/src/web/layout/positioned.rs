@@ -0,0 +1,134 @@
+use piet::{kurbo::{Point, Rect, Vec2}, Text};
+
+use crate::web::{ComputedStyle, Display, Position};
+
+use super::{content_size, BoxEdges, Fragment, FragmentContent, LayoutBox, Layouter};
+
+impl<'a, T> Layouter<'a, T> where T: Text {
+    /// Lays out an absolutely positioned box in its containing block, i.e.
+    /// the padding box of the nearest positioned ancestor (or the viewport
+    /// if fixed). Boxes without a specified width shrink to fit their content
+    /// unless both their left and right insets are specified. Returns the
+    /// fragment with its margin box positioned at the origin, i.e. at its
+    /// static position, the insets are applied after layout.
+    /// See https://www.w3.org/TR/CSS2/visudet.html#abs-non-replaced-width
+    pub(super) fn layout_absolute(&mut self, block: &LayoutBox) -> Fragment<T::TextLayout> {
+        let style = &block.style;
+        let containing_width = match style.position {
+            Position::Fixed => self.media.viewport.width,
+            _ => self.containing_width,
+        };
+        let mut edges = BoxEdges::of(style, containing_width);
+        // Auto margins are treated as zero
+        edges.margin = edges.margin.map(|m| Some(m.unwrap_or(0.0)));
+        let margin = edges.margin.map(|m| m.unwrap_or(0.0));
+        let inner = edges.inner();
+        let left = style.inset.left.resolve_definite(containing_width.is_finite().then_some(containing_width));
+        let right = style.inset.right.resolve_definite(containing_width.is_finite().then_some(containing_width));
+        let available = (containing_width - left.unwrap_or(0.0) - right.unwrap_or(0.0) - margin.horizontal() - inner.horizontal()).max(0.0);
+        let replaced = self.replaced_size(block, containing_width, None);
+        let specified_width = style.width.resolve_definite(containing_width.is_finite().then_some(containing_width)).map(|width| content_size(style, width, inner.horizontal()));
+        let width = match replaced.map(|size| size.width).or(specified_width) {
+            Some(width) => width,
+            None if left.is_some() && right.is_some() => available,
+            // Tables shrink to fit by themselves
+            None if style.display == Display::Table => available,
+            None => self.shrink_to_fit_width(block, available),
+        };
+        let height = replaced.map(|size| size.height).or_else(|| {
+            style.height.resolve_definite(None).map(|h| content_size(style, h, inner.vertical()))
+        });
+        let mut fragment = self.layout_sized_block(block, &edges, width, height, true).fragment;
+        fragment.translate(Vec2::new(margin.left, margin.top));
+        fragment
+    }
+
+    /// Lays out the absolutely positioned children of a flex or grid
+    /// container, whose static position is the given content origin.
+    pub(super) fn layout_absolute_children(&mut self, container: &LayoutBox, origin: Point) -> Vec<Fragment<T::TextLayout>> {
+        container.children.iter()
+            .filter(|c| c.is_absolute())
+            .map(|child| {
+                let mut fragment = self.layout_absolute(child);
+                fragment.translate(origin.to_vec2());
+                fragment
+            })
+            .collect()
+    }
+}
+
+/// Moves the positioned descendants of a laid out fragment to their final
+/// positions, i.e. offsets relatively positioned boxes and places absolutely
+/// positioned boxes in the given containing block (or the viewport if fixed),
+/// unless they have no insets and stay at their static position. Sticky boxes
+/// are offset when painting, since that depends on the scroll position.
+/// See https://www.w3.org/TR/CSS2/visuren.html#choose-position
+pub(super) fn apply_insets<L>(fragment: &mut Fragment<L>, containing_block: Rect, viewport: Rect) {
+    let parent = fragment.rect;
+    for child in &mut fragment.children {
+        if !matches!(child.content, FragmentContent::Box) {
+            continue;
+        }
+        let style = child.style.clone();
+        match style.position {
+            Position::Static | Position::Sticky => {},
+            Position::Relative => child.translate(relative_offset(&style, parent)),
+            Position::Absolute => place_absolute(child, containing_block),
+            Position::Fixed => place_absolute(child, viewport),
+        }
+        // Positioned boxes are the containing blocks of their descendants
+        let containing_block = if style.position.is_positioned() {
+            child.padding_box()
+        } else {
+            containing_block
+        };
+        apply_insets(child, containing_block, viewport);
+    }
+}
+
+/// The offset of a relatively positioned box in the given containing block,
+/// where the left and top insets win over the right and bottom ones.
+/// See https://www.w3.org/TR/CSS2/visuren.html#relative-positioning
+fn relative_offset(style: &ComputedStyle, containing_block: Rect) -> Vec2 {
+    let (width, height) = (containing_block.width(), containing_block.height());
+    let inset = &style.inset;
+    let x = match (inset.left.resolve(width), inset.right.resolve(width)) {
+        (Some(left), _) => left,
+        (None, Some(right)) => -right,
+        (None, None) => 0.0,
+    };
+    let y = match (inset.top.resolve(height), inset.bottom.resolve(height)) {
+        (Some(top), _) => top,
+        (None, Some(bottom)) => -bottom,
+        (None, None) => 0.0,
+    };
+    Vec2::new(x, y)
+}
+
+/// Places an absolutely positioned fragment in its containing block
+/// according to its insets. Boxes with an `auto` height stretch between
+/// their top and bottom insets if both are specified.
+/// See https://www.w3.org/TR/CSS2/visudet.html#abs-non-replaced-height
+fn place_absolute<L>(fragment: &mut Fragment<L>, containing_block: Rect) {
+    let style = fragment.style.clone();
+    let (width, height) = (containing_block.width(), containing_block.height());
+    let margin = style.margin.map(|m| m.resolve(width).unwrap_or(0.0));
+    let inset = &style.inset;
+    let (left, right) = (inset.left.resolve(width), inset.right.resolve(width));
+    let (top, bottom) = (inset.top.resolve(height), inset.bottom.resolve(height));
+    let rect = fragment.rect;
+    let x = match (left, right) {
+        (Some(left), _) => containing_block.x0 + left + margin.left,
+        (None, Some(right)) => containing_block.x1 - right - margin.right - rect.width(),
+        (None, None) => rect.x0,
+    };
+    let y = match (top, bottom) {
+        (Some(top), _) => containing_block.y0 + top + margin.top,
+        (None, Some(bottom)) => containing_block.y1 - bottom - margin.bottom - rect.height(),
+        (None, None) => rect.y0,
+    };
+    fragment.translate(Vec2::new(x - rect.x0, y - rect.y0));
+    if let (Some(_), Some(bottom), true) = (top, bottom, style.height.is_auto()) {
+        fragment.rect.y1 = fragment.rect.y1.max(containing_block.y1 - bottom - margin.bottom);
+    }
+}
@@ -0,0 +1,405 @@
+use std::sync::Arc;
+
+use piet::{kurbo::{Point, Rect, Size, Vec2}, Text};
+
+use crate::web::{parse_integer, ComputedStyle, Display, Sides, TableLayout, VerticalAlign};
+
+use super::{content_size, BlockLayout, BoxEdges, CollapsedMargin, Fragment, FragmentContent, IntrinsicWidths, LayoutBox, Layouter};
+
+/// The maximum number of columns a cell may span.
+const MAX_COLUMN_SPAN: usize = 1000;
+/// The maximum number of rows a cell may span.
+const MAX_ROW_SPAN: usize = 65534;
+
+/// A cell placed in the table grid.
+struct GridCell<'b, 'd> {
+    cell: &'b LayoutBox<'d>,
+    row: usize,
+    column: usize,
+    row_span: usize,
+    column_span: usize,
+}
+
+/// A row of the table grid.
+struct GridRow<'b, 'd> {
+    row: &'b LayoutBox<'d>,
+    /// The index of the row group containing the row, if any.
+    group: Option<usize>,
+}
+
+/// The captions, rows and cells of a table, with the cells placed in a
+/// grid of slots according to their row and column spans.
+/// See https://html.spec.whatwg.org/multipage/tables.html#forming-a-table
+#[derive(Default)]
+struct TableGrid<'b, 'd> {
+    captions: Vec<&'b LayoutBox<'d>>,
+    groups: Vec<&'b LayoutBox<'d>>,
+    rows: Vec<GridRow<'b, 'd>>,
+    cells: Vec<GridCell<'b, 'd>>,
+    columns: usize,
+}
+
+impl<'b, 'd> TableGrid<'b, 'd> {
+    /// Forms the grid of a table box, whose children are captions, row
+    /// groups and rows (see `LayoutBox::build`). The first header group is
+    /// moved to the top and the first footer group to the bottom.
+    fn build(table: &'b LayoutBox<'d>) -> Self {
+        let mut grid = Self::default();
+        let children = &table.children;
+        let header = children.iter().position(|c| c.style.display == Display::TableHeaderGroup);
+        let footer = children.iter().position(|c| c.style.display == Display::TableFooterGroup);
+        let order = header.into_iter()
+            .chain((0..children.len()).filter(|&i| Some(i) != header && Some(i) != footer))
+            .chain(footer);
+
+        let mut rows = Vec::new();
+        for child in order.map(|i| &children[i]) {
+            match child.style.display {
+                Display::TableCaption => grid.captions.push(child),
+                Display::TableRow => rows.push(child),
+                Display::TableRowGroup | Display::TableHeaderGroup | Display::TableFooterGroup => {
+                    grid.add_rows(std::mem::take(&mut rows), None);
+                    grid.groups.push(child);
+                    grid.add_rows(child.children.iter().collect(), Some(grid.groups.len() - 1));
+                },
+                _ => {},
+            }
+        }
+        grid.add_rows(rows, None);
+        grid
+    }
+
+    /// Adds the rows of a row group (or consecutive rows outside of one),
+    /// placing their cells in the first free slots. Cells never span beyond
+    /// the end of the group, which is also where `rowspan="0"` spans to.
+    fn add_rows(&mut self, rows: Vec<&'b LayoutBox<'d>>, group: Option<usize>) {
+        let end = self.rows.len() + rows.len();
+        // The row (exclusive) until which each column is occupied
+        let mut occupied_until: Vec<usize> = Vec::new();
+        for (index, row) in (self.rows.len()..).zip(rows) {
+            self.rows.push(GridRow { row, group });
+            let mut column = 0;
+            for cell in &row.children {
+                while occupied_until.get(column).is_some_and(|&until| until > index) {
+                    column += 1;
+                }
+                let span = |name| cell.element.and_then(|e| e.attribute(name)).and_then(parse_integer).map(|s| s as usize);
+                let column_span = span("colspan").unwrap_or(1).clamp(1, MAX_COLUMN_SPAN);
+                let row_span = match span("rowspan").unwrap_or(1).min(MAX_ROW_SPAN) {
+                    0 => end - index,
+                    row_span => row_span.min(end - index),
+                };
+                if occupied_until.len() < column + column_span {
+                    occupied_until.resize(column + column_span, 0);
+                }
+                occupied_until[column..column + column_span].fill(index + row_span);
+                self.cells.push(GridCell { cell, row: index, column, row_span, column_span });
+                column += column_span;
+            }
+        }
+        self.columns = self.columns.max(occupied_until.len());
+    }
+}
+
+impl GridCell<'_, '_> {
+    /// The range of columns spanned by the cell.
+    fn columns(&self) -> std::ops::Range<usize> {
+        self.column..self.column + self.column_span
+    }
+
+    /// The range of rows spanned by the cell.
+    fn rows(&self) -> std::ops::Range<usize> {
+        self.row..self.row + self.row_span
+    }
+}
+
+impl<'a, T> Layouter<'a, T> where T: Text {
+    /// Lays out a table box (along with its captions) in a containing block
+    /// of the given width and (if definite) height, using the separated
+    /// borders model.
+    /// See https://www.w3.org/TR/CSS2/tables.html
+    pub(super) fn layout_table(&mut self, table: &LayoutBox, containing_width: f64, containing_height: Option<f64>) -> BlockLayout<T::TextLayout> {
+        let style = &table.style;
+        let grid = TableGrid::build(table);
+        let (spacing_x, spacing_y) = style.border_spacing;
+        let edges = BoxEdges::of(style, containing_width);
+        let inner = edges.inner();
+        let margin = edges.margin;
+        let gaps_x = gaps(spacing_x, grid.columns);
+
+        // Determine the widths of the columns and thereby of the table
+        let specified_width = style.width.resolve_definite(containing_width.is_finite().then_some(containing_width))
+            .map(|width| content_size(style, width, inner.horizontal()));
+        let columns = match (style.table_layout, specified_width) {
+            (TableLayout::Fixed, Some(width)) => self.fixed_column_widths(&grid, (width - gaps_x).max(0.0), spacing_x),
+            _ => {
+                let intrinsic = self.column_intrinsic_widths(&grid, spacing_x);
+                let min: f64 = intrinsic.iter().map(|c| c.min).sum();
+                let max: f64 = intrinsic.iter().map(|c| c.max).sum();
+                let available = containing_width - margin.left.unwrap_or(0.0) - margin.right.unwrap_or(0.0) - inner.horizontal() - gaps_x;
+                let width = match specified_width {
+                    Some(width) => (width - gaps_x).max(min),
+                    None => available.min(max).max(min),
+                };
+                distribute_width(&intrinsic, width)
+            },
+        };
+        let width = (columns.iter().sum::<f64>() + gaps_x).max(specified_width.unwrap_or(0.0));
+        let column_x = offsets(&columns, spacing_x);
+
+        // Lay out the cells and determine the heights of the rows
+        let mut cells: Vec<_> = grid.cells.iter()
+            .map(|cell| {
+                let cell_width = span_size(&columns[cell.columns()], spacing_x);
+                self.layout_cell(cell.cell, cell_width, width)
+            })
+            .collect();
+        let mut rows: Vec<f64> = grid.rows.iter()
+            .map(|r| r.row.style.height.resolve_definite(None).unwrap_or(0.0))
+            .collect();
+        for (cell, (_, height)) in grid.cells.iter().zip(&cells).filter(|(c, _)| c.row_span == 1) {
+            rows[cell.row] = rows[cell.row].max(*height);
+        }
+        for (cell, (_, height)) in grid.cells.iter().zip(&cells).filter(|(c, _)| c.row_span > 1) {
+            // Rows spanned by a cell that doesn't fit grow at the bottom
+            let spanned = span_size(&rows[cell.rows()], spacing_y);
+            if *height > spanned {
+                rows[cell.row + cell.row_span - 1] += height - spanned;
+            }
+        }
+
+        // Stretch the rows to the specified height of the table
+        let grid_height = rows.iter().sum::<f64>() + gaps(spacing_y, rows.len());
+        let specified_height = style.height.resolve_definite(containing_height)
+            .map(|height| content_size(style, height, inner.vertical()));
+        if let Some(extra) = specified_height.map(|h| h - grid_height).filter(|&e| e > 0.0) {
+            let total: f64 = rows.iter().sum();
+            let count = rows.len() as f64;
+            for row in &mut rows {
+                *row += if total > 0.0 { extra * *row / total } else { extra / count };
+            }
+        }
+        let height = grid_height.max(specified_height.unwrap_or(0.0));
+        let row_y = offsets(&rows, spacing_y);
+
+        // Build the fragments of the rows (and row groups) containing the cells
+        let mut row_fragments: Vec<_> = grid.rows.iter().enumerate()
+            .map(|(i, row)| Fragment {
+                rect: Rect::new(spacing_x, row_y[i], (width - spacing_x).max(spacing_x), row_y[i] + rows[i]),
+                style: row.row.style.clone(),
+                content: FragmentContent::Box,
+                border: Sides::default(),
+                href: None,
+                children: Vec::new(),
+            })
+            .collect();
+        for (cell, (mut fragment, _)) in grid.cells.iter().zip(cells.drain(..)) {
+            let height = span_size(&rows[cell.rows()], spacing_y);
+            let free = height - fragment.rect.height();
+            let offset = match cell.cell.style.vertical_align {
+                VerticalAlign::Middle => free / 2.0,
+                VerticalAlign::Bottom => free,
+                _ => 0.0,
+            };
+            for child in &mut fragment.children {
+                child.translate(Vec2::new(0.0, offset));
+            }
+            fragment.rect.y1 = fragment.rect.y0 + height;
+            fragment.translate(Vec2::new(column_x[cell.column], row_y[cell.row]));
+            row_fragments[cell.row].children.push(fragment);
+        }
+        let mut children: Vec<Fragment<T::TextLayout>> = Vec::new();
+        let mut group_indices = vec![None; grid.groups.len()];
+        for (row, fragment) in grid.rows.iter().zip(row_fragments) {
+            match row.group {
+                Some(group) => {
+                    let index = *group_indices[group].get_or_insert_with(|| {
+                        children.push(Fragment {
+                            rect: fragment.rect,
+                            style: grid.groups[group].style.clone(),
+                            content: FragmentContent::Box,
+                            border: Sides::default(),
+                            href: None,
+                            children: Vec::new(),
+                        });
+                        children.len() - 1
+                    });
+                    let group_fragment = &mut children[index];
+                    group_fragment.rect = group_fragment.rect.union(fragment.rect);
+                    group_fragment.children.push(fragment);
+                },
+                None => children.push(fragment),
+            }
+        }
+        for child in &mut children {
+            child.translate(Vec2::new(inner.left, inner.top));
+        }
+        let mut fragment = Fragment {
+            rect: Rect::from_origin_size(Point::ZERO, Size::new(width + inner.horizontal(), height + inner.vertical())),
+            style: style.clone(),
+            content: FragmentContent::Box,
+            border: edges.border,
+            href: None,
+            children,
+        };
+
+        // Place the captions above the table, wrapping both in an anonymous fragment
+        let border_width = fragment.rect.width();
+        if !grid.captions.is_empty() {
+            let mut children = Vec::new();
+            let mut y = 0.0;
+            for caption in &grid.captions {
+                let mut layout = self.in_new_context(|this| this.layout_block(caption, border_width, None));
+                y += layout.margin_top.value();
+                layout.fragment.translate(Vec2::new(layout.margin_left, y));
+                y += layout.fragment.rect.height() + layout.margin_bottom.value();
+                children.push(layout.fragment);
+            }
+            fragment.translate(Vec2::new(0.0, y));
+            let table_height = fragment.rect.height();
+            children.push(fragment);
+            fragment = Fragment {
+                rect: Rect::from_origin_size(Point::ZERO, Size::new(border_width, y + table_height)),
+                style: Arc::new(ComputedStyle::inherit(style)),
+                content: FragmentContent::Box,
+                border: Sides::default(),
+                href: None,
+                children,
+            };
+        }
+
+        let remaining = containing_width - border_width;
+        let margin_left = match (margin.left, margin.right) {
+            (None, None) => (remaining / 2.0).max(0.0),
+            (None, Some(right)) => remaining - right,
+            (Some(left), _) => left,
+        };
+        BlockLayout {
+            fragment,
+            margin_left,
+            margin_top: CollapsedMargin::new(margin.top.unwrap_or(0.0)),
+            margin_bottom: CollapsedMargin::new(margin.bottom.unwrap_or(0.0)),
+            collapses_through: false,
+        }
+    }
+
+    /// Lays out a table cell with the given border box width, returning its
+    /// fragment and the height it needs (which may exceed the content if a
+    /// height is specified).
+    fn layout_cell(&mut self, cell: &LayoutBox, width: f64, table_width: f64) -> (Fragment<T::TextLayout>, f64) {
+        let style = &cell.style;
+        let mut edges = BoxEdges::of(style, table_width);
+        // Margins don't apply to table cells
+        edges.margin = Sides::all(Some(0.0));
+        let inner = edges.inner();
+        let content_width = (width - inner.horizontal()).max(0.0);
+        let layout = self.layout_block_contents(cell, &edges, content_width, None, true);
+        let specified_height = style.height.resolve_definite(None)
+            .map(|height| content_size(style, height, inner.vertical()) + inner.vertical());
+        let height = layout.fragment.rect.height().max(specified_height.unwrap_or(0.0));
+        (layout.fragment, height)
+    }
+
+    /// Computes the intrinsic widths of the content box of a table.
+    pub(super) fn table_intrinsic_widths(&mut self, table: &LayoutBox) -> IntrinsicWidths {
+        let grid = TableGrid::build(table);
+        let (spacing_x, _) = table.style.border_spacing;
+        let columns = self.column_intrinsic_widths(&grid, spacing_x);
+        let min = columns.iter().map(|c| c.min).sum::<f64>();
+        let max = columns.iter().map(|c| c.max).sum::<f64>();
+        IntrinsicWidths { min, max } + gaps(spacing_x, grid.columns)
+    }
+
+    /// Computes the intrinsic widths of the columns from those of the cells,
+    /// distributing the widths of cells spanning several columns among them.
+    /// See https://www.w3.org/TR/CSS2/tables.html#auto-table-layout
+    fn column_intrinsic_widths(&mut self, grid: &TableGrid, spacing: f64) -> Vec<IntrinsicWidths> {
+        let mut cells: Vec<_> = grid.cells.iter()
+            .map(|cell| (cell, self.intrinsic_widths(cell.cell)))
+            .collect();
+        cells.sort_by_key(|(cell, _)| cell.column_span);
+
+        let mut columns = vec![IntrinsicWidths::default(); grid.columns];
+        for (cell, widths) in cells {
+            let spanned = &mut columns[cell.columns()];
+            let gaps = spacing * (cell.column_span - 1) as f64;
+            let min = spanned.iter().map(|c| c.min).sum::<f64>() + gaps;
+            let max = spanned.iter().map(|c| c.max).sum::<f64>() + gaps;
+            let total = spanned.iter().map(|c| c.max).sum::<f64>();
+            let count = spanned.len() as f64;
+            for column in spanned {
+                // Distribute the excess in proportion to the maximum widths
+                let share = if total > 0.0 { column.max / total } else { 1.0 / count };
+                column.min += (widths.min - min).max(0.0) * share;
+                column.max += (widths.max - max).max(0.0) * share;
+                column.max = column.max.max(column.min);
+            }
+        }
+        columns
+    }
+
+    /// Determines the widths of the columns in the fixed table layout, where
+    /// only the widths of the cells in the first row are taken into account
+    /// and the remaining width is divided equally among the other columns.
+    /// See https://www.w3.org/TR/CSS2/tables.html#fixed-table-layout
+    fn fixed_column_widths(&mut self, grid: &TableGrid, width: f64, spacing: f64) -> Vec<f64> {
+        let mut columns = vec![None; grid.columns];
+        for cell in grid.cells.iter().filter(|c| c.row == 0) {
+            let style = &cell.cell.style;
+            let inner = BoxEdges::of(style, width).inner().horizontal();
+            if let Some(cell_width) = style.width.resolve(width) {
+                let cell_width = content_size(style, cell_width, inner) + inner;
+                let column_width = (cell_width - spacing * (cell.column_span - 1) as f64).max(0.0) / cell.column_span as f64;
+                columns[cell.columns()].fill(Some(column_width));
+            }
+        }
+        let fixed: f64 = columns.iter().flatten().sum();
+        let auto_count = columns.iter().filter(|c| c.is_none()).count();
+        let remaining = (width - fixed).max(0.0);
+        let scale = if auto_count == 0 && fixed > 0.0 && fixed < width { width / fixed } else { 1.0 };
+        columns.into_iter()
+            .map(|c| c.map_or(remaining / auto_count as f64, |w| w * scale))
+            .collect()
+    }
+}
+
+/// Distributes the given width among columns with the given intrinsic
+/// widths. Widths between the sums of the minimum and maximum widths are
+/// interpolated between them, any excess is distributed in proportion to
+/// the maximum widths.
+fn distribute_width(columns: &[IntrinsicWidths], width: f64) -> Vec<f64> {
+    let min: f64 = columns.iter().map(|c| c.min).sum();
+    let max: f64 = columns.iter().map(|c| c.max).sum();
+    columns.iter()
+        .map(|c| if width <= min {
+            c.min
+        } else if width <= max {
+            c.min + (c.max - c.min) * (width - min) / (max - min)
+        } else if max > 0.0 {
+            c.max + (width - max) * c.max / max
+        } else {
+            width / columns.len() as f64
+        })
+        .collect()
+}
+
+/// The total spacing around and between the given number of columns (or rows).
+fn gaps(spacing: f64, count: usize) -> f64 {
+    if count == 0 { 0.0 } else { spacing * (count + 1) as f64 }
+}
+
+/// The total size of consecutive columns (or rows), including the spacing between them.
+fn span_size(sizes: &[f64], spacing: f64) -> f64 {
+    sizes.iter().sum::<f64>() + spacing * sizes.len().saturating_sub(1) as f64
+}
+
+/// The offsets of columns (or rows) with the given sizes, separated by the given spacing.
+fn offsets(sizes: &[f64], spacing: f64) -> Vec<f64> {
+    sizes.iter()
+        .scan(spacing, |offset, size| {
+            let start = *offset;
+            *offset += size + spacing;
+            Some(start)
+        })
+        .collect()
+}
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use piet::{kurbo::Rect, Text};
+
+use crate::web::{Sides, WritingMode};
+
+use super::{BlockLayout, BoxEdges, CollapsedMargin, Fragment, FragmentContent, LayoutBox, Layouter};
+
+impl<'a, T> Layouter<'a, T> where T: Text {
+    /// Lays out a block-level box whose writing mode differs from the one of
+    /// the current flow in a containing block of the given width and (if
+    /// definite) height, both relative to the current flow. If the flows
+    /// are orthogonal, the box's lines are as long as the containing block
+    /// is tall (or the viewport, if its height isn't definite).
+    /// See https://www.w3.org/TR/css-writing-modes-3/#orthogonal-flows
+    pub(super) fn layout_flow_block(&mut self, block: &LayoutBox, containing_width: f64, containing_height: Option<f64>) -> BlockLayout<T::TextLayout> {
+        let mode = block.style.writing_mode;
+        let (inline_size, block_size) = if mode.is_vertical() == self.writing_mode.is_vertical() {
+            (containing_width, containing_height)
+        } else {
+            let viewport = self.media.viewport;
+            let viewport_size = if mode.is_vertical() { viewport.height } else { viewport.width };
+            (containing_height.unwrap_or(viewport_size), Some(containing_width))
+        };
+        let margin_right = BoxEdges::of(&block.style, inline_size).margin.right.unwrap_or(0.0);
+        self.layout_flow(mode, margin_right, |this| this.layout_horizontal_block(block, inline_size, block_size, true))
+    }
+
+    /// Lays out a box establishing a flow in the given writing mode (and
+    /// thus an independent formatting context) inside of the current flow.
+    /// The box is laid out by the given function as a horizontal one, then
+    /// rotated into the physical coordinates and those of the current flow.
+    /// The right margin is passed in since block layouts don't track it.
+    pub(super) fn layout_flow(&mut self, mode: WritingMode, margin_right: f64, layout: impl FnOnce(&mut Self) -> BlockLayout<T::TextLayout>) -> BlockLayout<T::TextLayout> {
+        let outer = std::mem::replace(&mut self.writing_mode, mode);
+        let layout = self.in_new_context(layout);
+        self.writing_mode = outer;
+
+        let margin = mode.to_physical(Sides {
+            top: layout.margin_top.value(),
+            right: margin_right,
+            bottom: layout.margin_bottom.value(),
+            left: layout.margin_left,
+        });
+        let mut fragment = layout.fragment;
+        if mode.is_vertical() {
+            let height = fragment.rect.height();
+            rotate_flow(&mut fragment, mode, height);
+        }
+        let margin = outer.to_logical(margin);
+        if outer.is_vertical() {
+            let width = fragment.rect.width();
+            unrotate_rects(&mut fragment, outer, width);
+        }
+        BlockLayout {
+            fragment,
+            margin_left: margin.left,
+            margin_top: CollapsedMargin::new(margin.top),
+            margin_bottom: CollapsedMargin::new(margin.bottom),
+            collapses_through: false,
+        }
+    }
+}
+
+/// Rotates the fragments of a flow in the given vertical writing mode, laid
+/// out as a horizontal box (with the given height), into the physical ones,
+/// where the top of the box is on the right (or on the left for
+/// `vertical-lr`). Text is rotated sideways unless it is upright already.
+/// Boxes of nested flows in other writing modes are physical already, so
+/// they are only moved.
+pub(super) fn rotate_flow<L>(fragment: &mut Fragment<L>, mode: WritingMode, height: f64) {
+    fragment.rect = rotate_rect(fragment.rect, mode, height);
+    fragment.border = mode.to_physical(fragment.border);
+    fragment.style = Arc::new(fragment.style.to_physical());
+    fragment.content = match std::mem::replace(&mut fragment.content, FragmentContent::Box) {
+        FragmentContent::Text(layout) => FragmentContent::VerticalText { layout, upright: false },
+        content => content,
+    };
+    for child in &mut fragment.children {
+        if matches!(child.content, FragmentContent::Box) && child.style.writing_mode != mode {
+            rotate_rects(child, mode, height);
+        } else {
+            rotate_flow(child, mode, height);
+        }
+    }
+}
+
+/// Rotates the rectangles of a fragment and its descendants like `rotate_flow`.
+fn rotate_rects<L>(fragment: &mut Fragment<L>, mode: WritingMode, height: f64) {
+    fragment.rect = rotate_rect(fragment.rect, mode, height);
+    for child in &mut fragment.children {
+        rotate_rects(child, mode, height);
+    }
+}
+
+/// Rotates the rectangles of a physical fragment (with the given width) and
+/// its descendants into the coordinates of a flow in the given vertical
+/// writing mode, i.e. the inverse of `rotate_rects`, so that they end up
+/// where they were (relative to the fragment) when the flow is rotated.
+fn unrotate_rects<L>(fragment: &mut Fragment<L>, mode: WritingMode, width: f64) {
+    let Rect { x0, y0, x1, y1 } = fragment.rect;
+    fragment.rect = match mode {
+        WritingMode::VerticalLr => Rect::new(y0, x0, y1, x1),
+        WritingMode::VerticalRl | WritingMode::HorizontalTb => Rect::new(y0, width - x1, y1, width - x0),
+    };
+    for child in &mut fragment.children {
+        unrotate_rects(child, mode, width);
+    }
+}
+
+/// Rotates a rectangle in a box of the given height laid out horizontally
+/// into the given vertical writing mode.
+fn rotate_rect(rect: Rect, mode: WritingMode, height: f64) -> Rect {
+    let Rect { x0, y0, x1, y1 } = rect;
+    match mode {
+        WritingMode::VerticalLr => Rect::new(y0, x0, y1, x1),
+        WritingMode::VerticalRl | WritingMode::HorizontalTb => Rect::new(height - y1, x0, height - y0, x1),
+    }
+}
@@ -0,0 +1,524 @@
+use std::borrow::Cow;
+use std::io::Read;
+
+use trails_base::{bail, Result};
+
+/// The tags of the tables WOFF2 refers to by index in its table directory.
+const WOFF2_KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post",
+    b"cvt ", b"fpgm", b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT",
+    b"EBLC", b"gasp", b"hdmx", b"kern", b"LTSH", b"PCLT", b"VDMX", b"vhea",
+    b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC", b"JSTF", b"MATH",
+    b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar",
+    b"gvar", b"hsty", b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop",
+    b"trak", b"Zapf", b"Silf", b"Glat", b"Gloc", b"Feat", b"Sill",
+];
+
+/// The maximum ratio between the decompressed and the compressed size of
+/// zlib data, which bounds the (untrusted) lengths of WOFF tables.
+const MAX_ZLIB_RATIO: usize = 1032;
+
+/// Decodes a WOFF or WOFF2 font to the TrueType or OpenType (sfnt) font it
+/// wraps, which the text backends can load. Other fonts are returned as-is.
+/// See https://www.w3.org/TR/WOFF/ and https://www.w3.org/TR/WOFF2/
+pub(super) fn decode_font(data: &[u8]) -> Result<Cow<'_, [u8]>> {
+    match data.get(..4) {
+        Some(b"wOFF") => Ok(Cow::Owned(decode_woff(data)?)),
+        Some(b"wOF2") => Ok(Cow::Owned(decode_woff2(data)?)),
+        _ => Ok(Cow::Borrowed(data)),
+    }
+}
+
+/// A table of an sfnt font.
+struct Table {
+    tag: [u8; 4],
+    data: Vec<u8>,
+}
+
+/// Decodes a WOFF font, whose tables are compressed individually with zlib.
+fn decode_woff(data: &[u8]) -> Result<Vec<u8>> {
+    let mut header = Reader::new(data);
+    header.skip(4)?;
+    let flavor = header.u32()?;
+    header.skip(4)?;
+    let num_tables = header.u16()?;
+    header.skip(30)?;
+
+    let mut tables = Vec::new();
+    for _ in 0..num_tables {
+        let tag = header.tag()?;
+        let offset = header.u32()? as usize;
+        let comp_length = header.u32()? as usize;
+        let orig_length = header.u32()? as usize;
+        header.skip(4)?;
+        let Some(compressed) = data.get(offset..offset + comp_length) else {
+            bail!("WOFF table {} is out of bounds", String::from_utf8_lossy(&tag));
+        };
+        let table_data = if comp_length < orig_length {
+            let mut decompressed = Vec::with_capacity(orig_length.min(comp_length.saturating_mul(MAX_ZLIB_RATIO)));
+            flate2::read::ZlibDecoder::new(compressed).take(orig_length as u64 + 1).read_to_end(&mut decompressed)?;
+            decompressed
+        } else {
+            compressed.to_vec()
+        };
+        if table_data.len() != orig_length {
+            bail!("WOFF table {} has the wrong length", String::from_utf8_lossy(&tag));
+        }
+        tables.push(Table { tag, data: table_data });
+    }
+    write_sfnt(flavor, tables)
+}
+
+/// An entry of the table directory of a WOFF2 font.
+struct Woff2Entry {
+    tag: [u8; 4],
+    orig_length: usize,
+    /// The length of the table in the decompressed data.
+    length: usize,
+    /// Whether the table is stored in a transformed (i.e. preprocessed) form.
+    transformed: bool,
+}
+
+/// Decodes a WOFF2 font, whose tables are compressed together with Brotli
+/// and whose `glyf`, `loca` and `hmtx` tables may be transformed.
+fn decode_woff2(data: &[u8]) -> Result<Vec<u8>> {
+    let mut header = Reader::new(data);
+    header.skip(4)?;
+    let flavor = header.u32()?;
+    header.skip(4)?;
+    let num_tables = header.u16()?;
+    header.skip(6)?;
+    let total_compressed_size = header.u32()? as usize;
+    header.skip(24)?;
+    if &flavor.to_be_bytes() == b"ttcf" {
+        bail!("WOFF2 font collections are not supported");
+    }
+
+    let mut entries = Vec::new();
+    for _ in 0..num_tables {
+        let flags = header.u8()?;
+        let tag = match (flags & 0x3f) as usize {
+            0x3f => header.tag()?,
+            index => *WOFF2_KNOWN_TAGS[index],
+        };
+        let version = flags >> 6;
+        // The null transform of glyf and loca is version 3, that of the other tables 0
+        let transformed = match &tag {
+            b"glyf" | b"loca" => version != 3,
+            _ => version != 0,
+        };
+        let orig_length = header.base128()? as usize;
+        let length = if transformed { header.base128()? as usize } else { orig_length };
+        entries.push(Woff2Entry { tag, orig_length, length, transformed });
+    }
+
+    let Some(compressed) = data.get(header.pos..header.pos + total_compressed_size) else {
+        bail!("WOFF2 data is out of bounds");
+    };
+    let mut decompressed = Vec::new();
+    brotli_decompressor::Decompressor::new(compressed, 4096).read_to_end(&mut decompressed)?;
+
+    let mut offset = 0;
+    let mut tables = Vec::new();
+    for entry in &entries {
+        let Some(table_data) = decompressed.get(offset..offset + entry.length) else {
+            bail!("WOFF2 table {} is out of bounds", String::from_utf8_lossy(&entry.tag));
+        };
+        offset += entry.length;
+        tables.push(Table { tag: entry.tag, data: table_data.to_vec() });
+    }
+
+    // Reconstruct the transformed tables
+    let find = |tag: &[u8; 4]| entries.iter().position(|e| &e.tag == tag);
+    let mut x_mins = Vec::new();
+    if let (Some(glyf), Some(loca)) = (find(b"glyf"), find(b"loca")) {
+        if entries[glyf].transformed {
+            let (glyf_data, loca_data, mins) = reconstruct_glyf(&tables[glyf].data)?;
+            if loca_data.len() != entries[loca].orig_length {
+                bail!("Reconstructed loca table has the wrong length");
+            }
+            tables[glyf].data = glyf_data;
+            tables[loca].data = loca_data;
+            x_mins = mins;
+        }
+    }
+    if let Some(hmtx) = find(b"hmtx").filter(|&i| entries[i].transformed) {
+        let (Some(hhea), Some(maxp)) = (find(b"hhea"), find(b"maxp")) else {
+            bail!("Transformed hmtx table requires hhea and maxp tables");
+        };
+        let num_h_metrics = Reader::at(&tables[hhea].data, 34).u16()? as usize;
+        let num_glyphs = Reader::at(&tables[maxp].data, 4).u16()? as usize;
+        tables[hmtx].data = reconstruct_hmtx(&tables[hmtx].data, num_h_metrics, num_glyphs, &x_mins)?;
+    }
+
+    write_sfnt(flavor, tables)
+}
+
+/// The streams of a transformed `glyf` table.
+struct GlyfStreams<'a> {
+    n_contours: Reader<'a>,
+    n_points: Reader<'a>,
+    flags: Reader<'a>,
+    glyphs: Reader<'a>,
+    composites: Reader<'a>,
+    bbox_bitmap: &'a [u8],
+    bboxes: Reader<'a>,
+    instructions: Reader<'a>,
+    overlap_bitmap: Option<&'a [u8]>,
+}
+
+/// Reconstructs the `glyf` and `loca` tables from a transformed `glyf`
+/// table, returning them along with the minimum x coordinates of the glyphs.
+fn reconstruct_glyf(data: &[u8]) -> Result<(Vec<u8>, Vec<u8>, Vec<i16>)> {
+    let mut header = Reader::new(data);
+    header.skip(2)?;
+    let option_flags = header.u16()?;
+    let num_glyphs = header.u16()? as usize;
+    let index_format = header.u16()?;
+    let mut sizes = [0; 7];
+    for size in &mut sizes {
+        *size = header.u32()? as usize;
+    }
+    let bitmap_size = num_glyphs.div_ceil(32) * 4;
+    let mut streams = GlyfStreams {
+        n_contours: Reader::new(header.bytes(sizes[0])?),
+        n_points: Reader::new(header.bytes(sizes[1])?),
+        flags: Reader::new(header.bytes(sizes[2])?),
+        glyphs: Reader::new(header.bytes(sizes[3])?),
+        composites: Reader::new(header.bytes(sizes[4])?),
+        bbox_bitmap: header.bytes(bitmap_size)?,
+        bboxes: Reader::new(header.bytes(sizes[5].saturating_sub(bitmap_size))?),
+        instructions: Reader::new(header.bytes(sizes[6])?),
+        overlap_bitmap: None,
+    };
+    if option_flags & 1 != 0 {
+        streams.overlap_bitmap = Some(header.bytes(num_glyphs.div_ceil(8))?);
+    }
+
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+    for index in 0..num_glyphs {
+        offsets.push(glyf.len());
+        let x_min = reconstruct_glyph(index, &mut streams, &mut glyf)?;
+        x_mins.push(x_min);
+        while glyf.len() % 4 != 0 {
+            glyf.push(0);
+        }
+    }
+    offsets.push(glyf.len());
+
+    let mut loca = Vec::new();
+    for offset in offsets {
+        if index_format == 0 {
+            loca.extend_from_slice(&((offset / 2) as u16).to_be_bytes());
+        } else {
+            loca.extend_from_slice(&(offset as u32).to_be_bytes());
+        }
+    }
+    Ok((glyf, loca, x_mins))
+}
+
+/// Reconstructs a glyph from the streams of a transformed `glyf` table,
+/// appending it to the given table. Returns the minimum x coordinate.
+fn reconstruct_glyph(index: usize, streams: &mut GlyfStreams<'_>, glyf: &mut Vec<u8>) -> Result<i16> {
+    let n_contours = streams.n_contours.i16()?;
+    let has_bbox = streams.bbox_bitmap[index / 8] & (0x80 >> (index % 8)) != 0;
+    let mut bbox = if has_bbox {
+        Some([streams.bboxes.i16()?, streams.bboxes.i16()?, streams.bboxes.i16()?, streams.bboxes.i16()?])
+    } else {
+        None
+    };
+
+    if n_contours == 0 {
+        // An empty glyph
+        return Ok(0);
+    }
+
+    if n_contours < 0 {
+        // A composite glyph, whose components are stored as-is
+        let Some(bbox) = bbox else {
+            bail!("Composite glyph {} has no bounding box", index);
+        };
+        let start = streams.composites.pos;
+        let mut has_instructions = false;
+        loop {
+            let flags = streams.composites.u16()?;
+            has_instructions |= flags & 0x0100 != 0;
+            let mut length = 2 + if flags & 0x0001 != 0 { 4 } else { 2 };
+            if flags & 0x0008 != 0 {
+                length += 2;
+            } else if flags & 0x0040 != 0 {
+                length += 4;
+            } else if flags & 0x0080 != 0 {
+                length += 8;
+            }
+            streams.composites.skip(length)?;
+            if flags & 0x0020 == 0 {
+                break;
+            }
+        }
+        let components = &streams.composites.data[start..streams.composites.pos];
+        glyf.extend_from_slice(&n_contours.to_be_bytes());
+        glyf.extend(bbox.iter().flat_map(|v| v.to_be_bytes()));
+        glyf.extend_from_slice(components);
+        if has_instructions {
+            let length = streams.glyphs.u255_16()? as usize;
+            glyf.extend_from_slice(&(length as u16).to_be_bytes());
+            glyf.extend_from_slice(streams.instructions.bytes(length)?);
+        }
+        return Ok(bbox[0]);
+    }
+
+    // A simple glyph, whose points are stored as triplets of a flag and deltas
+    let mut end_points = Vec::with_capacity(n_contours as usize);
+    let mut total_points = 0usize;
+    for _ in 0..n_contours {
+        total_points += streams.n_points.u255_16()? as usize;
+        end_points.push(total_points.wrapping_sub(1) as u16);
+    }
+    let mut points = Vec::with_capacity(total_points);
+    let (mut x, mut y) = (0i32, 0i32);
+    for _ in 0..total_points {
+        let flag = streams.flags.u8()?;
+        let (dx, dy) = decode_triplet(flag & 0x7f, &mut streams.glyphs)?;
+        x += dx;
+        y += dy;
+        points.push((x, y, flag & 0x80 == 0));
+    }
+    let instruction_length = streams.glyphs.u255_16()? as usize;
+    let instructions = streams.instructions.bytes(instruction_length)?;
+
+    let bbox = *bbox.get_or_insert_with(|| {
+        let (mut x_min, mut y_min, mut x_max, mut y_max) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for &(x, y, _) in &points {
+            x_min = x_min.min(x);
+            y_min = y_min.min(y);
+            x_max = x_max.max(x);
+            y_max = y_max.max(y);
+        }
+        if points.is_empty() {
+            [0; 4]
+        } else {
+            [x_min as i16, y_min as i16, x_max as i16, y_max as i16]
+        }
+    });
+    let overlaps = streams.overlap_bitmap.is_some_and(|bitmap| bitmap[index / 8] & (0x80 >> (index % 8)) != 0);
+
+    glyf.extend_from_slice(&n_contours.to_be_bytes());
+    glyf.extend(bbox.iter().flat_map(|v| v.to_be_bytes()));
+    glyf.extend(end_points.iter().flat_map(|v| v.to_be_bytes()));
+    glyf.extend_from_slice(&(instruction_length as u16).to_be_bytes());
+    glyf.extend_from_slice(instructions);
+
+    // Encode the coordinates as (short) deltas again
+    let mut flags = Vec::with_capacity(points.len());
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+    let (mut last_x, mut last_y) = (0, 0);
+    for (i, &(x, y, on_curve)) in points.iter().enumerate() {
+        let mut flag = u8::from(on_curve);
+        if i == 0 && overlaps {
+            flag |= 0x40;
+        }
+        flag |= encode_delta(x - last_x, 0x02, 0x10, &mut xs);
+        flag |= encode_delta(y - last_y, 0x04, 0x20, &mut ys);
+        flags.push(flag);
+        last_x = x;
+        last_y = y;
+    }
+    glyf.extend_from_slice(&flags);
+    glyf.extend_from_slice(&xs);
+    glyf.extend_from_slice(&ys);
+    Ok(bbox[0])
+}
+
+/// Decodes the coordinate deltas of a point in a transformed `glyf` table
+/// from its flag and the bytes following in the glyph stream.
+fn decode_triplet(flag: u8, glyphs: &mut Reader<'_>) -> Result<(i32, i32)> {
+    let with_sign = |flag: u8, value: i32| if flag & 1 != 0 { value } else { -value };
+    let flag_value = flag as i32;
+    Ok(if flag < 10 {
+        let b0 = glyphs.u8()? as i32;
+        (0, with_sign(flag, ((flag_value & 14) << 7) + b0))
+    } else if flag < 20 {
+        let b0 = glyphs.u8()? as i32;
+        (with_sign(flag, (((flag_value - 10) & 14) << 7) + b0), 0)
+    } else if flag < 84 {
+        let b0 = flag_value - 20;
+        let b1 = glyphs.u8()? as i32;
+        (with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)), with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)))
+    } else if flag < 120 {
+        let b0 = flag_value - 84;
+        let (b1, b2) = (glyphs.u8()? as i32, glyphs.u8()? as i32);
+        (with_sign(flag, 1 + ((b0 / 12) << 8) + b1), with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2))
+    } else if flag < 124 {
+        let (b1, b2, b3) = (glyphs.u8()? as i32, glyphs.u8()? as i32, glyphs.u8()? as i32);
+        (with_sign(flag, (b1 << 4) + (b2 >> 4)), with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3))
+    } else {
+        let (b1, b2, b3, b4) = (glyphs.u8()? as i32, glyphs.u8()? as i32, glyphs.u8()? as i32, glyphs.u8()? as i32);
+        (with_sign(flag, (b1 << 8) + b2), with_sign(flag >> 1, (b3 << 8) + b4))
+    })
+}
+
+/// Appends a coordinate delta of a simple glyph, returning its flags given
+/// those for a short delta and for a positive short (or same) delta.
+fn encode_delta(delta: i32, short: u8, same_or_positive: u8, out: &mut Vec<u8>) -> u8 {
+    if delta == 0 {
+        same_or_positive
+    } else if delta.abs() < 256 {
+        out.push(delta.unsigned_abs() as u8);
+        short | if delta > 0 { same_or_positive } else { 0 }
+    } else {
+        out.extend_from_slice(&(delta as i16).to_be_bytes());
+        0
+    }
+}
+
+/// Reconstructs the `hmtx` table from a transformed one, whose left side
+/// bearings may be omitted if they equal the minimum x of the glyphs.
+fn reconstruct_hmtx(data: &[u8], num_h_metrics: usize, num_glyphs: usize, x_mins: &[i16]) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(data);
+    let flags = reader.u8()?;
+    if flags & 0x03 != 0 && x_mins.len() < num_glyphs {
+        bail!("Transformed hmtx table requires a transformed glyf table");
+    }
+    let mut advances = Vec::with_capacity(num_h_metrics);
+    for _ in 0..num_h_metrics {
+        advances.push(reader.u16()?);
+    }
+    // Proportional glyphs have a metric of advance and side bearing, the
+    // remaining (monospaced) ones only a side bearing
+    let mut hmtx = Vec::new();
+    for i in 0..num_glyphs.max(num_h_metrics) {
+        let omitted = flags & if i < num_h_metrics { 0x01 } else { 0x02 } != 0;
+        let lsb = match x_mins.get(i) {
+            Some(&x_min) if omitted => x_min,
+            _ => reader.i16()?,
+        };
+        if let Some(advance) = advances.get(i) {
+            hmtx.extend_from_slice(&advance.to_be_bytes());
+        }
+        hmtx.extend_from_slice(&lsb.to_be_bytes());
+    }
+    Ok(hmtx)
+}
+
+/// Writes an sfnt font from its flavor (i.e. sfnt version) and tables.
+fn write_sfnt(flavor: u32, mut tables: Vec<Table>) -> Result<Vec<u8>> {
+    tables.sort_by_key(|t| t.tag);
+    // The header stores the size of the table directory in 16 bits
+    let num_tables = tables.len() as u32;
+    if num_tables * 16 > u16::MAX as u32 {
+        bail!("Too many tables for an sfnt font: {}", num_tables);
+    }
+    let entry_selector = num_tables.max(1).ilog2();
+    let search_range = (1 << entry_selector) * 16;
+    let mut sfnt = Vec::new();
+    sfnt.extend_from_slice(&flavor.to_be_bytes());
+    for value in [num_tables, search_range, entry_selector, num_tables * 16 - search_range] {
+        sfnt.extend_from_slice(&(value as u16).to_be_bytes());
+    }
+    let mut offset = 12 + 16 * tables.len();
+    for table in &tables {
+        sfnt.extend_from_slice(&table.tag);
+        sfnt.extend_from_slice(&checksum(&table.data).to_be_bytes());
+        sfnt.extend_from_slice(&(offset as u32).to_be_bytes());
+        sfnt.extend_from_slice(&(table.data.len() as u32).to_be_bytes());
+        offset += table.data.len().next_multiple_of(4);
+    }
+    for table in &tables {
+        sfnt.extend_from_slice(&table.data);
+        sfnt.resize(sfnt.len().next_multiple_of(4), 0);
+    }
+    Ok(sfnt)
+}
+
+/// The checksum of an sfnt table, i.e. the sum of its (zero-padded) words.
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4)
+        .map(|chunk| {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_be_bytes(word)
+        })
+        .fold(0, u32::wrapping_add)
+}
+
+/// Reads big-endian values from font data.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self::at(data, 0)
+    }
+
+    fn at(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let Some(bytes) = self.data.get(self.pos..self.pos + length) else {
+            bail!("Unexpected end of font data");
+        };
+        self.pos += length;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, length: usize) -> Result<()> {
+        self.bytes(length).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn tag(&mut self) -> Result<[u8; 4]> {
+        Ok(self.bytes(4)?.try_into()?)
+    }
+
+    /// Reads a `UIntBase128`, i.e. a variable-length integer of 7-bit groups.
+    fn base128(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for i in 0..5 {
+            let byte = self.u8()?;
+            if i == 0 && byte == 0x80 {
+                bail!("UIntBase128 with leading zeros");
+            }
+            if value & 0xfe00_0000 != 0 {
+                bail!("UIntBase128 overflows");
+            }
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("UIntBase128 is too long")
+    }
+
+    /// Reads a `255UInt16`, i.e. a variable-length 16-bit integer.
+    fn u255_16(&mut self) -> Result<u16> {
+        Ok(match self.u8()? {
+            253 => self.u16()?,
+            254 => self.u8()? as u16 + 506,
+            255 => self.u8()? as u16 + 253,
+            code => code as u16,
+        })
+    }
+}
@@ -0,0 +1,57 @@
+use piet::kurbo::{Point, Rect, Vec2};
+
+use super::{Enclosing, Fragment, ScrollAreas};
+
+/// A clickable area on the page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkArea {
+    /// The clickable area.
+    pub area: Rect,
+    /// The link target.
+    pub href: String,
+    /// Whether the area is inside a fixed box, i.e. moves along with the viewport.
+    pub fixed: bool,
+    /// The index of the innermost scroll area enclosing the area, if any.
+    pub scroll_area: Option<usize>,
+}
+
+/// Link areas on the page.
+// TODO: Explore using a more efficient data structure, e.g. a quadtree
+#[derive(Debug, Clone)]
+pub struct LinkAreas {
+    /// A list of (clickable) link areas.
+    pub areas: Vec<LinkArea>,
+}
+
+impl LinkAreas {
+    /// Collects the link areas from a fragment tree with the given scroll areas.
+    pub fn from_fragments<L>(root: &Fragment<L>, scroll_areas: &ScrollAreas) -> Self {
+        let mut areas = Vec::new();
+        collect_link_areas(root, Enclosing::default(), scroll_areas, &mut areas);
+        Self { areas }
+    }
+
+    /// Finds the link area at the given point on a page scrolled by the
+    /// given offset, whose scroll areas are scrolled as given.
+    pub fn find(&self, point: Point, scroll_offset: Vec2, scroll_areas: &ScrollAreas) -> Option<&LinkArea> {
+        // Later (i.e. nested or subsequently painted) areas take precedence
+        self.areas.iter().rev().find(|a| {
+            scroll_areas.to_content(point, a.scroll_area, a.fixed, scroll_offset)
+                .is_some_and(|point| a.area.contains(point))
+        })
+    }
+}
+
+/// Collects the link areas from a fragment and its descendants inside the
+/// given scroll areas.
+fn collect_link_areas<L>(fragment: &Fragment<L>, enclosing: Enclosing, scroll_areas: &ScrollAreas, areas: &mut Vec<LinkArea>) {
+    let index = scroll_areas.index_of(fragment);
+    if let Some(href) = &fragment.href {
+        let outer = enclosing.enter(fragment, None);
+        areas.push(LinkArea { area: fragment.rect, href: href.clone(), fixed: outer.fixed, scroll_area: outer.area });
+    }
+    let inner = enclosing.enter(fragment, index);
+    for child in &fragment.children {
+        collect_link_areas(child, inner, scroll_areas, areas);
+    }
+}
@@ -1,5 +1,20 @@
-mod renderer;
+mod background;
+mod effects;
+mod layout;
+mod links;
+mod paint;
+mod scroll;
+mod style;
+mod svg;
 mod tags;
 
-pub use renderer::*;
+pub(crate) use background::*;
+pub(crate) use effects::*;
+pub use layout::*;
+pub use links::*;
+pub use paint::*;
+pub use scroll::*;
+pub use style::StyleCache;
+pub(crate) use style::*;
+pub(crate) use svg::*;
 pub(crate) use tags::*;
@@ -0,0 +1,451 @@
+use std::f64::consts::FRAC_PI_2;
+
+use piet::{kurbo::{Affine, BezPath, Line, Point, Rect, Size, Vec2}, ImageFormat, InterpolationMode, LineCap, RenderContext, StrokeStyle, TextLayout};
+use trails_base::log::warn;
+use trails_model::css::Color;
+
+use super::{append_rounded_rect, paint_background_layers, paint_box_shadows, paint_svg, rounded_rect, shadow_bounds, text_baseline, BorderStyle, ComputedStyle, CornerRadii, Fragment, FragmentContent, ImageData, LayoutResult, RasterImage, Position, ScrollArea, ScrollAreas, Side};
+
+/// Paints groups of content into offscreen layers, which are then drawn
+/// with an opacity. Render contexts can't do this by themselves, but it is
+/// needed to composite a translucent box and its content as a whole.
+pub trait Layers<L> {
+    /// Paints the given content into an offscreen layer covering the given
+    /// area (in the current coordinates), then draws the layer with the
+    /// given opacity.
+    fn paint_layer<P>(&mut self, paint: &mut P, area: Rect, opacity: f64, content: &impl LayerContent<L>) where P: RenderContext<TextLayout = L>;
+}
+
+/// Content painted into an offscreen layer.
+pub trait LayerContent<L> {
+    /// Paints the content, using the given layers for nested groups.
+    fn paint<P, Y>(&self, paint: &mut P, layers: &mut Y) where P: RenderContext<TextLayout = L>, Y: Layers<L>;
+}
+
+/// A translucent stacking context, which is painted into a layer.
+struct Group<'f, L> {
+    fragment: &'f Fragment<L>,
+    region: Rect,
+    viewport: Rect,
+    layout: &'f LayoutResult<L>,
+}
+
+impl<L> LayerContent<L> for Group<'_, L> {
+    fn paint<P, Y>(&self, paint: &mut P, layers: &mut Y) where P: RenderContext<TextLayout = L>, Y: Layers<L> {
+        paint_stacking_order(paint, self.fragment, self.region, self.viewport, self.layout, layers);
+    }
+}
+
+/// A positioned (or translucent) descendant of a stacking context.
+struct Positioned<'f, L> {
+    fragment: &'f Fragment<L>,
+    /// The rectangle of the parent fragment.
+    parent: Rect,
+    /// The scroll areas between the stacking context (inclusive) and the
+    /// fragment that clip and move it, from the outermost.
+    scroll_areas: Vec<&'f ScrollArea>,
+}
+
+/// Paints a laid out document, skipping fragments outside the given
+/// region (e.g. the visible part of a scrolled page). Fixed and sticky
+/// boxes are painted relative to the given (scrolled) viewport, translucent
+/// ones are composited using the given layers.
+pub fn paint_layout<P, Y>(paint: &mut P, layout: &LayoutResult<P::TextLayout>, size: Size, region: Rect, viewport: Rect, layers: &mut Y) where P: RenderContext, Y: Layers<P::TextLayout> {
+    // Draw the canvas, which is white unless the document specifies a
+    // background, whose images are positioned relative to the root
+    let canvas = Rect::from_origin_size(Point::ZERO, size);
+    paint.fill(canvas, &to_piet_color(Color::WHITE));
+    if let Some(style) = &layout.canvas_background {
+        let origin = layout.root.as_ref().map_or(canvas, |r| r.rect);
+        paint_background_layers(paint, style, canvas, origin, &layout.background_images, region);
+    }
+
+    if let Some(root) = &layout.root {
+        paint_stacking_context(paint, root, region, viewport, layout, layers);
+    }
+}
+
+/// Paints a fragment that forms a stacking context, which is composited as
+/// a group in a layer if it is translucent.
+/// See https://www.w3.org/TR/css-color-3/#transparency
+fn paint_stacking_context<P, Y>(paint: &mut P, fragment: &Fragment<P::TextLayout>, region: Rect, viewport: Rect, layout: &LayoutResult<P::TextLayout>, layers: &mut Y) where P: RenderContext, Y: Layers<P::TextLayout> {
+    let opacity = fragment.style.opacity;
+    if opacity >= 1.0 {
+        paint_stacking_order(paint, fragment, region, viewport, layout, layers);
+    } else if opacity > 0.0 {
+        let area = painted_area(fragment, region);
+        if area.area() > 0.0 {
+            layers.paint_layer(paint, area, opacity, &Group { fragment, region, viewport, layout });
+        }
+    }
+}
+
+/// Paints the layers of a stacking context in order, i.e. the fragment
+/// itself, its positioned descendants with negative `z-index`, its other
+/// descendants and finally the remaining positioned descendants in
+/// `z-index` order. Positioned (and translucent) descendants are painted
+/// atomically, even if their `z-index` is `auto`.
+/// See https://www.w3.org/TR/CSS2/zindex.html
+fn paint_stacking_order<P, Y>(paint: &mut P, fragment: &Fragment<P::TextLayout>, region: Rect, viewport: Rect, layout: &LayoutResult<P::TextLayout>, layers: &mut Y) where P: RenderContext, Y: Layers<P::TextLayout> {
+    let own_area = layout.scroll_areas.get(fragment);
+    let mut positioned = Vec::new();
+    collect_positioned(fragment, own_area, &[], &layout.scroll_areas, &mut positioned);
+    // Sorting is stable, so fragments with the same z-index stay in tree order
+    positioned.sort_by_key(|p| p.fragment.style.z_index.unwrap_or(0));
+    let negative = positioned.partition_point(|p| p.fragment.style.z_index.unwrap_or(0) < 0);
+
+    paint_self(paint, fragment, region, layout);
+    for child in &positioned[..negative] {
+        paint_positioned(paint, child, region, viewport, layout, layers);
+    }
+    paint_in_scroll_areas(paint, own_area.as_slice(), region, |paint, region| paint_children(paint, fragment, region, layout));
+    for child in &positioned[negative..] {
+        paint_positioned(paint, child, region, viewport, layout, layers);
+    }
+}
+
+/// The area painted by a fragment and its descendants (including their
+/// shadows) within the given region. Fixed and sticky descendants move
+/// with the viewport, so they may be painted anywhere in it.
+fn painted_area<L>(fragment: &Fragment<L>, region: Rect) -> Rect {
+    fragment.children.iter()
+        .map(|child| match child.style.position {
+            Position::Fixed | Position::Sticky if child.is_positioned() => region,
+            _ => painted_area(child, region),
+        })
+        .fold(shadow_bounds(&fragment.style, fragment.rect), |area, child_area| area.union(child_area))
+        .intersect(region)
+}
+
+/// Collects the outermost positioned (or translucent) descendants of a
+/// fragment with the given scroll area, passing through the given other
+/// scroll areas. Fixed descendants escape all of them, absolutely positioned
+/// ones those that aren't the fragment's own (since it is their containing
+/// block).
+fn collect_positioned<'f, L>(fragment: &'f Fragment<L>, own_area: Option<&'f ScrollArea>, between: &[&'f ScrollArea], scroll_areas: &'f ScrollAreas, positioned: &mut Vec<Positioned<'f, L>>) {
+    for child in &fragment.children {
+        if child.is_stacking_context() {
+            let areas = match child.style.position {
+                Position::Fixed => Vec::new(),
+                Position::Absolute => own_area.into_iter().collect(),
+                _ => own_area.into_iter().chain(between.iter().copied()).collect(),
+            };
+            positioned.push(Positioned { fragment: child, parent: fragment.rect, scroll_areas: areas });
+        } else if let Some(area) = scroll_areas.get(child) {
+            let between: Vec<_> = between.iter().copied().chain([area]).collect();
+            collect_positioned(child, own_area, &between, scroll_areas, positioned);
+        } else {
+            collect_positioned(child, own_area, between, scroll_areas, positioned);
+        }
+    }
+}
+
+/// Paints a positioned (or translucent) fragment, moving fixed fragments
+/// along with the viewport and sticky ones into their nearest scroll area.
+fn paint_positioned<P, Y>(paint: &mut P, positioned: &Positioned<P::TextLayout>, region: Rect, viewport: Rect, layout: &LayoutResult<P::TextLayout>, layers: &mut Y) where P: RenderContext, Y: Layers<P::TextLayout> {
+    let fragment = positioned.fragment;
+    let viewport = positioned.scroll_areas.last().map_or(viewport, |a| a.area + a.offset);
+    paint_in_scroll_areas(paint, &positioned.scroll_areas, region, |paint, region| {
+        let offset = match fragment.style.position {
+            Position::Fixed if fragment.is_positioned() => viewport.origin().to_vec2(),
+            Position::Sticky if fragment.is_positioned() => sticky_offset(fragment, positioned.parent, viewport),
+            _ => Vec2::ZERO,
+        };
+        if offset == Vec2::ZERO {
+            paint_stacking_context(paint, fragment, region, viewport, layout, layers);
+        } else {
+            let result = paint.with_save(|paint| {
+                paint.transform(Affine::translate(offset));
+                paint_stacking_context(paint, fragment, region - offset, viewport - offset, layout, layers);
+                Ok(())
+            });
+            if let Err(e) = result {
+                warn!("Could not paint positioned fragment: {}", e);
+            }
+        }
+    });
+}
+
+/// Runs the given painting function clipped to the given (nested) scroll
+/// areas and moved by their scroll offsets, passing the region in the
+/// coordinates of the innermost area's content.
+fn paint_in_scroll_areas<P>(paint: &mut P, areas: &[&ScrollArea], region: Rect, f: impl FnOnce(&mut P, Rect)) where P: RenderContext {
+    if areas.is_empty() {
+        f(paint, region);
+        return;
+    }
+    let result = paint.with_save(|paint| {
+        let mut region = region;
+        for area in areas {
+            paint.clip(area.area);
+            paint.transform(Affine::translate(-area.offset));
+            region = region.intersect(area.area) + area.offset;
+        }
+        f(paint, region);
+        Ok(())
+    });
+    if let Err(e) = result {
+        warn!("Could not paint scrolled content: {}", e);
+    }
+}
+
+/// The offset that keeps a sticky fragment within the viewport, shifted by
+/// its insets, as long as it stays inside its parent.
+/// See https://www.w3.org/TR/css-position-3/#stickypos-insets
+fn sticky_offset<L>(fragment: &Fragment<L>, parent: Rect, viewport: Rect) -> Vec2 {
+    let inset = &fragment.style.inset;
+    let rect = fragment.rect;
+    let x = match (inset.left.resolve(viewport.width()), inset.right.resolve(viewport.width())) {
+        (Some(left), _) => (viewport.x0 + left - rect.x0).min(parent.x1 - rect.x1).max(0.0),
+        (None, Some(right)) => (viewport.x1 - right - rect.x1).max(parent.x0 - rect.x0).min(0.0),
+        (None, None) => 0.0,
+    };
+    let y = match (inset.top.resolve(viewport.height()), inset.bottom.resolve(viewport.height())) {
+        (Some(top), _) => (viewport.y0 + top - rect.y0).min(parent.y1 - rect.y1).max(0.0),
+        (None, Some(bottom)) => (viewport.y1 - bottom - rect.y1).max(parent.y0 - rect.y0).min(0.0),
+        (None, None) => 0.0,
+    };
+    Vec2::new(x, y)
+}
+
+/// Paints a fragment and its descendants, except for positioned (and
+/// translucent) ones.
+fn paint_fragment<P>(paint: &mut P, fragment: &Fragment<P::TextLayout>, region: Rect, layout: &LayoutResult<P::TextLayout>) where P: RenderContext {
+    paint_self(paint, fragment, region, layout);
+    let area = layout.scroll_areas.get(fragment);
+    paint_in_scroll_areas(paint, area.as_slice(), region, |paint, region| paint_children(paint, fragment, region, layout));
+}
+
+/// Paints the descendants of a fragment, except for positioned (and
+/// translucent) ones.
+fn paint_children<P>(paint: &mut P, fragment: &Fragment<P::TextLayout>, region: Rect, layout: &LayoutResult<P::TextLayout>) where P: RenderContext {
+    for child in fragment.children.iter().filter(|c| !c.is_stacking_context()) {
+        paint_fragment(paint, child, region, layout);
+    }
+}
+
+/// Paints a fragment without its descendants, i.e. the shadows, background
+/// and borders of a box, a piece of text or an image. Invisible fragments
+/// aren't painted.
+fn paint_self<P>(paint: &mut P, fragment: &Fragment<P::TextLayout>, region: Rect, layout: &LayoutResult<P::TextLayout>) where P: RenderContext {
+    let style = &fragment.style;
+    if !style.visibility.is_visible() {
+        return;
+    }
+    match &fragment.content {
+        FragmentContent::Box => if shadow_bounds(style, fragment.rect).intersect(region).area() > 0.0 {
+            paint_box_shadows(paint, style, fragment.rect, fragment.border, false);
+            paint_background(paint, fragment, layout, region);
+            paint_box_shadows(paint, style, fragment.rect, fragment.border, true);
+            paint_borders(paint, fragment);
+        },
+        FragmentContent::Text(layout) => if fragment.rect.intersect(region).area() > 0.0 {
+            paint.draw_text(layout, fragment.rect.origin());
+            paint_text_decorations(paint, fragment.rect, style, layout);
+        },
+        // TODO: Draw the decorations of upright text beside it
+        FragmentContent::VerticalText { layout, upright: true } => if fragment.rect.intersect(region).area() > 0.0 {
+            paint.draw_text(layout, fragment.rect.origin());
+        },
+        FragmentContent::VerticalText { layout, upright: false } => if fragment.rect.intersect(region).area() > 0.0 {
+            // Sideways text is drawn with its top facing the right edge
+            let rect = fragment.rect;
+            let result = paint.with_save(|paint| {
+                paint.transform(Affine::translate((rect.x1, rect.y0)) * Affine::rotate(FRAC_PI_2));
+                paint.draw_text(layout, Point::ZERO);
+                paint_text_decorations(paint, Rect::from_origin_size(Point::ZERO, layout.size()), style, layout);
+                Ok(())
+            });
+            if let Err(e) = result {
+                warn!("Could not paint vertical text: {}", e);
+            }
+        },
+        // TODO: Clip images to the rounded corners of their boxes
+        FragmentContent::Image(image) => if fragment.rect.intersect(region).area() > 0.0 {
+            match &**image {
+                ImageData::Raster(image) => paint_raster_image(paint, fragment.rect, image),
+                ImageData::Vector(tree) => paint_svg(paint, fragment.rect, tree),
+            }
+        },
+    }
+}
+
+/// Paints a bitmap image scaled to the given rectangle.
+fn paint_raster_image<P>(paint: &mut P, rect: Rect, image: &RasterImage) where P: RenderContext {
+    // TODO: Keep the images created by the backend across paints
+    match paint.make_image(image.width, image.height, &image.pixels, ImageFormat::RgbaSeparate) {
+        Ok(backend_image) => paint.draw_image(&backend_image, rect, InterpolationMode::Bilinear),
+        Err(e) => warn!("Could not create image: {}", e),
+    }
+}
+
+/// Paints the decorations propagated to a piece of text with the given
+/// style across the given rectangle.
+/// See https://www.w3.org/TR/css-text-decor-3/#line-decoration
+fn paint_text_decorations<P>(paint: &mut P, rect: Rect, style: &ComputedStyle, layout: &P::TextLayout) where P: RenderContext {
+    let font_size = style.font_size;
+    let thickness = (font_size / 14.0).max(1.0);
+    let baseline = rect.y0 + text_baseline(layout);
+    for decoration in &style.text_decorations {
+        let line = decoration.line;
+        let offsets = [
+            line.underline.then_some(baseline + font_size * 0.1),
+            line.overline.then_some(rect.y0 + thickness / 2.0),
+            line.line_through.then_some(baseline - font_size * 0.3),
+        ];
+        let color = to_piet_color(decoration.color);
+        for y in offsets.into_iter().flatten() {
+            paint.fill(Rect::new(rect.x0, y - thickness / 2.0, rect.x1, y + thickness / 2.0), &color);
+        }
+    }
+}
+
+/// Paints the background of a box fragment across its (rounded) border box,
+/// with the images positioned relative to its padding box.
+fn paint_background<P>(paint: &mut P, fragment: &Fragment<P::TextLayout>, layout: &LayoutResult<P::TextLayout>, region: Rect) where P: RenderContext {
+    if !fragment.style.has_background() {
+        return;
+    }
+    let rect = fragment.rect;
+    let padding_box = padding_box(fragment);
+    let radii = CornerRadii::of(&fragment.style.border_radius, rect);
+    if radii.is_zero() {
+        paint_background_layers(paint, &fragment.style, rect, padding_box, &layout.background_images, region);
+        return;
+    }
+    let result = paint.with_save(|paint| {
+        paint.clip(rounded_rect(rect, &radii));
+        paint_background_layers(paint, &fragment.style, rect, padding_box, &layout.background_images, region);
+        Ok(())
+    });
+    if let Err(e) = result {
+        warn!("Could not paint rounded background: {}", e);
+    }
+}
+
+/// The padding box of a box fragment, i.e. its border box without the borders.
+fn padding_box<L>(fragment: &Fragment<L>) -> Rect {
+    let rect = fragment.rect;
+    let border = fragment.border;
+    Rect::new(rect.x0 + border.left, rect.y0 + border.top, rect.x1 - border.right, rect.y1 - border.bottom)
+}
+
+/// Paints the borders of a box fragment.
+fn paint_borders<P>(paint: &mut P, fragment: &Fragment<P::TextLayout>) where P: RenderContext {
+    let rect = fragment.rect;
+    let radii = CornerRadii::of(&fragment.style.border_radius, rect);
+    if !radii.is_zero() {
+        paint_rounded_borders(paint, fragment, &radii);
+        return;
+    }
+    let widths = fragment.border;
+    let colors = fragment.style.border_colors();
+    for side in Side::ALL {
+        let width = *widths.get(side);
+        let style = *fragment.style.border_style.get(side);
+        if width <= 0.0 || !style.is_visible() {
+            continue;
+        }
+        // The area covered by the border, the vertical borders are drawn between the horizontal ones
+        let area = match side {
+            Side::Top => Rect::new(rect.x0, rect.y0, rect.x1, rect.y0 + width),
+            Side::Bottom => Rect::new(rect.x0, rect.y1 - width, rect.x1, rect.y1),
+            Side::Left => Rect::new(rect.x0, rect.y0 + widths.top, rect.x0 + width, rect.y1 - widths.bottom),
+            Side::Right => Rect::new(rect.x1 - width, rect.y0 + widths.top, rect.x1, rect.y1 - widths.bottom),
+        };
+        let color = *colors.get(side);
+        match style {
+            BorderStyle::Dashed | BorderStyle::Dotted => {
+                let center = area.center();
+                let line = match side {
+                    Side::Top | Side::Bottom => Line::new((area.x0, center.y), (area.x1, center.y)),
+                    Side::Left | Side::Right => Line::new((center.x, area.y0), (center.x, area.y1)),
+                };
+                let mut stroke_style = StrokeStyle::new();
+                if style == BorderStyle::Dotted {
+                    // Zero-length dashes with round caps are drawn as dots
+                    stroke_style.set_dash_pattern(vec![0.0, 2.0 * width]);
+                    stroke_style.set_line_cap(LineCap::Round);
+                } else {
+                    stroke_style.set_dash_pattern(vec![2.0 * width, width]);
+                }
+                paint.stroke_styled(line, &to_piet_color(color), width, &stroke_style);
+            },
+            BorderStyle::Double => {
+                // Two lines separated by a gap, each a third of the width
+                let third = width / 3.0;
+                let (outer, inner) = match side {
+                    Side::Top => (area.with_size((area.width(), third)), Rect::new(area.x0, area.y1 - third, area.x1, area.y1)),
+                    Side::Bottom => (Rect::new(area.x0, area.y1 - third, area.x1, area.y1), area.with_size((area.width(), third))),
+                    Side::Left => (area.with_size((third, area.height())), Rect::new(area.x1 - third, area.y0, area.x1, area.y1)),
+                    Side::Right => (Rect::new(area.x1 - third, area.y0, area.x1, area.y1), area.with_size((third, area.height()))),
+                };
+                paint.fill(outer, &to_piet_color(color));
+                paint.fill(inner, &to_piet_color(color));
+            },
+            _ => paint.fill(area, &to_piet_color(shaded_border_color(style, side, color))),
+        }
+    }
+}
+
+/// Paints the borders of a box fragment with rounded corners, each filling
+/// the area between the outer and inner edges of the border on its side,
+/// which is divided diagonally from the neighboring sides at the corners.
+// TODO: Draw dashed, dotted and double borders with rounded corners
+fn paint_rounded_borders<P>(paint: &mut P, fragment: &Fragment<P::TextLayout>, radii: &CornerRadii) where P: RenderContext {
+    let Rect { x0, y0, x1, y1 } = fragment.rect;
+    let inner = padding_box(fragment);
+    let mut ring = rounded_rect(fragment.rect, radii);
+    append_rounded_rect(&mut ring, inner, &radii.inner(fragment.border), true);
+    let colors = fragment.style.border_colors();
+    let result = paint.with_save(|paint| {
+        paint.clip(ring);
+        for side in Side::ALL {
+            let style = *fragment.style.border_style.get(side);
+            if *fragment.border.get(side) <= 0.0 || !style.is_visible() {
+                continue;
+            }
+            let corners = match side {
+                Side::Top => [(x0, y0), (x1, y0), (inner.x1, inner.y0), (inner.x0, inner.y0)],
+                Side::Right => [(x1, y0), (x1, y1), (inner.x1, inner.y1), (inner.x1, inner.y0)],
+                Side::Bottom => [(x1, y1), (x0, y1), (inner.x0, inner.y1), (inner.x1, inner.y1)],
+                Side::Left => [(x0, y1), (x0, y0), (inner.x0, inner.y0), (inner.x0, inner.y1)],
+            };
+            let mut area = BezPath::new();
+            area.move_to(corners[0]);
+            for corner in &corners[1..] {
+                area.line_to(*corner);
+            }
+            area.close_path();
+            paint.fill(area, &to_piet_color(shaded_border_color(style, side, *colors.get(side))));
+        }
+        Ok(())
+    });
+    if let Err(e) = result {
+        warn!("Could not paint rounded borders: {}", e);
+    }
+}
+
+/// The color of a border on the given side, which approximates the 3D
+/// effect of grooved, ridged, inset and outset borders by shading the
+/// top/left or bottom/right sides.
+fn shaded_border_color(style: BorderStyle, side: Side, color: Color) -> Color {
+    match style {
+        BorderStyle::Groove | BorderStyle::Ridge | BorderStyle::Inset | BorderStyle::Outset => {
+            let top_left = matches!(side, Side::Top | Side::Left);
+            let darker = matches!(style, BorderStyle::Inset | BorderStyle::Groove) == top_left;
+            if darker { shade(color, 0.6) } else { color }
+        },
+        _ => color,
+    }
+}
+
+/// Darkens a color by the given factor.
+fn shade(color: Color, factor: f64) -> Color {
+    Color { red: color.red * factor, green: color.green * factor, blue: color.blue * factor, alpha: color.alpha }
+}
+
+/// Converts a CSS color to a piet color.
+pub(crate) fn to_piet_color(color: Color) -> piet::Color {
+    piet::Color::rgba(color.red, color.green, color.blue, color.alpha)
+}
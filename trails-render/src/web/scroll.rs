@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use piet::kurbo::{Point, Rect, Vec2};
+
+use super::{ComputedStyle, Fragment, Position};
+
+/// A box that clips its overflowing content, which (depending on its
+/// `overflow`) may be scrolled by the user.
+#[derive(Debug, Clone)]
+pub struct ScrollArea {
+    /// The clipped area, i.e. the padding box along the clipped axes, in
+    /// the coordinates of the content of the enclosing area.
+    pub area: Rect,
+    /// The current scroll offset.
+    pub offset: Vec2,
+    /// The largest scroll offset, at which the end of the content is visible.
+    pub max_offset: Vec2,
+    /// Whether the user can scroll horizontally.
+    pub scrollable_x: bool,
+    /// Whether the user can scroll vertically.
+    pub scrollable_y: bool,
+    /// The index of the innermost area enclosing this one, if any.
+    pub parent: Option<usize>,
+    /// Whether the area is inside a fixed box (and no other scroll area),
+    /// i.e. moves along with the viewport.
+    pub fixed: bool,
+    /// The style of the box, which identifies its fragment since boxes
+    /// clipping their content only generate a single one.
+    style: Arc<ComputedStyle>,
+}
+
+impl ScrollArea {
+    /// Creates the (initially unscrolled) scroll area of a fragment that
+    /// clips its overflowing content.
+    fn new<L>(fragment: &Fragment<L>, parent: Option<usize>, fixed: bool) -> Self {
+        let style = &fragment.style;
+        let padding_box = fragment.padding_box();
+        let content = fragment.children.iter().fold(padding_box, |rect, child| rect.union(child.bounds()));
+        // Content overflowing along axes that aren't clipped stays visible
+        let area = Rect::new(
+            if style.overflow_x.clips() { padding_box.x0 } else { content.x0 },
+            if style.overflow_y.clips() { padding_box.y0 } else { content.y0 },
+            if style.overflow_x.clips() { padding_box.x1 } else { content.x1 },
+            if style.overflow_y.clips() { padding_box.y1 } else { content.y1 },
+        );
+        Self {
+            area,
+            offset: Vec2::ZERO,
+            max_offset: Vec2::new((content.x1 - area.x1).max(0.0), (content.y1 - area.y1).max(0.0)),
+            scrollable_x: style.overflow_x.is_scrollable(),
+            scrollable_y: style.overflow_y.is_scrollable(),
+            parent,
+            fixed,
+            style: style.clone(),
+        }
+    }
+}
+
+/// The scroll areas on the page, in tree order.
+#[derive(Debug, Clone)]
+pub struct ScrollAreas {
+    pub areas: Vec<ScrollArea>,
+}
+
+impl ScrollAreas {
+    /// Collects the scroll areas from a fragment tree.
+    pub fn from_fragments<L>(root: &Fragment<L>) -> Self {
+        let mut areas = Vec::new();
+        collect_scroll_areas(root, Enclosing::default(), &mut areas);
+        Self { areas }
+    }
+
+    /// The index of the scroll area of the given fragment, if any.
+    pub fn index_of<L>(&self, fragment: &Fragment<L>) -> Option<usize> {
+        if !fragment.clips_overflow() {
+            return None;
+        }
+        self.areas.iter().position(|a| Arc::ptr_eq(&a.style, &fragment.style))
+    }
+
+    /// The scroll area of the given fragment, if any.
+    pub fn get<L>(&self, fragment: &Fragment<L>) -> Option<&ScrollArea> {
+        self.index_of(fragment).map(|i| &self.areas[i])
+    }
+
+    /// Maps a point on the page scrolled by the given offset to the content
+    /// of the given scroll area (or to the viewport if there is none and
+    /// the content is `fixed`). Returns `None` if the point is clipped by
+    /// the area or one of the areas enclosing it.
+    pub fn to_content(&self, point: Point, area: Option<usize>, fixed: bool, page_offset: Vec2) -> Option<Point> {
+        match area {
+            Some(i) => {
+                let area = &self.areas[i];
+                let point = self.to_content(point, area.parent, area.fixed, page_offset)?;
+                area.area.contains(point).then(|| point + area.offset)
+            },
+            None if fixed => Some(point - page_offset),
+            None => Some(point),
+        }
+    }
+
+    /// Scrolls the innermost area at the given point on the page (scrolled
+    /// by the given offset) that can still be scrolled in the direction of
+    /// the given delta. Returns whether any area was scrolled.
+    pub fn scroll(&mut self, point: Point, delta: Vec2, page_offset: Vec2) -> bool {
+        // Nested areas come after the areas enclosing them
+        for i in (0..self.areas.len()).rev() {
+            if self.to_content(point, Some(i), false, page_offset).is_none() {
+                continue;
+            }
+            let area = &mut self.areas[i];
+            let offset = Vec2::new(
+                if area.scrollable_x { (area.offset.x + delta.x).clamp(0.0, area.max_offset.x) } else { area.offset.x },
+                if area.scrollable_y { (area.offset.y + delta.y).clamp(0.0, area.max_offset.y) } else { area.offset.y },
+            );
+            if offset != area.offset {
+                area.offset = offset;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// The scroll areas enclosing a fragment, tracked while walking the
+/// fragment tree. Fixed boxes escape all of them, absolutely positioned
+/// boxes those inside of their containing block.
+#[derive(Debug, Clone, Copy, Default)]
+pub(super) struct Enclosing {
+    /// The innermost enclosing scroll area.
+    pub(super) area: Option<usize>,
+    /// The innermost scroll area enclosing the nearest positioned ancestor (or that ancestor itself).
+    positioned: Option<usize>,
+    /// Whether the fragment is inside a fixed box (and no other scroll area).
+    pub(super) fixed: bool,
+}
+
+impl Enclosing {
+    /// The areas enclosing the content of the given fragment, whose scroll
+    /// area (if any) has the given index.
+    pub(super) fn enter<L>(self, fragment: &Fragment<L>, index: Option<usize>) -> Self {
+        let mut enclosing = self;
+        if fragment.is_positioned() {
+            match fragment.style.position {
+                Position::Fixed => enclosing = Self { area: None, positioned: None, fixed: true },
+                Position::Absolute => enclosing.area = enclosing.positioned,
+                _ => {},
+            }
+        }
+        if index.is_some() {
+            enclosing.area = index;
+            enclosing.fixed = false;
+        }
+        if fragment.is_positioned() {
+            enclosing.positioned = enclosing.area;
+        }
+        enclosing
+    }
+}
+
+/// Collects the scroll areas from a fragment and its descendants in tree order.
+fn collect_scroll_areas<L>(fragment: &Fragment<L>, enclosing: Enclosing, areas: &mut Vec<ScrollArea>) {
+    let index = fragment.clips_overflow().then_some(areas.len());
+    if index.is_some() {
+        let outer = enclosing.enter(fragment, None);
+        areas.push(ScrollArea::new(fragment, outer.area, outer.fixed));
+    }
+    let inner = enclosing.enter(fragment, index);
+    for child in &fragment.children {
+        collect_scroll_areas(child, inner, areas);
+    }
+}
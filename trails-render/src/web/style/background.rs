@@ -0,0 +1,549 @@
+use trails_base::{Result, bail};
+use trails_model::css::{Color, ColorValue, ComputedLength, Length, ResolveContext};
+use trails_model::parse::css::{parse_url, split_commas, split_components};
+
+use super::LengthOrAuto;
+
+/// An image painted in a layer of a background.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackgroundImage {
+    /// An image loaded from the given URL, which the cascade resolves
+    /// against the URL of the stylesheet (or document) referencing it.
+    Url(String),
+    LinearGradient(LinearGradient),
+    RadialGradient(RadialGradient),
+}
+
+impl BackgroundImage {
+    /// Parses an image or `none`.
+    pub fn parse(value: &str, ctx: &ResolveContext) -> Result<Option<Self>> {
+        if value.eq_ignore_ascii_case("none") {
+            return Ok(None);
+        }
+        if let Some(url) = parse_url(value) {
+            return Ok(Some(Self::Url(url)));
+        }
+        let Some((function, args)) = value.strip_suffix(')').and_then(|v| v.split_once('(')) else {
+            bail!("Invalid image {}", value);
+        };
+        let function = function.to_ascii_lowercase();
+        let (repeating, function) = match function.strip_prefix("repeating-") {
+            Some(function) => (true, function),
+            None => (false, function.as_str()),
+        };
+        Ok(Some(match function {
+            "linear-gradient" => Self::LinearGradient(LinearGradient::parse(args, repeating, ctx)?),
+            "radial-gradient" => Self::RadialGradient(RadialGradient::parse(args, repeating, ctx)?),
+            // TODO: Support conic gradients and image-set()
+            _ => bail!("Unsupported image function {}", function),
+        }))
+    }
+}
+
+/// A linear gradient, e.g. `linear-gradient(to right, red, blue)`.
+/// See https://www.w3.org/TR/css-images-3/#linear-gradients
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearGradient {
+    pub direction: GradientDirection,
+    pub stops: Vec<ColorStop>,
+    /// Whether the stops are repeated along the gradient line.
+    pub repeating: bool,
+}
+
+impl LinearGradient {
+    /// Parses the arguments of a `linear-gradient()`.
+    fn parse(args: &str, repeating: bool, ctx: &ResolveContext) -> Result<Self> {
+        let args = split_commas(args);
+        let (direction, stops) = match args.split_first() {
+            Some((first, rest)) => match GradientDirection::parse(first) {
+                Some(direction) => (direction, rest),
+                None => (GradientDirection::Angle(180.0), args.as_slice()),
+            },
+            None => bail!("Expected gradient arguments"),
+        };
+        Ok(Self { direction, stops: parse_color_stops(stops, ctx)?, repeating })
+    }
+}
+
+/// The direction of the gradient line of a linear gradient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientDirection {
+    /// An angle in degrees, clockwise from pointing upwards (`to top`).
+    Angle(f64),
+    /// Pointing towards a corner of the box, e.g. `to top right`. The angle
+    /// depends on the aspect ratio of the box.
+    Corner { right: bool, bottom: bool },
+}
+
+impl GradientDirection {
+    /// Parses an angle or a `to` direction, `None` if the argument isn't a
+    /// direction (but presumably a color stop).
+    fn parse(value: &str) -> Option<Self> {
+        let components = split_components(value);
+        let (first, sides) = components.split_first()?;
+        if !first.eq_ignore_ascii_case("to") {
+            return parse_angle(value).map(Self::Angle);
+        }
+        let mut horizontal = None;
+        let mut vertical = None;
+        for side in sides {
+            match side.to_ascii_lowercase().as_str() {
+                "left" if horizontal.is_none() => horizontal = Some(false),
+                "right" if horizontal.is_none() => horizontal = Some(true),
+                "top" if vertical.is_none() => vertical = Some(false),
+                "bottom" if vertical.is_none() => vertical = Some(true),
+                _ => return None,
+            }
+        }
+        Some(match (horizontal, vertical) {
+            (Some(right), Some(bottom)) => Self::Corner { right, bottom },
+            (Some(right), None) => Self::Angle(if right { 90.0 } else { 270.0 }),
+            (None, Some(bottom)) => Self::Angle(if bottom { 180.0 } else { 0.0 }),
+            (None, None) => return None,
+        })
+    }
+}
+
+/// A radial gradient, e.g. `radial-gradient(circle at top, red, blue)`.
+/// See https://www.w3.org/TR/css-images-3/#radial-gradients
+#[derive(Debug, Clone, PartialEq)]
+pub struct RadialGradient {
+    pub shape: RadialShape,
+    pub size: RadialSize,
+    /// The center of the gradient, relative to the box it is painted in.
+    pub position: BackgroundPosition,
+    pub stops: Vec<ColorStop>,
+    /// Whether the stops are repeated along the gradient ray.
+    pub repeating: bool,
+}
+
+impl RadialGradient {
+    /// Parses the arguments of a `radial-gradient()`.
+    fn parse(args: &str, repeating: bool, ctx: &ResolveContext) -> Result<Self> {
+        let args = split_commas(args);
+        let Some((first, rest)) = args.split_first() else {
+            bail!("Expected gradient arguments");
+        };
+        let components = split_components(first);
+        let at = components.iter().position(|c| c.eq_ignore_ascii_case("at"));
+        let (ending_shape, position) = match at {
+            Some(at) => (&components[..at], Some(BackgroundPosition::parse_components(&components[at + 1..], ctx)?)),
+            None => (components.as_slice(), None),
+        };
+        let prelude = if ending_shape.is_empty() && position.is_some() {
+            Some((None, None))
+        } else {
+            parse_ending_shape(ending_shape, ctx)
+        };
+        let ((shape, size), stops) = match prelude {
+            Some(prelude) => (prelude, rest),
+            None => ((None, None), args.as_slice()),
+        };
+        let shape = shape.unwrap_or(match size {
+            Some(RadialSize::Explicit(x, y)) if x == y => RadialShape::Circle,
+            _ => RadialShape::Ellipse,
+        });
+        Ok(Self {
+            shape,
+            size: size.unwrap_or(RadialSize::FarthestCorner),
+            position: position.unwrap_or(BackgroundPosition::CENTER),
+            stops: parse_color_stops(stops, ctx)?,
+            repeating,
+        })
+    }
+}
+
+/// The shape of a radial gradient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RadialShape {
+    Circle,
+    Ellipse,
+}
+
+/// The size of the ending shape of a radial gradient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RadialSize {
+    ClosestSide,
+    FarthestSide,
+    ClosestCorner,
+    FarthestCorner,
+    /// The horizontal and vertical radius (which are the same for circles).
+    Explicit(ComputedLength, ComputedLength),
+}
+
+/// Parses the shape and size of a radial gradient, `None` if the
+/// components don't describe them (but presumably a color stop).
+fn parse_ending_shape(components: &[&str], ctx: &ResolveContext) -> Option<(Option<RadialShape>, Option<RadialSize>)> {
+    let mut shape = None;
+    let mut size = None;
+    let mut lengths = Vec::new();
+    for component in components {
+        match component.to_ascii_lowercase().as_str() {
+            "circle" if shape.is_none() => shape = Some(RadialShape::Circle),
+            "ellipse" if shape.is_none() => shape = Some(RadialShape::Ellipse),
+            "closest-side" if size.is_none() => size = Some(RadialSize::ClosestSide),
+            "farthest-side" if size.is_none() => size = Some(RadialSize::FarthestSide),
+            "closest-corner" if size.is_none() => size = Some(RadialSize::ClosestCorner),
+            "farthest-corner" | "cover" if size.is_none() => size = Some(RadialSize::FarthestCorner),
+            "contain" if size.is_none() => size = Some(RadialSize::ClosestSide),
+            _ => lengths.push(Length::parse(component).ok()?.compute(ctx)),
+        }
+    }
+    match lengths.as_slice() {
+        [] => {},
+        // Circles can't be sized with percentages
+        [radius] if radius.percent == 0.0 && shape != Some(RadialShape::Ellipse) && size.is_none() => {
+            size = Some(RadialSize::Explicit(*radius, *radius));
+        },
+        [x, y] if shape != Some(RadialShape::Circle) && size.is_none() => {
+            size = Some(RadialSize::Explicit(*x, *y));
+        },
+        _ => return None,
+    }
+    (shape.is_some() || size.is_some()).then_some((shape, size))
+}
+
+/// A color at some (or else an evenly distributed) position along a gradient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorStop {
+    pub color: ColorValue,
+    /// The position, where percentages refer to the length of the gradient line.
+    pub position: Option<ComputedLength>,
+}
+
+/// Parses the color stops of a gradient. Stops with two positions are split
+/// into two stops.
+fn parse_color_stops(args: &[&str], ctx: &ResolveContext) -> Result<Vec<ColorStop>> {
+    let mut stops = Vec::new();
+    for arg in args {
+        let components = split_components(arg);
+        let Some((first, positions)) = components.split_first() else {
+            bail!("Expected a color stop");
+        };
+        let Ok(stop_color) = ColorValue::parse(first) else {
+            // TODO: Support color interpolation hints, which are skipped for now
+            if positions.is_empty() && Length::parse(first).is_ok() {
+                continue;
+            }
+            bail!("Invalid color stop {}", arg);
+        };
+        if positions.len() > 2 {
+            bail!("Invalid color stop {}", arg);
+        }
+        if positions.is_empty() {
+            stops.push(ColorStop { color: stop_color, position: None });
+        }
+        for position in positions {
+            stops.push(ColorStop { color: stop_color, position: Some(Length::parse(position)?.compute(ctx)) });
+        }
+    }
+    if stops.is_empty() {
+        bail!("Expected at least one color stop");
+    }
+    Ok(stops)
+}
+
+/// Parses an angle, e.g. `45deg` or `0.25turn`, into degrees.
+fn parse_angle(value: &str) -> Option<f64> {
+    let value = value.trim().to_ascii_lowercase();
+    let (number, factor) = if let Some(n) = value.strip_suffix("deg") {
+        (n, 1.0)
+    } else if let Some(n) = value.strip_suffix("grad") {
+        (n, 0.9)
+    } else if let Some(n) = value.strip_suffix("rad") {
+        (n, 180.0 / std::f64::consts::PI)
+    } else if let Some(n) = value.strip_suffix("turn") {
+        (n, 360.0)
+    } else if value == "0" {
+        ("0", 1.0)
+    } else {
+        return None;
+    };
+    number.parse::<f64>().ok().map(|n| n * factor)
+}
+
+/// The position of a background image (or the center of a radial
+/// gradient), where percentages align that point of the image with the
+/// same point of the box.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BackgroundPosition {
+    pub x: ComputedLength,
+    pub y: ComputedLength,
+}
+
+/// A component of a `background-position`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PositionComponent {
+    /// A keyword with its percentage and whether it refers to the horizontal
+    /// axis, `None` for `center`.
+    Keyword(ComputedLength, Option<bool>),
+    Length(ComputedLength),
+}
+
+impl BackgroundPosition {
+    /// The center of the box.
+    pub const CENTER: Self = Self {
+        x: ComputedLength { px: 0.0, percent: 50.0 },
+        y: ComputedLength { px: 0.0, percent: 50.0 },
+    };
+
+    /// Parses a position, e.g. `center`, `left 10px top` or `25% 50%`.
+    pub fn parse(value: &str, ctx: &ResolveContext) -> Result<Self> {
+        Self::parse_components(&split_components(value), ctx)
+    }
+
+    /// Parses a position from its 1 to 4 components.
+    /// See https://www.w3.org/TR/css-backgrounds-3/#background-position
+    fn parse_components(components: &[&str], ctx: &ResolveContext) -> Result<Self> {
+        let parsed = components.iter()
+            .map(|c| PositionComponent::parse(c, ctx))
+            .collect::<Result<Vec<_>>>()?;
+        let center = ComputedLength { px: 0.0, percent: 50.0 };
+        Ok(match parsed.as_slice() {
+            [PositionComponent::Keyword(y, Some(false))] => Self { x: center, y: *y },
+            [PositionComponent::Keyword(x, _) | PositionComponent::Length(x)] => Self { x: *x, y: center },
+            [first, second] => {
+                use PositionComponent::{Keyword, Length};
+                match (first, second) {
+                    // Keywords may be given in any order
+                    (Keyword(y, Some(false)), Keyword(x, Some(true) | None)) | (Keyword(y, None), Keyword(x, Some(true))) => Self { x: *x, y: *y },
+                    (Keyword(x, Some(true) | None) | Length(x), Keyword(y, Some(false) | None) | Length(y)) => Self { x: *x, y: *y },
+                    _ => bail!("Invalid position {}", components.join(" ")),
+                }
+            },
+            [_, _, _] | [_, _, _, _] => {
+                // Keywords with optional offsets from the edges, e.g. `right 10px bottom`
+                let mut x = None;
+                let mut y = None;
+                let mut centered = 0;
+                let mut rest = parsed.as_slice();
+                while let Some((PositionComponent::Keyword(edge, axis), tail)) = rest.split_first() {
+                    let (offset, tail) = match tail.split_first() {
+                        Some((PositionComponent::Length(offset), tail)) if axis.is_some() => (Some(*offset), tail),
+                        _ => (None, tail),
+                    };
+                    let value = match offset {
+                        Some(offset) if edge.percent == 0.0 => offset,
+                        Some(offset) => *edge + -offset,
+                        None => *edge,
+                    };
+                    match axis {
+                        Some(true) if x.is_none() => x = Some(value),
+                        Some(false) if y.is_none() => y = Some(value),
+                        None => centered += 1,
+                        _ => bail!("Invalid position {}", components.join(" ")),
+                    }
+                    rest = tail;
+                }
+                if !rest.is_empty() || x.is_some() as usize + y.is_some() as usize + centered != 2 {
+                    bail!("Invalid position {}", components.join(" "));
+                }
+                Self { x: x.unwrap_or(center), y: y.unwrap_or(center) }
+            },
+            _ => bail!("Expected 1 to 4 position components"),
+        })
+    }
+}
+
+impl PositionComponent {
+    /// Parses a keyword or length.
+    fn parse(value: &str, ctx: &ResolveContext) -> Result<Self> {
+        let percent = |percent| ComputedLength { px: 0.0, percent };
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "left" => Self::Keyword(percent(0.0), Some(true)),
+            "right" => Self::Keyword(percent(100.0), Some(true)),
+            "top" => Self::Keyword(percent(0.0), Some(false)),
+            "bottom" => Self::Keyword(percent(100.0), Some(false)),
+            "center" => Self::Keyword(percent(50.0), None),
+            _ => Self::Length(Length::parse(value)?.compute(ctx)),
+        })
+    }
+}
+
+/// The size of a background image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundSize {
+    /// Scales the image to cover the whole box, preserving its aspect ratio.
+    Cover,
+    /// Scales the image to fit into the box, preserving its aspect ratio.
+    Contain,
+    /// A width and height, where `auto` is derived from the other one.
+    Explicit(LengthOrAuto, LengthOrAuto),
+}
+
+impl Default for BackgroundSize {
+    fn default() -> Self {
+        Self::Explicit(LengthOrAuto::Auto, LengthOrAuto::Auto)
+    }
+}
+
+impl BackgroundSize {
+    /// Parses a background size, e.g. `cover` or `50% auto`.
+    pub fn parse(value: &str, ctx: &ResolveContext) -> Result<Self> {
+        Self::parse_components(&split_components(value), ctx)
+    }
+
+    /// Parses a background size from its 1 or 2 components.
+    fn parse_components(components: &[&str], ctx: &ResolveContext) -> Result<Self> {
+        let parse_length = |value: &str| {
+            let length = LengthOrAuto::parse(value, ctx)?;
+            if let LengthOrAuto::Length(l) = length {
+                if l.px < 0.0 || l.percent < 0.0 {
+                    bail!("Background size may not be negative");
+                }
+            }
+            Ok(length)
+        };
+        Ok(match components {
+            [keyword] if keyword.eq_ignore_ascii_case("cover") => Self::Cover,
+            [keyword] if keyword.eq_ignore_ascii_case("contain") => Self::Contain,
+            [width] => Self::Explicit(parse_length(width)?, LengthOrAuto::Auto),
+            [width, height] => Self::Explicit(parse_length(width)?, parse_length(height)?),
+            _ => bail!("Expected 1 or 2 background size components"),
+        })
+    }
+}
+
+/// How a background image is repeated along an axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepeatStyle {
+    #[default]
+    Repeat,
+    /// Repeats the image as often as it fits, spacing the images evenly.
+    Space,
+    /// Repeats the image, scaling it so that it fits a whole number of times.
+    Round,
+    NoRepeat,
+}
+
+impl RepeatStyle {
+    /// Parses a repeat keyword.
+    fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "repeat" => Self::Repeat,
+            "space" => Self::Space,
+            "round" => Self::Round,
+            "no-repeat" => Self::NoRepeat,
+            _ => bail!("Unknown repeat style {}", value),
+        })
+    }
+}
+
+/// How a background image is repeated horizontally and vertically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BackgroundRepeat {
+    pub x: RepeatStyle,
+    pub y: RepeatStyle,
+}
+
+impl BackgroundRepeat {
+    /// Parses a background repeat, e.g. `repeat-x` or `space no-repeat`.
+    pub fn parse(value: &str) -> Result<Self> {
+        Self::parse_components(&split_components(value))
+    }
+
+    /// Parses a background repeat from its 1 or 2 components.
+    fn parse_components(components: &[&str]) -> Result<Self> {
+        Ok(match components {
+            [keyword] if keyword.eq_ignore_ascii_case("repeat-x") => Self { x: RepeatStyle::Repeat, y: RepeatStyle::NoRepeat },
+            [keyword] if keyword.eq_ignore_ascii_case("repeat-y") => Self { x: RepeatStyle::NoRepeat, y: RepeatStyle::Repeat },
+            [both] => {
+                let both = RepeatStyle::parse(both)?;
+                Self { x: both, y: both }
+            },
+            [x, y] => Self { x: RepeatStyle::parse(x)?, y: RepeatStyle::parse(y)? },
+            _ => bail!("Expected 1 or 2 repeat components"),
+        })
+    }
+}
+
+/// A layer of a background, i.e. an image with its placement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundLayer {
+    pub image: Option<BackgroundImage>,
+    pub position: BackgroundPosition,
+    pub size: BackgroundSize,
+    pub repeat: BackgroundRepeat,
+}
+
+/// Parses a `background` shorthand into its color and layers (from the
+/// top), e.g. `url(a.png) no-repeat center / cover, #eee`. Only the last
+/// (bottom) layer may specify a color.
+pub fn parse_background(value: &str, ctx: &ResolveContext) -> Result<(ColorValue, Vec<BackgroundLayer>)> {
+    let layers = split_commas(value);
+    let mut background_color = ColorValue::Color(Color::TRANSPARENT);
+    let mut parsed = Vec::new();
+    for (i, layer) in layers.iter().enumerate() {
+        // Split slashes into separate components, e.g. in `center/cover`
+        let mut components = Vec::new();
+        for component in split_components(layer) {
+            if component.contains('(') || !component.contains('/') {
+                components.push(component);
+                continue;
+            }
+            for (j, part) in component.split('/').enumerate() {
+                if j > 0 {
+                    components.push("/");
+                }
+                if !part.is_empty() {
+                    components.push(part);
+                }
+            }
+        }
+
+        let mut image = None;
+        let mut position = None;
+        let mut size = None;
+        let mut repeat = None;
+        let mut layer_color = None;
+        let mut rest = components.as_slice();
+        while let Some(&component) = rest.first() {
+            let is_position = |c: &&str| PositionComponent::parse(c, ctx).is_ok();
+            let is_repeat_style = |c: &&str| RepeatStyle::parse(c).is_ok();
+            let lowercase = component.to_ascii_lowercase();
+            if position.is_none() && is_position(&component) {
+                let count = rest.iter().take(4).take_while(|c| is_position(c)).count();
+                position = Some(BackgroundPosition::parse_components(&rest[..count], ctx)?);
+                rest = &rest[count..];
+                if rest.first() == Some(&"/") {
+                    let count = rest[1..].iter().take(2).take_while(|c| c.eq_ignore_ascii_case("auto") || BackgroundSize::parse(c, ctx).is_ok()).count();
+                    size = Some(BackgroundSize::parse_components(&rest[1..1 + count], ctx)?);
+                    rest = &rest[1 + count..];
+                }
+                continue;
+            }
+            if repeat.is_none() && (matches!(lowercase.as_str(), "repeat-x" | "repeat-y") || is_repeat_style(&component)) {
+                let count = rest.iter().take(2).take_while(|c| is_repeat_style(c)).count().max(1);
+                repeat = Some(BackgroundRepeat::parse_components(&rest[..count])?);
+                rest = &rest[count..];
+                continue;
+            }
+            match lowercase.as_str() {
+                // TODO: Support background-attachment, background-origin and background-clip
+                "scroll" | "fixed" | "local" | "border-box" | "padding-box" | "content-box" | "text" => {},
+                _ => if let (None, Ok(parsed_image)) = (&image, BackgroundImage::parse(component, ctx)) {
+                    image = Some(parsed_image);
+                } else if let (None, Ok(parsed_color)) = (layer_color, ColorValue::parse(component)) {
+                    if i + 1 < layers.len() {
+                        bail!("Only the last background layer may have a color");
+                    }
+                    layer_color = Some(parsed_color);
+                } else {
+                    bail!("Invalid background component {}", component);
+                },
+            }
+            rest = &rest[1..];
+        }
+        background_color = layer_color.unwrap_or(ColorValue::Color(Color::TRANSPARENT));
+        parsed.push(BackgroundLayer {
+            image: image.flatten(),
+            position: position.unwrap_or_default(),
+            size: size.unwrap_or_default(),
+            repeat: repeat.unwrap_or_default(),
+        });
+    }
+    if parsed.is_empty() {
+        bail!("Expected at least one background layer");
+    }
+    Ok((background_color, parsed))
+}
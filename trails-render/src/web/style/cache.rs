@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use trails_base::log::debug;
+use trails_model::css::{MediaEnvironment, PseudoElement};
+use trails_model::dom::{Document, Element, Node};
+use trails_model::parse::css;
+use url::Url;
+
+use super::{Cascade, ComputedStyle, FontFace, Invalidation, Origin, DEFAULT_FONT_SIZE, is_root_element};
+
+/// The computed styles of an element and its descendants, mirroring the DOM.
+#[derive(Debug, Clone)]
+pub struct StyleTree {
+    /// The computed style of the element.
+    style: Arc<ComputedStyle>,
+    /// The computed style of the `::before` pseudo-element, if it generates a box.
+    before: Option<Arc<ComputedStyle>>,
+    /// The computed style of the `::after` pseudo-element, if it generates a box.
+    after: Option<Arc<ComputedStyle>>,
+    /// The style trees of the child elements, in document order.
+    children: Vec<StyleTree>,
+}
+
+impl StyleTree {
+    /// The computed style of the element.
+    pub fn style(&self) -> &Arc<ComputedStyle> {
+        &self.style
+    }
+
+    /// The computed style of the `::before` pseudo-element, if it generates a box.
+    pub fn before(&self) -> Option<&Arc<ComputedStyle>> {
+        self.before.as_ref()
+    }
+
+    /// The computed style of the `::after` pseudo-element, if it generates a box.
+    pub fn after(&self) -> Option<&Arc<ComputedStyle>> {
+        self.after.as_ref()
+    }
+
+    /// The style trees of the child elements, in document order.
+    pub fn children(&self) -> &[StyleTree] {
+        &self.children
+    }
+}
+
+/// The computed styles of the last styled document, along with everything
+/// needed to determine what to restyle after a change.
+struct CachedStyles {
+    document: Arc<Document>,
+    env: MediaEnvironment,
+    document_url: Option<Url>,
+    sources: Vec<(Option<Url>, String)>,
+    cascade: Cascade,
+    tree: StyleTree,
+}
+
+/// A cache of computed styles that only restyles the elements affected by
+/// changes to the document.
+#[derive(Default)]
+pub struct StyleCache {
+    /// The sources of the user stylesheets.
+    user_sources: Vec<String>,
+    cached: Option<CachedStyles>,
+}
+
+impl StyleCache {
+    /// Creates a new (empty) style cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the sources of the user stylesheets (e.g. from the user's
+    /// configuration), which apply with user origin. Changing them causes a
+    /// full restyle.
+    pub fn set_user_stylesheets(&mut self, sources: Vec<String>) {
+        if sources != self.user_sources {
+            self.user_sources = sources;
+            self.cached = None;
+        }
+    }
+
+    /// Fetches the computed styles for the given document, restyling it if
+    /// needed. Changes to the environment or the stylesheets cause a full
+    /// restyle, other changes to the document only restyle the elements
+    /// whose styles may depend on them.
+    pub fn styles(&mut self, document: &Arc<Document>, env: MediaEnvironment) -> &StyleTree {
+        let sources = Cascade::author_sources(document, &env);
+        let document_url = document.url().and_then(|url| Url::parse(url).ok());
+        let cached = match self.cached.take() {
+            Some(cached) if cached.env == env && cached.document_url == document_url && cached.sources == sources => {
+                if Arc::ptr_eq(&cached.document, document) {
+                    cached
+                } else {
+                    let mut restyler = Restyler { cascade: &cached.cascade, root_font_size: DEFAULT_FONT_SIZE, restyled: 0 };
+                    let tree = restyler.restyle(cached.document.root(), document.root(), &cached.tree, &ComputedStyle::initial(), Invalidation::None, &mut Vec::new());
+                    debug!("Restyled {} element(s)", restyler.restyled);
+                    CachedStyles { document: document.clone(), tree, ..cached }
+                }
+            },
+            _ => {
+                let parser = css::Parser::default();
+                let user_sheets: Vec<_> = self.user_sources.iter().map(|s| parser.parse_stylesheet(s)).collect();
+                let author_sheets: Vec<_> = sources.iter().map(|(url, s)| (url.as_ref(), parser.parse_stylesheet(s))).collect();
+                let sheets = user_sheets.iter().map(|s| (Origin::User, None, s))
+                    .chain(author_sheets.iter().map(|(url, s)| (Origin::Author, *url, s)));
+                let cascade = Cascade::new(sheets, document_url.clone(), env);
+                let mut restyler = Restyler { cascade: &cascade, root_font_size: DEFAULT_FONT_SIZE, restyled: 0 };
+                let tree = restyler.compute(document.root(), &ComputedStyle::initial(), &mut Vec::new());
+                debug!("Styled {} element(s)", restyler.restyled);
+                CachedStyles { document: document.clone(), env, document_url, sources, cascade, tree }
+            },
+        };
+        &self.cached.insert(cached).tree
+    }
+
+    /// The `@font-face` rules of the stylesheets of the last styled document.
+    pub fn font_faces(&self) -> &[FontFace] {
+        self.cached.as_ref().map_or(&[], |cached| cached.cascade.font_faces())
+    }
+}
+
+/// Computes style trees, reusing cached styles where possible.
+struct Restyler<'c> {
+    cascade: &'c Cascade,
+    /// The font size of the root element, which is styled first.
+    root_font_size: f64,
+    /// The number of elements whose style has been computed.
+    restyled: usize,
+}
+
+impl<'c> Restyler<'c> {
+    /// Computes the style tree for an element from scratch.
+    fn compute<'d>(&mut self, element: &'d Element, parent: &ComputedStyle, ancestors: &mut Vec<&'d Element>) -> StyleTree {
+        self.restyled += 1;
+        let style = Arc::new(self.cascade.compute_style(element, ancestors, parent, self.root_font_size));
+        if is_root_element(ancestors) {
+            self.root_font_size = style.font_size;
+        }
+        let (before, after) = self.compute_pseudo_styles(element, &style, ancestors);
+        ancestors.push(element);
+        let children = element.child_elements()
+            .map(|child| self.compute(child, &style, ancestors))
+            .collect();
+        ancestors.pop();
+        StyleTree { style, before, after, children }
+    }
+
+    /// Computes the styles of the `::before` and `::after` pseudo-elements of an element.
+    fn compute_pseudo_styles(&self, element: &Element, style: &ComputedStyle, ancestors: &[&Element]) -> (Option<Arc<ComputedStyle>>, Option<Arc<ComputedStyle>>) {
+        let compute = |pseudo_element| self.cascade.compute_pseudo_style(element, ancestors, style, pseudo_element, self.root_font_size).map(Arc::new);
+        (compute(PseudoElement::Before), compute(PseudoElement::After))
+    }
+
+    /// Updates the style tree of an element that changed from the old to the
+    /// new version. The given invalidation is inherited from the parent (whose
+    /// style may have changed) or from preceding siblings.
+    fn restyle<'d>(&mut self, old: &Element, new: &'d Element, old_tree: &StyleTree, parent: &ComputedStyle, invalidation: Invalidation, ancestors: &mut Vec<&'d Element>) -> StyleTree {
+        if invalidation >= Invalidation::Subtree || old.tag_name() != new.tag_name() {
+            return self.compute(new, parent, ancestors);
+        }
+
+        // Structural changes to the children affect structural pseudo-classes
+        // (e.g. `:empty` on this element or `:nth-child` on the children)
+        let structure_changed = old.children().count() != new.children().count()
+            || old.children().zip(new.children()).any(|(o, n)| !same_structure(o, n));
+        let invalidation = invalidation
+            .max(self.cascade.dependencies().invalidation_between(old, new))
+            .max(if structure_changed { Invalidation::Subtree } else { Invalidation::None });
+        if invalidation >= Invalidation::Subtree {
+            return self.compute(new, parent, ancestors);
+        }
+
+        // Restyle this element (and its pseudo-elements) if needed
+        let (style, before, after) = if invalidation == Invalidation::Element {
+            self.restyled += 1;
+            let style = self.cascade.compute_style(new, ancestors, parent, self.root_font_size);
+            let style = if style == *old_tree.style { old_tree.style.clone() } else { Arc::new(style) };
+            let (before, after) = self.compute_pseudo_styles(new, &style, ancestors);
+            (style, before, after)
+        } else {
+            (old_tree.style.clone(), old_tree.before.clone(), old_tree.after.clone())
+        };
+        // Children only need to be restyled if this style changed (since they
+        // may inherit from it) or due to invalidations from preceding siblings.
+        // A changed root font size affects all lengths in `rem`.
+        let child_invalidation = if is_root_element(ancestors) && style.font_size != old_tree.style.font_size {
+            Invalidation::Subtree
+        } else if Arc::ptr_eq(&style, &old_tree.style) {
+            Invalidation::None
+        } else {
+            Invalidation::Element
+        };
+        if is_root_element(ancestors) {
+            self.root_font_size = style.font_size;
+        }
+        ancestors.push(new);
+        let mut sibling_invalidation = Invalidation::None;
+        let children = old.child_elements()
+            .zip(new.child_elements())
+            .zip(old_tree.children.iter())
+            .map(|((old_child, new_child), old_child_tree)| {
+                let invalidation = child_invalidation.max(sibling_invalidation);
+                let tree = self.restyle(old_child, new_child, old_child_tree, &style, invalidation, ancestors);
+                if self.cascade.dependencies().invalidation_between(old_child, new_child) == Invalidation::Siblings {
+                    sibling_invalidation = Invalidation::Siblings;
+                }
+                tree
+            })
+            .collect();
+        ancestors.pop();
+
+        StyleTree { style, before, after, children }
+    }
+}
+
+/// Whether two nodes are structurally the same as far as selectors are
+/// concerned, i.e. both are elements with the same tag name or both are text
+/// that is (non-)empty.
+fn same_structure(old: &Node, new: &Node) -> bool {
+    match (old, new) {
+        (Node::Element(o), Node::Element(n)) => o.tag_name() == n.tag_name(),
+        (Node::Text(o), Node::Text(n)) => o.is_empty() == n.is_empty(),
+        _ => false,
+    }
+}
@@ -0,0 +1,364 @@
+use std::sync::Arc;
+
+use trails_base::Error;
+use trails_base::log::debug;
+use trails_base::once_cell::sync::Lazy;
+use trails_model::css::{Declaration, FontFaceRule, MediaEnvironment, MediaQueryList, PseudoElement, ResolveContext, Selector, Specificity, Stylesheet};
+use trails_model::dom::{Document, Element};
+use trails_model::parse::css;
+use url::Url;
+
+use crate::web::{INLINE_TAGS, RENDERED_TAGS};
+
+use super::{BackgroundImage, compute_custom_properties, presentational_hints, StyleDependencies, contains_vars, substitute_vars, ComputedStyle, Content, Display, Float, TextDecoration, DEFAULT_FONT_SIZE};
+
+static USER_AGENT_STYLESHEET: Lazy<Stylesheet> = Lazy::new(|| {
+    css::Parser::default().parse_stylesheet(include_str!("ua.css"))
+});
+
+/// The origin of a stylesheet, in ascending order of precedence (for normal declarations).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
+}
+
+/// A style rule flattened from a stylesheet.
+struct CascadeRule {
+    origin: Origin,
+    selector: Selector,
+    specificity: Specificity,
+    declarations: Arc<Vec<Declaration>>,
+    /// The URL of the stylesheet, which URLs in the declarations are resolved against.
+    base_url: Option<Url>,
+}
+
+/// The position of a declaration in the cascade. Declarations with a
+/// greater key take precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct CascadeKey {
+    /// The precedence derived from origin and importance.
+    level: u8,
+    /// Whether the declaration is from a `style` attribute.
+    inline: bool,
+    specificity: Specificity,
+    /// The position in the source order.
+    order: usize,
+}
+
+/// A web font declared by a `@font-face` rule.
+#[derive(Debug, Clone)]
+pub struct FontFace {
+    pub rule: FontFaceRule,
+    /// The URL of the stylesheet declaring the font, which the sources are
+    /// resolved against.
+    pub base_url: Option<Url>,
+}
+
+impl FontFace {
+    /// The absolute URLs of the font files the font may be loaded from, in
+    /// order of preference.
+    pub fn source_urls(&self) -> Vec<String> {
+        self.rule.sources().into_iter()
+            .filter_map(|source| resolve_url(self.base_url.as_ref(), &source.url))
+            .map(String::from)
+            .collect()
+    }
+}
+
+/// The set of style rules that apply to a document in some environment.
+pub struct Cascade {
+    rules: Vec<CascadeRule>,
+    /// The web fonts declared by the stylesheets.
+    font_faces: Vec<FontFace>,
+    /// The URL of the document, which URLs in `style` attributes and
+    /// presentational hints are resolved against.
+    document_url: Option<Url>,
+    env: MediaEnvironment,
+    dependencies: StyleDependencies,
+    parser: css::Parser,
+}
+
+impl Cascade {
+    /// Creates a cascade from the given stylesheets (along with the URLs
+    /// they are loaded from, if any) for a document at the given URL,
+    /// keeping only the rules whose media queries match the given
+    /// environment. The user agent stylesheet is included automatically.
+    pub fn new<'a>(sheets: impl IntoIterator<Item=(Origin, Option<&'a Url>, &'a Stylesheet)>, document_url: Option<Url>, env: MediaEnvironment) -> Self {
+        let mut rules = Vec::new();
+        let mut font_faces = Vec::new();
+        let mut dependencies = StyleDependencies::default();
+        let sheets = [(Origin::UserAgent, None, &*USER_AGENT_STYLESHEET)].into_iter().chain(sheets);
+        for (origin, base_url, sheet) in sheets {
+            for style_rule in sheet.style_rules(&env) {
+                let declarations = Arc::new(style_rule.declarations.clone());
+                for selector in &style_rule.selectors {
+                    dependencies.add_selector(selector);
+                    rules.push(CascadeRule {
+                        origin,
+                        selector: selector.clone(),
+                        specificity: selector.specificity(),
+                        declarations: declarations.clone(),
+                        base_url: base_url.cloned(),
+                    });
+                }
+            }
+            font_faces.extend(sheet.font_face_rules().into_iter().map(|rule| FontFace {
+                rule: rule.clone(),
+                base_url: base_url.cloned(),
+            }));
+        }
+        Self { rules, font_faces, document_url, env, dependencies, parser: css::Parser::default() }
+    }
+
+    /// Collects the sources of the author stylesheets of a document (from
+    /// `<style>` elements and loaded `<link rel="stylesheet">` resources)
+    /// whose `media` attribute matches the given environment, along with the
+    /// URLs relative references in them are resolved against.
+    pub fn author_sources(document: &Document, env: &MediaEnvironment) -> Vec<(Option<Url>, String)> {
+        let document_url = document.url().and_then(|url| Url::parse(url).ok());
+        let mut sources = Vec::new();
+        for element in document.root().descendants() {
+            let media_matches = element.attribute("media")
+                .is_none_or(|media| MediaQueryList::parse(media).matches(env));
+            if !media_matches {
+                debug!("Skipping <{}> stylesheet for non-matching media {:?}", element.tag_name(), element.attribute("media"));
+                continue;
+            }
+            match element.tag_name() {
+                "style" => sources.push((document_url.clone(), element.text())),
+                "link" if is_stylesheet_link(element) => {
+                    if let Some(href) = element.attribute("href") {
+                        match document.resource(href) {
+                            Some(raw) => sources.push((resolve_url(document_url.as_ref(), href), String::from_utf8_lossy(raw).into_owned())),
+                            None => debug!("Stylesheet {} is not loaded", href),
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+        sources
+    }
+
+    /// The `@font-face` rules declaring the web fonts of the stylesheets.
+    pub fn font_faces(&self) -> &[FontFace] {
+        &self.font_faces
+    }
+
+    /// The classes, ids and attributes the selectors in this cascade depend on.
+    pub fn dependencies(&self) -> &StyleDependencies {
+        &self.dependencies
+    }
+
+    /// Computes the style of an element, which has the given ancestors (from
+    /// the root to the parent) and the given parent style, in a document
+    /// whose root element has the given font size (which `rem` refers to,
+    /// except in the style of the root element itself).
+    pub fn compute_style(&self, element: &Element, ancestors: &[&Element], parent: &ComputedStyle, root_font_size: f64) -> ComputedStyle {
+        let inline_declarations = element.attribute("style")
+            .map(|style| self.parser.parse_declarations(style))
+            .unwrap_or_default();
+        let hints = presentational_hints(element, ancestors);
+        let matched = self.matching_declarations(element, ancestors, None, &hints, &inline_declarations);
+        let mut style = ComputedStyle::inherit(parent);
+        style.display = default_display(element.tag_name());
+        let root_font_size = (!is_root_element(ancestors)).then_some(root_font_size);
+        self.apply_declarations(&mut style, matched, parent, root_font_size);
+        style
+    }
+
+    /// Computes the style of a pseudo-element (e.g. `::before`) of an element
+    /// with the given style, in a document whose root element has the given
+    /// font size. Returns `None` if the pseudo-element has no content and
+    /// thus doesn't generate a box.
+    pub fn compute_pseudo_style(&self, element: &Element, ancestors: &[&Element], style: &ComputedStyle, pseudo_element: PseudoElement, root_font_size: f64) -> Option<ComputedStyle> {
+        let matched = self.matching_declarations(element, ancestors, Some(pseudo_element), &[], &[]);
+        if matched.is_empty() {
+            return None;
+        }
+        let mut pseudo_style = ComputedStyle::inherit(style);
+        self.apply_declarations(&mut pseudo_style, matched, style, Some(root_font_size));
+        matches!(pseudo_style.content, Content::Items(_)).then_some(pseudo_style)
+    }
+
+    /// Collects the declarations matching an element (or one of its
+    /// pseudo-elements) in ascending order of precedence, along with the
+    /// URLs they are resolved against.
+    fn matching_declarations<'a>(&'a self, element: &Element, ancestors: &[&Element], pseudo_element: Option<PseudoElement>, hints: &'a [Declaration], inline_declarations: &'a [Declaration]) -> Vec<(&'a Declaration, Option<&'a Url>)> {
+        let mut matched: Vec<(CascadeKey, &Declaration, Option<&Url>)> = Vec::new();
+        // Presentational hints precede all other author declarations (the sort below is stable)
+        for declaration in hints {
+            let key = CascadeKey {
+                level: cascade_level(Origin::Author, false),
+                inline: false,
+                specificity: Specificity::default(),
+                order: 0,
+            };
+            matched.push((key, declaration, self.document_url.as_ref()));
+        }
+        for (order, rule) in self.rules.iter().enumerate() {
+            if rule.selector.pseudo_element == pseudo_element && rule.selector.matches(element, ancestors) {
+                for declaration in rule.declarations.iter() {
+                    let key = CascadeKey {
+                        level: cascade_level(rule.origin, declaration.important),
+                        inline: false,
+                        specificity: rule.specificity,
+                        order,
+                    };
+                    matched.push((key, declaration, rule.base_url.as_ref()));
+                }
+            }
+        }
+        for declaration in inline_declarations {
+            let key = CascadeKey {
+                level: cascade_level(Origin::Author, declaration.important),
+                inline: true,
+                specificity: Specificity::default(),
+                order: self.rules.len(),
+            };
+            matched.push((key, declaration, self.document_url.as_ref()));
+        }
+        matched.sort_by_key(|(key, _, _)| *key);
+        matched.into_iter().map(|(_, d, base_url)| (d, base_url)).collect()
+    }
+
+    /// Applies the given declarations (in ascending order of precedence) to a
+    /// style inheriting from the given parent style, given the font size of
+    /// the root element or `None` if this is the style of the root element.
+    fn apply_declarations(&self, style: &mut ComputedStyle, matched: Vec<(&Declaration, Option<&Url>)>, parent: &ComputedStyle, root_font_size: Option<f64>) {
+        // Compute the custom properties first, since other declarations may reference them
+        let (custom, matched): (Vec<_>, Vec<_>) = matched.into_iter()
+            .partition(|(d, _)| d.name.starts_with("--"));
+        if !custom.is_empty() {
+            let declarations = custom.iter().map(|(d, _)| (d.name.as_str(), d.value.as_str()));
+            style.custom_properties = Arc::new(compute_custom_properties(declarations, &parent.custom_properties));
+        }
+
+        // Apply the others in ascending order of precedence, starting with the
+        // font size since other lengths may depend on it and the writing mode
+        // and direction, which logical properties are mapped through
+        let is_early = |(d, _): &&(&Declaration, Option<&Url>)| matches!(d.name.as_str(), "font-size" | "writing-mode" | "direction");
+        // The root element's font size is relative to the initial one, its other lengths to its own
+        let parent_ctx = self.resolve_context(parent.font_size, root_font_size.unwrap_or(DEFAULT_FONT_SIZE));
+        for (declaration, base_url) in matched.iter().filter(is_early) {
+            apply_declaration(style, declaration, *base_url, parent, &parent_ctx);
+        }
+        let ctx = self.resolve_context(style.font_size, root_font_size.unwrap_or(style.font_size));
+        for (declaration, base_url) in matched.iter().filter(|d| !is_early(d)) {
+            apply_declaration(style, declaration, *base_url, parent, &ctx);
+        }
+
+        // Absolutely positioned boxes don't float, both are always block-level
+        if style.position.is_absolute() {
+            style.float = Float::None;
+        }
+        if style.position.is_absolute() || style.float != Float::None {
+            style.display = style.display.blockified();
+        }
+
+        // Scroll containers can't let content overflow along the other axis
+        if style.is_scroll_container() {
+            style.overflow_x = style.overflow_x.in_scroll_container();
+            style.overflow_y = style.overflow_y.in_scroll_container();
+        }
+
+        // Text decorations are propagated to in-flow descendants, drawn in the
+        // color of the decorating box
+        if style.position.is_absolute() || style.float != Float::None {
+            style.text_decorations.clear();
+        }
+        if !style.text_decoration_line.is_none() {
+            style.text_decorations.push(TextDecoration {
+                line: style.text_decoration_line,
+                color: style.text_decoration_color.resolve(style.color),
+            });
+        }
+    }
+
+    /// The context for resolving lengths with the given font size and that
+    /// of the root element.
+    fn resolve_context(&self, font_size: f64, root_font_size: f64) -> ResolveContext {
+        ResolveContext {
+            font_size,
+            root_font_size,
+            viewport: self.env.viewport,
+        }
+    }
+}
+
+/// Applies a declaration to the given style, substituting `var()` references
+/// and resolving image URLs against the given base URL.
+fn apply_declaration(style: &mut ComputedStyle, declaration: &Declaration, base_url: Option<&Url>, parent: &ComputedStyle, ctx: &ResolveContext) {
+    let name = declaration.name.as_str();
+    if contains_vars(&declaration.value) {
+        // A declaration with var() references can only be validated after
+        // substitution and behaves like 'unset' if it turns out to be invalid
+        // See https://www.w3.org/TR/css-variables-1/#invalid-at-computed-value-time
+        let result = substitute_vars(&declaration.value, &style.custom_properties)
+            .ok_or_else(|| Error::msg("Invalid var() reference"))
+            .and_then(|value| style.apply(name, &value, parent, ctx));
+        if let Err(e) = result {
+            debug!("Unsetting '{}: {}' at computed-value time: {}", name, declaration.value, e);
+            style.unset(name, parent);
+        }
+    } else if let Err(e) = style.apply(name, &declaration.value, parent, ctx) {
+        debug!("Ignoring declaration '{}: {}': {}", name, declaration.value, e);
+    }
+    if matches!(name, "background" | "background-image") {
+        for image in style.background_image.iter_mut().flatten() {
+            if let BackgroundImage::Url(url) = image {
+                if let Some(resolved) = resolve_url(base_url, url) {
+                    *url = resolved.into();
+                }
+            }
+        }
+    }
+}
+
+/// The precedence level for a declaration from the given origin.
+fn cascade_level(origin: Origin, important: bool) -> u8 {
+    // Important declarations reverse the order of origins
+    // See https://www.w3.org/TR/css-cascade-4/#cascade-origin
+    match (important, origin) {
+        (false, Origin::UserAgent) => 0,
+        (false, Origin::User) => 1,
+        (false, Origin::Author) => 2,
+        (true, Origin::Author) => 3,
+        (true, Origin::User) => 4,
+        (true, Origin::UserAgent) => 5,
+    }
+}
+
+/// Whether an element with the given ancestors is the root element (i.e.
+/// usually `<html>`), whose parent is the document's root node.
+pub(crate) fn is_root_element(ancestors: &[&Element]) -> bool {
+    ancestors.last().is_some_and(|p| p.tag_name() == "$root")
+}
+
+/// The display type of an element before applying any styles.
+fn default_display(tag_name: &str) -> Display {
+    if !RENDERED_TAGS.contains(tag_name) {
+        Display::None
+    } else if INLINE_TAGS.contains(tag_name) {
+        Display::Inline
+    } else {
+        Display::Block
+    }
+}
+
+/// Resolves a (possibly relative) URL against the given base URL.
+pub(crate) fn resolve_url(base_url: Option<&Url>, url: &str) -> Option<Url> {
+    match base_url {
+        Some(base_url) => base_url.join(url).ok(),
+        None => Url::parse(url).ok(),
+    }
+}
+
+/// Whether the given element is a `<link>` to a (non-alternate) stylesheet.
+fn is_stylesheet_link(element: &Element) -> bool {
+    let rel = element.attribute("rel").unwrap_or_default().to_ascii_lowercase();
+    let mut rels = rel.split_whitespace();
+    rels.clone().any(|r| r == "stylesheet") && !rels.any(|r| r == "alternate")
+}
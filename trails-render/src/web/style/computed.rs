@@ -0,0 +1,1007 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use piet::{FontStyle, FontWeight};
+use trails_base::{Result, bail};
+use trails_model::css::{Color, ColorValue, ComputedLength, Length, ResolveContext};
+use trails_model::parse::css::{split_commas, split_components};
+
+use super::{parse_align_self, parse_background, parse_border_width, parse_counter_list, parse_flex, parse_flex_basis, parse_flex_factor, parse_font_family, parse_font_style, parse_letter_spacing, parse_opacity, parse_text_decoration, AlignItems, BackgroundImage, BackgroundLayer, BackgroundPosition, BackgroundRepeat, BackgroundSize, BorderRadius, BorderStyle, BoxShadow, BoxSizing, CornerRadius, Clear, Content, CustomProperties, Direction, FamilyName, FlexDirection, FlexWrap, Float, GridAreas, GridAutoFlow, GenericFamily, GridLine, JustifyContent, LengthOrAuto, LineHeight, ListStylePosition, ListStyleType, LogicalSide, Overflow, Position, Side, Sides, TableLayout, TextAlign, TextDecoration, TextDecorationLine, TextTransform, TrackList, TrackSize, UnicodeBidi, VerticalAlign, Visibility, WhiteSpace, WritingMode};
+
+/// The default font size.
+pub const DEFAULT_FONT_SIZE: f64 = 12.0;
+
+/// The display type of an element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Display {
+    None,
+    Inline,
+    Block,
+    /// A block with a list marker.
+    ListItem,
+    Table,
+    TableRowGroup,
+    TableHeaderGroup,
+    TableFooterGroup,
+    TableRow,
+    TableCell,
+    TableCaption,
+    /// A block container laying out its children as flex items.
+    Flex,
+    /// A block container laying out its children as grid items.
+    Grid,
+    /// A table placed on a line as an atomic inline-level box.
+    InlineTable,
+    /// A flex container placed on a line as an atomic inline-level box.
+    InlineFlex,
+    /// A grid container placed on a line as an atomic inline-level box.
+    InlineGrid,
+}
+
+impl Display {
+    /// The block-level equivalent of this display type, e.g. for floats.
+    /// See https://www.w3.org/TR/CSS2/visuren.html#dis-pos-flo
+    pub fn blockified(self) -> Self {
+        match self {
+            Self::Inline | Self::TableRowGroup | Self::TableHeaderGroup | Self::TableFooterGroup
+                | Self::TableRow | Self::TableCell | Self::TableCaption => Self::Block,
+            Self::InlineTable => Self::Table,
+            Self::InlineFlex => Self::Flex,
+            Self::InlineGrid => Self::Grid,
+            _ => self,
+        }
+    }
+}
+
+/// The computed values of all CSS properties for an element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedStyle {
+    /// The display type.
+    pub display: Display,
+    /// The color to render text with.
+    pub color: Color,
+    /// The font families in order of preference.
+    pub font_family: Arc<Vec<FamilyName>>,
+    /// The font size in pixels.
+    pub font_size: f64,
+    /// The font weight.
+    pub font_weight: FontWeight,
+    /// The font style, i.e. whether the text is italic.
+    pub font_style: FontStyle,
+    /// How whitespace is handled.
+    pub white_space: WhiteSpace,
+    /// The horizontal alignment of the lines in a block container.
+    pub text_align: TextAlign,
+    /// The height of the lines of text.
+    pub line_height: LineHeight,
+    /// The additional spacing between letters in pixels.
+    pub letter_spacing: f64,
+    /// The case transformation applied to text.
+    pub text_transform: TextTransform,
+    /// The indentation of the first line in a block container.
+    pub text_indent: ComputedLength,
+    /// The base direction of the text.
+    pub direction: Direction,
+    /// How the box takes part in the reordering of bidirectional text.
+    pub unicode_bidi: UnicodeBidi,
+    /// Whether lines are horizontal or vertical and how they are stacked.
+    pub writing_mode: WritingMode,
+    /// The lines specified to be drawn across the text of the box.
+    pub text_decoration_line: TextDecorationLine,
+    /// The color of the text decoration lines.
+    pub text_decoration_color: ColorValue,
+    /// The decorations drawn across the text, i.e. those of this box and its
+    /// ancestors (which are propagated to descendants, but not inherited).
+    /// See https://www.w3.org/TR/css-text-decor-3/#line-decoration
+    pub text_decorations: Vec<TextDecoration>,
+    /// The style of list markers.
+    pub list_style_type: ListStyleType,
+    /// The position of list markers.
+    pub list_style_position: ListStylePosition,
+    /// The counters to reset (i.e. create) on the element, with their initial values.
+    pub counter_reset: Vec<(String, i32)>,
+    /// The counters to increment on the element, with the increments.
+    pub counter_increment: Vec<(String, i32)>,
+    /// The counters to set on the element, with their new values.
+    pub counter_set: Vec<(String, i32)>,
+    /// The generated content of a pseudo-element.
+    pub content: Content,
+    /// The horizontal and vertical spacing between the cells of a table.
+    pub border_spacing: (f64, f64),
+    /// The table layout algorithm.
+    pub table_layout: TableLayout,
+    /// The vertical alignment.
+    pub vertical_align: VerticalAlign,
+    /// The positioning scheme.
+    pub position: Position,
+    /// The offsets of a positioned box (`top`, `right`, `bottom` and `left`).
+    pub inset: Sides<LengthOrAuto>,
+    /// The stack level of a positioned box, `None` if `auto`.
+    pub z_index: Option<i32>,
+    /// How content overflowing the box horizontally is handled.
+    pub overflow_x: Overflow,
+    /// How content overflowing the box vertically is handled.
+    pub overflow_y: Overflow,
+    /// The side the box is floated to.
+    pub float: Float,
+    /// The sides of preceding floats the box is moved below.
+    pub clear: Clear,
+    /// The direction of the main axis of a flex container.
+    pub flex_direction: FlexDirection,
+    /// Whether a flex container wraps its items onto multiple lines.
+    pub flex_wrap: FlexWrap,
+    /// How a flex container distributes free space along the main axis.
+    pub justify_content: JustifyContent,
+    /// How a flex container aligns its items along the cross axis.
+    pub align_items: AlignItems,
+    /// How a flex item is aligned along the cross axis, `None` to use the container's `align-items`.
+    pub align_self: Option<AlignItems>,
+    /// How much a flex item grows relative to its siblings.
+    pub flex_grow: f64,
+    /// How much a flex item shrinks relative to its siblings.
+    pub flex_shrink: f64,
+    /// The initial main size of a flex item.
+    pub flex_basis: LengthOrAuto,
+    /// The position of a flex item among its siblings.
+    pub order: i32,
+    /// The gap between the rows (or lines) of a flex container.
+    pub row_gap: ComputedLength,
+    /// The gap between the columns (or items in a line) of a flex container.
+    pub column_gap: ComputedLength,
+    /// The explicit columns of a grid container.
+    pub grid_template_columns: TrackList,
+    /// The explicit rows of a grid container.
+    pub grid_template_rows: TrackList,
+    /// The named areas of a grid container.
+    pub grid_template_areas: GridAreas,
+    /// The sizes of implicitly created columns, repeated as needed.
+    pub grid_auto_columns: Vec<TrackSize>,
+    /// The sizes of implicitly created rows, repeated as needed.
+    pub grid_auto_rows: Vec<TrackSize>,
+    /// How grid items without a definite position are placed.
+    pub grid_auto_flow: GridAutoFlow,
+    /// The line a grid item starts at in the row axis.
+    pub grid_row_start: GridLine,
+    /// The line a grid item ends at in the row axis.
+    pub grid_row_end: GridLine,
+    /// The line a grid item starts at in the column axis.
+    pub grid_column_start: GridLine,
+    /// The line a grid item ends at in the column axis.
+    pub grid_column_end: GridLine,
+    /// The margins.
+    pub margin: Sides<LengthOrAuto>,
+    /// The padding.
+    pub padding: Sides<ComputedLength>,
+    /// The specified border widths. See `border_widths` for the used ones.
+    pub border_width: Sides<f64>,
+    /// The border styles.
+    pub border_style: Sides<BorderStyle>,
+    /// The border colors.
+    pub border_color: Sides<ColorValue>,
+    /// Which box `width` and `height` refer to.
+    pub box_sizing: BoxSizing,
+    /// The width.
+    pub width: LengthOrAuto,
+    /// The height.
+    pub height: LengthOrAuto,
+    /// The background color.
+    pub background_color: ColorValue,
+    /// The images of the background layers from the top, `None` for `none`.
+    /// Their number determines the number of layers.
+    pub background_image: Vec<Option<BackgroundImage>>,
+    /// The positions of the background images, repeated as needed.
+    pub background_position: Vec<BackgroundPosition>,
+    /// The sizes of the background images, repeated as needed.
+    pub background_size: Vec<BackgroundSize>,
+    /// How the background images are repeated, repeated as needed.
+    pub background_repeat: Vec<BackgroundRepeat>,
+    /// The radii of the corners of the border box.
+    pub border_radius: BorderRadius,
+    /// The shadows cast by the box, from the top.
+    pub box_shadow: Vec<BoxShadow>,
+    /// The opacity, from 0 (transparent) to 1, with which the box and its
+    /// content are composited as a group.
+    pub opacity: f64,
+    /// Whether the box is painted.
+    pub visibility: Visibility,
+    /// The (inherited) custom properties.
+    pub custom_properties: Arc<CustomProperties>,
+}
+
+impl Default for ComputedStyle {
+    fn default() -> Self {
+        Self::initial()
+    }
+}
+
+impl ComputedStyle {
+    /// The initial values for all properties.
+    pub fn initial() -> Self {
+        Self {
+            display: Display::Inline,
+            color: Color::BLACK,
+            font_family: Arc::new(vec![FamilyName::Generic(GenericFamily::Serif)]),
+            font_size: DEFAULT_FONT_SIZE,
+            font_weight: FontWeight::REGULAR,
+            font_style: FontStyle::Regular,
+            white_space: WhiteSpace::Normal,
+            text_align: TextAlign::Start,
+            line_height: LineHeight::Normal,
+            letter_spacing: 0.0,
+            text_transform: TextTransform::None,
+            text_indent: ComputedLength::default(),
+            direction: Direction::Ltr,
+            unicode_bidi: UnicodeBidi::Normal,
+            writing_mode: WritingMode::HorizontalTb,
+            text_decoration_line: TextDecorationLine::default(),
+            text_decoration_color: ColorValue::CurrentColor,
+            text_decorations: Vec::new(),
+            list_style_type: ListStyleType::Disc,
+            list_style_position: ListStylePosition::Outside,
+            counter_reset: Vec::new(),
+            counter_increment: Vec::new(),
+            counter_set: Vec::new(),
+            content: Content::Normal,
+            border_spacing: (0.0, 0.0),
+            table_layout: TableLayout::Auto,
+            vertical_align: VerticalAlign::Baseline,
+            position: Position::Static,
+            inset: Sides::all(LengthOrAuto::Auto),
+            z_index: None,
+            overflow_x: Overflow::Visible,
+            overflow_y: Overflow::Visible,
+            float: Float::None,
+            clear: Clear::None,
+            flex_direction: FlexDirection::Row,
+            flex_wrap: FlexWrap::NoWrap,
+            justify_content: JustifyContent::FlexStart,
+            align_items: AlignItems::Stretch,
+            align_self: None,
+            flex_grow: 0.0,
+            flex_shrink: 1.0,
+            flex_basis: LengthOrAuto::Auto,
+            order: 0,
+            row_gap: ComputedLength::default(),
+            column_gap: ComputedLength::default(),
+            grid_template_columns: TrackList::default(),
+            grid_template_rows: TrackList::default(),
+            grid_template_areas: GridAreas::default(),
+            grid_auto_columns: vec![TrackSize::default()],
+            grid_auto_rows: vec![TrackSize::default()],
+            grid_auto_flow: GridAutoFlow::default(),
+            grid_row_start: GridLine::Auto,
+            grid_row_end: GridLine::Auto,
+            grid_column_start: GridLine::Auto,
+            grid_column_end: GridLine::Auto,
+            margin: Sides::default(),
+            padding: Sides::default(),
+            border_width: Sides::all(3.0),
+            border_style: Sides::default(),
+            border_color: Sides::all(ColorValue::CurrentColor),
+            box_sizing: BoxSizing::ContentBox,
+            width: LengthOrAuto::Auto,
+            height: LengthOrAuto::Auto,
+            background_color: ColorValue::Color(Color::TRANSPARENT),
+            background_image: vec![None],
+            background_position: vec![BackgroundPosition::default()],
+            background_size: vec![BackgroundSize::default()],
+            background_repeat: vec![BackgroundRepeat::default()],
+            border_radius: BorderRadius::default(),
+            box_shadow: Vec::new(),
+            opacity: 1.0,
+            visibility: Visibility::Visible,
+            custom_properties: Arc::default(),
+        }
+    }
+
+    /// Creates a style for a child element, i.e. with the inherited
+    /// properties taken from the parent and all others set to their initial values.
+    pub fn inherit(parent: &Self) -> Self {
+        Self {
+            color: parent.color,
+            font_family: parent.font_family.clone(),
+            font_size: parent.font_size,
+            font_weight: parent.font_weight,
+            font_style: parent.font_style,
+            white_space: parent.white_space,
+            text_align: parent.text_align,
+            line_height: parent.line_height,
+            letter_spacing: parent.letter_spacing,
+            text_transform: parent.text_transform,
+            text_indent: parent.text_indent,
+            direction: parent.direction,
+            writing_mode: parent.writing_mode,
+            text_decorations: parent.text_decorations.clone(),
+            list_style_type: parent.list_style_type.clone(),
+            list_style_position: parent.list_style_position,
+            border_spacing: parent.border_spacing,
+            visibility: parent.visibility,
+            custom_properties: parent.custom_properties.clone(),
+            ..Self::initial()
+        }
+    }
+
+    /// Whether the given property is inherited by default.
+    fn is_inherited(name: &str) -> bool {
+        matches!(name, "color" | "font-family" | "font-size" | "font-weight" | "font-style" | "white-space" | "text-align" | "line-height"
+            | "letter-spacing" | "text-transform" | "text-indent" | "direction" | "writing-mode" | "list-style" | "list-style-type" | "list-style-position" | "border-spacing"
+            | "visibility")
+    }
+
+    /// Whether the box clips its overflowing content.
+    pub fn clips_overflow(&self) -> bool {
+        self.overflow_x.clips() || self.overflow_y.clips()
+    }
+
+    /// Whether the box is a scroll container, i.e. clips its content but
+    /// (possibly only programmatically) allows scrolling to it.
+    pub fn is_scroll_container(&self) -> bool {
+        self.overflow_x.is_scroll_container() || self.overflow_y.is_scroll_container()
+    }
+
+    /// The used border widths, which are zero for borders that aren't drawn.
+    pub fn border_widths(&self) -> Sides<f64> {
+        Sides {
+            top: if self.border_style.top.is_visible() { self.border_width.top } else { 0.0 },
+            right: if self.border_style.right.is_visible() { self.border_width.right } else { 0.0 },
+            bottom: if self.border_style.bottom.is_visible() { self.border_width.bottom } else { 0.0 },
+            left: if self.border_style.left.is_visible() { self.border_width.left } else { 0.0 },
+        }
+    }
+
+    /// The border colors with `currentColor` resolved.
+    pub fn border_colors(&self) -> Sides<Color> {
+        self.border_color.map(|c| c.resolve(self.color))
+    }
+
+    /// The background color with `currentColor` resolved.
+    pub fn used_background_color(&self) -> Color {
+        self.background_color.resolve(self.color)
+    }
+
+    /// The style of a box in a vertical writing mode as seen by the layout,
+    /// which lays it out as a horizontal box, i.e. with its physical sides,
+    /// sizes and overflow rotated (see `WritingMode::to_logical`).
+    pub fn to_logical(&self) -> Self {
+        let mode = self.writing_mode;
+        let vertical = mode.is_vertical();
+        Self {
+            margin: mode.to_logical(self.margin),
+            padding: mode.to_logical(self.padding),
+            border_width: mode.to_logical(self.border_width),
+            border_style: mode.to_logical(self.border_style),
+            border_color: mode.to_logical(self.border_color),
+            inset: mode.to_logical(self.inset),
+            width: if vertical { self.height } else { self.width },
+            height: if vertical { self.width } else { self.height },
+            overflow_x: if vertical { self.overflow_y } else { self.overflow_x },
+            overflow_y: if vertical { self.overflow_x } else { self.overflow_y },
+            ..self.clone()
+        }
+    }
+
+    /// The physical style of a box laid out as a horizontal one, i.e. the
+    /// inverse of `to_logical`.
+    pub fn to_physical(&self) -> Self {
+        let mode = self.writing_mode;
+        let vertical = mode.is_vertical();
+        Self {
+            margin: mode.to_physical(self.margin),
+            padding: mode.to_physical(self.padding),
+            border_width: mode.to_physical(self.border_width),
+            border_style: mode.to_physical(self.border_style),
+            border_color: mode.to_physical(self.border_color),
+            inset: mode.to_physical(self.inset),
+            width: if vertical { self.height } else { self.width },
+            height: if vertical { self.width } else { self.height },
+            overflow_x: if vertical { self.overflow_y } else { self.overflow_x },
+            overflow_y: if vertical { self.overflow_x } else { self.overflow_y },
+            ..self.clone()
+        }
+    }
+
+    /// Whether the box has a visible background color or any background images.
+    pub fn has_background(&self) -> bool {
+        self.used_background_color().alpha > 0.0 || self.background_image.iter().any(|i| i.is_some())
+    }
+
+    /// The layers of the background with an image, from the top. The lists
+    /// of positions, sizes and repeats are repeated to match the images.
+    /// See https://www.w3.org/TR/css-backgrounds-3/#layering
+    pub fn background_layers(&self) -> Vec<BackgroundLayer> {
+        self.background_image.iter().enumerate()
+            .filter_map(|(i, image)| Some(BackgroundLayer {
+                image: Some(image.clone()?),
+                position: self.background_position[i % self.background_position.len()],
+                size: self.background_size[i % self.background_size.len()],
+                repeat: self.background_repeat[i % self.background_repeat.len()],
+            }))
+            .collect()
+    }
+
+    /// Copies the value of the given property from another style.
+    fn copy_property(&mut self, name: &str, other: &Self) {
+        let (name, side) = split_side(name);
+        match name.as_ref() {
+            "display" => self.display = other.display,
+            "color" => self.color = other.color,
+            "font-family" => self.font_family = other.font_family.clone(),
+            "font-size" => self.font_size = other.font_size,
+            "font-weight" => self.font_weight = other.font_weight,
+            "font-style" => self.font_style = other.font_style,
+            "white-space" => self.white_space = other.white_space,
+            "text-align" => self.text_align = other.text_align,
+            "line-height" => self.line_height = other.line_height,
+            "letter-spacing" => self.letter_spacing = other.letter_spacing,
+            "text-transform" => self.text_transform = other.text_transform,
+            "text-indent" => self.text_indent = other.text_indent,
+            "direction" => self.direction = other.direction,
+            "unicode-bidi" => self.unicode_bidi = other.unicode_bidi,
+            "writing-mode" => self.writing_mode = other.writing_mode,
+            "text-decoration-line" => self.text_decoration_line = other.text_decoration_line,
+            "text-decoration-color" => self.text_decoration_color = other.text_decoration_color,
+            "text-decoration" => {
+                self.text_decoration_line = other.text_decoration_line;
+                self.text_decoration_color = other.text_decoration_color;
+            },
+            "list-style" => {
+                self.list_style_type = other.list_style_type.clone();
+                self.list_style_position = other.list_style_position;
+            },
+            "list-style-type" => self.list_style_type = other.list_style_type.clone(),
+            "list-style-position" => self.list_style_position = other.list_style_position,
+            "counter-reset" => self.counter_reset = other.counter_reset.clone(),
+            "counter-increment" => self.counter_increment = other.counter_increment.clone(),
+            "counter-set" => self.counter_set = other.counter_set.clone(),
+            "content" => self.content = other.content.clone(),
+            "border-spacing" => self.border_spacing = other.border_spacing,
+            "table-layout" => self.table_layout = other.table_layout,
+            "vertical-align" => self.vertical_align = other.vertical_align,
+            "position" => self.position = other.position,
+            "top" => self.inset.top = other.inset.top,
+            "right" => self.inset.right = other.inset.right,
+            "bottom" => self.inset.bottom = other.inset.bottom,
+            "left" => self.inset.left = other.inset.left,
+            "inset" => self.inset = other.inset,
+            "z-index" => self.z_index = other.z_index,
+            "overflow-x" => self.overflow_x = other.overflow_x,
+            "overflow-y" => self.overflow_y = other.overflow_y,
+            "overflow" => {
+                self.overflow_x = other.overflow_x;
+                self.overflow_y = other.overflow_y;
+            },
+            "float" => self.float = other.float,
+            "clear" => self.clear = other.clear,
+            "flex-direction" => self.flex_direction = other.flex_direction,
+            "flex-wrap" => self.flex_wrap = other.flex_wrap,
+            "flex-flow" => {
+                self.flex_direction = other.flex_direction;
+                self.flex_wrap = other.flex_wrap;
+            },
+            "justify-content" => self.justify_content = other.justify_content,
+            "align-items" => self.align_items = other.align_items,
+            "align-self" => self.align_self = other.align_self,
+            "flex-grow" => self.flex_grow = other.flex_grow,
+            "flex-shrink" => self.flex_shrink = other.flex_shrink,
+            "flex-basis" => self.flex_basis = other.flex_basis,
+            "flex" => {
+                self.flex_grow = other.flex_grow;
+                self.flex_shrink = other.flex_shrink;
+                self.flex_basis = other.flex_basis;
+            },
+            "order" => self.order = other.order,
+            "row-gap" => self.row_gap = other.row_gap,
+            "column-gap" => self.column_gap = other.column_gap,
+            "gap" | "grid-gap" => {
+                self.row_gap = other.row_gap;
+                self.column_gap = other.column_gap;
+            },
+            "grid-template-columns" => self.grid_template_columns = other.grid_template_columns.clone(),
+            "grid-template-rows" => self.grid_template_rows = other.grid_template_rows.clone(),
+            "grid-template-areas" => self.grid_template_areas = other.grid_template_areas.clone(),
+            "grid-auto-columns" => self.grid_auto_columns = other.grid_auto_columns.clone(),
+            "grid-auto-rows" => self.grid_auto_rows = other.grid_auto_rows.clone(),
+            "grid-auto-flow" => self.grid_auto_flow = other.grid_auto_flow,
+            "grid-row-start" => self.grid_row_start = other.grid_row_start.clone(),
+            "grid-row-end" => self.grid_row_end = other.grid_row_end.clone(),
+            "grid-column-start" => self.grid_column_start = other.grid_column_start.clone(),
+            "grid-column-end" => self.grid_column_end = other.grid_column_end.clone(),
+            "grid-row" => {
+                self.grid_row_start = other.grid_row_start.clone();
+                self.grid_row_end = other.grid_row_end.clone();
+            },
+            "grid-column" => {
+                self.grid_column_start = other.grid_column_start.clone();
+                self.grid_column_end = other.grid_column_end.clone();
+            },
+            "grid-area" => {
+                self.grid_row_start = other.grid_row_start.clone();
+                self.grid_row_end = other.grid_row_end.clone();
+                self.grid_column_start = other.grid_column_start.clone();
+                self.grid_column_end = other.grid_column_end.clone();
+            },
+            "margin" => copy_sides(&mut self.margin, &other.margin, side),
+            "padding" => copy_sides(&mut self.padding, &other.padding, side),
+            "border-width" => copy_sides(&mut self.border_width, &other.border_width, side),
+            "border-style" => copy_sides(&mut self.border_style, &other.border_style, side),
+            "border-color" => copy_sides(&mut self.border_color, &other.border_color, side),
+            "border" => {
+                copy_sides(&mut self.border_width, &other.border_width, side);
+                copy_sides(&mut self.border_style, &other.border_style, side);
+                copy_sides(&mut self.border_color, &other.border_color, side);
+            },
+            "box-sizing" => self.box_sizing = other.box_sizing,
+            "width" => self.width = other.width,
+            "height" => self.height = other.height,
+            "background-color" => self.background_color = other.background_color,
+            "background-image" => self.background_image = other.background_image.clone(),
+            "background-position" => self.background_position = other.background_position.clone(),
+            "background-size" => self.background_size = other.background_size.clone(),
+            "background-repeat" => self.background_repeat = other.background_repeat.clone(),
+            "background" => {
+                self.background_color = other.background_color;
+                self.background_image = other.background_image.clone();
+                self.background_position = other.background_position.clone();
+                self.background_size = other.background_size.clone();
+                self.background_repeat = other.background_repeat.clone();
+            },
+            "border-radius" => self.border_radius = other.border_radius,
+            "border-top-left-radius" => self.border_radius.top_left = other.border_radius.top_left,
+            "border-top-right-radius" => self.border_radius.top_right = other.border_radius.top_right,
+            "border-bottom-right-radius" => self.border_radius.bottom_right = other.border_radius.bottom_right,
+            "border-bottom-left-radius" => self.border_radius.bottom_left = other.border_radius.bottom_left,
+            "box-shadow" => self.box_shadow = other.box_shadow.clone(),
+            "opacity" => self.opacity = other.opacity,
+            "visibility" => self.visibility = other.visibility,
+            _ => {},
+        }
+    }
+
+    /// Applies a declaration, leaving the style unchanged if it is invalid.
+    /// Lengths are resolved against the given context, which should have this
+    /// element's font size (or the parent's when applying `font-size` itself).
+    pub fn apply(&mut self, name: &str, value: &str, parent: &Self, ctx: &ResolveContext) -> Result<()> {
+        let value = value.trim();
+
+        // Logical properties set the physical ones they map to
+        if let Some(physical) = self.physical_properties(name) {
+            let values = split_components(value);
+            for (i, physical_name) in physical.iter().enumerate() {
+                // Shorthands like `margin-inline` take the values for the start and the end
+                let value = match values.as_slice() {
+                    [start, end] if physical.len() == 2 && !name.starts_with("border") => if i == 0 { start } else { end },
+                    _ => value,
+                };
+                self.apply(physical_name, value, parent, ctx)?;
+            }
+            return Ok(());
+        }
+
+        // Handle the CSS-wide keywords
+        match value.to_ascii_lowercase().as_str() {
+            "inherit" => self.copy_property(name, parent),
+            "initial" => self.copy_property(name, &Self::initial()),
+            "unset" => self.unset(name, parent),
+            _ => return self.apply_value(name, value, parent, ctx),
+        }
+        Ok(())
+    }
+
+    /// Resets the given property to its inherited value if it is inherited
+    /// and to its initial value otherwise.
+    pub fn unset(&mut self, name: &str, parent: &Self) {
+        if let Some(physical) = self.physical_properties(name) {
+            for name in physical {
+                self.unset(&name, parent);
+            }
+        } else if Self::is_inherited(name) {
+            self.copy_property(name, parent)
+        } else {
+            self.copy_property(name, &Self::initial())
+        }
+    }
+
+    /// The physical properties a logical property maps to given the writing
+    /// mode and direction (which have to be applied first), e.g. `margin-left`
+    /// for `margin-inline-start` in horizontal left-to-right text or
+    /// `margin-top` and `margin-bottom` for `margin-block`. Returns `None`
+    /// for physical properties.
+    /// See https://www.w3.org/TR/css-logical-1/
+    fn physical_properties(&self, name: &str) -> Option<Vec<String>> {
+        let vertical = self.writing_mode.is_vertical();
+        let sized = |prefix: &str, rest: &str| match rest {
+            "inline-size" => Some(vec![format!("{}{}", prefix, if vertical { "height" } else { "width" })]),
+            "block-size" => Some(vec![format!("{}{}", prefix, if vertical { "width" } else { "height" })]),
+            _ => None,
+        };
+        if let Some(physical) = ["", "min-", "max-"].into_iter().find_map(|p| name.strip_prefix(p).and_then(|rest| sized(p, rest))) {
+            return Some(physical);
+        }
+        let (base, rest) = name.split_once('-')?;
+        if !matches!(base, "margin" | "padding" | "border" | "inset") {
+            return None;
+        }
+        let (axis, rest) = rest.split_once('-').unwrap_or((rest, ""));
+        let (start, end) = LogicalSide::pair(axis)?;
+        let (sides, suffix) = match rest.split_once('-').unwrap_or((rest, "")) {
+            ("start", suffix) => (vec![start], suffix),
+            ("end", suffix) => (vec![end], suffix),
+            _ => (vec![start, end], rest),
+        };
+        if !matches!(suffix, "" | "width" | "style" | "color") || (base != "border" && !suffix.is_empty()) {
+            return None;
+        }
+        Some(sides.into_iter()
+            .map(|side| {
+                let side = self.writing_mode.physical_side(self.direction, side).name();
+                match (base, suffix) {
+                    // The offsets of positioned boxes are named by their sides alone
+                    ("inset", _) => side.to_owned(),
+                    (_, "") => format!("{}-{}", base, side),
+                    _ => format!("{}-{}-{}", base, side, suffix),
+                }
+            })
+            .collect())
+    }
+
+    /// Parses and applies a value for the given property.
+    fn apply_value(&mut self, name: &str, value: &str, parent: &Self, ctx: &ResolveContext) -> Result<()> {
+        let (name, side) = split_side(name);
+        match name.as_ref() {
+            "display" => self.display = match value.to_ascii_lowercase().as_str() {
+                "none" => Display::None,
+                "inline" => Display::Inline,
+                "block" => Display::Block,
+                "list-item" => Display::ListItem,
+                "table" => Display::Table,
+                "inline-table" => Display::InlineTable,
+                "table-row-group" => Display::TableRowGroup,
+                "table-header-group" => Display::TableHeaderGroup,
+                "table-footer-group" => Display::TableFooterGroup,
+                "table-row" => Display::TableRow,
+                "table-cell" => Display::TableCell,
+                "table-caption" => Display::TableCaption,
+                "flex" => Display::Flex,
+                "inline-flex" => Display::InlineFlex,
+                "grid" => Display::Grid,
+                "inline-grid" => Display::InlineGrid,
+                // Columns don't generate boxes
+                "table-column" | "table-column-group" => Display::None,
+                // TODO: Support other display types
+                _ => bail!("Unsupported display type"),
+            },
+            "color" => self.color = ColorValue::parse(value)?.resolve(parent.color),
+            "font-family" => self.font_family = Arc::new(parse_font_family(value)?),
+            "font-size" => self.font_size = parse_font_size(value, parent.font_size, ctx)?,
+            "font-weight" => self.font_weight = parse_font_weight(value, parent.font_weight)?,
+            "font-style" => self.font_style = parse_font_style(value)?,
+            "white-space" => self.white_space = WhiteSpace::parse(value)?,
+            "text-align" => self.text_align = TextAlign::parse(value)?,
+            "line-height" => self.line_height = LineHeight::parse(value, self.font_size, ctx)?,
+            "letter-spacing" => self.letter_spacing = parse_letter_spacing(value, self.font_size, ctx)?,
+            "text-transform" => self.text_transform = TextTransform::parse(value)?,
+            "text-indent" => self.text_indent = Length::parse(value)?.compute(ctx),
+            "direction" => self.direction = Direction::parse(value)?,
+            "unicode-bidi" => self.unicode_bidi = UnicodeBidi::parse(value)?,
+            "writing-mode" => self.writing_mode = WritingMode::parse(value)?,
+            "text-decoration-line" => self.text_decoration_line = TextDecorationLine::parse(value)?,
+            "text-decoration-color" => self.text_decoration_color = ColorValue::parse(value)?,
+            "text-decoration-style" => {},
+            "text-decoration" => (self.text_decoration_line, self.text_decoration_color) = parse_text_decoration(value)?,
+            "list-style" => {
+                // TODO: Support list-style-image
+                let mut list_style_type = None;
+                let mut list_style_position = None;
+                for component in split_components(value) {
+                    if let (None, Ok(p)) = (list_style_position, ListStylePosition::parse(component)) {
+                        list_style_position = Some(p);
+                    } else if list_style_type.is_none() {
+                        list_style_type = Some(ListStyleType::parse(component)?);
+                    } else {
+                        bail!("Invalid list-style component {}", component);
+                    }
+                }
+                self.list_style_type = list_style_type.unwrap_or_default();
+                self.list_style_position = list_style_position.unwrap_or_default();
+            },
+            "list-style-type" => self.list_style_type = ListStyleType::parse(value)?,
+            "list-style-position" => self.list_style_position = ListStylePosition::parse(value)?,
+            "counter-reset" => self.counter_reset = parse_counter_list(value, 0)?,
+            "counter-increment" => self.counter_increment = parse_counter_list(value, 1)?,
+            "counter-set" => self.counter_set = parse_counter_list(value, 0)?,
+            "content" => self.content = Content::parse(value)?,
+            "border-spacing" => {
+                let lengths = split_components(value).into_iter()
+                    .map(|c| parse_non_negative_length(c, ctx))
+                    .collect::<Result<Vec<_>>>()?;
+                if lengths.iter().any(|l| l.percent != 0.0) {
+                    bail!("Border spacing may not be a percentage");
+                }
+                let lengths: Vec<_> = lengths.into_iter().map(|l| l.px).collect();
+                self.border_spacing = match lengths.as_slice() {
+                    [both] => (*both, *both),
+                    [horizontal, vertical] => (*horizontal, *vertical),
+                    _ => bail!("Expected 1 or 2 lengths"),
+                };
+            },
+            "table-layout" => self.table_layout = match value.to_ascii_lowercase().as_str() {
+                "auto" => TableLayout::Auto,
+                "fixed" => TableLayout::Fixed,
+                _ => bail!("Unknown table layout"),
+            },
+            "vertical-align" => self.vertical_align = VerticalAlign::parse(value)?,
+            "position" => self.position = Position::parse(value)?,
+            "top" => set_sides(&mut self.inset, Some(Side::Top), value, |v| LengthOrAuto::parse(v, ctx))?,
+            "right" => set_sides(&mut self.inset, Some(Side::Right), value, |v| LengthOrAuto::parse(v, ctx))?,
+            "bottom" => set_sides(&mut self.inset, Some(Side::Bottom), value, |v| LengthOrAuto::parse(v, ctx))?,
+            "left" => set_sides(&mut self.inset, Some(Side::Left), value, |v| LengthOrAuto::parse(v, ctx))?,
+            "inset" => set_sides(&mut self.inset, None, value, |v| LengthOrAuto::parse(v, ctx))?,
+            "z-index" => self.z_index = match value.to_ascii_lowercase().as_str() {
+                "auto" => None,
+                _ => Some(value.parse()?),
+            },
+            "overflow-x" => self.overflow_x = Overflow::parse(value)?,
+            "overflow-y" => self.overflow_y = Overflow::parse(value)?,
+            "overflow" => {
+                let values = split_components(value).into_iter()
+                    .map(Overflow::parse)
+                    .collect::<Result<Vec<_>>>()?;
+                (self.overflow_x, self.overflow_y) = match values.as_slice() {
+                    [both] => (*both, *both),
+                    [x, y] => (*x, *y),
+                    _ => bail!("Expected 1 or 2 overflow values"),
+                };
+            },
+            "float" => self.float = Float::parse(value)?,
+            "clear" => self.clear = Clear::parse(value)?,
+            "flex-direction" => self.flex_direction = FlexDirection::parse(value)?,
+            "flex-wrap" => self.flex_wrap = FlexWrap::parse(value)?,
+            "flex-flow" => {
+                let mut direction = None;
+                let mut wrap = None;
+                for component in split_components(value) {
+                    if let (None, Ok(d)) = (direction, FlexDirection::parse(component)) {
+                        direction = Some(d);
+                    } else if let (None, Ok(w)) = (wrap, FlexWrap::parse(component)) {
+                        wrap = Some(w);
+                    } else {
+                        bail!("Invalid flex-flow component {}", component);
+                    }
+                }
+                self.flex_direction = direction.unwrap_or_default();
+                self.flex_wrap = wrap.unwrap_or_default();
+            },
+            "justify-content" => self.justify_content = JustifyContent::parse(value)?,
+            "align-items" => self.align_items = AlignItems::parse(value)?,
+            "align-self" => self.align_self = parse_align_self(value)?,
+            "flex-grow" => self.flex_grow = parse_flex_factor(value)?,
+            "flex-shrink" => self.flex_shrink = parse_flex_factor(value)?,
+            "flex-basis" => self.flex_basis = parse_flex_basis(value, ctx)?,
+            "flex" => (self.flex_grow, self.flex_shrink, self.flex_basis) = parse_flex(value, ctx)?,
+            "order" => self.order = value.parse()?,
+            "row-gap" => self.row_gap = parse_gap(value, ctx)?,
+            "column-gap" => self.column_gap = parse_gap(value, ctx)?,
+            "gap" | "grid-gap" => {
+                let gaps = split_components(value).into_iter()
+                    .map(|c| parse_gap(c, ctx))
+                    .collect::<Result<Vec<_>>>()?;
+                (self.row_gap, self.column_gap) = match gaps.as_slice() {
+                    [both] => (*both, *both),
+                    [row, column] => (*row, *column),
+                    _ => bail!("Expected 1 or 2 gaps"),
+                };
+            },
+            "grid-template-columns" => self.grid_template_columns = TrackList::parse(value, ctx)?,
+            "grid-template-rows" => self.grid_template_rows = TrackList::parse(value, ctx)?,
+            "grid-template-areas" => self.grid_template_areas = GridAreas::parse(value)?,
+            "grid-auto-columns" => self.grid_auto_columns = TrackSize::parse_list(value, ctx)?,
+            "grid-auto-rows" => self.grid_auto_rows = TrackSize::parse_list(value, ctx)?,
+            "grid-auto-flow" => self.grid_auto_flow = GridAutoFlow::parse(value)?,
+            "grid-row-start" => self.grid_row_start = GridLine::parse(value)?,
+            "grid-row-end" => self.grid_row_end = GridLine::parse(value)?,
+            "grid-column-start" => self.grid_column_start = GridLine::parse(value)?,
+            "grid-column-end" => self.grid_column_end = GridLine::parse(value)?,
+            "grid-row" => {
+                let [start, end] = GridLine::parse_shorthand(value)?;
+                (self.grid_row_start, self.grid_row_end) = (start, end);
+            },
+            "grid-column" => {
+                let [start, end] = GridLine::parse_shorthand(value)?;
+                (self.grid_column_start, self.grid_column_end) = (start, end);
+            },
+            "grid-area" => {
+                let [row_start, column_start, row_end, column_end] = GridLine::parse_shorthand(value)?;
+                (self.grid_row_start, self.grid_column_start, self.grid_row_end, self.grid_column_end) = (row_start, column_start, row_end, column_end);
+            },
+            "margin" => set_sides(&mut self.margin, side, value, |v| LengthOrAuto::parse(v, ctx))?,
+            "padding" => set_sides(&mut self.padding, side, value, |v| parse_non_negative_length(v, ctx))?,
+            "border-width" => set_sides(&mut self.border_width, side, value, |v| parse_border_width(v, ctx))?,
+            "border-style" => set_sides(&mut self.border_style, side, value, BorderStyle::parse)?,
+            "border-color" => set_sides(&mut self.border_color, side, value, ColorValue::parse)?,
+            "border" => {
+                let (width, style, color) = parse_border(value, ctx)?;
+                for side in side.map(|s| vec![s]).unwrap_or_else(|| Side::ALL.to_vec()) {
+                    *self.border_width.get_mut(side) = width;
+                    *self.border_style.get_mut(side) = style;
+                    *self.border_color.get_mut(side) = color;
+                }
+            },
+            "box-sizing" => self.box_sizing = match value.to_ascii_lowercase().as_str() {
+                "content-box" => BoxSizing::ContentBox,
+                "border-box" => BoxSizing::BorderBox,
+                _ => bail!("Unknown box sizing"),
+            },
+            "width" => self.width = parse_non_negative_length_or_auto(value, ctx)?,
+            "height" => self.height = parse_non_negative_length_or_auto(value, ctx)?,
+            "background-color" => self.background_color = ColorValue::parse(value)?,
+            "background-image" => self.background_image = split_commas(value).into_iter()
+                .map(|v| BackgroundImage::parse(v, ctx))
+                .collect::<Result<_>>()?,
+            "background-position" => self.background_position = split_commas(value).into_iter()
+                .map(|v| BackgroundPosition::parse(v, ctx))
+                .collect::<Result<_>>()?,
+            "background-size" => self.background_size = split_commas(value).into_iter()
+                .map(|v| BackgroundSize::parse(v, ctx))
+                .collect::<Result<_>>()?,
+            "background-repeat" => self.background_repeat = split_commas(value).into_iter()
+                .map(BackgroundRepeat::parse)
+                .collect::<Result<_>>()?,
+            "background" => {
+                let (color, layers) = parse_background(value, ctx)?;
+                self.background_color = color;
+                self.background_image = layers.iter().map(|l| l.image.clone()).collect();
+                self.background_position = layers.iter().map(|l| l.position).collect();
+                self.background_size = layers.iter().map(|l| l.size).collect();
+                self.background_repeat = layers.iter().map(|l| l.repeat).collect();
+            },
+            "border-radius" => self.border_radius = BorderRadius::parse(value, ctx)?,
+            "border-top-left-radius" | "border-top-right-radius" | "border-bottom-right-radius" | "border-bottom-left-radius" => {
+                let corner = &name["border-".len()..name.len() - "-radius".len()];
+                *self.border_radius.get_mut(corner).expect("Unknown corner") = CornerRadius::parse(value, ctx)?;
+            },
+            "box-shadow" => self.box_shadow = BoxShadow::parse_list(value, ctx)?,
+            "opacity" => self.opacity = parse_opacity(value)?,
+            "visibility" => self.visibility = Visibility::parse(value)?,
+            _ => bail!("Unsupported property"),
+        }
+        Ok(())
+    }
+}
+
+/// The properties that can refer to a single side of a box, e.g. `margin`
+/// as in `margin-top`.
+const SIDED_PROPERTIES: [&str; 6] = ["margin", "padding", "border", "border-width", "border-style", "border-color"];
+
+/// Splits the name of a property referring to a single side of a box into
+/// the name of the corresponding shorthand and the side, e.g. `border-top-width`
+/// into `border-width` and `Side::Top`. Other names are returned as-is.
+fn split_side(name: &str) -> (Cow<'_, str>, Option<Side>) {
+    for side in Side::ALL {
+        let infix = format!("-{}", side.name());
+        if let Some(i) = name.find(&infix) {
+            let rest = &name[i + infix.len()..];
+            let base = format!("{}{}", &name[..i], rest);
+            if (rest.is_empty() || rest.starts_with('-')) && SIDED_PROPERTIES.contains(&base.as_str()) {
+                return (Cow::Owned(base), Some(side));
+            }
+        }
+    }
+    (Cow::Borrowed(name), None)
+}
+
+/// Sets a single side of a property or all sides from 1 to 4 components
+/// as in shorthand properties.
+fn set_sides<T>(sides: &mut Sides<T>, side: Option<Side>, value: &str, parse: impl Fn(&str) -> Result<T>) -> Result<()> where T: Clone {
+    match side {
+        Some(side) => *sides.get_mut(side) = parse(value)?,
+        None => *sides = Sides::from_components(split_components(value).into_iter().map(parse).collect::<Result<_>>()?)?,
+    }
+    Ok(())
+}
+
+/// Copies a single side or all sides of a property.
+fn copy_sides<T>(sides: &mut Sides<T>, other: &Sides<T>, side: Option<Side>) where T: Clone {
+    match side {
+        Some(side) => *sides.get_mut(side) = other.get(side).clone(),
+        None => *sides = other.clone(),
+    }
+}
+
+/// Parses a non-negative length, e.g. for `padding`.
+fn parse_non_negative_length(value: &str, ctx: &ResolveContext) -> Result<ComputedLength> {
+    let length = Length::parse(value)?.compute(ctx);
+    if length.px < 0.0 || length.percent < 0.0 {
+        bail!("Length may not be negative");
+    }
+    Ok(length)
+}
+
+/// Parses a non-negative length or `auto`, e.g. for `width`.
+fn parse_non_negative_length_or_auto(value: &str, ctx: &ResolveContext) -> Result<LengthOrAuto> {
+    if value.eq_ignore_ascii_case("auto") {
+        Ok(LengthOrAuto::Auto)
+    } else {
+        Ok(LengthOrAuto::Length(parse_non_negative_length(value, ctx)?))
+    }
+}
+
+/// Parses a `row-gap` or `column-gap` value, where `normal` is zero.
+fn parse_gap(value: &str, ctx: &ResolveContext) -> Result<ComputedLength> {
+    if value.eq_ignore_ascii_case("normal") {
+        Ok(ComputedLength::default())
+    } else {
+        parse_non_negative_length(value, ctx)
+    }
+}
+
+/// Parses a `border` shorthand, i.e. a width, style and color in any order.
+/// Omitted values are set to their initial values.
+fn parse_border(value: &str, ctx: &ResolveContext) -> Result<(f64, BorderStyle, ColorValue)> {
+    let mut width = None;
+    let mut style = None;
+    let mut color = None;
+    for component in split_components(value) {
+        if let (None, Ok(w)) = (width, parse_border_width(component, ctx)) {
+            width = Some(w);
+        } else if let (None, Ok(s)) = (style, BorderStyle::parse(component)) {
+            style = Some(s);
+        } else if let (None, Ok(c)) = (color, ColorValue::parse(component)) {
+            color = Some(c);
+        } else {
+            bail!("Invalid border component {}", component);
+        }
+    }
+    Ok((width.unwrap_or(3.0), style.unwrap_or_default(), color.unwrap_or(ColorValue::CurrentColor)))
+}
+
+/// Parses a `font-size` value given the parent's font size.
+fn parse_font_size(value: &str, parent_font_size: f64, ctx: &ResolveContext) -> Result<f64> {
+    // See https://www.w3.org/TR/css-fonts-4/#absolute-size-mapping
+    let scale = match value.to_ascii_lowercase().as_str() {
+        "xx-small" => 3.0 / 5.0,
+        "x-small" => 3.0 / 4.0,
+        "small" => 8.0 / 9.0,
+        "medium" => 1.0,
+        "large" => 6.0 / 5.0,
+        "x-large" => 3.0 / 2.0,
+        "xx-large" => 2.0,
+        "xxx-large" => 3.0,
+        "larger" => return Ok(parent_font_size * 1.2),
+        "smaller" => return Ok(parent_font_size / 1.2),
+        _ => {
+            let font_size = Length::parse(value)?.resolve(ctx, parent_font_size);
+            if font_size < 0.0 {
+                bail!("Font size may not be negative");
+            }
+            return Ok(font_size);
+        },
+    };
+    Ok(DEFAULT_FONT_SIZE * scale)
+}
+
+/// Parses a `font-weight` value given the parent's font weight.
+fn parse_font_weight(value: &str, parent_font_weight: FontWeight) -> Result<FontWeight> {
+    let parent = parent_font_weight.to_raw();
+    Ok(match value.to_ascii_lowercase().as_str() {
+        "normal" => FontWeight::REGULAR,
+        "bold" => FontWeight::BOLD,
+        // See https://www.w3.org/TR/css-fonts-4/#relative-weights
+        "bolder" => FontWeight::new(match parent {
+            0..=349 => 400,
+            350..=549 => 700,
+            _ => 900,
+        }),
+        "lighter" => FontWeight::new(match parent {
+            0..=549 => 100,
+            550..=749 => 400,
+            _ => 700,
+        }),
+        raw => {
+            let weight: f64 = raw.parse()?;
+            if !(1.0..=1000.0).contains(&weight) {
+                bail!("Font weight out of range");
+            }
+            FontWeight::new(weight as u16)
+        },
+    })
+}
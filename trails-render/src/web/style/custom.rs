@@ -0,0 +1,158 @@
+use std::collections::{HashMap, HashSet};
+
+use trails_base::log::debug;
+
+/// The computed values of custom properties (e.g. `--accent-color`), with
+/// all `var()` references substituted.
+pub type CustomProperties = HashMap<String, String>;
+
+/// The maximum length of a value after substituting `var()` references. This
+/// guards against exponentially growing values from nested references.
+const MAX_SUBSTITUTED_LENGTH: usize = 65536;
+
+/// Computes the custom properties of an element from its specified custom
+/// property declarations (in ascending order of precedence) and the parent's
+/// custom properties. References between custom properties are resolved and
+/// properties in reference cycles become guaranteed-invalid (i.e. are removed).
+pub fn compute_custom_properties<'a>(declarations: impl IntoIterator<Item=(&'a str, &'a str)>, parent: &CustomProperties) -> CustomProperties {
+    // Cascade the specified values
+    let mut specified: HashMap<&str, Option<&str>> = HashMap::new();
+    for (name, value) in declarations {
+        let value = match value.trim().to_ascii_lowercase().as_str() {
+            // Custom properties are inherited, so `unset` is the same as `inherit`
+            "inherit" | "unset" => parent.get(name).map(|v| v.as_str()),
+            "initial" => None,
+            _ => Some(value.trim()),
+        };
+        specified.insert(name, value);
+    }
+
+    // Resolve references between them
+    let mut resolver = Resolver {
+        specified: &specified,
+        parent,
+        computed: HashMap::new(),
+        stack: Vec::new(),
+        in_cycle: HashSet::new(),
+    };
+    for name in specified.keys() {
+        resolver.resolve(name);
+    }
+
+    let mut computed = parent.clone();
+    for (name, value) in resolver.computed {
+        match value {
+            Some(value) => computed.insert(name.to_owned(), value),
+            None => computed.remove(name),
+        };
+    }
+    computed
+}
+
+/// Resolves the specified custom properties of a single element via
+/// depth-first search, detecting cycles along the way.
+struct Resolver<'a> {
+    specified: &'a HashMap<&'a str, Option<&'a str>>,
+    parent: &'a CustomProperties,
+    computed: HashMap<&'a str, Option<String>>,
+    /// The properties currently being resolved.
+    stack: Vec<&'a str>,
+    /// The properties found to be part of a cycle.
+    in_cycle: HashSet<&'a str>,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve(&mut self, name: &str) -> Option<String> {
+        let Some((&name, &value)) = self.specified.get_key_value(name) else {
+            // Not specified on this element, so the value is inherited (and already resolved)
+            return self.parent.get(name).cloned();
+        };
+        if let Some(computed) = self.computed.get(name) {
+            return computed.clone();
+        }
+        if let Some(i) = self.stack.iter().position(|&n| n == name) {
+            // We found a cycle, all properties on it are invalid
+            debug!("Custom properties {:?} form a cycle", &self.stack[i..]);
+            self.in_cycle.extend(self.stack[i..].iter().copied());
+            return None;
+        }
+
+        self.stack.push(name);
+        let resolved = value.and_then(|value| substitute_vars_with(value, &mut |n| self.resolve(n)));
+        self.stack.pop();
+
+        let resolved = if self.in_cycle.contains(name) { None } else { resolved };
+        self.computed.insert(name, resolved.clone());
+        resolved
+    }
+}
+
+/// Whether the given value contains `var()` references.
+pub fn contains_vars(value: &str) -> bool {
+    find_var(value).is_some()
+}
+
+/// Substitutes the `var()` references in the given value. Returns `None` if
+/// the value is invalid at computed-value time, i.e. if it references a
+/// guaranteed-invalid custom property without a fallback.
+pub fn substitute_vars(value: &str, custom_properties: &CustomProperties) -> Option<String> {
+    substitute_vars_with(value, &mut |name| custom_properties.get(name).cloned())
+}
+
+/// Substitutes the `var()` references in the given value using the given lookup.
+fn substitute_vars_with(value: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = value;
+
+    while let Some(start) = find_var(rest) {
+        result.push_str(&rest[..start]);
+        let args_start = start + "var(".len();
+        let args_end = args_start + matching_paren(&rest[args_start..])?;
+        let args = &rest[args_start..args_end];
+        let (name, fallback) = match args.find(',') {
+            Some(i) => (args[..i].trim(), Some(&args[i + 1..])),
+            None => (args.trim(), None),
+        };
+        if !name.starts_with("--") {
+            return None;
+        }
+        let substituted = match (lookup(name), fallback) {
+            (Some(value), _) => value,
+            (None, Some(fallback)) => substitute_vars_with(fallback.trim(), lookup)?,
+            (None, None) => return None,
+        };
+        result.push_str(&substituted);
+        if result.len() > MAX_SUBSTITUTED_LENGTH {
+            debug!("Value with var() references is too long after substitution");
+            return None;
+        }
+        rest = &rest[args_end + 1..];
+    }
+
+    result.push_str(rest);
+    Some(result)
+}
+
+/// Finds the first (case-insensitive) `var(` in the given string.
+fn find_var(value: &str) -> Option<usize> {
+    let bytes = value.as_bytes();
+    (0..bytes.len().saturating_sub(3)).find(|&i| {
+        // Make sure we don't match the end of another function name, e.g. `envvar(`
+        let preceded_by_name = i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'-' || bytes[i - 1] == b'_');
+        !preceded_by_name && bytes[i..i + 4].eq_ignore_ascii_case(b"var(")
+    })
+}
+
+/// Finds the index of the parenthesis closing an (already consumed) opening one.
+fn matching_paren(value: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(i),
+            ')' => depth -= 1,
+            _ => {},
+        }
+    }
+    None
+}
@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+
+use trails_model::css::{Combinator, PseudoClass, Selector};
+use trails_model::dom::Element;
+
+use super::PRESENTATIONAL_ATTRIBUTES;
+
+/// Something about an element that a selector can depend on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum DependencyKey {
+    Class(String),
+    Id(String),
+    Attribute(String),
+}
+
+/// Which elements have to be restyled after an element changed, in ascending
+/// order of extent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Invalidation {
+    /// No restyle is needed.
+    None,
+    /// Only the element itself (and descendants whose inherited values change).
+    Element,
+    /// The element and its descendants.
+    Subtree,
+    /// The element, its subsequent siblings and their descendants.
+    Siblings,
+}
+
+/// An index of the classes, ids and attributes that the selectors in a
+/// cascade depend on, used to determine which elements have to be restyled
+/// after a DOM change.
+#[derive(Debug, Default)]
+pub struct StyleDependencies {
+    /// The keys, mapped to whether they (also) affect subsequent siblings.
+    keys: HashMap<DependencyKey, bool>,
+}
+
+impl StyleDependencies {
+    /// Records the dependencies of a selector.
+    pub fn add_selector(&mut self, selector: &Selector) {
+        for (i, compound) in selector.compounds.iter().enumerate() {
+            // A compound left of a sibling combinator affects subsequent siblings
+            let affects_siblings = matches!(
+                selector.combinators.get(i),
+                Some(Combinator::NextSibling | Combinator::SubsequentSibling)
+            );
+            for class in &compound.classes {
+                self.add(DependencyKey::Class(class.clone()), affects_siblings);
+            }
+            for id in &compound.ids {
+                self.add(DependencyKey::Id(id.clone()), affects_siblings);
+            }
+            for attribute in &compound.attributes {
+                self.add(DependencyKey::Attribute(attribute.name.to_ascii_lowercase()), affects_siblings);
+            }
+            for pseudo_class in &compound.pseudo_classes {
+                match pseudo_class {
+                    PseudoClass::Link => self.add(DependencyKey::Attribute("href".to_owned()), affects_siblings),
+                    PseudoClass::Not(selectors) | PseudoClass::Is(selectors) | PseudoClass::Where(selectors) => {
+                        for inner in selectors {
+                            let mut inner_dependencies = Self::default();
+                            inner_dependencies.add_selector(inner);
+                            for (key, inner_affects_siblings) in inner_dependencies.keys {
+                                self.add(key, affects_siblings || inner_affects_siblings);
+                            }
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+    }
+
+    fn add(&mut self, key: DependencyKey, affects_siblings: bool) {
+        let entry = self.keys.entry(key).or_default();
+        *entry = *entry || affects_siblings;
+    }
+
+    fn invalidation(&self, key: DependencyKey) -> Invalidation {
+        match self.keys.get(&key) {
+            Some(true) => Invalidation::Siblings,
+            Some(false) => Invalidation::Subtree,
+            None => Invalidation::None,
+        }
+    }
+
+    /// Determines which elements have to be restyled after the attributes of
+    /// an element changed from the old to the new version.
+    pub fn invalidation_between(&self, old: &Element, new: &Element) -> Invalidation {
+        let names: HashSet<&str> = old.attributes().chain(new.attributes()).map(|(k, _)| k).collect();
+        let mut invalidation = Invalidation::None;
+        for name in names {
+            let (old_value, new_value) = (old.attribute(name), new.attribute(name));
+            if old_value == new_value {
+                continue;
+            }
+            let attribute = self.invalidation(DependencyKey::Attribute(name.to_ascii_lowercase()));
+            let specific = match name {
+                "style" => Invalidation::Element,
+                name if PRESENTATIONAL_ATTRIBUTES.contains(&name) => Invalidation::Subtree,
+                "class" => {
+                    let old_classes: HashSet<&str> = old_value.unwrap_or_default().split_whitespace().collect();
+                    let new_classes: HashSet<&str> = new_value.unwrap_or_default().split_whitespace().collect();
+                    old_classes.symmetric_difference(&new_classes)
+                        .map(|&c| self.invalidation(DependencyKey::Class(c.to_owned())))
+                        .max()
+                        .unwrap_or(Invalidation::None)
+                },
+                "id" => [old_value, new_value].into_iter()
+                    .flatten()
+                    .map(|id| self.invalidation(DependencyKey::Id(id.to_owned())))
+                    .max()
+                    .unwrap_or(Invalidation::None),
+                _ => Invalidation::None,
+            };
+            invalidation = invalidation.max(attribute).max(specific);
+        }
+        invalidation
+    }
+}
@@ -0,0 +1,210 @@
+use trails_base::{Result, bail};
+use trails_model::css::{ColorValue, ComputedLength, Length, ResolveContext};
+use trails_model::parse::css::{split_commas, split_components};
+
+use super::Sides;
+
+/// The radii of a rounded corner of a border box.
+/// See https://www.w3.org/TR/css-backgrounds-3/#border-radius
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CornerRadius {
+    /// The horizontal radius, percentages refer to the width of the border box.
+    pub horizontal: ComputedLength,
+    /// The vertical radius, percentages refer to the height of the border box.
+    pub vertical: ComputedLength,
+}
+
+impl CornerRadius {
+    /// Parses the value of a longhand like `border-top-left-radius`, i.e. one
+    /// radius (for circular corners) or a horizontal and a vertical one.
+    pub fn parse(value: &str, ctx: &ResolveContext) -> Result<Self> {
+        let radii = split_components(value).into_iter()
+            .map(|c| parse_radius(c, ctx))
+            .collect::<Result<Vec<_>>>()?;
+        match radii.as_slice() {
+            [radius] => Ok(Self { horizontal: *radius, vertical: *radius }),
+            [horizontal, vertical] => Ok(Self { horizontal: *horizontal, vertical: *vertical }),
+            _ => bail!("Expected 1 or 2 radii"),
+        }
+    }
+
+    /// Whether the corner is square.
+    pub fn is_zero(&self) -> bool {
+        self.horizontal == ComputedLength::default() || self.vertical == ComputedLength::default()
+    }
+}
+
+/// The radii of the corners of a border box.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BorderRadius {
+    pub top_left: CornerRadius,
+    pub top_right: CornerRadius,
+    pub bottom_right: CornerRadius,
+    pub bottom_left: CornerRadius,
+}
+
+impl BorderRadius {
+    /// Parses the `border-radius` shorthand, i.e. 1 to 4 horizontal radii
+    /// (clockwise from the top left corner, like the sides of other
+    /// shorthands), optionally followed by a slash and the vertical ones.
+    pub fn parse(value: &str, ctx: &ResolveContext) -> Result<Self> {
+        let parse_corners = |value: &str| -> Result<Sides<ComputedLength>> {
+            Sides::from_components(split_components(value).into_iter()
+                .map(|c| parse_radius(c, ctx))
+                .collect::<Result<Vec<_>>>()?)
+        };
+        let (horizontal, vertical) = match value.split_once('/') {
+            Some((horizontal, vertical)) => (parse_corners(horizontal)?, parse_corners(vertical)?),
+            None => {
+                let radii = parse_corners(value)?;
+                (radii, radii)
+            },
+        };
+        Ok(Self {
+            top_left: CornerRadius { horizontal: horizontal.top, vertical: vertical.top },
+            top_right: CornerRadius { horizontal: horizontal.right, vertical: vertical.right },
+            bottom_right: CornerRadius { horizontal: horizontal.bottom, vertical: vertical.bottom },
+            bottom_left: CornerRadius { horizontal: horizontal.left, vertical: vertical.left },
+        })
+    }
+
+    /// The radius of the corner with the given property name, e.g. `top-left`.
+    pub fn get_mut(&mut self, corner: &str) -> Option<&mut CornerRadius> {
+        match corner {
+            "top-left" => Some(&mut self.top_left),
+            "top-right" => Some(&mut self.top_right),
+            "bottom-right" => Some(&mut self.bottom_right),
+            "bottom-left" => Some(&mut self.bottom_left),
+            _ => None,
+        }
+    }
+
+    /// Whether all corners are square.
+    pub fn is_zero(&self) -> bool {
+        [self.top_left, self.top_right, self.bottom_right, self.bottom_left].iter().all(CornerRadius::is_zero)
+    }
+}
+
+/// Parses a single (non-negative) radius of a corner.
+fn parse_radius(value: &str, ctx: &ResolveContext) -> Result<ComputedLength> {
+    let radius = Length::parse(value)?.compute(ctx);
+    if radius.px < 0.0 || radius.percent < 0.0 {
+        bail!("Radii may not be negative");
+    }
+    Ok(radius)
+}
+
+/// A shadow cast by a box (or into it, if inset).
+/// See https://www.w3.org/TR/css-backgrounds-3/#box-shadow
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxShadow {
+    /// Whether the shadow is cast inside the padding box instead of outside the border box.
+    pub inset: bool,
+    pub offset_x: f64,
+    pub offset_y: f64,
+    /// The blur radius, i.e. twice the standard deviation of the blur.
+    pub blur: f64,
+    /// The distance the shadow is expanded by (or shrunk, if negative).
+    pub spread: f64,
+    pub color: ColorValue,
+}
+
+impl BoxShadow {
+    /// Parses a list of shadows or `none`. The first shadow is the topmost
+    /// one.
+    pub fn parse_list(value: &str, ctx: &ResolveContext) -> Result<Vec<Self>> {
+        if value.eq_ignore_ascii_case("none") {
+            return Ok(Vec::new());
+        }
+        split_commas(value).into_iter()
+            .map(|v| Self::parse(v, ctx))
+            .collect()
+    }
+
+    /// Parses a single shadow, i.e. `inset`, 2 to 4 lengths and a color in any order
+    /// (except for the lengths, which have to be consecutive).
+    fn parse(value: &str, ctx: &ResolveContext) -> Result<Self> {
+        let mut inset = false;
+        let mut shadow_color = None;
+        let mut lengths = Vec::new();
+        let mut lengths_done = false;
+        for component in split_components(value) {
+            if component.eq_ignore_ascii_case("inset") && !inset {
+                inset = true;
+                lengths_done |= !lengths.is_empty();
+            } else if let (false, Ok(length)) = (lengths_done, Length::parse(component)) {
+                let length = length.compute(ctx);
+                if length.percent != 0.0 {
+                    bail!("Shadow lengths may not be percentages");
+                }
+                lengths.push(length.px);
+            } else if let (None, Ok(c)) = (shadow_color, ColorValue::parse(component)) {
+                shadow_color = Some(c);
+                lengths_done |= !lengths.is_empty();
+            } else {
+                bail!("Invalid box-shadow component {}", component);
+            }
+        }
+        let (offset_x, offset_y, blur, spread) = match lengths.as_slice() {
+            [x, y] => (*x, *y, 0.0, 0.0),
+            [x, y, blur] => (*x, *y, *blur, 0.0),
+            [x, y, blur, spread] => (*x, *y, *blur, *spread),
+            _ => bail!("Expected 2 to 4 shadow lengths"),
+        };
+        if blur < 0.0 {
+            bail!("Blur radii may not be negative");
+        }
+        Ok(Self {
+            inset,
+            offset_x,
+            offset_y,
+            blur,
+            spread,
+            color: shadow_color.unwrap_or(ColorValue::CurrentColor),
+        })
+    }
+}
+
+/// Whether a box is painted.
+/// See https://www.w3.org/TR/css-display-3/#visibility
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Visibility {
+    #[default]
+    Visible,
+    /// The box is invisible, but still affects the layout. Its descendants
+    /// are painted if they are visible themselves.
+    Hidden,
+    /// Like `hidden`, except for table rows and columns, which are removed.
+    // TODO: Remove collapsed table rows and columns
+    Collapse,
+}
+
+impl Visibility {
+    /// Parses the value of `visibility`.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "visible" => Self::Visible,
+            "hidden" => Self::Hidden,
+            "collapse" => Self::Collapse,
+            _ => bail!("Unknown visibility"),
+        })
+    }
+
+    /// Whether the box is painted.
+    pub fn is_visible(self) -> bool {
+        self == Self::Visible
+    }
+}
+
+/// Parses the value of `opacity`, i.e. a number or a percentage, which is
+/// clamped to the range from 0 to 1.
+pub fn parse_opacity(value: &str) -> Result<f64> {
+    let opacity: f64 = match value.strip_suffix('%') {
+        Some(percentage) => percentage.trim().parse::<f64>()? / 100.0,
+        None => value.parse()?,
+    };
+    if !opacity.is_finite() {
+        bail!("Invalid opacity");
+    }
+    Ok(opacity.clamp(0.0, 1.0))
+}
@@ -0,0 +1,164 @@
+use trails_base::{Result, bail};
+use trails_model::css::ResolveContext;
+use trails_model::parse::css::split_components;
+
+use super::LengthOrAuto;
+
+/// The direction of the main axis of a flex container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlexDirection {
+    #[default]
+    Row,
+    RowReverse,
+    Column,
+    ColumnReverse,
+}
+
+impl FlexDirection {
+    /// Parses a `flex-direction` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "row" => Self::Row,
+            "row-reverse" => Self::RowReverse,
+            "column" => Self::Column,
+            "column-reverse" => Self::ColumnReverse,
+            _ => bail!("Unknown flex direction {}", value),
+        })
+    }
+
+    /// Whether the main axis is horizontal.
+    pub fn is_row(self) -> bool {
+        matches!(self, Self::Row | Self::RowReverse)
+    }
+
+    /// Whether items are placed from the end of the main axis.
+    pub fn is_reverse(self) -> bool {
+        matches!(self, Self::RowReverse | Self::ColumnReverse)
+    }
+}
+
+/// Whether flex items are wrapped onto multiple lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlexWrap {
+    #[default]
+    NoWrap,
+    Wrap,
+    /// Wraps with lines stacked from the end of the cross axis.
+    WrapReverse,
+}
+
+impl FlexWrap {
+    /// Parses a `flex-wrap` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "nowrap" => Self::NoWrap,
+            "wrap" => Self::Wrap,
+            "wrap-reverse" => Self::WrapReverse,
+            _ => bail!("Unknown flex wrap {}", value),
+        })
+    }
+}
+
+/// How free space along the main axis is distributed between flex items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JustifyContent {
+    #[default]
+    FlexStart,
+    FlexEnd,
+    Center,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+}
+
+impl JustifyContent {
+    /// Parses a `justify-content` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            // TODO: Distinguish start/end from flex-start/flex-end in reversed containers
+            "normal" | "flex-start" | "start" | "left" => Self::FlexStart,
+            "flex-end" | "end" | "right" => Self::FlexEnd,
+            "center" => Self::Center,
+            "space-between" => Self::SpaceBetween,
+            "space-around" => Self::SpaceAround,
+            "space-evenly" => Self::SpaceEvenly,
+            _ => bail!("Unsupported justify-content value {}", value),
+        })
+    }
+}
+
+/// How flex items are aligned along the cross axis of their line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlignItems {
+    #[default]
+    Stretch,
+    FlexStart,
+    FlexEnd,
+    Center,
+    Baseline,
+}
+
+impl AlignItems {
+    /// Parses an `align-items` (or `align-self`, except for `auto`) keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "normal" | "stretch" => Self::Stretch,
+            "flex-start" | "start" | "self-start" => Self::FlexStart,
+            "flex-end" | "end" | "self-end" => Self::FlexEnd,
+            "center" => Self::Center,
+            "baseline" | "first baseline" => Self::Baseline,
+            _ => bail!("Unsupported alignment {}", value),
+        })
+    }
+}
+
+/// Parses an `align-self` value, `None` if `auto` (i.e. the parent's `align-items`).
+pub fn parse_align_self(value: &str) -> Result<Option<AlignItems>> {
+    if value.eq_ignore_ascii_case("auto") {
+        Ok(None)
+    } else {
+        AlignItems::parse(value).map(Some)
+    }
+}
+
+/// Parses a `flex-basis` value, where `content` behaves like `auto`.
+pub fn parse_flex_basis(value: &str, ctx: &ResolveContext) -> Result<LengthOrAuto> {
+    if value.eq_ignore_ascii_case("content") {
+        Ok(LengthOrAuto::Auto)
+    } else {
+        LengthOrAuto::parse(value, ctx)
+    }
+}
+
+/// Parses a non-negative flex factor, e.g. for `flex-grow`.
+pub fn parse_flex_factor(value: &str) -> Result<f64> {
+    let factor: f64 = value.parse()?;
+    if factor.is_nan() || factor < 0.0 {
+        bail!("Flex factor may not be negative");
+    }
+    Ok(factor)
+}
+
+/// Parses the `flex` shorthand into the grow and shrink factors and the basis.
+/// See https://www.w3.org/TR/css-flexbox-1/#flex-property
+pub fn parse_flex(value: &str, ctx: &ResolveContext) -> Result<(f64, f64, LengthOrAuto)> {
+    match value.to_ascii_lowercase().as_str() {
+        "none" => return Ok((0.0, 0.0, LengthOrAuto::Auto)),
+        "auto" => return Ok((1.0, 1.0, LengthOrAuto::Auto)),
+        _ => {},
+    }
+    let mut factors = Vec::new();
+    let mut basis = None;
+    for component in split_components(value) {
+        match parse_flex_factor(component) {
+            Ok(factor) if factors.len() < 2 && (basis.is_none() || factors.is_empty()) => factors.push(factor),
+            _ if basis.is_none() => basis = Some(parse_flex_basis(component, ctx)?),
+            _ => bail!("Invalid flex component {}", component),
+        }
+    }
+    let grow = factors.first().copied().unwrap_or(1.0);
+    let shrink = factors.get(1).copied().unwrap_or(1.0);
+    // An omitted basis is zero if factors are given
+    let basis = basis.unwrap_or_default();
+    Ok((grow, shrink, basis))
+}
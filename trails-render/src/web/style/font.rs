@@ -0,0 +1,55 @@
+use trails_base::{Result, bail};
+use trails_model::css::parse_family_name;
+use trails_model::parse::css::{parse_string, split_commas};
+
+/// A generic font family, which is mapped to some installed font.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenericFamily {
+    Serif,
+    SansSerif,
+    Monospace,
+    Cursive,
+    Fantasy,
+    /// The default font of the user interface.
+    SystemUi,
+}
+
+impl GenericFamily {
+    /// Parses a generic family keyword, including the common aliases for
+    /// the system font.
+    fn parse(value: &str) -> Option<Self> {
+        Some(match value.to_ascii_lowercase().as_str() {
+            "serif" | "ui-serif" => Self::Serif,
+            "sans-serif" | "ui-sans-serif" => Self::SansSerif,
+            "monospace" | "ui-monospace" => Self::Monospace,
+            "cursive" => Self::Cursive,
+            "fantasy" => Self::Fantasy,
+            "system-ui" | "-apple-system" | "blinkmacsystemfont" => Self::SystemUi,
+            _ => return None,
+        })
+    }
+}
+
+/// An entry in a `font-family` list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FamilyName {
+    /// A specific family, e.g. `Helvetica`, which may be installed or a web font.
+    Named(String),
+    Generic(GenericFamily),
+}
+
+/// Parses a `font-family` value, i.e. a comma-separated list of families
+/// in order of preference.
+pub fn parse_font_family(value: &str) -> Result<Vec<FamilyName>> {
+    let families: Vec<_> = split_commas(value).into_iter()
+        .map(|raw| match (parse_string(raw), GenericFamily::parse(raw)) {
+            // Quoted names are never generic
+            (None, Some(generic)) => FamilyName::Generic(generic),
+            _ => FamilyName::Named(parse_family_name(raw)),
+        })
+        .collect();
+    if families.is_empty() {
+        bail!("Expected at least one font family");
+    }
+    Ok(families)
+}
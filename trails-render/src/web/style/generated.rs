@@ -0,0 +1,205 @@
+use trails_base::{Result, bail};
+use trails_model::parse::css::{parse_string, split_commas, split_components};
+
+/// The style of list markers and counters.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ListStyleType {
+    None,
+    #[default]
+    Disc,
+    Circle,
+    Square,
+    Decimal,
+    DecimalLeadingZero,
+    LowerAlpha,
+    UpperAlpha,
+    LowerRoman,
+    UpperRoman,
+    /// A fixed string, e.g. `"-"`.
+    String(String),
+}
+
+impl ListStyleType {
+    /// Parses a counter style name or string.
+    pub fn parse(value: &str) -> Result<Self> {
+        if let Some(string) = parse_string(value) {
+            return Ok(Self::String(string));
+        }
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "none" => Self::None,
+            "disc" => Self::Disc,
+            "circle" => Self::Circle,
+            "square" => Self::Square,
+            "decimal" => Self::Decimal,
+            "decimal-leading-zero" => Self::DecimalLeadingZero,
+            "lower-alpha" | "lower-latin" => Self::LowerAlpha,
+            "upper-alpha" | "upper-latin" => Self::UpperAlpha,
+            "lower-roman" => Self::LowerRoman,
+            "upper-roman" => Self::UpperRoman,
+            _ => bail!("Unknown list style type {}", value),
+        })
+    }
+
+    /// Formats a counter value in this style, e.g. `iv` for 4 in `lower-roman`.
+    /// Values outside the range of a style fall back to decimal.
+    pub fn format(&self, value: i32) -> String {
+        // See https://www.w3.org/TR/css-counter-styles-3/#predefined-counters
+        match self {
+            Self::None => String::new(),
+            Self::Disc => "•".to_owned(),
+            Self::Circle => "◦".to_owned(),
+            Self::Square => "▪".to_owned(),
+            Self::String(string) => string.clone(),
+            Self::Decimal => value.to_string(),
+            Self::DecimalLeadingZero if (0..10).contains(&value) => format!("0{}", value),
+            Self::LowerAlpha if value > 0 => format_alphabetic(value as u32),
+            Self::UpperAlpha if value > 0 => format_alphabetic(value as u32).to_ascii_uppercase(),
+            Self::LowerRoman if (1..4000).contains(&value) => format_roman(value as u32),
+            Self::UpperRoman if (1..4000).contains(&value) => format_roman(value as u32).to_ascii_uppercase(),
+            _ => value.to_string(),
+        }
+    }
+
+    /// The text of a list marker for the given value of the `list-item`
+    /// counter, e.g. `1. ` or `• `. `None` if there is no marker.
+    pub fn marker(&self, value: i32) -> Option<String> {
+        match self {
+            Self::None => None,
+            Self::Disc | Self::Circle | Self::Square => Some(format!("{} ", self.format(value))),
+            Self::String(string) => Some(string.clone()),
+            _ => Some(format!("{}. ", self.format(value))),
+        }
+    }
+}
+
+/// Where list markers are placed relative to the list item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListStylePosition {
+    /// Outside of the principal box, to the left of the first line.
+    #[default]
+    Outside,
+    /// As the first inline box in the list item.
+    Inside,
+}
+
+impl ListStylePosition {
+    /// Parses a `list-style-position` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "outside" => Self::Outside,
+            "inside" => Self::Inside,
+            _ => bail!("Unknown list style position {}", value),
+        })
+    }
+}
+
+/// An item in the value of the `content` property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentItem {
+    /// A literal string.
+    String(String),
+    /// The value of the innermost counter with the given name, e.g. `counter(item)`.
+    Counter { name: String, style: ListStyleType },
+    /// The values of all counters with the given name, e.g. `counters(item, ".")`.
+    Counters { name: String, separator: String, style: ListStyleType },
+    /// The value of an attribute of the element, e.g. `attr(title)`.
+    Attr(String),
+}
+
+/// The value of the `content` property for pseudo-elements.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Content {
+    /// No content, i.e. the pseudo-element isn't generated.
+    #[default]
+    Normal,
+    None,
+    Items(Vec<ContentItem>),
+}
+
+impl Content {
+    /// Parses a value of the `content` property.
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "normal" => return Ok(Self::Normal),
+            "none" => return Ok(Self::None),
+            _ => {},
+        }
+        let items = split_components(value).into_iter()
+            .map(ContentItem::parse)
+            .collect::<Result<_>>()?;
+        Ok(Self::Items(items))
+    }
+}
+
+impl ContentItem {
+    /// Parses a single component of a `content` value.
+    fn parse(component: &str) -> Result<Self> {
+        if let Some(string) = parse_string(component) {
+            return Ok(Self::String(string));
+        }
+        let Some((function, args)) = component.strip_suffix(')').and_then(|c| c.split_once('(')) else {
+            bail!("Unsupported content {}", component);
+        };
+        let args = split_commas(args);
+        let style = |i: usize| args.get(i).map_or(Ok(ListStyleType::Decimal), |s| ListStyleType::parse(s));
+        Ok(match (function.to_ascii_lowercase().as_str(), args.as_slice()) {
+            ("counter", [name, ..]) if args.len() <= 2 => Self::Counter { name: name.to_string(), style: style(1)? },
+            ("counters", [name, separator, ..]) if args.len() <= 3 => Self::Counters {
+                name: name.to_string(),
+                separator: parse_string(separator).ok_or_else(|| trails_base::Error::msg("Expected a string separator"))?,
+                style: style(2)?,
+            },
+            ("attr", [name]) => Self::Attr(name.to_ascii_lowercase()),
+            _ => bail!("Unsupported content function {}", function),
+        })
+    }
+}
+
+/// Parses a list of counter names followed by optional integers, e.g. for
+/// `counter-reset: section 2 item`, using the given default for missing values.
+pub fn parse_counter_list(value: &str, default: i32) -> Result<Vec<(String, i32)>> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(Vec::new());
+    }
+    let mut counters: Vec<(String, i32)> = Vec::new();
+    for component in split_components(value) {
+        match (component.parse::<i32>(), counters.last_mut()) {
+            (Ok(n), Some((_, value))) => *value = n,
+            (Ok(_), None) => bail!("Expected a counter name before {}", component),
+            (Err(_), _) if component.starts_with(|c: char| c.is_alphabetic() || c == '-' || c == '_') => {
+                counters.push((component.to_owned(), default));
+            },
+            (Err(_), _) => bail!("Invalid counter name {}", component),
+        }
+    }
+    Ok(counters)
+}
+
+/// Formats a positive number in bijective base 26 with letters, i.e. `a`
+/// to `z`, then `aa` and so on.
+fn format_alphabetic(mut value: u32) -> String {
+    let mut letters = Vec::new();
+    while value > 0 {
+        value -= 1;
+        letters.push((b'a' + (value % 26) as u8) as char);
+        value /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+/// Formats a number between 1 and 3999 as a (lowercase) roman numeral.
+fn format_roman(mut value: u32) -> String {
+    const NUMERALS: [(u32, &str); 13] = [
+        (1000, "m"), (900, "cm"), (500, "d"), (400, "cd"),
+        (100, "c"), (90, "xc"), (50, "l"), (40, "xl"),
+        (10, "x"), (9, "ix"), (5, "v"), (4, "iv"), (1, "i"),
+    ];
+    let mut roman = String::new();
+    for (n, numeral) in NUMERALS {
+        while value >= n {
+            roman.push_str(numeral);
+            value -= n;
+        }
+    }
+    roman
+}
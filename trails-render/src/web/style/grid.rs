@@ -0,0 +1,360 @@
+use std::ops::Range;
+
+use trails_base::{Result, bail};
+use trails_model::css::{ComputedLength, Length, ResolveContext};
+use trails_model::parse::css::{parse_string, split_commas, split_components};
+
+use super::parse_integer;
+
+/// The minimum or maximum sizing function of a grid track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackBreadth {
+    Length(ComputedLength),
+    /// A fraction of the free space, e.g. `1fr`.
+    Flex(f64),
+    MinContent,
+    MaxContent,
+    Auto,
+}
+
+impl TrackBreadth {
+    /// Parses a track breadth, e.g. `100px`, `1fr` or `min-content`.
+    fn parse(value: &str, ctx: &ResolveContext) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "min-content" => Self::MinContent,
+            "max-content" => Self::MaxContent,
+            "auto" => Self::Auto,
+            lower => match lower.strip_suffix("fr") {
+                Some(flex) => {
+                    let flex: f64 = flex.parse()?;
+                    if flex.is_nan() || flex < 0.0 {
+                        bail!("Flex factor may not be negative");
+                    }
+                    Self::Flex(flex)
+                },
+                None => {
+                    let length = Length::parse(value)?.compute(ctx);
+                    if length.px < 0.0 || length.percent < 0.0 {
+                        bail!("Track size may not be negative");
+                    }
+                    Self::Length(length)
+                },
+            },
+        })
+    }
+
+    /// Whether the size depends on the contents of the track.
+    pub fn is_intrinsic(&self) -> bool {
+        matches!(self, Self::MinContent | Self::MaxContent | Self::Auto)
+    }
+}
+
+/// The size of a grid track, i.e. a range between a minimum and a maximum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackSize {
+    pub min: TrackBreadth,
+    pub max: TrackBreadth,
+}
+
+impl Default for TrackSize {
+    fn default() -> Self {
+        Self { min: TrackBreadth::Auto, max: TrackBreadth::Auto }
+    }
+}
+
+impl TrackSize {
+    /// Parses a track size, e.g. `1fr` or `minmax(100px, auto)`.
+    pub fn parse(value: &str, ctx: &ResolveContext) -> Result<Self> {
+        if let Some(args) = strip_function(value, "minmax") {
+            let [min, max] = split_commas(args)[..] else {
+                bail!("Expected two arguments to minmax()");
+            };
+            let min = TrackBreadth::parse(min, ctx)?;
+            if let TrackBreadth::Flex(_) = min {
+                bail!("The minimum of a track may not be flexible");
+            }
+            Ok(Self { min, max: TrackBreadth::parse(max, ctx)? })
+        } else if strip_function(value, "fit-content").is_some() {
+            // TODO: Clamp by the argument
+            Ok(Self { min: TrackBreadth::Auto, max: TrackBreadth::MaxContent })
+        } else {
+            let breadth = TrackBreadth::parse(value, ctx)?;
+            // Flexible tracks have an automatic minimum
+            let min = if let TrackBreadth::Flex(_) = breadth { TrackBreadth::Auto } else { breadth };
+            Ok(Self { min, max: breadth })
+        }
+    }
+
+    /// Parses a list of track sizes, e.g. for `grid-auto-rows`.
+    pub fn parse_list(value: &str, ctx: &ResolveContext) -> Result<Vec<Self>> {
+        split_components(value).into_iter()
+            .map(|c| Self::parse(c, ctx))
+            .collect()
+    }
+}
+
+/// A `repeat(auto-fill, ...)` or `repeat(auto-fit, ...)` in a track list,
+/// which is repeated as often as the grid container fits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoRepeat {
+    /// The index of the track before which the repetition is inserted.
+    pub index: usize,
+    /// Whether empty repeated tracks collapse (`auto-fit`).
+    pub fit: bool,
+    pub tracks: Vec<TrackSize>,
+    /// The names of the lines before each repeated track (and after the last).
+    pub line_names: Vec<Vec<String>>,
+}
+
+/// The explicit tracks of a grid, e.g. from `grid-template-columns`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TrackList {
+    /// The track sizes, with repetitions of fixed counts expanded.
+    pub tracks: Vec<TrackSize>,
+    /// The names of the lines before each track (and after the last). With
+    /// an automatic repetition, there is an additional entry for the names
+    /// after it.
+    pub line_names: Vec<Vec<String>>,
+    pub auto_repeat: Option<AutoRepeat>,
+}
+
+impl TrackList {
+    /// Parses a track list, e.g. `[full-start] repeat(3, 1fr) [full-end]` or `none`.
+    pub fn parse(value: &str, ctx: &ResolveContext) -> Result<Self> {
+        let mut list = Self::default();
+        if value.eq_ignore_ascii_case("none") {
+            return Ok(list);
+        }
+        list.line_names.push(Vec::new());
+        for component in split_components(value) {
+            if let Some(names) = parse_line_names(component) {
+                list.line_names.last_mut().unwrap().extend(names);
+            } else if let Some(args) = strip_function(component, "repeat") {
+                let Some((count, tracks)) = args.split_once(',') else {
+                    bail!("Expected a count and tracks in repeat()");
+                };
+                let repeated = Self::parse(tracks.trim(), ctx)?;
+                if repeated.auto_repeat.is_some() || repeated.tracks.is_empty() {
+                    bail!("Invalid tracks in repeat()");
+                }
+                match count.trim().to_ascii_lowercase().as_str() {
+                    fill @ ("auto-fill" | "auto-fit") => {
+                        if list.auto_repeat.is_some() {
+                            bail!("Only one automatic repetition is allowed");
+                        }
+                        list.auto_repeat = Some(AutoRepeat {
+                            index: list.tracks.len(),
+                            fit: fill == "auto-fit",
+                            tracks: repeated.tracks,
+                            line_names: repeated.line_names,
+                        });
+                        // The names after the repetition are kept separately
+                        list.line_names.push(Vec::new());
+                    },
+                    count => {
+                        let count = parse_integer(count).filter(|&c| c > 0 && c <= 10000)
+                            .ok_or_else(|| trails_base::Error::msg("Invalid repetition count"))?;
+                        for _ in 0..count {
+                            list.line_names.last_mut().unwrap().extend(repeated.line_names[0].iter().cloned());
+                            list.tracks.extend(repeated.tracks.iter().copied());
+                            list.line_names.extend(repeated.line_names[1..].iter().cloned());
+                        }
+                    },
+                }
+            } else {
+                list.tracks.push(TrackSize::parse(component, ctx)?);
+                list.line_names.push(Vec::new());
+            }
+        }
+        Ok(list)
+    }
+
+    /// Expands the automatic repetition (if any) the given number of times,
+    /// returning the track sizes and line names of the explicit grid along
+    /// with the range of tracks repeated by `auto-fit` (which may collapse).
+    pub fn expand(&self, repetitions: usize) -> (Vec<TrackSize>, Vec<Vec<String>>, Range<usize>) {
+        let Some(repeat) = &self.auto_repeat else {
+            return (self.tracks.clone(), self.line_names.clone(), 0..0);
+        };
+        // The line names before the repetition, within it and after it
+        // are merged where lines coincide
+        let index = repeat.index;
+        let mut tracks = self.tracks[..index].to_vec();
+        let mut names = self.line_names[..index].to_vec();
+        let mut line = self.line_names[index].clone();
+        for _ in 0..repetitions {
+            for (track, track_names) in repeat.tracks.iter().zip(&repeat.line_names) {
+                line.extend(track_names.iter().cloned());
+                names.push(std::mem::take(&mut line));
+                tracks.push(*track);
+            }
+            line.extend(repeat.line_names.last().into_iter().flatten().cloned());
+        }
+        line.extend(self.line_names[index + 1].iter().cloned());
+        names.push(line);
+        let repeated = index..tracks.len();
+        tracks.extend(self.tracks[index..].iter().copied());
+        names.extend(self.line_names[index + 2..].iter().cloned());
+        (tracks, names, if repeat.fit { repeated } else { 0..0 })
+    }
+}
+
+/// The named areas of a grid from `grid-template-areas`, as rows of cells
+/// with optional area names.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GridAreas {
+    pub rows: Vec<Vec<Option<String>>>,
+}
+
+impl GridAreas {
+    /// Parses `grid-template-areas`, e.g. `"head head" "nav main"` or `none`.
+    pub fn parse(value: &str) -> Result<Self> {
+        if value.eq_ignore_ascii_case("none") {
+            return Ok(Self::default());
+        }
+        let rows = split_components(value).into_iter()
+            .map(|row| {
+                let row = parse_string(row).ok_or_else(|| trails_base::Error::msg("Expected a string of area names"))?;
+                Ok(row.split_whitespace()
+                    .map(|cell| if cell.chars().all(|c| c == '.') { None } else { Some(cell.to_owned()) })
+                    .collect::<Vec<_>>())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if rows.iter().any(|r| r.len() != rows[0].len() || r.is_empty()) {
+            bail!("All rows of grid areas must have the same number of cells");
+        }
+        Ok(Self { rows })
+    }
+
+    /// The number of columns.
+    pub fn columns(&self) -> usize {
+        self.rows.first().map_or(0, |r| r.len())
+    }
+
+    /// The (0-based) lines bounding the area with the given name as
+    /// `(row_start, row_end, column_start, column_end)`, if it exists.
+    pub fn area(&self, name: &str) -> Option<(usize, usize, usize, usize)> {
+        let cells = || self.rows.iter().enumerate()
+            .flat_map(|(r, row)| row.iter().enumerate().map(move |(c, cell)| (r, c, cell)))
+            .filter(|(_, _, cell)| cell.as_deref() == Some(name));
+        let row_start = cells().map(|(r, _, _)| r).min()?;
+        let row_end = cells().map(|(r, _, _)| r + 1).max()?;
+        let column_start = cells().map(|(_, c, _)| c).min()?;
+        let column_end = cells().map(|(_, c, _)| c + 1).max()?;
+        Some((row_start, row_end, column_start, column_end))
+    }
+}
+
+/// The start or end line of a grid item in one axis.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum GridLine {
+    #[default]
+    Auto,
+    /// The name of an area or line, e.g. `main`.
+    Name(String),
+    /// The nth line (counting from the end if negative), optionally only
+    /// considering lines with the given name.
+    Line(i32, Option<String>),
+    /// Spans the given number of tracks from the line at the other end.
+    Span(u32),
+}
+
+impl GridLine {
+    /// Parses a grid line, e.g. `2`, `-1`, `span 2` or `main`.
+    pub fn parse(value: &str) -> Result<Self> {
+        if value.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        let mut span = false;
+        let mut number = None;
+        let mut name = None;
+        for component in split_components(value) {
+            if component.eq_ignore_ascii_case("span") && !span {
+                span = true;
+            } else if let (None, Ok(n)) = (number, component.parse::<i32>()) {
+                number = Some(n);
+            } else if name.is_none() && is_identifier(component) {
+                name = Some(component.to_owned());
+            } else {
+                bail!("Invalid grid line component {}", component);
+            }
+        }
+        Ok(match (span, number, name) {
+            // TODO: Support spanning to named lines
+            (true, Some(n), None) if n > 0 => Self::Span(n as u32),
+            (true, None, None) => Self::Span(1),
+            (false, Some(n), name) if n != 0 => Self::Line(n, name),
+            (false, None, Some(name)) => Self::Name(name),
+            _ => bail!("Invalid grid line {}", value),
+        })
+    }
+
+    /// Parses the lines of a shorthand such as `grid-row`, e.g. `1 / span 2`.
+    /// Omitted lines are copied from the corresponding start line if it is
+    /// a name and `auto` otherwise.
+    /// See https://www.w3.org/TR/css-grid-1/#propdef-grid-area
+    pub fn parse_shorthand<const N: usize>(value: &str) -> Result<[Self; N]> {
+        let mut lines = value.split('/')
+            .map(|line| Self::parse(line.trim()))
+            .collect::<Result<Vec<_>>>()?;
+        if lines.len() > N {
+            bail!("Too many grid lines");
+        }
+        while lines.len() < N {
+            let i = lines.len();
+            let source = i.saturating_sub(N / 2);
+            let copied = match &lines[source] {
+                Self::Name(name) => Self::Name(name.clone()),
+                _ => Self::Auto,
+            };
+            lines.push(copied);
+        }
+        lines.try_into().map_err(|_| trails_base::Error::msg("Invalid number of grid lines"))
+    }
+}
+
+/// How grid items without a definite position are placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GridAutoFlow {
+    /// Whether items fill columns (rather than rows) first.
+    pub column: bool,
+    /// Whether items may fill holes earlier in the grid.
+    pub dense: bool,
+}
+
+impl GridAutoFlow {
+    /// Parses a `grid-auto-flow` value, e.g. `row dense`.
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut flow = Self::default();
+        for component in split_components(value) {
+            match component.to_ascii_lowercase().as_str() {
+                "row" => flow.column = false,
+                "column" => flow.column = true,
+                "dense" => flow.dense = true,
+                _ => bail!("Invalid grid-auto-flow component {}", component),
+            }
+        }
+        Ok(flow)
+    }
+}
+
+/// Parses a bracketed list of line names, e.g. `[main-start sidebar]`.
+fn parse_line_names(component: &str) -> Option<Vec<String>> {
+    let inner = component.strip_prefix('[')?.strip_suffix(']')?;
+    Some(inner.split_whitespace().map(|name| name.to_owned()).collect())
+}
+
+/// Strips a (case-insensitive) function name and the parentheses around its arguments.
+fn strip_function<'a>(value: &'a str, name: &str) -> Option<&'a str> {
+    let (function, args) = value.split_once('(')?;
+    if !function.eq_ignore_ascii_case(name) {
+        return None;
+    }
+    args.strip_suffix(')')
+}
+
+/// Whether the given value is a (plain) CSS identifier.
+fn is_identifier(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_alphabetic() || c == '-' || c == '_')
+        && value.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
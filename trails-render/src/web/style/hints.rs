@@ -0,0 +1,128 @@
+use trails_model::css::Declaration;
+use trails_model::dom::Element;
+
+/// The attributes that presentational hints are derived from. Since they
+/// may affect descendants (e.g. `cellpadding`), changing them restyles
+/// the whole subtree.
+pub const PRESENTATIONAL_ATTRIBUTES: [&str; 9] = ["bgcolor", "background", "width", "height", "border", "cellspacing", "cellpadding", "valign", "align"];
+
+/// Derives declarations from presentational HTML attributes of an element
+/// with the given ancestors (from the root to the parent), e.g. `border`
+/// and `cellpadding` on tables. These apply as author declarations with
+/// zero specificity, preceding all other author rules.
+/// See https://html.spec.whatwg.org/multipage/rendering.html#presentational-hints
+pub fn presentational_hints(element: &Element, ancestors: &[&Element]) -> Vec<Declaration> {
+    let mut hints = Vec::new();
+    let mut hint = |name: &str, value: String| hints.push(Declaration { name: name.to_owned(), value, important: false });
+    let tag_name = element.tag_name();
+
+    if matches!(tag_name, "body" | "table" | "thead" | "tbody" | "tfoot" | "tr" | "td" | "th") {
+        if let Some(color) = element.attribute("bgcolor") {
+            hint("background-color", legacy_color(color));
+        }
+        if let Some(url) = element.attribute("background").map(str::trim).filter(|url| !url.is_empty()) {
+            hint("background-image", format!("url(\"{}\")", url.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+    }
+    if matches!(tag_name, "img" | "svg" | "table" | "td" | "th") {
+        if let Some(width) = element.attribute("width").and_then(parse_dimension) {
+            hint("width", width);
+        }
+    }
+    if matches!(tag_name, "img" | "svg" | "table" | "tr" | "td" | "th") {
+        if let Some(height) = element.attribute("height").and_then(parse_dimension) {
+            hint("height", height);
+        }
+    }
+    if matches!(tag_name, "img" | "table") {
+        // Aligning images and tables to the left or right floats them
+        if let Some(align) = element.attribute("align") {
+            match align.trim().to_ascii_lowercase().as_str() {
+                "left" => hint("float", "left".to_owned()),
+                "right" => hint("float", "right".to_owned()),
+                _ => {},
+            }
+        }
+    }
+    if matches!(tag_name, "div" | "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "caption" | "thead" | "tbody" | "tfoot" | "tr" | "td" | "th") {
+        // Aligning blocks aligns their text
+        if let Some(align) = element.attribute("align") {
+            match align.trim().to_ascii_lowercase().as_str() {
+                align @ ("left" | "right" | "center" | "justify") => hint("text-align", align.to_owned()),
+                "middle" => hint("text-align", "center".to_owned()),
+                _ => {},
+            }
+        }
+    }
+    if matches!(tag_name, "thead" | "tbody" | "tfoot" | "tr" | "td" | "th") {
+        if let Some(valign) = element.attribute("valign") {
+            hint("vertical-align", valign.trim().to_owned());
+        }
+    }
+
+    match tag_name {
+        "table" => {
+            if let Some(border) = table_border(element) {
+                hint("border", format!("{}px outset gray", border));
+            }
+            if let Some(spacing) = element.attribute("cellspacing").and_then(parse_integer) {
+                hint("border-spacing", format!("{}px", spacing));
+            }
+        },
+        "td" | "th" => {
+            // Cells inherit some presentational attributes from their table
+            if let Some(table) = ancestors.iter().rev().find(|a| a.tag_name() == "table") {
+                if let Some(padding) = table.attribute("cellpadding").and_then(parse_integer) {
+                    hint("padding", format!("{}px", padding));
+                }
+                if table_border(table).is_some_and(|b| b > 0) {
+                    hint("border", "1px inset gray".to_owned());
+                }
+            }
+        },
+        _ => {},
+    }
+
+    hints
+}
+
+/// The width of the border of a table from its `border` attribute, where
+/// an empty (or invalid) value means 1.
+fn table_border(table: &Element) -> Option<u32> {
+    table.attribute("border").map(|b| parse_integer(b).unwrap_or(1))
+}
+
+/// Parses a non-negative integer as in HTML attributes, i.e. ignoring
+/// leading whitespace and trailing garbage, e.g. `3` in ` 3px`.
+/// See https://html.spec.whatwg.org/multipage/common-microsyntaxes.html#rules-for-parsing-non-negative-integers
+pub fn parse_integer(value: &str) -> Option<u32> {
+    let value = value.trim_start();
+    let end = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+/// Converts a color attribute (e.g. `bgcolor`) to a CSS color. Unlike CSS,
+/// HTML accepts hex colors without a leading `#`, e.g. `ffcc00`.
+/// See https://html.spec.whatwg.org/multipage/common-microsyntaxes.html#rules-for-parsing-a-legacy-colour-value
+// TODO: Parse the other quirks of legacy colors, e.g. arbitrary garbage like "chucknorris"
+fn legacy_color(value: &str) -> String {
+    let value = value.trim();
+    if matches!(value.len(), 3 | 6) && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        format!("#{}", value)
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Parses a dimension attribute (e.g. `width="50%"` or `width="100"`)
+/// into a CSS length.
+fn parse_dimension(value: &str) -> Option<String> {
+    let value = value.trim_start();
+    let end = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let number: f64 = value[..end].parse().ok()?;
+    if value[end..].starts_with('%') {
+        Some(format!("{}%", number))
+    } else {
+        Some(format!("{}px", number))
+    }
+}
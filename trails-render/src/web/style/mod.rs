@@ -0,0 +1,29 @@
+mod background;
+mod cache;
+mod cascade;
+mod computed;
+mod custom;
+mod dependencies;
+mod effects;
+mod flex;
+mod font;
+mod generated;
+mod grid;
+mod hints;
+mod text;
+mod values;
+
+pub use background::*;
+pub use cache::*;
+pub use cascade::*;
+pub use computed::*;
+pub use custom::*;
+pub use dependencies::*;
+pub use effects::*;
+pub use flex::*;
+pub use font::*;
+pub use generated::*;
+pub use grid::*;
+pub use hints::*;
+pub use text::*;
+pub use values::*;
@@ -0,0 +1,358 @@
+use std::borrow::Cow;
+
+use piet::FontStyle;
+use trails_base::{Result, bail};
+use trails_model::css::{Color, ColorValue, Length, ResolveContext};
+use trails_model::parse::css::split_components;
+
+use super::{Side, Sides};
+
+/// Parses a `font-style` keyword, approximating oblique fonts with italic ones.
+pub fn parse_font_style(value: &str) -> Result<FontStyle> {
+    let value = value.to_ascii_lowercase();
+    Ok(match value.as_str() {
+        "normal" => FontStyle::Regular,
+        "italic" => FontStyle::Italic,
+        _ if value.starts_with("oblique") => FontStyle::Italic,
+        _ => bail!("Unknown font style {}", value),
+    })
+}
+
+/// The horizontal alignment of the lines in a block container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextAlign {
+    /// Aligned to the start of the line, i.e. the left for left-to-right text.
+    #[default]
+    Start,
+    /// Aligned to the end of the line, i.e. the right for left-to-right text.
+    End,
+    Left,
+    Right,
+    Center,
+    /// Stretched to fill the line by widening the spaces, except on the last line.
+    Justify,
+}
+
+impl TextAlign {
+    /// Parses a `text-align` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "start" => Self::Start,
+            "end" => Self::End,
+            "left" | "-webkit-left" | "-moz-left" => Self::Left,
+            "right" | "-webkit-right" | "-moz-right" => Self::Right,
+            "center" | "-webkit-center" | "-moz-center" => Self::Center,
+            "justify" => Self::Justify,
+            _ => bail!("Unknown text alignment {}", value),
+        })
+    }
+}
+
+/// The lines drawn by `text-decoration`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextDecorationLine {
+    pub underline: bool,
+    pub overline: bool,
+    pub line_through: bool,
+}
+
+impl TextDecorationLine {
+    /// Parses a `text-decoration-line` value, i.e. `none` or a list of lines.
+    pub fn parse(value: &str) -> Result<Self> {
+        let mut line = Self::default();
+        if value.eq_ignore_ascii_case("none") {
+            return Ok(line);
+        }
+        for component in split_components(value) {
+            if !line.add(component) {
+                bail!("Unknown text decoration line {}", component);
+            }
+        }
+        Ok(line)
+    }
+
+    /// Adds the line with the given keyword, returns false if it isn't one.
+    fn add(&mut self, keyword: &str) -> bool {
+        match keyword.to_ascii_lowercase().as_str() {
+            "underline" => self.underline = true,
+            "overline" => self.overline = true,
+            "line-through" => self.line_through = true,
+            // Blinking is not supported (nor desired)
+            "blink" => {},
+            _ => return false,
+        }
+        true
+    }
+
+    /// Whether no lines are drawn.
+    pub fn is_none(self) -> bool {
+        !(self.underline || self.overline || self.line_through)
+    }
+}
+
+/// A text decoration of a box, which is drawn across the text inside of it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextDecoration {
+    pub line: TextDecorationLine,
+    pub color: Color,
+}
+
+/// Parses a `text-decoration` shorthand, i.e. the lines, a style (which
+/// is ignored, all lines are drawn solid) and a color in any order.
+pub fn parse_text_decoration(value: &str) -> Result<(TextDecorationLine, ColorValue)> {
+    let mut line = TextDecorationLine::default();
+    let mut color = None;
+    for component in split_components(value) {
+        let keyword = component.to_ascii_lowercase();
+        if keyword == "none" || line.add(&keyword) || matches!(keyword.as_str(), "solid" | "double" | "dotted" | "dashed" | "wavy") {
+            continue;
+        } else if let (None, Ok(c)) = (color, ColorValue::parse(component)) {
+            color = Some(c);
+        } else {
+            bail!("Invalid text-decoration component {}", component);
+        }
+    }
+    Ok((line, color.unwrap_or(ColorValue::CurrentColor)))
+}
+
+/// The height of the lines of text.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LineHeight {
+    /// Determined by the font.
+    #[default]
+    Normal,
+    /// A multiple of the font size, which is inherited as such.
+    Number(f64),
+    /// A height in pixels.
+    Length(f64),
+}
+
+impl LineHeight {
+    /// Parses a `line-height` value, resolving percentages against the given font size.
+    pub fn parse(value: &str, font_size: f64, ctx: &ResolveContext) -> Result<Self> {
+        let line_height = if value.eq_ignore_ascii_case("normal") {
+            Self::Normal
+        } else if let Ok(number) = value.trim().parse::<f64>() {
+            Self::Number(number)
+        } else {
+            Self::Length(Length::parse(value)?.compute(ctx).resolve(font_size))
+        };
+        if let Self::Number(h) | Self::Length(h) = line_height {
+            if h < 0.0 {
+                bail!("Line height may not be negative");
+            }
+        }
+        Ok(line_height)
+    }
+
+    /// The used line height for text with the given font size, whose
+    /// height according to the font is given.
+    pub fn resolve(self, font_size: f64, normal: f64) -> f64 {
+        match self {
+            Self::Normal => normal,
+            Self::Number(factor) => factor * font_size,
+            Self::Length(height) => height,
+        }
+    }
+}
+
+/// Parses a `letter-spacing` value, where `normal` is zero.
+pub fn parse_letter_spacing(value: &str, font_size: f64, ctx: &ResolveContext) -> Result<f64> {
+    if value.eq_ignore_ascii_case("normal") {
+        Ok(0.0)
+    } else {
+        Ok(Length::parse(value)?.compute(ctx).resolve(font_size))
+    }
+}
+
+/// The case transformation applied to text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextTransform {
+    #[default]
+    None,
+    Uppercase,
+    Lowercase,
+    /// Uppercases the first letter of each word.
+    Capitalize,
+}
+
+impl TextTransform {
+    /// Parses a `text-transform` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "none" => Self::None,
+            "uppercase" => Self::Uppercase,
+            "lowercase" => Self::Lowercase,
+            "capitalize" => Self::Capitalize,
+            _ => bail!("Unknown text transform {}", value),
+        })
+    }
+
+    /// Applies the transformation to the given text.
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        match self {
+            Self::None => Cow::Borrowed(text),
+            Self::Uppercase => Cow::Owned(text.to_uppercase()),
+            Self::Lowercase => Cow::Owned(text.to_lowercase()),
+            Self::Capitalize => {
+                let mut capitalized = String::with_capacity(text.len());
+                let mut after_letter = false;
+                for c in text.chars() {
+                    if after_letter {
+                        capitalized.push(c);
+                    } else {
+                        capitalized.extend(c.to_uppercase());
+                    }
+                    after_letter = c.is_alphanumeric() || c == '\'';
+                }
+                Cow::Owned(capitalized)
+            },
+        }
+    }
+}
+
+/// The inline base direction of text.
+/// See https://www.w3.org/TR/css-writing-modes-3/#direction
+// TODO: Lay out blocks, tables, flex and grid containers from the right for right-to-left text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    /// Parses a `direction` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "ltr" => Self::Ltr,
+            "rtl" => Self::Rtl,
+            _ => bail!("Unknown direction {}", value),
+        })
+    }
+}
+
+/// How an inline box takes part in the bidirectional reordering of its text,
+/// i.e. whether it opens an embedding (in its direction) or overrides the
+/// direction of its content.
+/// See https://www.w3.org/TR/css-writing-modes-3/#unicode-bidi
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnicodeBidi {
+    #[default]
+    Normal,
+    Embed,
+    /// Like `embed`, but the content is reordered independently of its surroundings.
+    Isolate,
+    /// Lays out the content in the box's direction regardless of its characters.
+    BidiOverride,
+    IsolateOverride,
+    /// Like `isolate`, but the direction is determined from the content.
+    Plaintext,
+}
+
+impl UnicodeBidi {
+    /// Parses a `unicode-bidi` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "normal" => Self::Normal,
+            "embed" => Self::Embed,
+            "isolate" => Self::Isolate,
+            "bidi-override" => Self::BidiOverride,
+            "isolate-override" => Self::IsolateOverride,
+            "plaintext" => Self::Plaintext,
+            _ => bail!("Unknown unicode-bidi value {}", value),
+        })
+    }
+}
+
+/// The direction in which lines are stacked and text flows within them.
+/// See https://www.w3.org/TR/css-writing-modes-3/#block-flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritingMode {
+    /// Horizontal lines stacked from the top.
+    #[default]
+    HorizontalTb,
+    /// Vertical lines stacked from the right.
+    VerticalRl,
+    /// Vertical lines stacked from the left.
+    VerticalLr,
+}
+
+impl WritingMode {
+    /// Parses a `writing-mode` keyword, including the obsolete SVG ones.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "horizontal-tb" | "lr" | "lr-tb" | "rl" | "rl-tb" => Self::HorizontalTb,
+            "vertical-rl" | "tb" | "tb-rl" => Self::VerticalRl,
+            "vertical-lr" => Self::VerticalLr,
+            _ => bail!("Unknown writing mode {}", value),
+        })
+    }
+
+    /// Whether lines are vertical.
+    pub fn is_vertical(self) -> bool {
+        self != Self::HorizontalTb
+    }
+
+    /// The physical side corresponding to a logical one, e.g. `inline-start`,
+    /// in this writing mode and the given direction.
+    /// See https://www.w3.org/TR/css-writing-modes-3/#logical-to-physical
+    pub fn physical_side(self, direction: Direction, side: LogicalSide) -> Side {
+        let rtl = direction == Direction::Rtl;
+        match (self, side) {
+            (Self::HorizontalTb, LogicalSide::BlockStart) => Side::Top,
+            (Self::HorizontalTb, LogicalSide::BlockEnd) => Side::Bottom,
+            (Self::HorizontalTb, LogicalSide::InlineStart) => if rtl { Side::Right } else { Side::Left },
+            (Self::HorizontalTb, LogicalSide::InlineEnd) => if rtl { Side::Left } else { Side::Right },
+            (Self::VerticalRl, LogicalSide::BlockStart) | (Self::VerticalLr, LogicalSide::BlockEnd) => Side::Right,
+            (Self::VerticalRl, LogicalSide::BlockEnd) | (Self::VerticalLr, LogicalSide::BlockStart) => Side::Left,
+            (_, LogicalSide::InlineStart) => if rtl { Side::Bottom } else { Side::Top },
+            (_, LogicalSide::InlineEnd) => if rtl { Side::Top } else { Side::Bottom },
+        }
+    }
+
+    /// Rotates the physical sides of a box into the sides of a horizontal
+    /// box (with lines flowing from its left), which vertical boxes are laid
+    /// out as, i.e. the block start becomes the top and the inline start
+    /// (ignoring the direction) the left.
+    pub fn to_logical<T>(self, sides: Sides<T>) -> Sides<T> {
+        let Sides { top, right, bottom, left } = sides;
+        match self {
+            Self::HorizontalTb => Sides { top, right, bottom, left },
+            Self::VerticalRl => Sides { top: right, right: bottom, bottom: left, left: top },
+            Self::VerticalLr => Sides { top: left, right: bottom, bottom: right, left: top },
+        }
+    }
+
+    /// Rotates the sides of a box laid out as a horizontal one back to the
+    /// physical sides, i.e. the inverse of `to_logical`.
+    pub fn to_physical<T>(self, sides: Sides<T>) -> Sides<T> {
+        let Sides { top, right, bottom, left } = sides;
+        match self {
+            Self::HorizontalTb => Sides { top, right, bottom, left },
+            Self::VerticalRl => Sides { top: left, right: top, bottom: right, left: bottom },
+            Self::VerticalLr => Sides { top: left, right: bottom, bottom: right, left: top },
+        }
+    }
+}
+
+/// A side of a box relative to the flow of its content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogicalSide {
+    BlockStart,
+    BlockEnd,
+    InlineStart,
+    InlineEnd,
+}
+
+impl LogicalSide {
+    /// The start and end sides along the block or inline axis, as named
+    /// in property names (e.g. `margin-inline`).
+    pub fn pair(axis: &str) -> Option<(Self, Self)> {
+        match axis {
+            "block" => Some((Self::BlockStart, Self::BlockEnd)),
+            "inline" => Some((Self::InlineStart, Self::InlineEnd)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,458 @@
+use trails_base::{Result, bail};
+use trails_model::css::{ComputedLength, Length, ResolveContext};
+
+/// A side of a box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+impl Side {
+    /// All sides, in the order used by shorthand properties.
+    pub const ALL: [Side; 4] = [Side::Top, Side::Right, Side::Bottom, Side::Left];
+
+    /// The name as used in property names.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Top => "top",
+            Self::Right => "right",
+            Self::Bottom => "bottom",
+            Self::Left => "left",
+        }
+    }
+}
+
+/// A value for each side of a box, e.g. the margins.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Sides<T> {
+    pub top: T,
+    pub right: T,
+    pub bottom: T,
+    pub left: T,
+}
+
+impl<T> Sides<T> where T: Clone {
+    /// Creates sides that all have the same value.
+    pub fn all(value: T) -> Self {
+        Self {
+            top: value.clone(),
+            right: value.clone(),
+            bottom: value.clone(),
+            left: value,
+        }
+    }
+
+    /// Creates sides from 1 to 4 values as in shorthand properties, i.e.
+    /// `top [right [bottom [left]]]` where missing sides are copied from
+    /// the opposite side.
+    pub fn from_components(values: Vec<T>) -> Result<Self> {
+        let [top, right, bottom, left] = match values.as_slice() {
+            [all] => [all, all, all, all],
+            [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+            [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+            [top, right, bottom, left] => [top, right, bottom, left],
+            _ => bail!("Expected 1 to 4 values, but got {}", values.len()),
+        };
+        Ok(Self {
+            top: top.clone(),
+            right: right.clone(),
+            bottom: bottom.clone(),
+            left: left.clone(),
+        })
+    }
+}
+
+impl<T> Sides<T> {
+    /// The value for the given side.
+    pub fn get(&self, side: Side) -> &T {
+        match side {
+            Side::Top => &self.top,
+            Side::Right => &self.right,
+            Side::Bottom => &self.bottom,
+            Side::Left => &self.left,
+        }
+    }
+
+    /// The mutable value for the given side.
+    pub fn get_mut(&mut self, side: Side) -> &mut T {
+        match side {
+            Side::Top => &mut self.top,
+            Side::Right => &mut self.right,
+            Side::Bottom => &mut self.bottom,
+            Side::Left => &mut self.left,
+        }
+    }
+
+    /// Applies a function to each side.
+    pub fn map<U>(&self, mut f: impl FnMut(&T) -> U) -> Sides<U> {
+        Sides {
+            top: f(&self.top),
+            right: f(&self.right),
+            bottom: f(&self.bottom),
+            left: f(&self.left),
+        }
+    }
+}
+
+impl Sides<f64> {
+    /// The sum of the left and right sides.
+    pub fn horizontal(&self) -> f64 {
+        self.left + self.right
+    }
+
+    /// The sum of the top and bottom sides.
+    pub fn vertical(&self) -> f64 {
+        self.top + self.bottom
+    }
+}
+
+/// A computed length or `auto`, e.g. for `width` or `margin-left`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthOrAuto {
+    Length(ComputedLength),
+    Auto,
+}
+
+impl Default for LengthOrAuto {
+    fn default() -> Self {
+        Self::Length(ComputedLength::default())
+    }
+}
+
+impl LengthOrAuto {
+    /// Parses and computes a length or `auto`.
+    pub fn parse(value: &str, ctx: &ResolveContext) -> Result<Self> {
+        if value.eq_ignore_ascii_case("auto") {
+            Ok(Self::Auto)
+        } else {
+            Ok(Self::Length(Length::parse(value)?.compute(ctx)))
+        }
+    }
+
+    /// Resolves the length using the given base for percentages, `None` if `auto`.
+    pub fn resolve(&self, percentage_base: f64) -> Option<f64> {
+        match self {
+            Self::Length(length) => Some(length.resolve(percentage_base)),
+            Self::Auto => None,
+        }
+    }
+
+    /// Resolves the length against a base that may be indefinite (e.g. the
+    /// height of a box with `height: auto`), in which case percentages behave
+    /// like `auto`.
+    pub fn resolve_definite(&self, percentage_base: Option<f64>) -> Option<f64> {
+        match (self, percentage_base) {
+            (Self::Length(length), _) if length.percent == 0.0 => Some(length.px),
+            (Self::Length(length), Some(base)) => Some(length.resolve(base)),
+            _ => None,
+        }
+    }
+
+    /// Whether this is `auto`.
+    pub fn is_auto(&self) -> bool {
+        *self == Self::Auto
+    }
+}
+
+/// The style of a border.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BorderStyle {
+    #[default]
+    None,
+    Hidden,
+    Solid,
+    Dashed,
+    Dotted,
+    Double,
+    Groove,
+    Ridge,
+    Inset,
+    Outset,
+}
+
+impl BorderStyle {
+    /// Parses a border style keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "none" => Self::None,
+            "hidden" => Self::Hidden,
+            "solid" => Self::Solid,
+            "dashed" => Self::Dashed,
+            "dotted" => Self::Dotted,
+            "double" => Self::Double,
+            "groove" => Self::Groove,
+            "ridge" => Self::Ridge,
+            "inset" => Self::Inset,
+            "outset" => Self::Outset,
+            _ => bail!("Unknown border style {}", value),
+        })
+    }
+
+    /// Whether a border with this style is drawn (and takes up space).
+    pub fn is_visible(self) -> bool {
+        !matches!(self, Self::None | Self::Hidden)
+    }
+}
+
+/// Which box `width` and `height` refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoxSizing {
+    #[default]
+    ContentBox,
+    BorderBox,
+}
+
+/// How whitespace inside an element is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhiteSpace {
+    #[default]
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+    PreLine,
+}
+
+impl WhiteSpace {
+    /// Parses a `white-space` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "normal" => Self::Normal,
+            "pre" => Self::Pre,
+            "nowrap" => Self::Nowrap,
+            // TODO: Let preserved spaces at the end of a line wrap for break-spaces
+            "pre-wrap" | "break-spaces" => Self::PreWrap,
+            "pre-line" => Self::PreLine,
+            _ => bail!("Unknown white-space value {}", value),
+        })
+    }
+
+    /// Whether sequences of spaces and tabs are collapsed into a single space.
+    pub fn collapses_spaces(self) -> bool {
+        matches!(self, Self::Normal | Self::Nowrap | Self::PreLine)
+    }
+
+    /// Whether newlines are preserved as forced line breaks.
+    pub fn preserves_newlines(self) -> bool {
+        matches!(self, Self::Pre | Self::PreWrap | Self::PreLine)
+    }
+
+    /// Whether lines may be wrapped at soft break opportunities.
+    pub fn wraps(self) -> bool {
+        matches!(self, Self::Normal | Self::PreWrap | Self::PreLine)
+    }
+}
+
+/// The algorithm used to lay out a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableLayout {
+    /// Column widths depend on the contents of all cells.
+    #[default]
+    Auto,
+    /// Column widths only depend on the table width and the cells of the first row.
+    Fixed,
+}
+
+/// The vertical alignment of an inline-level box or of the contents of a table cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VerticalAlign {
+    #[default]
+    Baseline,
+    Sub,
+    Super,
+    TextTop,
+    TextBottom,
+    Top,
+    Middle,
+    Bottom,
+}
+
+impl VerticalAlign {
+    /// Parses a `vertical-align` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "baseline" => Self::Baseline,
+            "sub" => Self::Sub,
+            "super" => Self::Super,
+            "text-top" => Self::TextTop,
+            "text-bottom" => Self::TextBottom,
+            "top" => Self::Top,
+            "middle" => Self::Middle,
+            "bottom" => Self::Bottom,
+            // TODO: Support lengths and percentages
+            _ => bail!("Unsupported vertical-align value {}", value),
+        })
+    }
+}
+
+/// The positioning scheme of a box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Position {
+    #[default]
+    Static,
+    /// Offset from its position in the flow.
+    Relative,
+    /// Taken out of the flow and placed relative to its containing block.
+    Absolute,
+    /// Taken out of the flow and placed relative to the viewport.
+    Fixed,
+    /// Offset to stay within the viewport while its containing block is visible.
+    Sticky,
+}
+
+impl Position {
+    /// Parses a `position` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "static" => Self::Static,
+            "relative" => Self::Relative,
+            "absolute" => Self::Absolute,
+            "fixed" => Self::Fixed,
+            "sticky" | "-webkit-sticky" => Self::Sticky,
+            _ => bail!("Unknown position {}", value),
+        })
+    }
+
+    /// Whether the box is positioned, i.e. not static.
+    pub fn is_positioned(self) -> bool {
+        self != Self::Static
+    }
+
+    /// Whether the box is absolutely positioned, i.e. taken out of the flow.
+    pub fn is_absolute(self) -> bool {
+        matches!(self, Self::Absolute | Self::Fixed)
+    }
+}
+
+/// How content overflowing a box along an axis is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// The content is drawn outside of the box.
+    #[default]
+    Visible,
+    /// The content is clipped, but can still be scrolled programmatically.
+    Hidden,
+    /// The content is clipped and can't be scrolled at all.
+    Clip,
+    /// The content is clipped and can be scrolled by the user.
+    Scroll,
+    /// Like `scroll`, but only shows scrollbars if the content overflows.
+    Auto,
+}
+
+impl Overflow {
+    /// Parses an `overflow` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "visible" => Self::Visible,
+            "hidden" => Self::Hidden,
+            "clip" => Self::Clip,
+            "scroll" => Self::Scroll,
+            "auto" | "overlay" => Self::Auto,
+            _ => bail!("Unknown overflow {}", value),
+        })
+    }
+
+    /// Whether overflowing content is clipped.
+    pub fn clips(self) -> bool {
+        self != Self::Visible
+    }
+
+    /// Whether the box is a scroll container along this axis.
+    pub fn is_scroll_container(self) -> bool {
+        matches!(self, Self::Hidden | Self::Scroll | Self::Auto)
+    }
+
+    /// Whether the user can scroll the box along this axis.
+    pub fn is_scrollable(self) -> bool {
+        matches!(self, Self::Scroll | Self::Auto)
+    }
+
+    /// The used value along this axis if the box is a scroll container
+    /// along the other axis.
+    /// See https://www.w3.org/TR/css-overflow-3/#overflow-control
+    pub fn in_scroll_container(self) -> Self {
+        match self {
+            Self::Visible => Self::Auto,
+            Self::Clip => Self::Hidden,
+            _ => self,
+        }
+    }
+}
+
+/// The side a box is floated to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Float {
+    #[default]
+    None,
+    Left,
+    Right,
+}
+
+impl Float {
+    /// Parses a `float` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "none" => Self::None,
+            "left" | "inline-start" => Self::Left,
+            "right" | "inline-end" => Self::Right,
+            _ => bail!("Unknown float value {}", value),
+        })
+    }
+}
+
+/// The sides of preceding floats a box is moved below.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Clear {
+    #[default]
+    None,
+    Left,
+    Right,
+    Both,
+}
+
+impl Clear {
+    /// Parses a `clear` keyword.
+    pub fn parse(value: &str) -> Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "none" => Self::None,
+            "left" | "inline-start" => Self::Left,
+            "right" | "inline-end" => Self::Right,
+            "both" => Self::Both,
+            _ => bail!("Unknown clear value {}", value),
+        })
+    }
+
+    /// Whether floats on the given side are cleared.
+    pub fn clears(self, side: Float) -> bool {
+        match side {
+            Float::None => false,
+            Float::Left => matches!(self, Self::Left | Self::Both),
+            Float::Right => matches!(self, Self::Right | Self::Both),
+        }
+    }
+}
+
+/// Parses a border width, e.g. `thin` or `2px`.
+pub fn parse_border_width(value: &str, ctx: &ResolveContext) -> Result<f64> {
+    // See https://www.w3.org/TR/css-backgrounds-3/#border-width
+    let width = match value.to_ascii_lowercase().as_str() {
+        "thin" => 1.0,
+        "medium" => 3.0,
+        "thick" => 5.0,
+        _ => {
+            let length = Length::parse(value)?.compute(ctx);
+            if length.percent != 0.0 {
+                bail!("Border widths may not be percentages");
+            }
+            length.px
+        },
+    };
+    if width < 0.0 {
+        bail!("Border widths may not be negative");
+    }
+    Ok(width)
+}
@@ -0,0 +1,245 @@
+use piet::{kurbo::{Affine, BezPath, Point, Rect}, FixedLinearGradient, FixedRadialGradient, GradientStop, LineCap, LineJoin, RenderContext, StrokeStyle};
+use trails_base::log::warn;
+use trails_model::dom::{Document, Element, Node};
+use usvg::tiny_skia_path::PathSegment;
+
+/// The namespaces declared on the root of serialized inline SVG, which
+/// HTML documents usually leave out.
+const NAMESPACES: [(&str, &str); 2] = [
+    ("xmlns", "http://www.w3.org/2000/svg"),
+    ("xmlns:xlink", "http://www.w3.org/1999/xlink"),
+];
+
+/// Serializes an inline `<svg>` element of a document to standalone SVG
+/// markup. Elements referenced by `<use>` from outside of it (e.g. symbols
+/// in a sprite sheet elsewhere on the page) are copied into its definitions.
+pub fn svg_markup(svg: &Element, document: &Document) -> String {
+    let mut markup = String::new();
+    markup.push_str("<svg");
+    for (name, value) in NAMESPACES {
+        write_attribute(&mut markup, name, value);
+    }
+    for (name, value) in svg.attributes().filter(|(name, _)| !name.starts_with("xmlns")) {
+        write_attribute(&mut markup, name, value);
+    }
+    markup.push('>');
+    let mut referenced: Vec<&str> = svg.descendants()
+        .filter(|e| e.tag_name() == "use")
+        .filter_map(|e| e.attribute("href").or_else(|| e.attribute("xlink:href")))
+        .filter_map(|href| href.strip_prefix('#'))
+        .filter(|id| !svg.descendants().any(|e| e.attribute("id") == Some(*id)))
+        .collect();
+    referenced.sort_unstable();
+    referenced.dedup();
+    if !referenced.is_empty() {
+        markup.push_str("<defs>");
+        for id in referenced {
+            if let Some(element) = document.root().descendants().find(|e| e.attribute("id") == Some(id)) {
+                write_element(&mut markup, element);
+            }
+        }
+        markup.push_str("</defs>");
+    }
+    for child in svg.children() {
+        write_node(&mut markup, child);
+    }
+    markup.push_str("</svg>");
+    markup
+}
+
+/// Serializes a node (and its descendants) as XML. Text and attribute
+/// values are written as in the source, except for characters that
+/// would end them.
+fn write_node(markup: &mut String, node: &Node) {
+    match node {
+        Node::Text(text) => markup.push_str(&text.replace('<', "&lt;")),
+        Node::Element(element) => write_element(markup, element),
+    }
+}
+
+/// Serializes an element (and its descendants) as XML.
+fn write_element(markup: &mut String, element: &Element) {
+    markup.push('<');
+    markup.push_str(element.tag_name());
+    for (name, value) in element.attributes() {
+        write_attribute(markup, name, value);
+    }
+    markup.push('>');
+    for child in element.children() {
+        write_node(markup, child);
+    }
+    markup.push_str("</");
+    markup.push_str(element.tag_name());
+    markup.push('>');
+}
+
+/// Serializes an attribute as XML.
+fn write_attribute(markup: &mut String, name: &str, value: &str) {
+    markup.push(' ');
+    markup.push_str(name);
+    markup.push_str("=\"");
+    markup.push_str(&value.replace('"', "&quot;").replace('<', "&lt;"));
+    markup.push('"');
+}
+
+/// Draws a parsed SVG document scaled to the given rectangle, i.e. its
+/// paths with their fills and strokes.
+// TODO: Draw text, nested images, clip paths, masks, patterns and filters
+pub fn paint_svg<P>(paint: &mut P, rect: Rect, tree: &usvg::Tree) where P: RenderContext {
+    let size = tree.size();
+    let scale = Affine::scale_non_uniform(rect.width() / size.width() as f64, rect.height() / size.height() as f64);
+    let result = paint.with_save(|paint| {
+        paint.clip(rect);
+        paint.transform(Affine::translate(rect.origin().to_vec2()) * scale);
+        paint_group(paint, tree.root(), 1.0);
+        Ok(())
+    });
+    if let Err(e) = result {
+        warn!("Could not paint SVG: {}", e);
+    }
+}
+
+/// Draws the children of an SVG group, whose opacity (multiplied with that
+/// of its ancestors) is given.
+// TODO: Composite the opacity of groups instead of applying it to each of their paths
+fn paint_group<P>(paint: &mut P, group: &usvg::Group, opacity: f64) where P: RenderContext {
+    for node in group.children() {
+        match node {
+            usvg::Node::Group(child) => {
+                let opacity = opacity * child.opacity().get() as f64;
+                let result = paint.with_save(|paint| {
+                    paint.transform(to_affine(child.transform()));
+                    paint_group(paint, child, opacity);
+                    Ok(())
+                });
+                if let Err(e) = result {
+                    warn!("Could not paint SVG group: {}", e);
+                }
+            },
+            usvg::Node::Path(path) if path.is_visible() => paint_path(paint, path, opacity),
+            _ => {},
+        }
+    }
+}
+
+/// Draws an SVG path, filling and stroking it in its paint order.
+fn paint_path<P>(paint: &mut P, path: &usvg::Path, opacity: f64) where P: RenderContext {
+    let shape = to_bez_path(path.data());
+    let fill = |paint: &mut P| if let Some(fill) = path.fill() {
+        if let Some(brush) = to_brush(paint, fill.paint(), opacity * fill.opacity().get() as f64) {
+            match fill.rule() {
+                usvg::FillRule::NonZero => paint.fill(&shape, &brush),
+                usvg::FillRule::EvenOdd => paint.fill_even_odd(&shape, &brush),
+            }
+        }
+    };
+    let stroke = |paint: &mut P| if let Some(stroke) = path.stroke() {
+        if let Some(brush) = to_brush(paint, stroke.paint(), opacity * stroke.opacity().get() as f64) {
+            paint.stroke_styled(&shape, &brush, stroke.width().get() as f64, &to_stroke_style(stroke));
+        }
+    };
+    match path.paint_order() {
+        usvg::PaintOrder::FillAndStroke => {
+            fill(paint);
+            stroke(paint);
+        },
+        usvg::PaintOrder::StrokeAndFill => {
+            stroke(paint);
+            fill(paint);
+        },
+    }
+}
+
+/// Converts an SVG paint with the given opacity to a brush, `None` if it
+/// isn't supported.
+fn to_brush<P>(paint: &mut P, svg_paint: &usvg::Paint, opacity: f64) -> Option<P::Brush> where P: RenderContext {
+    let result = match svg_paint {
+        usvg::Paint::Color(color) => return Some(paint.solid_brush(to_color(*color, opacity))),
+        usvg::Paint::LinearGradient(gradient) => {
+            // Gradient transforms are applied to the end points, which is exact for uniform scaling
+            let transform = to_affine(gradient.transform());
+            paint.gradient(FixedLinearGradient {
+                start: transform * Point::new(gradient.x1() as f64, gradient.y1() as f64),
+                end: transform * Point::new(gradient.x2() as f64, gradient.y2() as f64),
+                stops: to_gradient_stops(gradient.stops(), opacity),
+            })
+        },
+        usvg::Paint::RadialGradient(gradient) => {
+            let transform = to_affine(gradient.transform());
+            let center = transform * Point::new(gradient.cx() as f64, gradient.cy() as f64);
+            let focus = transform * Point::new(gradient.fx() as f64, gradient.fy() as f64);
+            paint.gradient(FixedRadialGradient {
+                center,
+                origin_offset: focus - center,
+                radius: gradient.r().get() as f64 * transform.determinant().abs().sqrt(),
+                stops: to_gradient_stops(gradient.stops(), opacity),
+            })
+        },
+        usvg::Paint::Pattern(_) => return None,
+    };
+    match result {
+        Ok(brush) => Some(brush),
+        Err(e) => {
+            warn!("Could not create SVG gradient: {}", e);
+            None
+        },
+    }
+}
+
+/// Converts the stops of an SVG gradient with the given opacity.
+fn to_gradient_stops(stops: &[usvg::Stop], opacity: f64) -> Vec<GradientStop> {
+    stops.iter()
+        .map(|stop| GradientStop {
+            pos: stop.offset().get(),
+            color: to_color(stop.color(), opacity * stop.opacity().get() as f64),
+        })
+        .collect()
+}
+
+/// Converts the style of an SVG stroke.
+fn to_stroke_style(stroke: &usvg::Stroke) -> StrokeStyle {
+    let mut style = StrokeStyle::new();
+    style.set_line_cap(match stroke.linecap() {
+        usvg::LineCap::Butt => LineCap::Butt,
+        usvg::LineCap::Round => LineCap::Round,
+        usvg::LineCap::Square => LineCap::Square,
+    });
+    style.set_line_join(match stroke.linejoin() {
+        usvg::LineJoin::Miter | usvg::LineJoin::MiterClip => LineJoin::Miter { limit: stroke.miterlimit().get() as f64 },
+        usvg::LineJoin::Round => LineJoin::Round,
+        usvg::LineJoin::Bevel => LineJoin::Bevel,
+    });
+    if let Some(dashes) = stroke.dasharray() {
+        style.set_dash_pattern(dashes.iter().map(|&d| d as f64).collect::<Vec<_>>());
+        style.set_dash_offset(stroke.dashoffset() as f64);
+    }
+    style
+}
+
+/// Converts the geometry of an SVG path.
+fn to_bez_path(data: &usvg::tiny_skia_path::Path) -> BezPath {
+    let point = |p: usvg::tiny_skia_path::Point| Point::new(p.x as f64, p.y as f64);
+    let mut path = BezPath::new();
+    for segment in data.segments() {
+        match segment {
+            PathSegment::MoveTo(p) => path.move_to(point(p)),
+            PathSegment::LineTo(p) => path.line_to(point(p)),
+            PathSegment::QuadTo(p1, p) => path.quad_to(point(p1), point(p)),
+            PathSegment::CubicTo(p1, p2, p) => path.curve_to(point(p1), point(p2), point(p)),
+            PathSegment::Close => path.close_path(),
+        }
+    }
+    path
+}
+
+/// Converts an SVG transform.
+fn to_affine(transform: usvg::Transform) -> Affine {
+    let usvg::Transform { sx, kx, ky, sy, tx, ty } = transform;
+    Affine::new([sx as f64, ky as f64, kx as f64, sy as f64, tx as f64, ty as f64])
+}
+
+/// Converts an SVG color with the given opacity.
+fn to_color(color: usvg::Color, opacity: f64) -> piet::Color {
+    piet::Color::rgba8(color.red, color.green, color.blue, (opacity.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
@@ -10,14 +10,28 @@ pub static RENDERED_TAGS: Lazy<HashSet<&str>> = Lazy::new(|| {
     set.insert("div");
     set.insert("a");
     set.insert("ul");
+    set.insert("ol");
     set.insert("li");
     set.insert("p");
+    set.insert("pre");
+    set.insert("code");
+    set.insert("kbd");
+    set.insert("samp");
+    set.insert("tt");
     set.insert("span");
     set.insert("b");
     set.insert("i");
     set.insert("u");
     set.insert("strong");
     set.insert("em");
+    set.insert("s");
+    set.insert("strike");
+    set.insert("del");
+    set.insert("ins");
+    set.insert("cite");
+    set.insert("var");
+    set.insert("dfn");
+    set.insert("address");
     set.insert("h1");
     set.insert("h2");
     set.insert("h3");
@@ -25,6 +39,10 @@ pub static RENDERED_TAGS: Lazy<HashSet<&str>> = Lazy::new(|| {
     set.insert("h5");
     set.insert("h6");
     set.insert("table");
+    set.insert("caption");
+    set.insert("thead");
+    set.insert("tbody");
+    set.insert("tfoot");
     set.insert("th");
     set.insert("tr");
     set.insert("td");
@@ -40,6 +58,18 @@ pub static RENDERED_TAGS: Lazy<HashSet<&str>> = Lazy::new(|| {
     set.insert("nobr");
     set.insert("wbr");
     set.insert("center");
+    set.insert("img");
+    set.insert("svg");
+    set.insert("br");
+    set.insert("hr");
+    set.insert("blockquote");
+    set.insert("dl");
+    set.insert("dt");
+    set.insert("dd");
+    set.insert("figure");
+    set.insert("figcaption");
+    set.insert("bdi");
+    set.insert("bdo");
     set
 });
 
@@ -52,5 +82,22 @@ pub static INLINE_TAGS: Lazy<HashSet<&str>> = Lazy::new(|| {
     set.insert("u");
     set.insert("strong");
     set.insert("em");
+    set.insert("s");
+    set.insert("strike");
+    set.insert("del");
+    set.insert("ins");
+    set.insert("cite");
+    set.insert("var");
+    set.insert("dfn");
+    set.insert("code");
+    set.insert("kbd");
+    set.insert("samp");
+    set.insert("tt");
+    set.insert("nobr");
+    set.insert("img");
+    set.insert("svg");
+    set.insert("br");
+    set.insert("bdi");
+    set.insert("bdo");
     set
 });
@@ -1,10 +1,15 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::Mutex;
 
 use trails_base::Result;
+use trails_base::log::{debug, warn};
 use trails_base::once_cell::sync::Lazy;
 use trails_base::regex::Regex;
+use trails_model::css::{FontFaceSource, Stylesheet};
 use trails_model::dom::Document;
-use trails_model::parse::html;
+use trails_model::parse::{css, html};
 use trails_network::{url::{self, Url}, Session};
 
 static SEARCH_QUERY: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[^\s\.:\[\]]+(?:\s+\S.*)?$").unwrap());
@@ -13,6 +18,8 @@ static SEARCH_QUERY: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[^\s\.:\[\]]+(?:\s
 pub struct AppServices {
     html_parser: html::Parser,
     session: Mutex<Session>,
+    /// The directory containing the user's configuration (e.g. user stylesheets).
+    config_dir: Option<PathBuf>,
 }
 
 impl AppServices {
@@ -20,16 +27,149 @@ impl AppServices {
         Self {
             html_parser: html::Parser::default(),
             session: Mutex::new(Session::default()),
+            config_dir: config_dir(),
         }
     }
 
     /// Loads a document.
     pub fn load_document(&self, url: Url) -> Result<Document> {
-        let raw = self.session.lock().unwrap().get_text(url)?;
-        let doc = self.html_parser.parse(raw.as_str())?;
+        let raw = self.session.lock().unwrap().get_text(url.clone())?;
+        let mut doc = self.html_parser.parse(raw.as_str())?;
+        doc.set_url(url.as_str());
+        self.load_stylesheets(&mut doc, &url);
         Ok(doc)
     }
 
+    /// Loads the stylesheets linked from a document. Stylesheets that fail
+    /// to load are skipped.
+    fn load_stylesheets(&self, doc: &mut Document, base_url: &Url) {
+        let hrefs = stylesheet_hrefs(doc);
+        let mut session = self.session.lock().unwrap();
+        for href in hrefs {
+            let result = base_url.join(&href)
+                .map_err(|e| e.into())
+                .and_then(|url| session.get(url));
+            match result {
+                Ok(contents) => doc.add_resource(&href, contents),
+                Err(e) => warn!("Could not load stylesheet {}: {:?}", href, e),
+            }
+        }
+    }
+
+    /// Loads the web fonts declared by `@font-face` rules in the (already
+    /// loaded) stylesheets of a document that aren't loaded yet, i.e. the
+    /// first source of each font in a format the text backend can load (after
+    /// decoding), returning them by their absolute URL. Sources are resolved
+    /// against the URL of the stylesheet declaring them. Like images, this is
+    /// done after the document has been displayed (in the background). Fonts
+    /// that fail to load are skipped.
+    pub fn load_fonts(&self, doc: &Document, base_url: &Url) -> Vec<(String, Vec<u8>)> {
+        let mut urls = Vec::new();
+        for (sheet_url, sheet) in author_stylesheets(doc, base_url) {
+            for font_face in sheet.font_face_rules() {
+                let Some(source) = font_face.sources().into_iter().find(is_loadable_font) else {
+                    debug!("Skipping font family {:?} without a loadable source", font_face.family());
+                    continue;
+                };
+                match sheet_url.join(&source.url) {
+                    Ok(url) => urls.push(url),
+                    Err(e) => warn!("Could not resolve font {}: {:?}", source.url, e),
+                }
+            }
+        }
+        urls.retain(|url| doc.resource(url.as_str()).is_none());
+        urls.sort_unstable();
+        urls.dedup();
+        let mut fonts = Vec::new();
+        for url in urls {
+            // Only lock the session per request, so navigating isn't blocked by loading fonts
+            let result = self.session.lock().unwrap().get(url.clone());
+            match result {
+                Ok(contents) => fonts.push((url.to_string(), contents)),
+                Err(e) => warn!("Could not load font {}: {:?}", url, e),
+            }
+        }
+        fonts
+    }
+
+    /// Loads the images referenced by a document that aren't loaded yet,
+    /// i.e. those of its `<img>` elements and its backgrounds (from its
+    /// stylesheets, `style` and `background` attributes), returning them by
+    /// their absolute URL. Background images from stylesheets are resolved
+    /// against the URL of the stylesheet, the others against the document's.
+    /// Since this may take a while, it is done after the document has been
+    /// displayed (in the background). Images that fail to load are skipped.
+    pub fn load_images(&self, doc: &Document, base_url: &Url) -> Vec<(String, Vec<u8>)> {
+        let mut refs: Vec<(String, Url)> = Vec::new();
+        let parser = css::Parser::default();
+        for element in doc.root().descendants() {
+            let mut srcs = Vec::new();
+            if element.tag_name() == "img" {
+                srcs.extend(element.attribute("src").map(|src| src.to_owned()));
+            }
+            if let Some(background) = element.attribute("background") {
+                srcs.push(background.trim().to_owned());
+            }
+            if let Some(style) = element.attribute("style") {
+                srcs.extend(parser.parse_declarations(style).iter().flat_map(|d| d.image_urls()));
+            }
+            refs.extend(srcs.into_iter().map(|src| (src, base_url.clone())));
+        }
+        for (sheet_url, sheet) in author_stylesheets(doc, base_url) {
+            refs.extend(sheet.image_urls().into_iter().map(|src| (src, sheet_url.clone())));
+        }
+        let mut urls = Vec::new();
+        for (src, ref_url) in refs {
+            match ref_url.join(&src) {
+                Ok(url) => urls.push(url),
+                Err(e) => warn!("Could not resolve image {}: {:?}", src, e),
+            }
+        }
+        urls.retain(|url| doc.resource(url.as_str()).is_none());
+        urls.sort_unstable();
+        urls.dedup();
+        let mut images = Vec::new();
+        for url in urls {
+            // Only lock the session per request, so navigating isn't blocked by loading images
+            let result = self.session.lock().unwrap().get(url.clone());
+            match result {
+                Ok(contents) => images.push((url.to_string(), contents)),
+                Err(e) => warn!("Could not load image {}: {:?}", url, e),
+            }
+        }
+        images
+    }
+
+    /// Loads the user stylesheets applying to the given URL from the config
+    /// directory, i.e. `user.css` followed by the per-site overrides in
+    /// `sites/<host>.css` for the host and its parent domains (e.g.
+    /// `sites/example.com.css` also applies to `docs.example.com`). More
+    /// specific stylesheets come later. Missing files are skipped.
+    pub fn load_user_stylesheets(&self, url: &Url) -> Vec<String> {
+        let Some(config_dir) = &self.config_dir else {
+            return Vec::new();
+        };
+        let mut paths = vec![config_dir.join("user.css")];
+        if let Some(domain) = url.domain() {
+            // Go from the second-level domain to the full domain
+            let labels: Vec<&str> = domain.split('.').collect();
+            for i in (0..=labels.len().saturating_sub(2)).rev() {
+                paths.push(config_dir.join("sites").join(format!("{}.css", labels[i..].join("."))));
+            }
+        } else if let Some(host) = url.host_str() {
+            paths.push(config_dir.join("sites").join(format!("{}.css", host)));
+        }
+        paths.into_iter()
+            .filter_map(|path| match fs::read_to_string(&path) {
+                Ok(contents) => {
+                    debug!("Loaded user stylesheet {}", path.display());
+                    Some(contents)
+                },
+                Err(_) => None,
+            })
+            .collect()
+    }
+
     /// Parses an address-bar query to a URL.
     pub fn parse_bar_query(&self, query: &str) -> Result<Url> {
         let url_result = if query.is_empty() {
@@ -53,3 +193,60 @@ impl AppServices {
         })?)
     }
 }
+
+/// The hrefs of the stylesheets linked from a document.
+fn stylesheet_hrefs(doc: &Document) -> Vec<String> {
+    doc.root().descendants()
+        .filter(|e| e.tag_name() == "link")
+        .filter(|e| e.attribute("rel").is_some_and(|rel| rel.split_whitespace().any(|r| r.eq_ignore_ascii_case("stylesheet"))))
+        .filter_map(|e| e.attribute("href").map(|href| href.to_owned()))
+        .collect()
+}
+
+/// Parses the (already loaded) author stylesheets of a document, i.e. those
+/// in `<style>` elements and linked ones, along with the URLs relative
+/// references in them are resolved against.
+fn author_stylesheets(doc: &Document, base_url: &Url) -> Vec<(Url, Stylesheet)> {
+    let mut sheets: Vec<(Url, String)> = doc.root().descendants()
+        .filter(|e| e.tag_name() == "style")
+        .map(|e| (base_url.clone(), e.text()))
+        .collect();
+    for href in stylesheet_hrefs(doc) {
+        if let (Ok(url), Some(raw)) = (base_url.join(&href), doc.resource(&href)) {
+            sheets.push((url, String::from_utf8_lossy(raw).into_owned()));
+        }
+    }
+    let parser = css::Parser::default();
+    sheets.into_iter()
+        .map(|(url, raw)| (url, parser.parse_stylesheet(&raw)))
+        .collect()
+}
+
+/// Whether a font source is in a format the text backends can load, i.e.
+/// TrueType or OpenType (possibly wrapped in WOFF or WOFF2, which is decoded
+/// before loading), judging by its format hint or else its extension.
+fn is_loadable_font(source: &FontFaceSource) -> bool {
+    match &source.format {
+        Some(format) => matches!(
+            format.as_str(),
+            "truetype" | "opentype" | "woff" | "woff2" | "truetype-variations" | "opentype-variations" | "woff-variations" | "woff2-variations"
+        ),
+        None => {
+            let url = source.url.to_ascii_lowercase();
+            let path = url.split(['?', '#']).next().unwrap_or_default();
+            [".ttf", ".otf", ".woff", ".woff2"].iter().any(|ext| path.ends_with(ext))
+        },
+    }
+}
+
+/// The directory containing the user's configuration. This is `$TRAILS_CONFIG_DIR`
+/// if set and otherwise `trails` in the platform's config directory (e.g. `~/.config/trails`).
+fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("TRAILS_CONFIG_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    let base = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("trails"))
+}
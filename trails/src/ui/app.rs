@@ -3,7 +3,7 @@ use std::sync::Arc;
 use crate::{state::AppState, services::AppServices};
 use druid::{Widget, widget::{Flex, Scroll}};
 
-use super::{bar_widget, content_widget, Tighten};
+use super::{bar_widget, content_widget, OffsetScroll, Tighten};
 
 pub fn app_widget(services: &Arc<AppServices>) -> impl Widget<AppState> {
     Flex::column()
@@ -12,8 +12,10 @@ pub fn app_widget(services: &Arc<AppServices>) -> impl Widget<AppState> {
         // Content
         .with_flex_child(
             Tighten::new(
-                Scroll::new(content_widget(services))
-                    .content_must_fill(true)
+                OffsetScroll::new(
+                    Scroll::new(content_widget(services))
+                        .content_must_fill(true)
+                )
             ),
             1.0
         )
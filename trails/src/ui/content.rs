@@ -1,15 +1,34 @@
 use std::sync::Arc;
+use std::thread;
 
-use druid::{EventCtx, Event, LifeCycleCtx, LayoutCtx, PaintCtx, Widget, Size, UpdateCtx, Env, LifeCycle, BoxConstraints};
+use druid::{Data, EventCtx, Event, ExtEventSink, LifeCycleCtx, LayoutCtx, PaintCtx, Selector, SingleUse, Widget, WidgetId, Size, UpdateCtx, Env, LifeCycle, BoxConstraints};
+use trails_base::log::warn;
+use trails_model::dom::Document;
 
 use crate::{state::AppState, services::AppServices};
 
 use super::WebRenderer;
 
+/// Subresources (i.e. fonts or images) loaded in the background for a document.
+struct LoadedResources {
+    /// The document the resources were loaded for.
+    document: Arc<Document>,
+    /// The resources by the URL they are looked up by.
+    resources: Vec<(String, Vec<u8>)>,
+}
+
+/// Delivers subresources loaded in the background to the content widget.
+const RESOURCES_LOADED: Selector<SingleUse<LoadedResources>> = Selector::new("trails.content.resources-loaded");
+
 /// The content widget that wires up a WebRenderer with the services and the state.
 pub struct Content {
     renderer: WebRenderer,
     services: Arc<AppServices>,
+    /// The document of the page whose resources are being loaded.
+    loading: Option<Arc<Document>>,
+    /// The document the last loaded resources were added to, which is still
+    /// the same page as the one they were loaded for.
+    with_resources: Option<Arc<Document>>,
 }
 
 impl Content {
@@ -17,6 +36,38 @@ impl Content {
         Self {
             renderer: WebRenderer::new(),
             services,
+            loading: None,
+            with_resources: None,
+        }
+    }
+
+    /// Loads the web fonts and then the images of the current page in the
+    /// background, the page is laid out again with each once they arrive.
+    fn load_resources(&mut self, sink: ExtEventSink, target: WidgetId, data: &AppState) {
+        self.loading = Some(data.document.clone());
+        let Ok(url) = data.url() else {
+            return;
+        };
+        let document = data.document.clone();
+        let services = self.services.clone();
+        thread::spawn(move || {
+            let deliver = |resources: Vec<(String, Vec<u8>)>| {
+                if !resources.is_empty() {
+                    let loaded = LoadedResources { document: document.clone(), resources };
+                    if let Err(e) = sink.submit_command(RESOURCES_LOADED, SingleUse::new(loaded), target) {
+                        warn!("Could not deliver loaded resources: {:?}", e);
+                    }
+                }
+            };
+            deliver(services.load_fonts(&document, &url));
+            deliver(services.load_images(&document, &url));
+        });
+    }
+
+    /// Loads the user stylesheets for the current page into the renderer.
+    fn load_user_stylesheets(&mut self, data: &AppState) {
+        if let Ok(url) = data.url() {
+            self.renderer.set_user_stylesheets(self.services.load_user_stylesheets(&url));
         }
     }
 }
@@ -25,6 +76,22 @@ impl Widget<AppState> for Content {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
         let services = &self.services;
 
+        if let Event::Command(cmd) = event {
+            if let Some(loaded) = cmd.get(RESOURCES_LOADED).and_then(SingleUse::take) {
+                // Resources of pages that have been navigated away from are dropped
+                if self.loading.as_ref().is_some_and(|d| d.same(&loaded.document)) {
+                    let mut document = (*data.document).clone();
+                    for (url, contents) in loaded.resources {
+                        document.add_resource(&url, contents);
+                    }
+                    data.document = Arc::new(document);
+                    self.with_resources = Some(data.document.clone());
+                }
+                ctx.set_handled();
+                return;
+            }
+        }
+
         self.renderer.event(ctx, event, &mut data.document, env);
 
         // Visit link if clicked
@@ -38,10 +105,24 @@ impl Widget<AppState> for Content {
     }
 
     fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &AppState, env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.load_user_stylesheets(data);
+            self.load_resources(ctx.get_external_handle(), ctx.widget_id(), data);
+        }
         self.renderer.lifecycle(ctx, event, &data.document, env);
     }
 
     fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, env: &Env) {
+        // Reload the user stylesheets on navigation (or reload), which also
+        // picks up changes to them, and load the resources of the new page
+        if !old_data.document.same(&data.document) {
+            let is_new_page = !self.with_resources.as_ref().is_some_and(|d| d.same(&data.document));
+            if is_new_page {
+                self.load_user_stylesheets(data);
+                self.load_resources(ctx.get_external_handle(), ctx.widget_id(), data);
+            }
+            ctx.request_layout();
+        }
         self.renderer.update(ctx, &old_data.document, &data.document, env);
     }
 
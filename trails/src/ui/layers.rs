@@ -0,0 +1,50 @@
+use druid::{Affine, Rect, piet::{Device, Error, ImageFormat, InterpolationMode, PietTextLayout, RenderContext}};
+use trails_base::log::warn;
+use trails_render::web::{LayerContent, Layers};
+
+/// Paints layers into offscreen bitmaps in device pixels, which are then
+/// drawn with their opacity.
+pub struct BitmapLayers;
+
+impl BitmapLayers {
+    fn try_paint_layer<P>(&mut self, paint: &mut P, area: Rect, opacity: f64, content: &impl LayerContent<PietTextLayout>) -> Result<(), Error> where P: RenderContext<TextLayout = PietTextLayout> {
+        let transform = paint.current_transform();
+        let device_area = transform.transform_rect_bbox(area).expand();
+        let (width, height) = (device_area.width() as usize, device_area.height() as usize);
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        // Paint the content with the bitmap's origin at the top left corner of the area
+        let mut pixels = vec![0; width * height * 4];
+        let mut device = Device::new()?;
+        let mut target = device.bitmap_target(width, height, 1.0)?;
+        {
+            let mut layer = target.render_context();
+            layer.transform(Affine::translate(-device_area.origin().to_vec2()) * transform);
+            content.paint(&mut layer, self);
+            layer.finish()?;
+        }
+        target.copy_raw_pixels(ImageFormat::RgbaPremul, &mut pixels)?;
+
+        // The pixels are premultiplied, so all channels fade
+        for channel in &mut pixels {
+            *channel = (*channel as f64 * opacity).round() as u8;
+        }
+
+        let image = paint.make_image(width, height, &pixels, ImageFormat::RgbaPremul)?;
+        paint.with_save(|paint| {
+            paint.transform(transform.inverse());
+            paint.draw_image(&image, device_area, InterpolationMode::NearestNeighbor);
+            Ok(())
+        })
+    }
+}
+
+impl Layers<PietTextLayout> for BitmapLayers {
+    fn paint_layer<P>(&mut self, paint: &mut P, area: Rect, opacity: f64, content: &impl LayerContent<PietTextLayout>) where P: RenderContext<TextLayout = PietTextLayout> {
+        if let Err(e) = self.try_paint_layer(paint, area, opacity, content) {
+            warn!("Could not paint layer: {}", e);
+        }
+    }
+}
@@ -2,6 +2,8 @@ mod app;
 mod bar;
 mod content;
 mod icon_button;
+mod layers;
+mod offset_scroll;
 mod submit;
 mod tighten;
 mod web;
@@ -12,5 +14,7 @@ pub use web::*;
 pub(crate) use bar::*;
 pub(crate) use content::*;
 pub(crate) use icon_button::*;
+pub(crate) use layers::*;
+pub(crate) use offset_scroll::*;
 pub(crate) use submit::*;
 pub(crate) use tighten::*;
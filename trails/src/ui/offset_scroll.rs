@@ -0,0 +1,47 @@
+use druid::{Widget, Size, Env, BoxConstraints, LifeCycle, Event, PaintCtx, LayoutCtx, UpdateCtx, LifeCycleCtx, EventCtx, Data, widget::Scroll};
+
+use super::SCROLL_OFFSET;
+
+/// A scroll container that provides its current scroll offset to its
+/// content through the environment (under `SCROLL_OFFSET`).
+pub struct OffsetScroll<T, W> {
+    scroll: Scroll<T, W>,
+}
+
+impl<T, W> OffsetScroll<T, W> {
+    pub fn new(scroll: Scroll<T, W>) -> Self {
+        Self { scroll }
+    }
+
+    /// The environment with the current scroll offset added.
+    fn env(&self, env: &Env) -> Env {
+        env.clone().adding(SCROLL_OFFSET, self.scroll.offset().to_point())
+    }
+}
+
+impl<T, W> Widget<T> for OffsetScroll<T, W> where T: Data, W: Widget<T> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut T, env: &Env) {
+        let env = self.env(env);
+        self.scroll.event(ctx, event, data, &env);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &T, env: &Env) {
+        let env = self.env(env);
+        self.scroll.lifecycle(ctx, event, data, &env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &T, data: &T, env: &Env) {
+        let env = self.env(env);
+        self.scroll.update(ctx, old_data, data, &env);
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &T, env: &Env) -> Size {
+        let env = self.env(env);
+        self.scroll.layout(ctx, bc, data, &env)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &T, env: &Env) {
+        let env = self.env(env);
+        self.scroll.paint(ctx, data, &env);
+    }
+}
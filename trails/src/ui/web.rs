@@ -1,25 +1,67 @@
 use std::sync::Arc;
 
-use druid::{Widget, Size, Env, BoxConstraints, LifeCycle, Event, PaintCtx, LayoutCtx, UpdateCtx, LifeCycleCtx, EventCtx, piet::NullRenderContext};
+use druid::{Widget, Size, Rect, Point, Vec2, Env, Key, BoxConstraints, LifeCycle, Event, PaintCtx, LayoutCtx, UpdateCtx, LifeCycleCtx, EventCtx, piet::PietTextLayout};
 use trails_base::log::{debug, info};
+use trails_model::css::{ColorScheme, MediaType};
 use trails_model::dom::Document;
-use trails_render::web::{LinkAreas, RenderParams, Renderer};
+use trails_render::web::{paint_layout, FontCache, ImageCache, LayoutParams, LayoutResult, Layouter, StyleCache};
+
+use super::BitmapLayers;
+
+/// The offset by which the page is scrolled, provided by the enclosing scroll container.
+pub const SCROLL_OFFSET: Key<Point> = Key::new("trails.web.scroll-offset");
 
 pub struct WebRenderer {
-    /// The clickable link areas from the last render.
-    link_areas: Option<LinkAreas>,
+    /// The laid out document from the last layout, which is painted (and
+    /// hit-tested) until the document or the available size changes.
+    layout: Option<LayoutResult<PietTextLayout>>,
     /// Tracks a visit request after an event. The parent may or may not choose to honor this.
     active_link: Option<String>,
+    /// The window title set from the document.
+    title: Option<String>,
+    /// The size of the visible viewport from the last layout.
+    viewport_size: Size,
+    /// The offset by which the page is scrolled, which fixed and sticky boxes follow.
+    scroll_offset: Vec2,
+    /// The type of media to render for.
+    media_type: MediaType,
+    /// The user's preferred color scheme.
+    color_scheme: ColorScheme,
+    /// The computed styles, which are only updated when the document or viewport change.
+    styles: StyleCache,
+    /// The web fonts registered with the text backend so far.
+    fonts: FontCache,
+    /// The decoded images of the document.
+    images: ImageCache,
 }
 
 impl WebRenderer {
     pub fn new() -> Self {
         Self {
-            link_areas: None,
+            layout: None,
             active_link: None,
+            title: None,
+            viewport_size: Size::ZERO,
+            scroll_offset: Vec2::ZERO,
+            media_type: MediaType::Screen,
+            color_scheme: ColorScheme::Light,
+            styles: StyleCache::new(),
+            fonts: FontCache::new(),
+            images: ImageCache::new(),
         }
     }
 
+    /// Sets the sources of the user stylesheets to apply.
+    pub fn set_user_stylesheets(&mut self, sources: Vec<String>) {
+        self.styles.set_user_stylesheets(sources);
+        self.layout = None;
+    }
+
+    /// Tracks the scroll offset provided by the enclosing scroll container, if any.
+    fn update_scroll_offset(&mut self, env: &Env) {
+        self.scroll_offset = env.try_get(SCROLL_OFFSET).map(|p| p.to_vec2()).unwrap_or(Vec2::ZERO);
+    }
+
     /// The clicked link after an event.
     pub fn active_link(&self) -> Option<&str> {
         self.active_link.as_ref().map(|s| s.as_str())
@@ -27,20 +69,31 @@ impl WebRenderer {
 }
 
 impl Widget<Arc<Document>> for WebRenderer {
-    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _document: &mut Arc<Document>, _env: &Env) {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _document: &mut Arc<Document>, env: &Env) {
         self.active_link = None;
+        self.update_scroll_offset(env);
 
         match event {
             Event::MouseUp(e) => {
                 let point = e.pos;
 
                 // Find the clicked link area
-                if let Some(area) = self.link_areas.as_ref().and_then(|l| l.areas.iter().find(|a| a.area.contains(point)).cloned()) {
+                if let Some(area) = self.layout.as_ref().and_then(|l| l.link_areas.find(point, self.scroll_offset, &l.scroll_areas).cloned()) {
                     info!("Clicked {:?}", area);
                     self.active_link = Some(area.href);
                     ctx.set_handled();
                 }
             },
+            Event::Wheel(e) => {
+                // Scroll the innermost scroll container under the mouse, the
+                // scroll wrapper around us scrolls the page otherwise
+                if let Some(layout) = &mut self.layout {
+                    if layout.scroll_areas.scroll(e.pos, e.wheel_delta, self.scroll_offset) {
+                        ctx.request_paint();
+                        ctx.set_handled();
+                    }
+                }
+            },
             _ => {},
         }
     }
@@ -50,45 +103,57 @@ impl Widget<Arc<Document>> for WebRenderer {
 
     fn update(&mut self, ctx: &mut UpdateCtx, old_document: &Arc<Document>, document: &Arc<Document>, _env: &Env) {
         if old_document != document {
+            self.layout = None;
             ctx.request_layout();
             ctx.request_paint();
         }
     }
 
-    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, document: &Arc<Document>, _env: &Env) -> Size {
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, document: &Arc<Document>, _env: &Env) -> Size {
         let min_size = bc.min();
 
-        // Perform a render pass without a paint context to determine the document's size
-        let params = RenderParams::<NullRenderContext> {
-            paint: None,
-            base_size: min_size
-        };
-        let result = Renderer::new(params).render_document(document);
+        // Only lay out the document again if it or the viewport changed
+        if self.layout.is_none() || self.viewport_size != min_size {
+            // Track the viewport size, which media queries are (re-)evaluated against
+            self.viewport_size = min_size;
+
+            let params = LayoutParams {
+                width: min_size.width,
+                viewport_size: self.viewport_size,
+                media_type: self.media_type,
+                color_scheme: self.color_scheme,
+            };
+            let result = Layouter::new(params, ctx.text()).layout_document(document, &mut self.styles, &mut self.fonts, &mut self.images);
+            debug!("Document size: {}", result.size);
+            self.layout = Some(result);
+        }
 
-        debug!("Document size: {}", result.size);
+        let size = self.layout.as_ref().map(|l| l.size).unwrap_or_default();
         Size::new(
-            min_size.width.max(result.size.width),
-            min_size.height.max(result.size.height),
+            min_size.width.max(size.width),
+            min_size.height.max(size.height),
         )
     }
 
-    fn paint(&mut self, ctx: &mut PaintCtx, document: &Arc<Document>, _env: &Env) {
+    fn paint(&mut self, ctx: &mut PaintCtx, _document: &Arc<Document>, env: &Env) {
         let size = ctx.size();
+        let region = ctx.region().bounding_box();
 
-        // Perform a render pass over the document
-        let params = RenderParams {
-            paint: Some(&mut **ctx),
-            base_size: size,
-        };
-        let result = Renderer::new(params).render_document(document);
+        self.update_scroll_offset(env);
+        let viewport = Rect::from_origin_size(self.scroll_offset.to_point(), self.viewport_size);
 
-        // Update found link areas
-        self.link_areas = Some(result.link_areas);
+        if let Some(layout) = &self.layout {
+            // Paint the laid out document
+            paint_layout(&mut **ctx, layout, size, region, viewport, &mut BitmapLayers);
 
-        // Update window title if needed
-        if let Some(title) = result.title {
-            info!("Setting title to '{}'", &title);
-            ctx.window().set_title(&title);
+            // Update window title if needed
+            if layout.title != self.title {
+                if let Some(title) = &layout.title {
+                    info!("Setting title to '{}'", title);
+                    ctx.window().set_title(title);
+                }
+                self.title = layout.title.clone();
+            }
         }
     }
 }